@@ -0,0 +1,232 @@
+use crate::auth::AuthenticatedUser;
+use aws_sdk_dynamodb::types::AttributeValue;
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use shared::User;
+
+use crate::AppState;
+
+/// Upper bound on the size of a block list, keeping queue entries small
+pub const MAX_BLOCKED_USERS: usize = 200;
+
+type ApiError = (StatusCode, Json<serde_json::Value>);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct BlockListResponse {
+    pub blocked_user_ids: Vec<String>,
+}
+
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn list_blocks(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+) -> Result<Json<BlockListResponse>, ApiError> {
+    let user = fetch_user(&state, &auth_user.claims.sub).await?;
+    Ok(Json(BlockListResponse {
+        blocked_user_ids: user.blocked_user_ids,
+    }))
+}
+
+/// Adds `blocked_user_id` to the caller's block list. Blocking someone twice is a no-op.
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn block_user(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(blocked_user_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let user = fetch_user(&state, &auth_user.claims.sub).await?;
+
+    match validate_block(&user, &blocked_user_id) {
+        Ok(true) => {}
+        Ok(false) => return Ok(StatusCode::NO_CONTENT),
+        Err(message) => return Err(error(StatusCode::BAD_REQUEST, message)),
+    }
+
+    // The condition guards against a concurrent update adding the same user or growing
+    // the list past the limit since it was read
+    let result = state
+        .dynamo_client
+        .update_item()
+        .table_name(&state.users_table)
+        .key("user_id", AttributeValue::S(user.user_id.clone()))
+        .update_expression(
+            "SET blocked_user_ids = list_append(if_not_exists(blocked_user_ids, :empty), :new)",
+        )
+        .condition_expression(
+            "attribute_exists(user_id) AND (attribute_not_exists(blocked_user_ids) OR \
+             (NOT contains(blocked_user_ids, :blocked) AND size(blocked_user_ids) < :max))",
+        )
+        .expression_attribute_values(":empty", AttributeValue::L(Vec::new()))
+        .expression_attribute_values(
+            ":new",
+            AttributeValue::L(vec![AttributeValue::S(blocked_user_id.clone())]),
+        )
+        .expression_attribute_values(":blocked", AttributeValue::S(blocked_user_id))
+        .expression_attribute_values(":max", AttributeValue::N(MAX_BLOCKED_USERS.to_string()))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(update_error(e)),
+    }
+}
+
+/// Removes `blocked_user_id` from the caller's block list. Unblocking someone who isn't
+/// blocked is a no-op.
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn unblock_user(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(blocked_user_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let user = fetch_user(&state, &auth_user.claims.sub).await?;
+
+    let Some(index) = user
+        .blocked_user_ids
+        .iter()
+        .position(|id| *id == blocked_user_id)
+    else {
+        return Ok(StatusCode::NO_CONTENT);
+    };
+
+    // Lists can only be edited by index, so make sure the entry hasn't moved since it
+    // was read
+    let result = state
+        .dynamo_client
+        .update_item()
+        .table_name(&state.users_table)
+        .key("user_id", AttributeValue::S(user.user_id.clone()))
+        .update_expression(format!("REMOVE blocked_user_ids[{}]", index))
+        .condition_expression(format!("blocked_user_ids[{}] = :blocked", index))
+        .expression_attribute_values(":blocked", AttributeValue::S(blocked_user_id))
+        .send()
+        .await;
+
+    match result {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(update_error(e)),
+    }
+}
+
+/// Checks that `blocked_user_id` can be added to `user`'s block list
+///
+/// Returns Ok(false) if they are already blocked.
+fn validate_block(user: &User, blocked_user_id: &str) -> Result<bool, &'static str> {
+    if blocked_user_id.is_empty() {
+        return Err("User id must not be empty");
+    }
+    if blocked_user_id == user.user_id {
+        return Err("You cannot block yourself");
+    }
+    if user.blocked_user_ids.iter().any(|id| id == blocked_user_id) {
+        return Ok(false);
+    }
+    if user.blocked_user_ids.len() >= MAX_BLOCKED_USERS {
+        return Err("Block list is full");
+    }
+    Ok(true)
+}
+
+async fn fetch_user(state: &AppState, user_id: &str) -> Result<User, ApiError> {
+    let response = state
+        .dynamo_client
+        .get_item()
+        .table_name(&state.users_table)
+        .key("user_id", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await
+        .map_err(|e| {
+            error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get item: {:?}", e),
+            )
+        })?;
+
+    let item = response
+        .item
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "User not found"))?;
+    serde_dynamo::from_item(item).map_err(|e| {
+        error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Deserialization error: {:?}", e),
+        )
+    })
+}
+
+fn update_error<E: std::fmt::Debug>(
+    e: aws_sdk_dynamodb::error::SdkError<
+        aws_sdk_dynamodb::operation::update_item::UpdateItemError,
+        E,
+    >,
+) -> ApiError {
+    if e.as_service_error()
+        .is_some_and(|se| se.is_conditional_check_failed_exception())
+    {
+        return error(
+            StatusCode::CONFLICT,
+            "Block list changed concurrently, please retry",
+        );
+    }
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to update block list: {:?}", e),
+    )
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> ApiError {
+    (status, Json(serde_json::json!({ "error": message.into() })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(blocked: &[&str]) -> User {
+        User {
+            user_id: "me".to_string(),
+            rating: 1200,
+            chess960_rating: None,
+            blocked_user_ids: blocked.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate_block_accepts_new_user() {
+        assert_eq!(validate_block(&user(&["a"]), "b"), Ok(true));
+    }
+
+    #[test]
+    fn test_validate_block_is_idempotent() {
+        assert_eq!(validate_block(&user(&["a"]), "a"), Ok(false));
+    }
+
+    #[test]
+    fn test_validate_block_rejects_self_and_empty() {
+        assert!(validate_block(&user(&[]), "me").is_err());
+        assert!(validate_block(&user(&[]), "").is_err());
+    }
+
+    #[test]
+    fn test_validate_block_rejects_full_list() {
+        let ids: Vec<String> = (0..MAX_BLOCKED_USERS).map(|i| i.to_string()).collect();
+        let full = User {
+            blocked_user_ids: ids,
+            ..user(&[])
+        };
+        assert!(validate_block(&full, "new").is_err());
+    }
+
+    #[test]
+    fn test_block_list_response_serialization() {
+        let json = serde_json::to_value(BlockListResponse {
+            blocked_user_ids: vec!["a".to_string()],
+        })
+        .unwrap();
+        assert_eq!(json, serde_json::json!({ "blocked_user_ids": ["a"] }));
+    }
+}
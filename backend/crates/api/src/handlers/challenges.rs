@@ -0,0 +1,70 @@
+use crate::auth::AuthenticatedUser;
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use matchmaker::challenges::{ChallengeRequest, ChallengeView};
+use shared::Game;
+
+use crate::handlers::errors::{service_error, ApiError};
+use crate::AppState;
+
+/// Challenges a specific user, or creates an open challenge link if no target is given
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn create_challenge(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Json(request): Json<ChallengeRequest>,
+) -> Result<(StatusCode, Json<ChallengeView>), ApiError> {
+    let view = state
+        .challenges
+        .create(&auth_user.claims.sub, request)
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(view)))
+}
+
+#[tracing::instrument(skip(_auth_user, state))]
+pub async fn get_challenge(
+    _auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(challenge_id): Path<String>,
+) -> Result<Json<ChallengeView>, ApiError> {
+    let challenge = state
+        .challenges
+        .get(&challenge_id)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(state.challenges.view(challenge)))
+}
+
+/// Accepts a challenge and returns the game created for it
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn accept_challenge(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(challenge_id): Path<String>,
+) -> Result<(StatusCode, Json<Game>), ApiError> {
+    let game = state
+        .challenges
+        .accept(&challenge_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(game)))
+}
+
+/// Withdraws a challenge (challenger) or declines it (challenged user)
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn cancel_challenge(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(challenge_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .challenges
+        .cancel(&challenge_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
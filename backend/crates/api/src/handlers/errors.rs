@@ -0,0 +1,55 @@
+use axum::{http::StatusCode, Json};
+use matchmaker::errors::ServiceError;
+
+pub type ApiError = (StatusCode, Json<serde_json::Value>);
+
+/// Maps a refused challenge, invite, simul or tournament action to its HTTP response
+pub fn service_error(e: ServiceError) -> ApiError {
+    let status = match &e {
+        ServiceError::Invalid(_) => StatusCode::BAD_REQUEST,
+        ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+        ServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+        ServiceError::Conflict(_) => StatusCode::CONFLICT,
+        ServiceError::Throttled(_) => StatusCode::TOO_MANY_REQUESTS,
+        ServiceError::Internal(_) => {
+            tracing::error!("Request failed: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    (status, Json(serde_json::json!({ "error": e.to_string() })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_errors_map_to_statuses() {
+        let cases = [
+            (
+                ServiceError::Invalid("bad".to_string()),
+                StatusCode::BAD_REQUEST,
+            ),
+            (ServiceError::NotFound("Game"), StatusCode::NOT_FOUND),
+            (ServiceError::Forbidden("no"), StatusCode::FORBIDDEN),
+            (ServiceError::unavailable("Seek"), StatusCode::CONFLICT),
+            (
+                ServiceError::Throttled("wait"),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            (
+                ServiceError::Internal("boom".into()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(service_error(error).0, expected);
+        }
+    }
+
+    #[test]
+    fn test_error_body_carries_the_message() {
+        let (_, body) = service_error(ServiceError::NotFound("Tournament"));
+        assert_eq!(body.0["error"], "Tournament not found");
+    }
+}
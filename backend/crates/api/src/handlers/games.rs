@@ -0,0 +1,24 @@
+use crate::auth::AuthenticatedUser;
+use axum::{
+    extract::{Extension, Path},
+    Json,
+};
+use shared::Game;
+
+use crate::handlers::errors::{service_error, ApiError};
+use crate::AppState;
+
+/// Returns a game to watch; private games are only returned to their players
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn get_game(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(game_id): Path<String>,
+) -> Result<Json<Game>, ApiError> {
+    let game = state
+        .challenges
+        .spectate_game(&game_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(game))
+}
@@ -0,0 +1,85 @@
+use crate::auth::AuthenticatedUser;
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use matchmaker::invites::{Invite, InviteRequest};
+use shared::Game;
+
+use crate::handlers::errors::{service_error, ApiError};
+use crate::AppState;
+
+/// Creates a private game and returns the code a friend joins it with
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn create_invite(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Json(request): Json<InviteRequest>,
+) -> Result<(StatusCode, Json<Invite>), ApiError> {
+    let invite = state
+        .challenges
+        .create_invite(&auth_user.claims.sub, request)
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(invite)))
+}
+
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn get_invite(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(code): Path<String>,
+) -> Result<Json<Invite>, ApiError> {
+    let invite = state
+        .challenges
+        .get_invite(&code, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(invite))
+}
+
+/// Joins a private game by its code and returns the game
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn join_invite(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(code): Path<String>,
+) -> Result<(StatusCode, Json<Game>), ApiError> {
+    let game = state
+        .challenges
+        .join_invite(&code, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(game)))
+}
+
+/// Withdraws an invite nobody has used yet
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn cancel_invite(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(code): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .challenges
+        .cancel_invite(&code, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lets the host of a private game show it in lobbies, to spectators and on leaderboards
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn make_game_public(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(game_id): Path<String>,
+) -> Result<Json<Game>, ApiError> {
+    let game = state
+        .challenges
+        .make_game_public(&game_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(game))
+}
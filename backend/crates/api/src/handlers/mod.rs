@@ -1,6 +1,21 @@
+pub mod blocks;
+pub mod challenges;
+pub mod errors;
+pub mod games;
 pub mod health;
+pub mod invites;
+pub mod seeks;
+pub mod simuls;
+pub mod time_controls;
+pub mod tournaments;
 pub mod users;
 
+pub use blocks::{block_user, list_blocks, unblock_user};
+pub use challenges::{accept_challenge, cancel_challenge, create_challenge, get_challenge};
 pub use health::health_check;
+pub use tournaments::{
+    create_tournament, get_tournament, register_for_tournament, start_next_round,
+    withdraw_from_tournament,
+};
 pub use users::delete_me;
 pub use users::get_me;
@@ -0,0 +1,23 @@
+use crate::auth::AuthenticatedUser;
+use axum::{extract::Extension, http::StatusCode, Json};
+use matchmaker::seeks::Seek;
+
+use crate::AppState;
+
+/// Returns the lobby: every open seek, oldest first
+///
+/// Clients load this once and then follow the lobby_update websocket pushes.
+#[tracing::instrument(skip(_auth_user, state))]
+pub async fn list_seeks(
+    _auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+) -> Result<Json<Vec<Seek>>, (StatusCode, Json<serde_json::Value>)> {
+    let seeks = state.seeks.list().await.map_err(|e| {
+        tracing::error!("Failed to list seeks: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": "Failed to load the lobby" })),
+        )
+    })?;
+    Ok(Json(seeks))
+}
@@ -0,0 +1,81 @@
+use crate::auth::AuthenticatedUser;
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use matchmaker::simuls::{Simul, SimulRequest};
+
+use crate::handlers::errors::{service_error, ApiError};
+use crate::AppState;
+
+/// Creates a simul hosted by the caller, open for participants
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn create_simul(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Json(request): Json<SimulRequest>,
+) -> Result<(StatusCode, Json<Simul>), ApiError> {
+    let simul = state
+        .simuls
+        .create(&auth_user.claims.sub, request)
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(simul)))
+}
+
+/// Returns the simul with every participant's board
+#[tracing::instrument(skip(_auth_user, state))]
+pub async fn get_simul(
+    _auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(simul_id): Path<String>,
+) -> Result<Json<Simul>, ApiError> {
+    let simul = state.simuls.get(&simul_id).await.map_err(service_error)?;
+    Ok(Json(simul))
+}
+
+/// Joins the caller to a simul that hasn't started
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn join_simul(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(simul_id): Path<String>,
+) -> Result<Json<Simul>, ApiError> {
+    let simul = state
+        .simuls
+        .join(&simul_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(simul))
+}
+
+/// Takes the caller out of a simul that hasn't started
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn leave_simul(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(simul_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .simuls
+        .leave(&simul_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts every board at once (host only)
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn start_simul(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(simul_id): Path<String>,
+) -> Result<Json<Simul>, ApiError> {
+    let simul = state
+        .simuls
+        .start(&simul_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(simul))
+}
@@ -0,0 +1,24 @@
+use axum::Json;
+use shared::time_control::{TimeControl, TIME_CONTROLS};
+
+/// Lists the time controls players can queue, seek and hold events in
+///
+/// Public, so clients can render their pickers before signing in.
+#[tracing::instrument]
+pub async fn list_time_controls() -> Json<&'static [TimeControl]> {
+    Json(TIME_CONTROLS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lists_the_whole_catalogue() {
+        let response = list_time_controls().await;
+        assert_eq!(response.0.len(), TIME_CONTROLS.len());
+        let json = serde_json::to_value(response.0).unwrap();
+        assert_eq!(json[0]["id"], "1+0");
+        assert_eq!(json[0]["category"], "bullet");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::auth::AuthenticatedUser;
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    Json,
+};
+use matchmaker::tournaments::{TournamentRequest, TournamentView};
+
+use crate::handlers::errors::{service_error, ApiError};
+use crate::AppState;
+
+/// Creates a tournament organized by the caller, open for registration
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn create_tournament(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Json(request): Json<TournamentRequest>,
+) -> Result<(StatusCode, Json<TournamentView>), ApiError> {
+    let tournament = state
+        .tournaments
+        .create(&auth_user.claims.sub, request)
+        .await
+        .map_err(service_error)?;
+    Ok((StatusCode::CREATED, Json(tournament.into())))
+}
+
+/// Returns the tournament with its pairings and standings
+#[tracing::instrument(skip(_auth_user, state))]
+pub async fn get_tournament(
+    _auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(tournament_id): Path<String>,
+) -> Result<Json<TournamentView>, ApiError> {
+    let tournament = state
+        .tournaments
+        .get(&tournament_id)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(tournament.into()))
+}
+
+/// Registers the caller for a tournament that hasn't started
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn register_for_tournament(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(tournament_id): Path<String>,
+) -> Result<Json<TournamentView>, ApiError> {
+    let tournament = state
+        .tournaments
+        .register(&tournament_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(tournament.into()))
+}
+
+/// Withdraws the caller's registration
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn withdraw_from_tournament(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(tournament_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state
+        .tournaments
+        .withdraw(&tournament_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Pairs the next round, or finishes the tournament after the last one (organizer only)
+#[tracing::instrument(skip(auth_user, state))]
+pub async fn start_next_round(
+    auth_user: AuthenticatedUser,
+    Extension(state): Extension<AppState>,
+    Path(tournament_id): Path<String>,
+) -> Result<Json<TournamentView>, ApiError> {
+    let tournament = state
+        .tournaments
+        .start_next_round(&tournament_id, &auth_user.claims.sub)
+        .await
+        .map_err(service_error)?;
+    Ok(Json(tournament.into()))
+}
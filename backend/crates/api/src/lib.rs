@@ -1,10 +1,15 @@
 use aws_config::BehaviorVersion;
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
 use aws_sdk_cognitoidentityprovider::Client as CognitoClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
 use axum::{
-    routing::{delete, get},
+    routing::{delete, get, post, put},
     Extension, Router,
 };
+use matchmaker::challenges::ChallengeContext;
+use matchmaker::seeks::SeekContext;
+use matchmaker::simuls::SimulContext;
+use matchmaker::tournaments::TournamentContext;
 use tower::ServiceBuilder;
 use tower_http::{
     cors::{Any, CorsLayer},
@@ -20,6 +25,10 @@ pub struct AppState {
     pub cognito_client: CognitoClient,
     pub users_table: String,
     pub cognito_user_pool_id: String,
+    pub challenges: ChallengeContext,
+    pub tournaments: TournamentContext,
+    pub simuls: SimulContext,
+    pub seeks: SeekContext,
 }
 
 impl AppState {
@@ -31,11 +40,31 @@ impl AppState {
         let cognito_user_pool_id =
             std::env::var("COGNITO_USER_POOL_ID").expect("COGNITO_USER_POOL_ID must be set");
 
+        // Challenge notifications are pushed to players over the websocket API
+        let websocket_api_endpoint =
+            std::env::var("WEBSOCKET_API_ENDPOINT").expect("WEBSOCKET_API_ENDPOINT must be set");
+        let api_config = aws_sdk_apigatewaymanagement::config::Builder::from(&config)
+            .endpoint_url(&websocket_api_endpoint)
+            .build();
+        let api_gateway = ApiGatewayClient::from_conf(api_config);
+        let challenges = ChallengeContext::from_env(dynamo_client.clone(), api_gateway.clone())
+            .expect("Invalid challenge configuration");
+        let tournaments = TournamentContext::from_env(dynamo_client.clone(), api_gateway.clone())
+            .expect("Invalid tournament configuration");
+        let simuls = SimulContext::from_env(dynamo_client.clone(), api_gateway.clone())
+            .expect("Invalid simul configuration");
+        let seeks = SeekContext::from_env(dynamo_client.clone(), api_gateway)
+            .expect("Invalid seek configuration");
+
         Self {
             dynamo_client,
             cognito_client,
             users_table,
             cognito_user_pool_id,
+            challenges,
+            tournaments,
+            simuls,
+            seeks,
         }
     }
 }
@@ -43,8 +72,75 @@ impl AppState {
 pub fn create_app(state: AppState) -> Router {
     Router::new()
         .route("/health", get(handlers::health::health_check))
+        .route(
+            "/time-controls",
+            get(handlers::time_controls::list_time_controls),
+        )
         .route("/users/me", get(handlers::users::get_me))
         .route("/users/me", delete(handlers::users::delete_me))
+        .route("/users/me/blocks", get(handlers::blocks::list_blocks))
+        .route(
+            "/users/me/blocks/:user_id",
+            put(handlers::blocks::block_user).delete(handlers::blocks::unblock_user),
+        )
+        .route("/challenges", post(handlers::challenges::create_challenge))
+        .route(
+            "/challenges/:challenge_id",
+            get(handlers::challenges::get_challenge).delete(handlers::challenges::cancel_challenge),
+        )
+        .route(
+            "/challenges/:challenge_id/accept",
+            post(handlers::challenges::accept_challenge),
+        )
+        .route("/invites", post(handlers::invites::create_invite))
+        .route(
+            "/invites/:code",
+            get(handlers::invites::get_invite).delete(handlers::invites::cancel_invite),
+        )
+        .route(
+            "/invites/:code/accept",
+            post(handlers::invites::join_invite),
+        )
+        .route("/games/:game_id", get(handlers::games::get_game))
+        .route(
+            "/games/:game_id/public",
+            post(handlers::invites::make_game_public),
+        )
+        .route(
+            "/tournaments",
+            post(handlers::tournaments::create_tournament),
+        )
+        .route(
+            "/tournaments/:tournament_id",
+            get(handlers::tournaments::get_tournament),
+        )
+        .route(
+            "/tournaments/:tournament_id/players",
+            post(handlers::tournaments::register_for_tournament),
+        )
+        .route(
+            "/tournaments/:tournament_id/players/me",
+            delete(handlers::tournaments::withdraw_from_tournament),
+        )
+        .route(
+            "/tournaments/:tournament_id/rounds",
+            post(handlers::tournaments::start_next_round),
+        )
+        .route("/seeks", get(handlers::seeks::list_seeks))
+        .route("/simuls", post(handlers::simuls::create_simul))
+        .route("/simuls/:simul_id", get(handlers::simuls::get_simul))
+        .route(
+            "/simuls/:simul_id/participants",
+            post(handlers::simuls::join_simul),
+        )
+        .route(
+            "/simuls/:simul_id/participants/me",
+            delete(handlers::simuls::leave_simul),
+        )
+        .route(
+            "/simuls/:simul_id/start",
+            post(handlers::simuls::start_simul),
+        )
         .layer(Extension(state))
         .layer(
             ServiceBuilder::new()
@@ -6,7 +6,6 @@ use lambda_runtime::{
     tracing::{info, init_default_subscriber},
     Error, LambdaEvent,
 };
-use serde_json;
 
 use shared::User;
 use tokio::sync::OnceCell as AsyncOnceCell;
@@ -46,6 +45,8 @@ async fn function_handler(
     let user = User {
         user_id,
         rating: 1200,
+        chess960_rating: None,
+        blocked_user_ids: Vec::new(),
     };
 
     // Serialize to DynamoDB item
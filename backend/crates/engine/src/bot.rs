@@ -0,0 +1,183 @@
+use rand::Rng;
+use std::time::Duration;
+
+use crate::movegen::Move;
+use crate::position::Position;
+use crate::search::Engine;
+use crate::strength::Strength;
+use crate::uci::{UciConfig, UciEngine, UciError, UciLimits};
+
+/// Lowest bot rating handed to the external engine when none is configured
+const DEFAULT_EXTERNAL_FROM_RATING: i32 = 2200;
+
+/// Picks moves for a bot, with the built-in engine or an external UCI engine
+pub enum BotEngine {
+    BuiltIn {
+        engine: Box<Engine>,
+        strength: Strength,
+    },
+    /// External engines back the strongest bots, so they always play their best move
+    External(UciEngine),
+}
+
+impl BotEngine {
+    /// The built-in engine tuned to play at about `rating`
+    pub fn for_rating(rating: i32) -> Self {
+        BotEngine::BuiltIn {
+            engine: Box::default(),
+            strength: Strength::for_rating(rating),
+        }
+    }
+
+    /// Chooses a move in `position` within `time_budget`; None if there are no legal moves
+    pub fn choose_move(
+        &mut self,
+        position: &Position,
+        time_budget: Duration,
+        rng: &mut impl Rng,
+    ) -> Result<Option<Move>, UciError> {
+        match self {
+            BotEngine::BuiltIn { engine, strength } => {
+                let result = engine.search(position, &strength.limits(Some(time_budget)));
+                Ok(strength.choose_move(&result, rng))
+            }
+            BotEngine::External(uci) => {
+                let limits = UciLimits {
+                    movetime: Some(time_budget),
+                    ..UciLimits::default()
+                };
+                // Chess960 positions need the engine to read and write castling by rook file
+                let chess960 = if position.is_chess960() {
+                    "true"
+                } else {
+                    "false"
+                };
+                if uci.option("UCI_Chess960").unwrap_or("false") != chess960 {
+                    uci.set_option("UCI_Chess960", chess960)?;
+                }
+                let result = uci.search(Some(&position.to_fen()), &[], &limits)?;
+                let Some(uci_move) = result.best_move else {
+                    return Ok(None);
+                };
+                let mv = position
+                    .clone()
+                    .parse_uci_move(&uci_move)
+                    .ok_or_else(|| UciError::Protocol(format!("illegal move {}", uci_move)))?;
+                Ok(Some(mv))
+            }
+        }
+    }
+}
+
+/// Which engine plays each bot level
+#[derive(Debug, Clone, PartialEq)]
+pub struct BotConfig {
+    /// External engine for the strongest bots; None plays every level with the built-in one
+    pub external: Option<UciConfig>,
+    /// Lowest bot rating the external engine plays
+    pub external_from_rating: i32,
+}
+
+impl BotConfig {
+    /// Reads the external engine from UCI_ENGINE_PATH and UCI_ENGINE_ARGS, and the lowest
+    /// rating it plays from BOT_EXTERNAL_FROM_RATING (2200 if unset)
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(
+            UciConfig::from_env(),
+            std::env::var("BOT_EXTERNAL_FROM_RATING").ok().as_deref(),
+        )
+    }
+
+    pub fn parse(external: Option<UciConfig>, from_rating: Option<&str>) -> Result<Self, String> {
+        let external_from_rating = match from_rating.filter(|s| !s.trim().is_empty()) {
+            Some(s) => s
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid BOT_EXTERNAL_FROM_RATING: {}", s))?,
+            None => DEFAULT_EXTERNAL_FROM_RATING,
+        };
+        Ok(Self {
+            external,
+            external_from_rating,
+        })
+    }
+
+    /// Starts the engine that plays the bot rated `rating`
+    pub fn engine_for(&self, rating: i32) -> Result<BotEngine, UciError> {
+        match &self.external {
+            Some(config) if rating >= self.external_from_rating => {
+                Ok(BotEngine::External(UciEngine::start(config.clone())?))
+            }
+            _ => Ok(BotEngine::for_rating(rating)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_built_in_bot_plays_a_legal_move() {
+        let mut position = Position::default();
+        let mut bot = BotEngine::for_rating(1200);
+        let mut rng = StdRng::seed_from_u64(5);
+        let mv = bot
+            .choose_move(&position, Duration::from_millis(200), &mut rng)
+            .unwrap()
+            .unwrap();
+        assert!(position.legal_moves().contains(&mv));
+    }
+
+    fn fake_engine() -> UciConfig {
+        let script = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/fake_uci_engine.sh"
+        );
+        UciConfig {
+            args: vec![script.to_string()],
+            ..UciConfig::new("sh")
+        }
+    }
+
+    #[test]
+    fn test_external_bot_move_is_checked() {
+        let mut bot = BotEngine::External(UciEngine::start(fake_engine()).unwrap());
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let mv = bot
+            .choose_move(&Position::default(), Duration::from_millis(100), &mut rng)
+            .unwrap();
+        assert_eq!(mv.map(|mv| mv.to_uci()).as_deref(), Some("e2e4"));
+
+        // The fake engine always answers e2e4, which is illegal here
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(matches!(
+            bot.choose_move(&position, Duration::from_millis(100), &mut rng),
+            Err(UciError::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_strongest_bots_use_the_external_engine() {
+        let config = BotConfig::parse(Some(fake_engine()), Some("2000")).unwrap();
+        assert!(matches!(
+            config.engine_for(1800).unwrap(),
+            BotEngine::BuiltIn { .. }
+        ));
+        assert!(matches!(
+            config.engine_for(2000).unwrap(),
+            BotEngine::External(_)
+        ));
+
+        let built_in_only = BotConfig::parse(None, None).unwrap();
+        assert_eq!(built_in_only.external_from_rating, 2200);
+        assert!(matches!(
+            built_in_only.engine_for(2400).unwrap(),
+            BotEngine::BuiltIn { .. }
+        ));
+        assert!(BotConfig::parse(None, Some("strong")).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use shared::Color;
+
+use crate::position::{
+    color_index, file_of, offset, rank_of, PieceKind, Position, Square, BISHOP_DIRECTIONS,
+    KNIGHT_OFFSETS, ROOK_DIRECTIONS,
+};
+
+/// Tunable evaluation weights, in centipawns unless noted otherwise
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalParams {
+    /// Indexed by `PieceKind::index`; the king's value is unused
+    pub piece_values: [i32; 6],
+    /// Percentage applied to the piece-square tables
+    pub positional_weight: i32,
+    /// Bonus per square attacked by a knight, bishop, rook or queen
+    pub mobility_weight: i32,
+    pub bishop_pair: i32,
+    /// Bonus for having the move
+    pub tempo: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            piece_values: [100, 320, 330, 500, 900, 0],
+            positional_weight: 100,
+            mobility_weight: 2,
+            bishop_pair: 30,
+            tempo: 10,
+        }
+    }
+}
+
+/// Game phase weight of each piece kind; the total at the start is MAX_PHASE
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Piece-square tables from White's point of view, a8 first
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+/// Index into the tables above for a piece of `color` on `sq`
+fn table_index(color: Color, sq: Square) -> usize {
+    let rank = match color {
+        Color::White => 7 - rank_of(sq),
+        Color::Black => rank_of(sq),
+    };
+    (rank * 8 + file_of(sq)) as usize
+}
+
+impl EvalParams {
+    /// Static evaluation in centipawns from the side to move's point of view
+    pub fn evaluate(&self, position: &Position) -> i32 {
+        let mut material = [0; 2];
+        let mut positional = [0; 2];
+        let mut king_middlegame = [0; 2];
+        let mut king_endgame = [0; 2];
+        let mut mobility = [0; 2];
+        let mut bishops = [0; 2];
+        let mut phase = 0;
+
+        for (sq, piece) in position.pieces() {
+            let side = color_index(piece.color);
+            let index = table_index(piece.color, sq);
+            material[side] += self.piece_values[piece.kind.index()];
+            phase += PHASE_WEIGHTS[piece.kind.index()];
+            positional[side] += match piece.kind {
+                PieceKind::Pawn => PAWN_TABLE[index],
+                PieceKind::Knight => KNIGHT_TABLE[index],
+                PieceKind::Bishop => BISHOP_TABLE[index],
+                PieceKind::Rook => ROOK_TABLE[index],
+                PieceKind::Queen => QUEEN_TABLE[index],
+                PieceKind::King => {
+                    king_middlegame[side] = KING_MIDDLEGAME_TABLE[index];
+                    king_endgame[side] = KING_ENDGAME_TABLE[index];
+                    0
+                }
+            };
+            mobility[side] += match piece.kind {
+                PieceKind::Knight => KNIGHT_OFFSETS
+                    .iter()
+                    .filter_map(|&d| offset(sq, d))
+                    .filter(|&t| position.piece_at(t).is_none_or(|p| p.color != piece.color))
+                    .count() as i32,
+                PieceKind::Bishop => sliding_mobility(position, sq, &BISHOP_DIRECTIONS),
+                PieceKind::Rook => sliding_mobility(position, sq, &ROOK_DIRECTIONS),
+                PieceKind::Queen => {
+                    sliding_mobility(position, sq, &BISHOP_DIRECTIONS)
+                        + sliding_mobility(position, sq, &ROOK_DIRECTIONS)
+                }
+                PieceKind::Pawn | PieceKind::King => 0,
+            };
+            if piece.kind == PieceKind::Bishop {
+                bishops[side] += 1;
+            }
+        }
+
+        // The king heads for the centre as pieces come off
+        let phase = phase.min(MAX_PHASE);
+        let score_for = |side: usize| {
+            let king = (king_middlegame[side] * phase + king_endgame[side] * (MAX_PHASE - phase))
+                / MAX_PHASE;
+            let pair = if bishops[side] >= 2 {
+                self.bishop_pair
+            } else {
+                0
+            };
+            material[side]
+                + (positional[side] + king) * self.positional_weight / 100
+                + mobility[side] * self.mobility_weight
+                + pair
+        };
+
+        let us = color_index(position.side_to_move());
+        score_for(us) - score_for(1 - us) + self.tempo
+    }
+}
+
+/// Squares a slider on `sq` can move to, including captures
+fn sliding_mobility(position: &Position, sq: Square, directions: &[i16]) -> i32 {
+    let Some(color) = position.piece_at(sq).map(|p| p.color) else {
+        return 0;
+    };
+    let mut count = 0;
+    for &delta in directions {
+        let mut current = offset(sq, delta);
+        while let Some(target) = current {
+            match position.piece_at(target) {
+                Some(p) => {
+                    if p.color != color {
+                        count += 1;
+                    }
+                    break;
+                }
+                None => {
+                    count += 1;
+                    current = offset(target, delta);
+                }
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(fen: &str) -> i32 {
+        EvalParams::default().evaluate(&Position::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn test_starting_position_is_balanced() {
+        assert_eq!(
+            evaluate(shared::fen::STARTING_FEN),
+            EvalParams::default().tempo
+        );
+    }
+
+    #[test]
+    fn test_mirrored_positions_score_the_same() {
+        let white = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+        let black = "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3";
+        assert_eq!(evaluate(white), evaluate(black));
+    }
+
+    #[test]
+    fn test_material_advantage_is_positive_for_its_owner() {
+        let white_up_queen = "4k3/8/8/8/8/8/8/3QK3 w - - 0 1";
+        assert!(evaluate(white_up_queen) > 800);
+        let black_to_move = "4k3/8/8/8/8/8/8/3QK3 b - - 0 1";
+        assert!(evaluate(black_to_move) < -800);
+    }
+
+    #[test]
+    fn test_positional_weight_scales_tables() {
+        let position = Position::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        let flat = EvalParams {
+            positional_weight: 0,
+            mobility_weight: 0,
+            tempo: 0,
+            ..EvalParams::default()
+        };
+        assert_eq!(flat.evaluate(&position), 320);
+        assert!(EvalParams::default().evaluate(&position) > 320);
+    }
+}
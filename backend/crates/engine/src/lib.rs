@@ -0,0 +1,26 @@
+//! Chess engine used for bot opponents
+//!
+//! Positions use a 0x88 board. The search is an iterative-deepening alpha-beta with a
+//! transposition table and quiescence search; `Strength` limits it to play at a target
+//! rating. Memory use is dominated by the transposition table, whose size is chosen by
+//! the caller so the engine fits in a 128MB Lambda.
+//!
+//! External engines that speak UCI can be driven through `UciEngine`, and `BotEngine`
+//! plays moves with either; `BotConfig` picks which one plays each bot level.
+
+pub mod bot;
+pub mod eval;
+pub mod movegen;
+pub mod position;
+pub mod search;
+pub mod strength;
+pub mod tt;
+pub mod uci;
+
+pub use bot::{BotConfig, BotEngine};
+pub use eval::EvalParams;
+pub use movegen::{Move, MoveKind};
+pub use position::{Piece, PieceKind, Position, Square};
+pub use search::{Engine, SearchLimits, SearchResult};
+pub use strength::Strength;
+pub use uci::{UciConfig, UciEngine, UciError, UciInfo, UciLimits, UciScore};
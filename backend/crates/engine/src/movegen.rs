@@ -0,0 +1,416 @@
+use shared::Color;
+use std::fmt;
+
+use crate::position::{
+    back_rank, castle_squares, file_of, offset, parse_square, rank_of, square, square_name, Piece,
+    PieceKind, Position, Square, BISHOP_DIRECTIONS, BLACK_KINGSIDE, BLACK_QUEENSIDE, KING_OFFSETS,
+    KNIGHT_OFFSETS, ROOK_DIRECTIONS, WHITE_KINGSIDE, WHITE_QUEENSIDE,
+};
+
+const PROMOTIONS: [PieceKind; 4] = [
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    Quiet,
+    Capture,
+    DoublePush,
+    EnPassant,
+    Castle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub from: Square,
+    /// For castling, the square of the rook the king castles with
+    pub to: Square,
+    pub promotion: Option<PieceKind>,
+    pub kind: MoveKind,
+}
+
+impl Move {
+    pub fn is_capture(&self) -> bool {
+        matches!(self.kind, MoveKind::Capture | MoveKind::EnPassant)
+    }
+
+    /// Long algebraic notation as used by UCI, e.g. "e2e4" or "e7e8q"
+    ///
+    /// Castling from the standard squares is written as the king's move ("e1g1"); any
+    /// other castle is written as the king taking its own rook ("f1h1"), as in Chess960.
+    pub fn to_uci(&self) -> String {
+        let to = if self.kind == MoveKind::Castle
+            && file_of(self.from) == 4
+            && matches!(file_of(self.to), 0 | 7)
+        {
+            castle_squares(self.from, self.to).0
+        } else {
+            self.to
+        };
+        let mut uci = format!("{}{}", square_name(self.from), square_name(to));
+        if let Some(kind) = self.promotion {
+            uci.push(kind.to_char());
+        }
+        uci
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_uci())
+    }
+}
+
+impl Position {
+    /// Every legal move for the side to move
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let moves = self.pseudo_legal_moves(false);
+        self.retain_legal(moves)
+    }
+
+    /// Legal captures and promotions, for quiescence search
+    pub fn legal_captures(&mut self) -> Vec<Move> {
+        let moves = self.pseudo_legal_moves(true);
+        self.retain_legal(moves)
+    }
+
+    /// Finds the legal move matching `uci`, e.g. "e2e4" or "a7a8q"
+    ///
+    /// Castling is accepted both as the king's move and as the king taking its rook.
+    pub fn parse_uci_move(&mut self, uci: &str) -> Option<Move> {
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return None;
+        }
+        let from = parse_square(&uci[0..2])?;
+        let to = parse_square(&uci[2..4])?;
+        let promotion = match uci[4..].chars().next() {
+            Some(c) => Some(PieceKind::from_char(c)?),
+            None => None,
+        };
+        self.legal_moves().into_iter().find(|mv| {
+            (mv.from == from && mv.to == to && mv.promotion == promotion)
+                || (mv.kind == MoveKind::Castle && mv.to_uci() == uci)
+        })
+    }
+
+    /// Counts leaf nodes of the legal move tree, for checking move generation
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|mv| {
+                let undo = self.make_move(mv);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move(mv, undo);
+                nodes
+            })
+            .sum()
+    }
+
+    fn retain_legal(&mut self, moves: Vec<Move>) -> Vec<Move> {
+        let us = self.side_to_move();
+        moves
+            .into_iter()
+            .filter(|&mv| {
+                let undo = self.make_move(mv);
+                let legal = !self.in_check(us);
+                self.unmake_move(mv, undo);
+                legal
+            })
+            .collect()
+    }
+
+    /// Moves that obey piece movement but may leave the mover's king in check
+    fn pseudo_legal_moves(&self, captures_only: bool) -> Vec<Move> {
+        let us = self.side_to_move();
+        let mut moves = Vec::with_capacity(48);
+        for (from, piece) in self.pieces().filter(|(_, p)| p.color == us) {
+            match piece.kind {
+                PieceKind::Pawn => self.pawn_moves(from, captures_only, &mut moves),
+                PieceKind::Knight => {
+                    self.step_moves(from, &KNIGHT_OFFSETS, captures_only, &mut moves)
+                }
+                PieceKind::King => {
+                    self.step_moves(from, &KING_OFFSETS, captures_only, &mut moves);
+                    if !captures_only {
+                        self.castle_moves(from, &mut moves);
+                    }
+                }
+                PieceKind::Bishop => {
+                    self.slide_moves(from, &BISHOP_DIRECTIONS, captures_only, &mut moves)
+                }
+                PieceKind::Rook => {
+                    self.slide_moves(from, &ROOK_DIRECTIONS, captures_only, &mut moves)
+                }
+                PieceKind::Queen => {
+                    self.slide_moves(from, &BISHOP_DIRECTIONS, captures_only, &mut moves);
+                    self.slide_moves(from, &ROOK_DIRECTIONS, captures_only, &mut moves);
+                }
+            }
+        }
+        moves
+    }
+
+    fn pawn_moves(&self, from: Square, captures_only: bool, moves: &mut Vec<Move>) {
+        let us = self.side_to_move();
+        let (forward, start_rank, last_rank) = match us {
+            Color::White => (16, 1, 7),
+            Color::Black => (-16, 6, 0),
+        };
+        let mut push = |to: Square, kind: MoveKind| {
+            if rank_of(to) == last_rank {
+                for promotion in PROMOTIONS {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: Some(promotion),
+                        kind,
+                    });
+                }
+            } else {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: None,
+                    kind,
+                });
+            }
+        };
+
+        for side in [-1, 1] {
+            let Some(to) = offset(from, forward + side) else {
+                continue;
+            };
+            match self.piece_at(to) {
+                Some(target) if target.color != us => push(to, MoveKind::Capture),
+                None if self.en_passant() == Some(to) => push(to, MoveKind::EnPassant),
+                _ => {}
+            }
+        }
+
+        let Some(one) = offset(from, forward).filter(|&to| self.piece_at(to).is_none()) else {
+            return;
+        };
+        // Promotions are searched in quiescence too, as they change material
+        if !captures_only || rank_of(one) == last_rank {
+            push(one, MoveKind::Quiet);
+        }
+        if !captures_only && rank_of(from) == start_rank {
+            if let Some(two) = offset(one, forward).filter(|&to| self.piece_at(to).is_none()) {
+                push(two, MoveKind::DoublePush);
+            }
+        }
+    }
+
+    fn step_moves(
+        &self,
+        from: Square,
+        offsets: &[i16],
+        captures_only: bool,
+        moves: &mut Vec<Move>,
+    ) {
+        for &delta in offsets {
+            if let Some(to) = offset(from, delta) {
+                self.push_unless_own(from, to, captures_only, moves);
+            }
+        }
+    }
+
+    fn slide_moves(
+        &self,
+        from: Square,
+        directions: &[i16],
+        captures_only: bool,
+        moves: &mut Vec<Move>,
+    ) {
+        for &delta in directions {
+            let mut current = offset(from, delta);
+            while let Some(to) = current {
+                self.push_unless_own(from, to, captures_only, moves);
+                if self.piece_at(to).is_some() {
+                    break;
+                }
+                current = offset(to, delta);
+            }
+        }
+    }
+
+    fn push_unless_own(
+        &self,
+        from: Square,
+        to: Square,
+        captures_only: bool,
+        moves: &mut Vec<Move>,
+    ) {
+        let kind = match self.piece_at(to) {
+            Some(target) if target.color == self.side_to_move() => return,
+            Some(_) => MoveKind::Capture,
+            None if captures_only => return,
+            None => MoveKind::Quiet,
+        };
+        moves.push(Move {
+            from,
+            to,
+            promotion: None,
+            kind,
+        });
+    }
+
+    fn castle_moves(&self, from: Square, moves: &mut Vec<Move>) {
+        let us = self.side_to_move();
+        let rights = match us {
+            Color::White => [WHITE_KINGSIDE, WHITE_QUEENSIDE],
+            Color::Black => [BLACK_KINGSIDE, BLACK_QUEENSIDE],
+        };
+        if rank_of(from) != back_rank(us) || self.in_check(us) {
+            return;
+        }
+
+        for right in rights {
+            let Some(rook_from) = self.castling_rook(right) else {
+                continue;
+            };
+            if self.piece_at(rook_from) != Some(Piece::new(us, PieceKind::Rook)) {
+                continue;
+            }
+            let (king_to, rook_to) = castle_squares(from, rook_from);
+            // Every square either piece crosses or lands on must be free of anything but
+            // the king and rook themselves
+            let path_clear = [(from, king_to), (rook_from, rook_to)]
+                .iter()
+                .flat_map(|&(a, b)| squares_between(a, b))
+                .all(|sq| sq == from || sq == rook_from || self.piece_at(sq).is_none());
+            // The king may not pass through check; its landing square is left to the
+            // legality check, which sees the rook already moved
+            let path_safe = squares_between(from, king_to)
+                .all(|sq| sq == from || !self.is_attacked(sq, us.opposite()));
+            if path_clear && path_safe {
+                moves.push(Move {
+                    from,
+                    to: rook_from,
+                    promotion: None,
+                    kind: MoveKind::Castle,
+                });
+            }
+        }
+    }
+}
+
+/// Squares on one rank from `a` to `b`, both included
+fn squares_between(a: Square, b: Square) -> impl Iterator<Item = Square> {
+    let rank = rank_of(a);
+    let (low, high) = (file_of(a).min(file_of(b)), file_of(a).max(file_of(b)));
+    (low..=high).map(move |file| square(file, rank))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE_CASTLING: &str = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+
+    fn perft(fen: &str, depth: u32) -> u64 {
+        Position::from_fen(fen).unwrap().perft(depth)
+    }
+
+    #[test]
+    fn test_perft_starting_position() {
+        let fen = shared::fen::STARTING_FEN;
+        assert_eq!(perft(fen, 1), 20);
+        assert_eq!(perft(fen, 2), 400);
+        assert_eq!(perft(fen, 3), 8_902);
+        assert_eq!(perft(fen, 4), 197_281);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        // Castling, en passant, promotions and pins all in one position
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(perft(fen, 1), 48);
+        assert_eq!(perft(fen, 2), 2_039);
+        assert_eq!(perft(fen, 3), 97_862);
+    }
+
+    #[test]
+    fn test_perft_endgame() {
+        let fen = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+        assert_eq!(perft(fen, 1), 14);
+        assert_eq!(perft(fen, 2), 191);
+        assert_eq!(perft(fen, 3), 2_812);
+        assert_eq!(perft(fen, 4), 43_238);
+    }
+
+    #[test]
+    fn test_perft_promotions() {
+        let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+        assert_eq!(perft(fen, 1), 6);
+        assert_eq!(perft(fen, 2), 264);
+        assert_eq!(perft(fen, 3), 9_467);
+    }
+
+    #[test]
+    fn test_perft_chess960() {
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        assert_eq!(perft(fen, 1), 21);
+        assert_eq!(perft(fen, 2), 528);
+        assert_eq!(perft(fen, 3), 12_189);
+
+        let fen = "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9";
+        assert_eq!(perft(fen, 1), 21);
+        assert_eq!(perft(fen, 2), 807);
+        assert_eq!(perft(fen, 3), 18_002);
+    }
+
+    #[test]
+    fn test_chess960_castling() {
+        // King on f1 next to its rook on g1, with the a-side rook on b1
+        let mut position = Position::from_fen("5k2/8/8/8/8/8/8/1R3KR1 w GB - 0 1").unwrap();
+        let kingside = position.parse_uci_move("f1g1").unwrap();
+        assert_eq!(kingside.kind, MoveKind::Castle);
+        assert_eq!(kingside.to_uci(), "f1g1");
+        let queenside = position.parse_uci_move("f1b1").unwrap();
+        assert_eq!(queenside.kind, MoveKind::Castle);
+
+        position.make_move(kingside);
+        assert_eq!(position.to_fen(), "5k2/8/8/8/8/8/8/1R3RK1 b - - 1 1");
+
+        // Standard castling keeps its usual notation, and the king-takes-rook form works too
+        let mut position = Position::from_fen(KIWIPETE_CASTLING).unwrap();
+        assert_eq!(position.parse_uci_move("e1g1").unwrap().to_uci(), "e1g1");
+        assert_eq!(position.parse_uci_move("e1a1").unwrap().to_uci(), "e1c1");
+    }
+
+    #[test]
+    fn test_parse_uci_move() {
+        let mut position = Position::default();
+        let mv = position.parse_uci_move("e2e4").unwrap();
+        assert_eq!(mv.kind, MoveKind::DoublePush);
+        assert_eq!(mv.to_uci(), "e2e4");
+        assert!(position.parse_uci_move("e2e5").is_none());
+        assert!(position.parse_uci_move("e2").is_none());
+
+        let mut position = Position::from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(
+            position.parse_uci_move("a7a8n").unwrap().promotion,
+            Some(PieceKind::Knight)
+        );
+        assert!(position.parse_uci_move("a7a8").is_none());
+    }
+
+    #[test]
+    fn test_captures_only_includes_promotions() {
+        let mut position = Position::from_fen("8/P6k/8/8/8/8/1p6/1K6 w - - 0 1").unwrap();
+        let captures = position.legal_captures();
+        assert_eq!(captures.len(), 5);
+        assert!(captures.iter().any(|mv| mv.is_capture()));
+    }
+}
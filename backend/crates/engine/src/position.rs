@@ -0,0 +1,886 @@
+use shared::Color;
+use std::sync::OnceLock;
+
+use crate::movegen::{Move, MoveKind};
+
+/// Index into a 0x88 board: `rank * 16 + file`
+///
+/// A square is on the board iff `square & 0x88 == 0`, which makes off-board detection
+/// for move offsets a single mask.
+pub type Square = u8;
+
+pub const WHITE_KINGSIDE: u8 = 1;
+pub const WHITE_QUEENSIDE: u8 = 2;
+pub const BLACK_KINGSIDE: u8 = 4;
+pub const BLACK_QUEENSIDE: u8 = 8;
+/// Every castling right, in the order `Position::castling_files` lists their rooks
+const CASTLING_RIGHTS: [u8; 4] = [
+    WHITE_KINGSIDE,
+    WHITE_QUEENSIDE,
+    BLACK_KINGSIDE,
+    BLACK_QUEENSIDE,
+];
+
+pub fn square(file: u8, rank: u8) -> Square {
+    rank * 16 + file
+}
+
+pub fn file_of(sq: Square) -> u8 {
+    sq & 7
+}
+
+pub fn rank_of(sq: Square) -> u8 {
+    sq >> 4
+}
+
+/// Applies a 0x88 offset, returning None if it leaves the board
+pub fn offset(sq: Square, delta: i16) -> Option<Square> {
+    let target = sq as i16 + delta;
+    if (0..128).contains(&target) && target & 0x88 == 0 {
+        Some(target as Square)
+    } else {
+        None
+    }
+}
+
+/// Index of `sq` in a 64-square array, a1 = 0 .. h8 = 63
+pub fn index64(sq: Square) -> usize {
+    (rank_of(sq) * 8 + file_of(sq)) as usize
+}
+
+pub fn square_name(sq: Square) -> String {
+    format!(
+        "{}{}",
+        (b'a' + file_of(sq)) as char,
+        (b'1' + rank_of(sq)) as char
+    )
+}
+
+pub fn parse_square(name: &str) -> Option<Square> {
+    match name.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(square(file - b'a', rank - b'1')),
+        _ => None,
+    }
+}
+
+pub fn color_index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceKind {
+    pub const ALL: [PieceKind; 6] = [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+        PieceKind::King,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Lowercase letter used in FEN and UCI promotions
+    pub fn to_char(self) -> char {
+        match self {
+            PieceKind::Pawn => 'p',
+            PieceKind::Knight => 'n',
+            PieceKind::Bishop => 'b',
+            PieceKind::Rook => 'r',
+            PieceKind::Queen => 'q',
+            PieceKind::King => 'k',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_lowercase() {
+            'p' => Some(PieceKind::Pawn),
+            'n' => Some(PieceKind::Knight),
+            'b' => Some(PieceKind::Bishop),
+            'r' => Some(PieceKind::Rook),
+            'q' => Some(PieceKind::Queen),
+            'k' => Some(PieceKind::King),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceKind,
+}
+
+impl Piece {
+    pub fn new(color: Color, kind: PieceKind) -> Self {
+        Self { color, kind }
+    }
+
+    /// Index 0..12, white pieces first
+    pub fn index(self) -> usize {
+        color_index(self.color) * 6 + self.kind.index()
+    }
+
+    pub fn to_fen_char(self) -> char {
+        let c = self.kind.to_char();
+        match self.color {
+            Color::White => c.to_ascii_uppercase(),
+            Color::Black => c,
+        }
+    }
+
+    pub fn from_fen_char(c: char) -> Option<Self> {
+        let kind = PieceKind::from_char(c)?;
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some(Self { color, kind })
+    }
+}
+
+/// State restored by `Position::unmake_move`
+#[derive(Debug, Clone, Copy)]
+pub struct Undo {
+    captured: Option<Piece>,
+    castling: u8,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    hash: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Position {
+    board: [Option<Piece>; 128],
+    side_to_move: Color,
+    /// WHITE_KINGSIDE | WHITE_QUEENSIDE | BLACK_KINGSIDE | BLACK_QUEENSIDE
+    castling: u8,
+    /// File of the rook each castling right castles with, in CASTLING_RIGHTS order
+    castling_files: [u8; 4],
+    /// Chess960 positions write castling rights in Shredder-FEN
+    chess960: bool,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    kings: [Square; 2],
+    hash: u64,
+    /// Hashes of the positions before each move made, for repetition detection
+    history: Vec<u64>,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::from_fen(shared::fen::STARTING_FEN).expect("starting position is valid")
+    }
+}
+
+impl Position {
+    /// Parses a FEN with standard, X-FEN or Shredder-FEN castling rights
+    ///
+    /// In X-FEN, K and Q castle with the outermost rook on that side of the king; rights
+    /// without such a rook are dropped. Shredder-FEN names the rook's file instead, and
+    /// a position castling with any rook other than from the standard squares is treated
+    /// as Chess960.
+    pub fn from_fen(fen: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!(
+                "FEN needs at least 4 fields, found {}",
+                fields.len()
+            ));
+        }
+
+        let mut board = [None; 128];
+        let mut kings = [None, None];
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != 8 {
+            return Err(format!("Board must have 8 ranks, found {}", ranks.len()));
+        }
+        for (i, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - i as u8;
+            let mut file = 0u8;
+            for c in rank_text.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    file += skip as u8;
+                    continue;
+                }
+                let piece = Piece::from_fen_char(c).ok_or(format!("Invalid piece: {}", c))?;
+                if file >= 8 {
+                    return Err(format!("Rank {} has more than 8 squares", rank + 1));
+                }
+                let sq = square(file, rank);
+                if piece.kind == PieceKind::King {
+                    let slot = &mut kings[color_index(piece.color)];
+                    if slot.is_some() {
+                        return Err("Each side must have exactly one king".to_string());
+                    }
+                    *slot = Some(sq);
+                }
+                board[sq as usize] = Some(piece);
+                file += 1;
+            }
+            if file != 8 {
+                return Err(format!("Rank {} does not have 8 squares", rank + 1));
+            }
+        }
+        let (Some(white_king), Some(black_king)) = (kings[0], kings[1]) else {
+            return Err("Each side must have exactly one king".to_string());
+        };
+
+        let side_to_move = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            other => return Err(format!("Invalid side to move: {}", other)),
+        };
+
+        let mut castling = 0;
+        let mut castling_files = [7, 0, 7, 0];
+        let mut chess960 = false;
+        if fields[2] != "-" {
+            let unsupported = || format!("Unsupported castling rights: {}", fields[2]);
+            for c in fields[2].chars() {
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                let rank = back_rank(color);
+                let king = [white_king, black_king][color_index(color)];
+                let rook = Some(Piece::new(color, PieceKind::Rook));
+                let is_rook = |file: u8| board[square(file, rank) as usize] == rook;
+                let king_file = file_of(king);
+                let rook_file = match c.to_ascii_lowercase() {
+                    _ if rank_of(king) != rank => None,
+                    'k' => (king_file + 1..8).rev().find(|&file| is_rook(file)),
+                    'q' => (0..king_file).find(|&file| is_rook(file)),
+                    file @ 'a'..='h' => {
+                        let file = file as u8 - b'a';
+                        chess960 = true;
+                        if !is_rook(file) || file == king_file {
+                            return Err(unsupported());
+                        }
+                        Some(file)
+                    }
+                    _ => return Err(unsupported()),
+                };
+                let Some(rook_file) = rook_file else {
+                    continue;
+                };
+
+                let right = match (color, rook_file > king_file) {
+                    (Color::White, true) => WHITE_KINGSIDE,
+                    (Color::White, false) => WHITE_QUEENSIDE,
+                    (Color::Black, true) => BLACK_KINGSIDE,
+                    (Color::Black, false) => BLACK_QUEENSIDE,
+                };
+                castling |= right;
+                castling_files[right_index(right)] = rook_file;
+                if king_file != 4 || !matches!(rook_file, 0 | 7) {
+                    chess960 = true;
+                }
+            }
+        }
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            name => Some(parse_square(name).ok_or(format!("Invalid en passant square: {}", name))?),
+        };
+
+        let halfmove_clock = match fields.get(4) {
+            Some(n) => n
+                .parse()
+                .map_err(|_| format!("Invalid halfmove clock: {}", n))?,
+            None => 0,
+        };
+        let fullmove_number = match fields.get(5) {
+            Some(n) => n
+                .parse()
+                .map_err(|_| format!("Invalid fullmove number: {}", n))?,
+            None => 1,
+        };
+
+        let mut position = Self {
+            board,
+            side_to_move,
+            castling,
+            castling_files,
+            chess960,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+            kings: [white_king, black_king],
+            hash: 0,
+            history: Vec::new(),
+        };
+        position.hash = position.compute_hash();
+        Ok(position)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at(square(file, rank)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.to_fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        let side = match self.side_to_move {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+        let mut castling: String = CASTLING_RIGHTS
+            .iter()
+            .zip(['k', 'q', 'k', 'q'])
+            .enumerate()
+            .filter(|(_, (&right, _))| self.castling & right != 0)
+            .map(|(i, (&right, side))| {
+                let c = if self.chess960 {
+                    (b'a' + self.castling_files[i]) as char
+                } else {
+                    side
+                };
+                if right & (WHITE_KINGSIDE | WHITE_QUEENSIDE) != 0 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = self.en_passant.map_or("-".to_string(), square_name);
+
+        format!(
+            "{} {} {} {} {} {}",
+            fen, side, castling, en_passant, self.halfmove_clock, self.fullmove_number
+        )
+    }
+
+    pub fn piece_at(&self, sq: Square) -> Option<Piece> {
+        self.board[sq as usize]
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    pub fn castling_rights(&self) -> u8 {
+        self.castling
+    }
+
+    /// Square of the rook that castles with `right`, if the right is still held
+    pub fn castling_rook(&self, right: u8) -> Option<Square> {
+        (self.castling & right != 0).then(|| self.castling_rook_square(right_index(right)))
+    }
+
+    /// Whether the position is from a Chess960 game, which changes how castling is written
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    pub fn en_passant(&self) -> Option<Square> {
+        self.en_passant
+    }
+
+    pub fn halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn king_square(&self, color: Color) -> Square {
+        self.kings[color_index(color)]
+    }
+
+    /// Zobrist hash of the position
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Iterates over every occupied square
+    pub fn pieces(&self) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        (0..128u8)
+            .filter(|sq| sq & 0x88 == 0)
+            .filter_map(|sq| self.piece_at(sq).map(|piece| (sq, piece)))
+    }
+
+    pub fn in_check(&self, color: Color) -> bool {
+        self.is_attacked(self.king_square(color), color.opposite())
+    }
+
+    /// Returns true if the position occurred before since the last irreversible move
+    pub fn is_repetition(&self) -> bool {
+        self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&hash| hash == self.hash)
+    }
+
+    /// Returns true if any piece of colour `by` attacks `sq`
+    pub fn is_attacked(&self, sq: Square, by: Color) -> bool {
+        let is = |target: Option<Square>, kinds: &[PieceKind]| {
+            target
+                .and_then(|t| self.piece_at(t))
+                .is_some_and(|p| p.color == by && kinds.contains(&p.kind))
+        };
+
+        // Pawns attack diagonally forwards, so look backwards from the target
+        let pawn_sources: [i16; 2] = match by {
+            Color::White => [-15, -17],
+            Color::Black => [15, 17],
+        };
+        if pawn_sources
+            .iter()
+            .any(|&d| is(offset(sq, d), &[PieceKind::Pawn]))
+        {
+            return true;
+        }
+        if KNIGHT_OFFSETS
+            .iter()
+            .any(|&d| is(offset(sq, d), &[PieceKind::Knight]))
+        {
+            return true;
+        }
+        if KING_OFFSETS
+            .iter()
+            .any(|&d| is(offset(sq, d), &[PieceKind::King]))
+        {
+            return true;
+        }
+
+        let slides = |directions: &[i16], kinds: &[PieceKind]| {
+            directions.iter().any(|&d| {
+                let mut current = offset(sq, d);
+                while let Some(t) = current {
+                    match self.piece_at(t) {
+                        Some(p) => return p.color == by && kinds.contains(&p.kind),
+                        None => current = offset(t, d),
+                    }
+                }
+                false
+            })
+        };
+        slides(&BISHOP_DIRECTIONS, &[PieceKind::Bishop, PieceKind::Queen])
+            || slides(&ROOK_DIRECTIONS, &[PieceKind::Rook, PieceKind::Queen])
+    }
+
+    /// Plays `mv`, which must be pseudo-legal in this position
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        let keys = zobrist();
+        let us = self.side_to_move;
+        let piece = self.board[mv.from as usize].expect("no piece on the from square");
+        let undo = Undo {
+            captured: None,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        self.history.push(self.hash);
+
+        if let Some(ep) = self.en_passant {
+            self.hash ^= keys.en_passant[file_of(ep) as usize];
+        }
+        self.hash ^= keys.castling[self.castling as usize];
+
+        // Worked out before the king moves, as a king move loses both of its rights
+        let castling_mask = self.castle_mask(mv.from) & self.castle_mask(mv.to);
+
+        let captured = if mv.kind == MoveKind::Castle {
+            // The king and rook may land on each other's squares, so lift both first
+            let (king_to, rook_to) = castle_squares(mv.from, mv.to);
+            let rook = self.board[mv.to as usize]
+                .take()
+                .expect("no rook to castle with");
+            self.board[mv.from as usize] = None;
+            self.board[king_to as usize] = Some(piece);
+            self.board[rook_to as usize] = Some(rook);
+            self.hash ^= keys.piece(piece, mv.from) ^ keys.piece(piece, king_to);
+            self.hash ^= keys.piece(rook, mv.to) ^ keys.piece(rook, rook_to);
+            self.kings[color_index(us)] = king_to;
+            None
+        } else {
+            let capture_square = if mv.kind == MoveKind::EnPassant {
+                match us {
+                    Color::White => mv.to - 16,
+                    Color::Black => mv.to + 16,
+                }
+            } else {
+                mv.to
+            };
+            let captured = self.board[capture_square as usize].take();
+            if let Some(captured) = captured {
+                self.hash ^= keys.piece(captured, capture_square);
+            }
+
+            self.board[mv.from as usize] = None;
+            self.hash ^= keys.piece(piece, mv.from);
+            let placed = match mv.promotion {
+                Some(kind) => Piece::new(us, kind),
+                None => piece,
+            };
+            self.board[mv.to as usize] = Some(placed);
+            self.hash ^= keys.piece(placed, mv.to);
+            if piece.kind == PieceKind::King {
+                self.kings[color_index(us)] = mv.to;
+            }
+            captured
+        };
+
+        self.castling &= castling_mask;
+        self.hash ^= keys.castling[self.castling as usize];
+
+        self.en_passant = if mv.kind == MoveKind::DoublePush {
+            let ep = (mv.from + mv.to) / 2;
+            self.hash ^= keys.en_passant[file_of(ep) as usize];
+            Some(ep)
+        } else {
+            None
+        };
+
+        if piece.kind == PieceKind::Pawn || captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.side_to_move = us.opposite();
+        self.hash ^= keys.black_to_move;
+
+        Undo { captured, ..undo }
+    }
+
+    /// Takes back `mv`, which must be the last move made, using the `Undo` it returned
+    pub fn unmake_move(&mut self, mv: Move, undo: Undo) {
+        self.side_to_move = self.side_to_move.opposite();
+        let us = self.side_to_move;
+        if us == Color::Black {
+            self.fullmove_number -= 1;
+        }
+
+        if mv.kind == MoveKind::Castle {
+            let (king_to, rook_to) = castle_squares(mv.from, mv.to);
+            let king = self.board[king_to as usize].take();
+            let rook = self.board[rook_to as usize].take();
+            self.board[mv.from as usize] = king;
+            self.board[mv.to as usize] = rook;
+            self.kings[color_index(us)] = mv.from;
+        } else {
+            let placed = self.board[mv.to as usize]
+                .take()
+                .expect("no piece on the to square");
+            let original = if mv.promotion.is_some() {
+                Piece::new(us, PieceKind::Pawn)
+            } else {
+                placed
+            };
+            self.board[mv.from as usize] = Some(original);
+
+            if mv.kind == MoveKind::EnPassant {
+                let capture_square = match us {
+                    Color::White => mv.to - 16,
+                    Color::Black => mv.to + 16,
+                };
+                self.board[capture_square as usize] = undo.captured;
+            } else {
+                self.board[mv.to as usize] = undo.captured;
+            }
+            if original.kind == PieceKind::King {
+                self.kings[color_index(us)] = mv.from;
+            }
+        }
+
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.history.pop();
+    }
+
+    /// Passes the turn without moving, for null-move style probes
+    pub fn make_null_move(&mut self) -> Undo {
+        let keys = zobrist();
+        let undo = Undo {
+            captured: None,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        self.history.push(self.hash);
+        if let Some(ep) = self.en_passant.take() {
+            self.hash ^= keys.en_passant[file_of(ep) as usize];
+        }
+        self.halfmove_clock += 1;
+        self.side_to_move = self.side_to_move.opposite();
+        self.hash ^= keys.black_to_move;
+        undo
+    }
+
+    pub fn unmake_null_move(&mut self, undo: Undo) {
+        self.side_to_move = self.side_to_move.opposite();
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.history.pop();
+    }
+
+    /// Square the rook for the `index`-th right in CASTLING_RIGHTS starts on
+    fn castling_rook_square(&self, index: usize) -> Square {
+        let rank = if index < 2 { 0 } else { 7 };
+        square(self.castling_files[index], rank)
+    }
+
+    /// Castling rights that survive a move touching `sq`
+    fn castle_mask(&self, sq: Square) -> u8 {
+        let mut lost = 0;
+        for (i, right) in CASTLING_RIGHTS.iter().enumerate() {
+            if self.castling_rook_square(i) == sq {
+                lost |= right;
+            }
+        }
+        if sq == self.kings[0] {
+            lost |= WHITE_KINGSIDE | WHITE_QUEENSIDE;
+        }
+        if sq == self.kings[1] {
+            lost |= BLACK_KINGSIDE | BLACK_QUEENSIDE;
+        }
+        !lost & 0x0f
+    }
+
+    fn compute_hash(&self) -> u64 {
+        let keys = zobrist();
+        let mut hash = self
+            .pieces()
+            .fold(0, |hash, (sq, piece)| hash ^ keys.piece(piece, sq));
+        hash ^= keys.castling[self.castling as usize];
+        if let Some(ep) = self.en_passant {
+            hash ^= keys.en_passant[file_of(ep) as usize];
+        }
+        if self.side_to_move == Color::Black {
+            hash ^= keys.black_to_move;
+        }
+        hash
+    }
+}
+
+pub const KNIGHT_OFFSETS: [i16; 8] = [-33, -31, -18, -14, 14, 18, 31, 33];
+pub const KING_OFFSETS: [i16; 8] = [-17, -16, -15, -1, 1, 15, 16, 17];
+pub const BISHOP_DIRECTIONS: [i16; 4] = [-17, -15, 15, 17];
+pub const ROOK_DIRECTIONS: [i16; 4] = [-16, -1, 1, 16];
+
+/// King and rook destinations when the king on `king_from` castles with the rook on
+/// `rook_from`
+///
+/// As in Chess960, the king always lands on the g- or c-file and the rook next to it,
+/// wherever they started.
+pub fn castle_squares(king_from: Square, rook_from: Square) -> (Square, Square) {
+    let rank = rank_of(king_from);
+    if file_of(rook_from) > file_of(king_from) {
+        (square(6, rank), square(5, rank))
+    } else {
+        (square(2, rank), square(3, rank))
+    }
+}
+
+pub fn back_rank(color: Color) -> u8 {
+    match color {
+        Color::White => 0,
+        Color::Black => 7,
+    }
+}
+
+fn right_index(right: u8) -> usize {
+    right.trailing_zeros() as usize
+}
+
+struct Zobrist {
+    pieces: [[u64; 64]; 12],
+    castling: [u64; 16],
+    en_passant: [u64; 8],
+    black_to_move: u64,
+}
+
+impl Zobrist {
+    fn piece(&self, piece: Piece, sq: Square) -> u64 {
+        self.pieces[piece.index()][index64(sq)]
+    }
+}
+
+/// Fixed pseudo-random keys, so hashes are stable across runs
+fn zobrist() -> &'static Zobrist {
+    static KEYS: OnceLock<Zobrist> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        // SplitMix64
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        let mut keys = Zobrist {
+            pieces: [[0; 64]; 12],
+            castling: [0; 16],
+            en_passant: [0; 8],
+            black_to_move: 0,
+        };
+        for piece in keys.pieces.iter_mut() {
+            for key in piece.iter_mut() {
+                *key = next();
+            }
+        }
+        for key in keys.castling.iter_mut().skip(1) {
+            *key = next();
+        }
+        for key in keys.en_passant.iter_mut() {
+            *key = next();
+        }
+        keys.black_to_move = next();
+        keys
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn test_fen_round_trip() {
+        for fen in [
+            shared::fen::STARTING_FEN,
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+        ] {
+            assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn test_invalid_fens_are_rejected() {
+        assert!(Position::from_fen("").is_err());
+        assert!(Position::from_fen("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(Position::from_fen("8/8/8/8/8/8/8/K6k x - - 0 1").is_err());
+        assert!(Position::from_fen("8/8/8/8/8/8/8/K6k w FA - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_chess960_castling_fens() {
+        for number in 0..shared::variant::CHESS960_POSITIONS {
+            let fen = shared::variant::chess960_fen(number);
+            let position = Position::from_fen(&fen).unwrap();
+            assert!(position.is_chess960());
+            assert_eq!(position.to_fen(), fen);
+        }
+
+        // X-FEN letters castle with the outermost rook and are written back by file
+        let position = Position::from_fen("rkr5/8/8/8/8/8/8/1R2K2R w KQkq - 0 1").unwrap();
+        assert_eq!(position.castling_rook(WHITE_QUEENSIDE), Some(square(1, 0)));
+        assert_eq!(position.castling_rook(BLACK_KINGSIDE), Some(square(2, 7)));
+        assert_eq!(position.to_fen(), "rkr5/8/8/8/8/8/8/1R2K2R w HBca - 0 1");
+        assert!(Position::from_fen("4k3/8/8/8/8/8/8/4K2R w G - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_squares() {
+        assert_eq!(parse_square("e4"), Some(square(4, 3)));
+        assert_eq!(square_name(square(7, 7)), "h8");
+        assert_eq!(parse_square("i1"), None);
+        assert_eq!(offset(square(7, 0), 1), None);
+        assert_eq!(offset(square(0, 0), 17), Some(square(1, 1)));
+    }
+
+    #[test]
+    fn test_make_unmake_restores_everything() {
+        let mut position = Position::from_fen(KIWIPETE).unwrap();
+        let fen = position.to_fen();
+        let hash = position.hash();
+        for mv in position.legal_moves() {
+            let undo = position.make_move(mv);
+            assert_eq!(position.hash(), position.compute_hash(), "{}", mv);
+            position.unmake_move(mv, undo);
+            assert_eq!(position.to_fen(), fen);
+            assert_eq!(position.hash(), hash);
+        }
+    }
+
+    #[test]
+    fn test_hash_is_independent_of_move_order() {
+        let play = |moves: &[&str]| {
+            let mut position = Position::default();
+            for uci in moves {
+                let mv = position.parse_uci_move(uci).unwrap();
+                position.make_move(mv);
+            }
+            position.hash()
+        };
+        assert_eq!(
+            play(&["g1f3", "g8f6", "b1c3"]),
+            play(&["b1c3", "g8f6", "g1f3"])
+        );
+    }
+
+    #[test]
+    fn test_repetition_is_detected() {
+        let mut position = Position::default();
+        for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            assert!(!position.is_repetition());
+            let mv = position.parse_uci_move(uci).unwrap();
+            position.make_move(mv);
+        }
+        assert!(position.is_repetition());
+    }
+
+    #[test]
+    fn test_attacks() {
+        let position = Position::from_fen("4k3/8/8/3q4/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(position.is_attacked(square(0, 1), Color::Black));
+        assert!(position.is_attacked(square(3, 0), Color::Black));
+        assert!(!position.is_attacked(square(4, 0), Color::Black));
+        assert!(!position.in_check(Color::White));
+    }
+}
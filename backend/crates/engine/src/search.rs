@@ -0,0 +1,506 @@
+use std::time::{Duration, Instant};
+
+use crate::eval::EvalParams;
+use crate::movegen::{Move, MoveKind};
+use crate::position::{PieceKind, Position};
+use crate::tt::{Bound, TranspositionTable};
+
+/// Score of delivering mate now; mate in n plies scores MATE_SCORE - n
+pub const MATE_SCORE: i32 = 30_000;
+/// Deepest ply the search reaches, including extensions and quiescence
+pub const MAX_PLY: usize = 64;
+/// Transposition table size used by `Engine::default`, well inside a 128MB Lambda
+pub const DEFAULT_HASH_MB: usize = 8;
+
+const INFINITY: i32 = 32_000;
+/// Nodes between checks of the node and time limits
+const LIMIT_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchLimits {
+    pub max_depth: u8,
+    pub max_nodes: Option<u64>,
+    pub time_budget: Option<Duration>,
+    /// Root moves scoring within this many centipawns of the best get exact scores in
+    /// `SearchResult::root_moves`, so a weaker player can pick among them
+    pub candidate_margin_cp: i32,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: (MAX_PLY / 2) as u8,
+            max_nodes: None,
+            time_budget: None,
+            candidate_margin_cp: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// None only when the side to move has no legal moves
+    pub best_move: Option<Move>,
+    /// Centipawns from the side to move's point of view
+    pub score: i32,
+    /// Deepest fully completed iteration
+    pub depth: u8,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    /// Root moves with their scores, best first. Moves more than the candidate margin
+    /// below the best only have an upper bound.
+    pub root_moves: Vec<(Move, i32)>,
+}
+
+impl SearchResult {
+    /// Returns the number of moves to mate if the score is a forced mate, negative when
+    /// the side to move is being mated
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_SCORE - MAX_PLY as i32 {
+            return None;
+        }
+        let plies = MATE_SCORE - self.score.abs();
+        let moves = (plies + 1) / 2;
+        Some(if self.score > 0 { moves } else { -moves })
+    }
+}
+
+/// Alpha-beta searcher; keeps its transposition table between searches
+pub struct Engine {
+    tt: TranspositionTable,
+    eval: EvalParams,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    nodes: u64,
+    max_nodes: Option<u64>,
+    deadline: Option<Instant>,
+    /// Limits are only enforced once an iteration has completed, so there is always a move
+    can_stop: bool,
+    stopped: bool,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB, EvalParams::default())
+    }
+}
+
+impl Engine {
+    pub fn new(hash_mb: usize, eval: EvalParams) -> Self {
+        Self {
+            tt: TranspositionTable::new(hash_mb),
+            eval,
+            killers: [[None; 2]; MAX_PLY],
+            nodes: 0,
+            max_nodes: None,
+            deadline: None,
+            can_stop: false,
+            stopped: false,
+        }
+    }
+
+    pub fn eval_params(&self) -> &EvalParams {
+        &self.eval
+    }
+
+    /// Forgets everything learned in earlier searches
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+    }
+
+    /// Searches `position` by iterative deepening until a limit in `limits` is reached
+    pub fn search(&mut self, position: &Position, limits: &SearchLimits) -> SearchResult {
+        let mut position = position.clone();
+        self.killers = [[None; 2]; MAX_PLY];
+        self.nodes = 0;
+        self.max_nodes = limits.max_nodes;
+        self.deadline = limits.time_budget.map(|budget| Instant::now() + budget);
+        self.can_stop = false;
+        self.stopped = false;
+
+        let mut root_moves: Vec<(Move, i32)> = position
+            .legal_moves()
+            .into_iter()
+            .map(|mv| (mv, -INFINITY))
+            .collect();
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+            root_moves: Vec::new(),
+        };
+        if root_moves.is_empty() {
+            let side = position.side_to_move();
+            result.score = if position.in_check(side) {
+                -MATE_SCORE
+            } else {
+                0
+            };
+            return result;
+        }
+
+        let margin = limits.candidate_margin_cp.max(0);
+        for depth in 1..=limits.max_depth.max(1) {
+            let Some(scored) = self.search_root(&mut position, &root_moves, depth, margin) else {
+                break;
+            };
+            root_moves = scored;
+            let (best_move, score) = root_moves[0];
+            self.tt.store(
+                position.hash(),
+                Some(best_move),
+                score,
+                depth,
+                Bound::Exact,
+                0,
+            );
+            result.best_move = Some(best_move);
+            result.score = score;
+            result.depth = depth;
+            result.root_moves = root_moves.clone();
+            self.can_stop = true;
+
+            // No point searching deeper once a forced mate is found
+            if score.abs() >= MATE_SCORE - MAX_PLY as i32 || self.limit_reached() {
+                break;
+            }
+        }
+
+        result.nodes = self.nodes;
+        result.pv = self.principal_variation(&mut position, result.depth);
+        result
+    }
+
+    /// Scores every root move at `depth`, best first, or None if stopped part way
+    fn search_root(
+        &mut self,
+        position: &mut Position,
+        root_moves: &[(Move, i32)],
+        depth: u8,
+        margin: i32,
+    ) -> Option<Vec<(Move, i32)>> {
+        let mut best = -INFINITY;
+        let mut scored = Vec::with_capacity(root_moves.len());
+        for &(mv, _) in root_moves {
+            let alpha = best.saturating_sub(margin).max(-INFINITY);
+            let undo = position.make_move(mv);
+            let score = -self.negamax(position, depth as i32 - 1, 1, -INFINITY, -alpha);
+            position.unmake_move(mv, undo);
+            if self.stopped {
+                return None;
+            }
+            // A fail-low only bounds the score, so keep it out of the candidate margin
+            let score = if score <= alpha && alpha > -INFINITY {
+                alpha - 1
+            } else {
+                score
+            };
+            best = best.max(score);
+            scored.push((mv, score));
+        }
+        scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+        Some(scored)
+    }
+
+    fn negamax(
+        &mut self,
+        position: &mut Position,
+        depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.check_limits() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        if position.halfmove_clock() >= 100 || position.is_repetition() {
+            return 0;
+        }
+        let side = position.side_to_move();
+        let in_check = position.in_check(side);
+        if ply >= MAX_PLY - 1 {
+            return self.eval.evaluate(position);
+        }
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth <= 0 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+
+        let hash = position.hash();
+        let tt_entry = self.tt.probe(hash, ply);
+        if let Some(entry) = tt_entry.filter(|e| e.depth as i32 >= depth) {
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+            };
+            if cutoff {
+                return entry.score;
+            }
+        }
+
+        let mut moves = position.legal_moves();
+        if moves.is_empty() {
+            return if in_check {
+                -MATE_SCORE + ply as i32
+            } else {
+                0
+            };
+        }
+        let tt_move = tt_entry.and_then(|e| e.best_move);
+        self.order_moves(position, &mut moves, tt_move, ply);
+
+        let original_alpha = alpha;
+        let mut best_move = None;
+        for mv in moves {
+            let undo = position.make_move(mv);
+            let score = -self.negamax(position, depth - 1, ply + 1, -beta, -alpha);
+            position.unmake_move(mv, undo);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                if !mv.is_capture() && mv.promotion.is_none() {
+                    let killers = &mut self.killers[ply];
+                    if killers[0] != Some(mv) {
+                        killers[1] = killers[0];
+                        killers[0] = Some(mv);
+                    }
+                }
+                self.tt
+                    .store(hash, Some(mv), beta, depth as u8, Bound::Lower, ply);
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+            }
+        }
+
+        let bound = if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(hash, best_move, alpha, depth as u8, bound, ply);
+        alpha
+    }
+
+    /// Searches captures and promotions only, so the static evaluation is never taken in
+    /// the middle of an exchange
+    fn quiescence(
+        &mut self,
+        position: &mut Position,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.check_limits() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        let stand_pat = self.eval.evaluate(position);
+        if stand_pat >= beta {
+            return beta;
+        }
+        if ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = position.legal_captures();
+        self.order_moves(position, &mut moves, None, ply);
+        for mv in moves {
+            let undo = position.make_move(mv);
+            let score = -self.quiescence(position, ply + 1, -beta, -alpha);
+            position.unmake_move(mv, undo);
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    /// Hash move first, then captures by most valuable victim and least valuable
+    /// attacker, then promotions, then killer moves
+    fn order_moves(
+        &self,
+        position: &Position,
+        moves: &mut [Move],
+        tt_move: Option<Move>,
+        ply: usize,
+    ) {
+        let killers = self.killers[ply];
+        moves.sort_by_cached_key(|mv| {
+            let score = if Some(*mv) == tt_move {
+                1_000_000
+            } else if mv.is_capture() {
+                let victim = match mv.kind {
+                    MoveKind::EnPassant => PieceKind::Pawn,
+                    _ => position.piece_at(mv.to).map_or(PieceKind::Pawn, |p| p.kind),
+                };
+                let attacker = position
+                    .piece_at(mv.from)
+                    .map_or(PieceKind::Pawn, |p| p.kind);
+                100_000 + 10 * self.eval.piece_values[victim.index()] - attacker.index() as i32
+            } else if mv.promotion == Some(PieceKind::Queen) {
+                90_000
+            } else if killers[0] == Some(*mv) {
+                80_000
+            } else if killers[1] == Some(*mv) {
+                70_000
+            } else {
+                0
+            };
+            std::cmp::Reverse(score)
+        });
+    }
+
+    /// Sets `stopped` once a limit is exceeded, checking every LIMIT_CHECK_INTERVAL nodes
+    fn check_limits(&mut self) -> bool {
+        if !self.stopped
+            && self.can_stop
+            && self.nodes.is_multiple_of(LIMIT_CHECK_INTERVAL)
+            && self.limit_reached()
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    fn limit_reached(&self) -> bool {
+        self.max_nodes.is_some_and(|max| self.nodes >= max)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Follows hash moves from the root to recover the expected line of play
+    fn principal_variation(&self, position: &mut Position, depth: u8) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut undos = Vec::new();
+        while pv.len() < depth as usize {
+            let Some(mv) = self.tt.probe(position.hash(), 0).and_then(|e| e.best_move) else {
+                break;
+            };
+            if !position.legal_moves().contains(&mv) || position.is_repetition() {
+                break;
+            }
+            undos.push(position.make_move(mv));
+            pv.push(mv);
+        }
+        for (&mv, undo) in pv.iter().zip(undos).rev() {
+            position.unmake_move(mv, undo);
+        }
+        pv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(fen: &str, max_depth: u8) -> SearchResult {
+        let position = Position::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            max_depth,
+            ..SearchLimits::default()
+        };
+        Engine::new(1, EvalParams::default()).search(&position, &limits)
+    }
+
+    #[test]
+    fn test_finds_mate_in_one() {
+        let result = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", 4);
+        assert_eq!(result.best_move.unwrap().to_uci(), "a1a8");
+        assert_eq!(result.score, MATE_SCORE - 1);
+        assert_eq!(result.mate_in(), Some(1));
+    }
+
+    #[test]
+    fn test_wins_hanging_queen() {
+        let result = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_uci(), "d2d5");
+        assert!(result.score > 300);
+    }
+
+    #[test]
+    fn test_no_moves() {
+        let stalemate = search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(stalemate.best_move, None);
+        assert_eq!(stalemate.score, 0);
+
+        let checkmate = search("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", 3);
+        assert_eq!(checkmate.best_move, None);
+        assert_eq!(checkmate.score, -MATE_SCORE);
+    }
+
+    #[test]
+    fn test_node_limit_stops_search() {
+        let position = Position::default();
+        let limits = SearchLimits {
+            max_depth: 30,
+            max_nodes: Some(5_000),
+            ..SearchLimits::default()
+        };
+        let result = Engine::default().search(&position, &limits);
+        assert!(result.best_move.is_some());
+        assert!(
+            result.nodes < 5_000 + LIMIT_CHECK_INTERVAL,
+            "{}",
+            result.nodes
+        );
+        assert!(result.depth < 30);
+    }
+
+    #[test]
+    fn test_time_budget_stops_search() {
+        let position = Position::default();
+        let limits = SearchLimits {
+            max_depth: 30,
+            time_budget: Some(Duration::from_millis(50)),
+            ..SearchLimits::default()
+        };
+        let started = Instant::now();
+        let result = Engine::default().search(&position, &limits);
+        assert!(result.best_move.is_some());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_candidate_margin_scores_close_moves() {
+        let position = Position::default();
+        let limits = SearchLimits {
+            max_depth: 2,
+            candidate_margin_cp: 50,
+            ..SearchLimits::default()
+        };
+        let result = Engine::default().search(&position, &limits);
+        let best = result.root_moves[0].1;
+        assert_eq!(best, result.score);
+        assert_eq!(result.root_moves.len(), 20);
+        assert!(
+            result
+                .root_moves
+                .iter()
+                .filter(|(_, score)| *score >= best - 50)
+                .count()
+                > 1
+        );
+    }
+
+    #[test]
+    fn test_pv_starts_with_best_move() {
+        let result = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 4);
+        assert_eq!(result.pv.first(), result.best_move.as_ref());
+    }
+}
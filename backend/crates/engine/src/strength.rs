@@ -0,0 +1,146 @@
+use rand::Rng;
+use std::time::Duration;
+
+use crate::movegen::Move;
+use crate::search::{SearchLimits, SearchResult, MAX_PLY};
+
+/// Lowest and highest ratings `Strength::for_rating` distinguishes
+const MIN_RATING: i32 = 800;
+const MAX_RATING: i32 = 2400;
+
+/// How hard the engine tries: how far it searches and how often it settles for a
+/// move that isn't its best
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strength {
+    pub max_depth: u8,
+    pub max_nodes: Option<u64>,
+    /// Moves scoring within this many centipawns of the best may be played instead of it
+    pub randomness_cp: i32,
+}
+
+impl Strength {
+    /// Full strength: no node limit and always the best move, so pass a time budget
+    pub fn full() -> Self {
+        Self {
+            max_depth: (MAX_PLY / 2) as u8,
+            max_nodes: None,
+            randomness_cp: 0,
+        }
+    }
+
+    /// Limits tuned so the engine plays at roughly `rating`
+    ///
+    /// Ratings are clamped to 800..=2400. At 800 the engine looks one move ahead and
+    /// happily drops a couple of pawns; at 2400 it searches 8 plies and plays its best move.
+    pub fn for_rating(rating: i32) -> Self {
+        let t = (rating.clamp(MIN_RATING, MAX_RATING) - MIN_RATING) as f64
+            / (MAX_RATING - MIN_RATING) as f64;
+        Self {
+            max_depth: 1 + (7.0 * t).round() as u8,
+            max_nodes: Some((500.0 * 2f64.powf(10.0 * t)) as u64),
+            randomness_cp: (250.0 * (1.0 - t)).round() as i32,
+        }
+    }
+
+    /// Search limits for one move, stopping early if `time_budget` runs out
+    pub fn limits(&self, time_budget: Option<Duration>) -> SearchLimits {
+        SearchLimits {
+            max_depth: self.max_depth,
+            max_nodes: self.max_nodes,
+            time_budget,
+            candidate_margin_cp: self.randomness_cp,
+        }
+    }
+
+    /// Picks the move to play from a search run with `self.limits`
+    ///
+    /// Candidates within `randomness_cp` of the best are weighted by how close they are, so
+    /// the engine's mistakes are plausible rather than random. Mates are never thrown away.
+    pub fn choose_move(&self, result: &SearchResult, rng: &mut impl Rng) -> Option<Move> {
+        let best = result.best_move?;
+        if self.randomness_cp <= 0 || result.mate_in().is_some_and(|n| n > 0) {
+            return Some(best);
+        }
+        let floor = result.score - self.randomness_cp;
+        let candidates: Vec<(Move, i32)> = result
+            .root_moves
+            .iter()
+            .filter(|(_, score)| *score >= floor)
+            .map(|&(mv, score)| (mv, score - floor + 1))
+            .collect();
+        let total: i32 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total <= 0 {
+            return Some(best);
+        }
+        let mut pick = rng.gen_range(0..total);
+        for (mv, weight) in candidates {
+            if pick < weight {
+                return Some(mv);
+            }
+            pick -= weight;
+        }
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Engine, Position};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_strength_increases_with_rating() {
+        let ratings = [600, 800, 1200, 1600, 2000, 2400, 3000];
+        for pair in ratings.windows(2) {
+            let weaker = Strength::for_rating(pair[0]);
+            let stronger = Strength::for_rating(pair[1]);
+            assert!(weaker.max_depth <= stronger.max_depth);
+            assert!(weaker.max_nodes <= stronger.max_nodes);
+            assert!(weaker.randomness_cp >= stronger.randomness_cp);
+        }
+        assert_eq!(Strength::for_rating(800).max_depth, 1);
+        assert_eq!(Strength::for_rating(2400).max_depth, 8);
+        assert_eq!(Strength::for_rating(2400).randomness_cp, 0);
+    }
+
+    #[test]
+    fn test_full_strength_plays_best_move() {
+        let position = Position::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap();
+        let strength = Strength {
+            max_depth: 4,
+            ..Strength::full()
+        };
+        let result = Engine::default().search(&position, &strength.limits(None));
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(strength.choose_move(&result, &mut rng), result.best_move);
+    }
+
+    #[test]
+    fn test_weak_strength_varies_its_moves() {
+        let position = Position::default();
+        let strength = Strength::for_rating(800);
+        let result = Engine::default().search(&position, &strength.limits(None));
+        let mut rng = StdRng::seed_from_u64(7);
+        let moves: std::collections::HashSet<String> = (0..50)
+            .filter_map(|_| strength.choose_move(&result, &mut rng))
+            .map(|mv| mv.to_uci())
+            .collect();
+        assert!(moves.len() > 1);
+    }
+
+    #[test]
+    fn test_weak_strength_still_takes_mate() {
+        let position = Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let strength = Strength::for_rating(800);
+        let result = Engine::default().search(&position, &strength.limits(None));
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            assert_eq!(
+                strength.choose_move(&result, &mut rng).unwrap().to_uci(),
+                "a1a8"
+            );
+        }
+    }
+}
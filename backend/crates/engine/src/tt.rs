@@ -0,0 +1,164 @@
+use crate::movegen::Move;
+use crate::search::{MATE_SCORE, MAX_PLY};
+
+/// How a stored score relates to the true value of the position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    /// The search failed high; the true score is at least this
+    Lower,
+    /// The search failed low; the true score is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TtEntry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+}
+
+/// Fixed-size hash table of search results, indexed by Zobrist hash
+///
+/// The table is allocated once up front, so its size bounds the engine's memory use.
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    /// Allocates the largest power-of-two number of entries that fits in `size_mb`
+    pub fn new(size_mb: usize) -> Self {
+        let entry_size = std::mem::size_of::<Option<TtEntry>>();
+        let budget = (size_mb.max(1) * 1024 * 1024 / entry_size).max(1);
+        let len = if budget.is_power_of_two() {
+            budget
+        } else {
+            budget.next_power_of_two() / 2
+        };
+        Self {
+            entries: vec![None; len],
+            mask: len - 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    /// Looks up `key`, converting mate scores to be relative to `ply`
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        self.entries[key as usize & self.mask]
+            .filter(|entry| entry.key == key)
+            .map(|entry| TtEntry {
+                score: score_from_tt(entry.score, ply),
+                ..entry
+            })
+    }
+
+    /// Stores a result, keeping a deeper entry for the same position
+    pub fn store(
+        &mut self,
+        key: u64,
+        best_move: Option<Move>,
+        score: i32,
+        depth: u8,
+        bound: Bound,
+        ply: usize,
+    ) {
+        let slot = &mut self.entries[key as usize & self.mask];
+        if let Some(existing) = slot {
+            if existing.key == key && existing.depth > depth && bound != Bound::Exact {
+                return;
+            }
+        }
+        // Keep the old move if this search didn't find one
+        let best_move = best_move.or(slot.filter(|e| e.key == key).and_then(|e| e.best_move));
+        *slot = Some(TtEntry {
+            key,
+            best_move,
+            score: score_to_tt(score, ply),
+            depth,
+            bound,
+        });
+    }
+}
+
+/// Mate scores are stored as distance from this node rather than from the root, so
+/// they stay correct when the position is reached at a different ply
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_SCORE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score < -MATE_SCORE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score > MATE_SCORE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score < -MATE_SCORE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_fits_budget() {
+        let tt = TranspositionTable::new(8);
+        assert!(tt.len().is_power_of_two());
+        assert!(tt.len() * std::mem::size_of::<Option<TtEntry>>() <= 8 * 1024 * 1024);
+        assert!(tt.len() * std::mem::size_of::<Option<TtEntry>>() > 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(42, None, 17, 3, Bound::Exact, 0);
+        let entry = tt.probe(42, 0).unwrap();
+        assert_eq!(
+            (entry.score, entry.depth, entry.bound),
+            (17, 3, Bound::Exact)
+        );
+        // Same slot, different position
+        assert!(tt.probe(42 + tt.len() as u64, 0).is_none());
+        tt.clear();
+        assert!(tt.probe(42, 0).is_none());
+    }
+
+    #[test]
+    fn test_shallower_bound_does_not_replace_deeper_entry() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(7, None, 50, 6, Bound::Exact, 0);
+        tt.store(7, None, 10, 2, Bound::Lower, 0);
+        assert_eq!(tt.probe(7, 0).unwrap().score, 50);
+        tt.store(7, None, 10, 8, Bound::Lower, 0);
+        assert_eq!(tt.probe(7, 0).unwrap().score, 10);
+    }
+
+    #[test]
+    fn test_mate_scores_are_relative_to_ply() {
+        let mut tt = TranspositionTable::new(1);
+        // Mate in 3 plies found at ply 4 is mate in 7 from the root
+        tt.store(9, None, MATE_SCORE - 7, 5, Bound::Exact, 4);
+        assert_eq!(tt.probe(9, 4).unwrap().score, MATE_SCORE - 7);
+        assert_eq!(tt.probe(9, 2).unwrap().score, MATE_SCORE - 5);
+    }
+}
@@ -0,0 +1,582 @@
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_SEARCH_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub enum UciError {
+    /// The engine binary could not be started
+    Spawn(std::io::Error),
+    /// The engine exited or closed its output
+    Crashed,
+    /// The engine didn't send the named reply in time
+    Timeout(&'static str),
+    /// The engine sent something we couldn't make sense of
+    Protocol(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciError::Spawn(e) => write!(f, "Failed to start engine: {}", e),
+            UciError::Crashed => write!(f, "Engine process exited"),
+            UciError::Timeout(reply) => write!(f, "Engine did not send {} in time", reply),
+            UciError::Protocol(message) => write!(f, "Unexpected engine output: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+impl UciError {
+    /// Errors after which the process can't be trusted and is restarted
+    fn needs_restart(&self) -> bool {
+        matches!(self, UciError::Crashed | UciError::Timeout(_))
+    }
+}
+
+/// How to start an external engine and how long to wait for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct UciConfig {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Allowed time for `uciok` and `readyok`
+    pub handshake_timeout: Duration,
+    /// Allowed time for searches that have no movetime, such as depth-limited ones
+    pub search_timeout: Duration,
+    /// Time past the movetime before `stop` is sent, and again before giving up
+    pub stop_grace: Duration,
+}
+
+impl UciConfig {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            search_timeout: DEFAULT_SEARCH_TIMEOUT,
+            stop_grace: DEFAULT_STOP_GRACE,
+        }
+    }
+
+    /// Reads UCI_ENGINE_PATH and the whitespace-separated UCI_ENGINE_ARGS, or None if no
+    /// external engine is configured
+    pub fn from_env() -> Option<Self> {
+        let program = std::env::var("UCI_ENGINE_PATH")
+            .ok()
+            .filter(|path| !path.trim().is_empty())?;
+        let args = std::env::var("UCI_ENGINE_ARGS")
+            .map(|args| args.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+        Some(Self {
+            args,
+            ..Self::new(program)
+        })
+    }
+}
+
+/// Limits sent with `go`; all unset means an infinite search, which needs `stop`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UciLimits {
+    pub movetime: Option<Duration>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+}
+
+impl UciLimits {
+    fn to_command(&self) -> String {
+        let mut command = "go".to_string();
+        if let Some(movetime) = self.movetime {
+            command.push_str(&format!(" movetime {}", movetime.as_millis()));
+        }
+        if let Some(depth) = self.depth {
+            command.push_str(&format!(" depth {}", depth));
+        }
+        if let Some(nodes) = self.nodes {
+            command.push_str(&format!(" nodes {}", nodes));
+        }
+        if command == "go" {
+            command.push_str(" infinite");
+        }
+        command
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UciScore {
+    Centipawns(i32),
+    /// Moves to mate, negative if the engine is being mated
+    Mate(i32),
+}
+
+/// One `info` line; fields the engine didn't send are None
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UciInfo {
+    pub depth: Option<u32>,
+    pub seldepth: Option<u32>,
+    pub multipv: Option<u32>,
+    pub score: Option<UciScore>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time_ms: Option<u64>,
+    pub pv: Vec<String>,
+}
+
+impl UciInfo {
+    /// Parses an `info` line, skipping tokens we don't use; None for other lines and
+    /// for `info string` messages
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("info") {
+            return None;
+        }
+        let mut info = UciInfo::default();
+        while let Some(token) = tokens.next() {
+            match token {
+                "string" => return None,
+                "depth" => info.depth = tokens.next().and_then(|t| t.parse().ok()),
+                "seldepth" => info.seldepth = tokens.next().and_then(|t| t.parse().ok()),
+                "multipv" => info.multipv = tokens.next().and_then(|t| t.parse().ok()),
+                "nodes" => info.nodes = tokens.next().and_then(|t| t.parse().ok()),
+                "nps" => info.nps = tokens.next().and_then(|t| t.parse().ok()),
+                "time" => info.time_ms = tokens.next().and_then(|t| t.parse().ok()),
+                "score" => {
+                    let kind = tokens.next();
+                    let value = tokens.next().and_then(|t| t.parse().ok());
+                    info.score = match (kind, value) {
+                        (Some("cp"), Some(cp)) => Some(UciScore::Centipawns(cp)),
+                        (Some("mate"), Some(moves)) => Some(UciScore::Mate(moves)),
+                        _ => None,
+                    };
+                }
+                // The principal variation runs to the end of the line
+                "pv" => info.pv = tokens.by_ref().map(str::to_string).collect(),
+                _ => {}
+            }
+        }
+        Some(info)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciBestMove {
+    /// None when the engine reports no legal move
+    pub best_move: Option<String>,
+    pub ponder: Option<String>,
+    /// The last `info` for the main line that carried a score
+    pub info: Option<UciInfo>,
+}
+
+fn parse_bestmove(line: &str) -> Option<(Option<String>, Option<String>)> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next() != Some("bestmove") {
+        return None;
+    }
+    let best_move = tokens
+        .next()
+        .filter(|mv| *mv != "(none)" && *mv != "0000")
+        .map(str::to_string);
+    let ponder = match (tokens.next(), tokens.next()) {
+        (Some("ponder"), Some(mv)) => Some(mv.to_string()),
+        _ => None,
+    };
+    Some((best_move, ponder))
+}
+
+/// A running engine process with its output read on a background thread
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Process {
+    fn send(&mut self, command: &str) -> Result<(), UciError> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| UciError::Crashed)
+    }
+
+    /// Returns the next line, or Ok(None) once `deadline` passes
+    fn next_line(&self, deadline: Instant) -> Result<Option<String>, UciError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(Some(line)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(UciError::Crashed),
+        }
+    }
+
+    /// Reads lines until one starts with `reply`, returning the lines before it
+    fn wait_for(&self, reply: &'static str, timeout: Duration) -> Result<Vec<String>, UciError> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        while let Some(line) = self.next_line(deadline)? {
+            if line.split_whitespace().next() == Some(reply) {
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+        Err(UciError::Timeout(reply))
+    }
+
+    fn kill(mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Drives an external engine over the UCI protocol
+///
+/// A process that crashes or stops responding is killed and restarted with the same
+/// options, and the failed search is tried once more on the new process.
+pub struct UciEngine {
+    config: UciConfig,
+    /// Options set so far, replayed after a restart
+    options: Vec<(String, String)>,
+    process: Option<Process>,
+    name: Option<String>,
+}
+
+impl UciEngine {
+    /// Starts the engine and completes the `uci`/`isready` handshake
+    pub fn start(config: UciConfig) -> Result<Self, UciError> {
+        let mut engine = Self {
+            config,
+            options: Vec::new(),
+            process: None,
+            name: None,
+        };
+        engine.restart()?;
+        Ok(engine)
+    }
+
+    /// The name the engine gave in `id name`
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The value last set for option `name`
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), UciError> {
+        self.options.retain(|(existing, _)| existing != name);
+        self.options.push((name.to_string(), value.to_string()));
+        let handshake_timeout = self.config.handshake_timeout;
+        let process = self.running()?;
+        process.send(&format!("setoption name {} value {}", name, value))?;
+        process.send("isready")?;
+        process.wait_for("readyok", handshake_timeout)?;
+        Ok(())
+    }
+
+    /// Tells the engine the next search is from an unrelated game
+    pub fn new_game(&mut self) -> Result<(), UciError> {
+        let handshake_timeout = self.config.handshake_timeout;
+        let process = self.running()?;
+        process.send("ucinewgame")?;
+        process.send("isready")?;
+        process.wait_for("readyok", handshake_timeout)?;
+        Ok(())
+    }
+
+    /// Searches the position reached by playing `moves` (in UCI notation) from `fen`, or
+    /// from the starting position if `fen` is None
+    pub fn search(
+        &mut self,
+        fen: Option<&str>,
+        moves: &[String],
+        limits: &UciLimits,
+    ) -> Result<UciBestMove, UciError> {
+        match self.try_search(fen, moves, limits) {
+            Err(e) if e.needs_restart() => {
+                warn!("Engine {:?} failed: {}; restarting", self.config.program, e);
+                self.restart()?;
+                self.try_search(fen, moves, limits)
+            }
+            result => result,
+        }
+    }
+
+    fn try_search(
+        &mut self,
+        fen: Option<&str>,
+        moves: &[String],
+        limits: &UciLimits,
+    ) -> Result<UciBestMove, UciError> {
+        let stop_grace = self.config.stop_grace;
+        let search_timeout = limits
+            .movetime
+            .map_or(self.config.search_timeout, |movetime| movetime + stop_grace);
+        let process = self.running()?;
+
+        let mut command = match fen {
+            Some(fen) => format!("position fen {}", fen),
+            None => "position startpos".to_string(),
+        };
+        if !moves.is_empty() {
+            command.push_str(" moves ");
+            command.push_str(&moves.join(" "));
+        }
+        process.send(&command)?;
+        process.send(&limits.to_command())?;
+
+        let mut last_info = None;
+        let mut deadline = Instant::now() + search_timeout;
+        let mut stop_sent = false;
+        loop {
+            let Some(line) = process.next_line(deadline)? else {
+                if stop_sent {
+                    return Err(UciError::Timeout("bestmove"));
+                }
+                // Ask for whatever it has, then give it a little longer to answer
+                process.send("stop")?;
+                stop_sent = true;
+                deadline = Instant::now() + stop_grace;
+                continue;
+            };
+            if let Some((best_move, ponder)) = parse_bestmove(&line) {
+                return Ok(UciBestMove {
+                    best_move,
+                    ponder,
+                    info: last_info,
+                });
+            }
+            if let Some(info) = UciInfo::parse(&line) {
+                if info.score.is_some() && info.multipv.unwrap_or(1) == 1 {
+                    last_info = Some(info);
+                }
+            }
+        }
+    }
+
+    /// The live process, restarting it if an earlier failure left none
+    fn running(&mut self) -> Result<&mut Process, UciError> {
+        if self.process.is_none() {
+            self.restart()?;
+        }
+        self.process.as_mut().ok_or(UciError::Crashed)
+    }
+
+    /// Kills any current process, starts a fresh one and replays the options
+    fn restart(&mut self) -> Result<(), UciError> {
+        if let Some(process) = self.process.take() {
+            process.kill();
+        }
+
+        let mut child = Command::new(&self.config.program)
+            .args(&self.config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(UciError::Spawn)?;
+        let stdin = child.stdin.take().ok_or(UciError::Crashed)?;
+        let stdout = child.stdout.take().ok_or(UciError::Crashed)?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut process = Process {
+            child,
+            stdin,
+            lines,
+        };
+
+        let timeout = self.config.handshake_timeout;
+        let handshake = (|| {
+            process.send("uci")?;
+            let id_lines = process.wait_for("uciok", timeout)?;
+            for (name, value) in &self.options {
+                process.send(&format!("setoption name {} value {}", name, value))?;
+            }
+            process.send("isready")?;
+            process.wait_for("readyok", timeout)?;
+            Ok(id_lines)
+        })();
+        let id_lines = match handshake {
+            Ok(lines) => lines,
+            Err(e) => {
+                process.kill();
+                return Err(e);
+            }
+        };
+
+        self.name = id_lines
+            .iter()
+            .find_map(|line| line.strip_prefix("id name "))
+            .map(|name| name.trim().to_string());
+        info!(
+            "Started engine {:?} ({})",
+            self.config.program,
+            self.name.as_deref().unwrap_or("unnamed")
+        );
+        self.process = Some(process);
+        Ok(())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if let Some(process) = self.process.take() {
+            process.kill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_engine(args: &[&str]) -> UciConfig {
+        let script = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/fake_uci_engine.sh"
+        );
+        UciConfig {
+            program: PathBuf::from("sh"),
+            args: std::iter::once(script)
+                .chain(args.iter().copied())
+                .map(str::to_string)
+                .collect(),
+            handshake_timeout: Duration::from_secs(5),
+            search_timeout: Duration::from_secs(5),
+            stop_grace: Duration::from_millis(200),
+        }
+    }
+
+    fn movetime(ms: u64) -> UciLimits {
+        UciLimits {
+            movetime: Some(Duration::from_millis(ms)),
+            ..UciLimits::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_info() {
+        let info = UciInfo::parse(
+            "info depth 12 seldepth 18 multipv 1 score cp -34 nodes 51234 nps 900000 \
+             time 57 pv e7e5 g1f3 b8c6",
+        )
+        .unwrap();
+        assert_eq!(info.depth, Some(12));
+        assert_eq!(info.seldepth, Some(18));
+        assert_eq!(info.score, Some(UciScore::Centipawns(-34)));
+        assert_eq!(info.nodes, Some(51_234));
+        assert_eq!(info.time_ms, Some(57));
+        assert_eq!(info.pv, vec!["e7e5", "g1f3", "b8c6"]);
+
+        let mate = UciInfo::parse("info depth 5 score mate -3 lowerbound pv h7h8").unwrap();
+        assert_eq!(mate.score, Some(UciScore::Mate(-3)));
+
+        assert_eq!(UciInfo::parse("info string NNUE enabled"), None);
+        assert_eq!(UciInfo::parse("bestmove e2e4"), None);
+    }
+
+    #[test]
+    fn test_parse_bestmove() {
+        assert_eq!(
+            parse_bestmove("bestmove e7e8q ponder d8e8"),
+            Some((Some("e7e8q".to_string()), Some("d8e8".to_string())))
+        );
+        assert_eq!(parse_bestmove("bestmove (none)"), Some((None, None)));
+        assert_eq!(parse_bestmove("info depth 1"), None);
+    }
+
+    #[test]
+    fn test_go_command() {
+        assert_eq!(movetime(250).to_command(), "go movetime 250");
+        let limits = UciLimits {
+            depth: Some(10),
+            nodes: Some(5000),
+            ..UciLimits::default()
+        };
+        assert_eq!(limits.to_command(), "go depth 10 nodes 5000");
+        assert_eq!(UciLimits::default().to_command(), "go infinite");
+    }
+
+    #[test]
+    fn test_handshake_and_search() {
+        let mut engine = UciEngine::start(fake_engine(&[])).unwrap();
+        assert_eq!(engine.name(), Some("FakeEngine 1.0"));
+        engine.set_option("Hash", "32").unwrap();
+        engine.new_game().unwrap();
+
+        let result = engine
+            .search(None, &["d2d4".to_string()], &movetime(100))
+            .unwrap();
+        assert_eq!(result.best_move.as_deref(), Some("e2e4"));
+        assert_eq!(result.ponder.as_deref(), Some("e7e5"));
+        let info = result.info.unwrap();
+        assert_eq!(info.depth, Some(2));
+        assert_eq!(info.score, Some(UciScore::Centipawns(25)));
+        assert_eq!(info.pv, vec!["e2e4", "e7e5"]);
+    }
+
+    #[test]
+    fn test_overdue_search_is_stopped() {
+        let mut engine = UciEngine::start(fake_engine(&["stall"])).unwrap();
+        let result = engine.search(None, &[], &movetime(50)).unwrap();
+        assert_eq!(result.best_move.as_deref(), Some("d2d4"));
+    }
+
+    #[test]
+    fn test_hung_engine_times_out() {
+        let mut engine = UciEngine::start(fake_engine(&["hang"])).unwrap();
+        assert!(matches!(
+            engine.search(None, &[], &movetime(50)),
+            Err(UciError::Timeout("bestmove"))
+        ));
+    }
+
+    #[test]
+    fn test_crashed_engine_is_restarted() {
+        let marker = std::env::temp_dir().join(format!("fake-uci-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let mut engine =
+            UciEngine::start(fake_engine(&["crash-once", marker.to_str().unwrap()])).unwrap();
+        engine.set_option("Hash", "64").unwrap();
+
+        let result = engine.search(None, &[], &movetime(100)).unwrap();
+        assert_eq!(result.best_move.as_deref(), Some("e2e4"));
+        assert!(marker.exists());
+        assert_eq!(engine.options, vec![("Hash".to_string(), "64".to_string())]);
+        assert_eq!(engine.option("Hash"), Some("64"));
+        let _ = std::fs::remove_file(&marker);
+    }
+
+    #[test]
+    fn test_engine_that_keeps_crashing_reports_it() {
+        let mut engine = UciEngine::start(fake_engine(&["crash"])).unwrap();
+        assert!(matches!(
+            engine.search(None, &[], &movetime(100)),
+            Err(UciError::Crashed)
+        ));
+    }
+
+    #[test]
+    fn test_missing_binary() {
+        let config = UciConfig::new("/nonexistent/engine");
+        assert!(matches!(UciEngine::start(config), Err(UciError::Spawn(_))));
+    }
+}
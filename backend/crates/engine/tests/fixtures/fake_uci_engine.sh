@@ -0,0 +1,58 @@
+#!/bin/sh
+# Minimal UCI engine standing in for a real binary in tests.
+#
+# Usage: fake_uci_engine.sh [MODE] [MARKER]
+#   normal      answers every search with e2e4 (default)
+#   stall       only answers a search once told to stop
+#   hang        never answers a search, nor stop
+#   crash       exits as soon as a search starts
+#   crash-once  crashes on the first search, creating MARKER; normal afterwards
+
+mode="${1:-normal}"
+marker="$2"
+
+if [ "$mode" = "crash-once" ]; then
+    if [ -e "$marker" ]; then
+        mode="normal"
+    else
+        mode="crash"
+    fi
+fi
+
+while read -r line; do
+    case "$line" in
+        uci)
+            echo "id name FakeEngine 1.0"
+            echo "id author Checkmate Team"
+            echo "option name Hash type spin default 16 min 1 max 1024"
+            echo "uciok"
+            ;;
+        isready)
+            echo "readyok"
+            ;;
+        "setoption name Hash value"*)
+            echo "info string hash set to ${line##* }"
+            ;;
+        go*)
+            case "$mode" in
+                crash)
+                    [ -n "$marker" ] && touch "$marker"
+                    exit 1
+                    ;;
+                stall|hang)
+                    ;;
+                *)
+                    echo "info depth 1 seldepth 1 score cp 13 nodes 20 nps 20000 time 1 pv e2e4"
+                    echo "info depth 2 seldepth 4 multipv 1 score cp 25 nodes 120 nps 60000 time 2 pv e2e4 e7e5"
+                    echo "bestmove e2e4 ponder e7e5"
+                    ;;
+            esac
+            ;;
+        stop)
+            [ "$mode" = "stall" ] && echo "bestmove d2d4"
+            ;;
+        quit)
+            exit 0
+            ;;
+    esac
+done
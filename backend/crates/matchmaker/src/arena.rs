@@ -0,0 +1,567 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use serde::Serialize;
+use shared::odds::StartingClocks;
+use shared::time_control::{find_time_control, unsupported_time_control};
+use shared::{Color, Game, GameStatus};
+use tournaments::arena::{pool_name, ArenaGame};
+use tournaments::{ArenaStanding, Tournament, TournamentFormat, TournamentStatus};
+use tracing::{info, warn};
+
+use crate::errors::ServiceError;
+use crate::matching::{unix_now, MatchParams};
+use crate::models::{QueueEntry, TournamentEntry};
+use crate::notifications::send_to_user;
+use crate::tournaments::TournamentContext;
+
+/// How long after its game is created a player may still berserk
+pub const BERSERK_WINDOW_SECS: u64 = 20;
+/// Attempts at berserking when the opponent berserks at the same time
+const MAX_BERSERK_ATTEMPTS: usize = 3;
+
+/// Websocket message with an arena's live standings
+#[derive(Debug, Serialize)]
+pub struct ArenaStandingsMessage {
+    pub action: String, // "arena_standings"
+    pub tournament_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<u64>,
+    pub standings: Vec<ArenaStanding>,
+}
+
+/// Websocket message telling both players that one of them berserked
+#[derive(Debug, Serialize)]
+pub struct BerserkMessage {
+    pub action: String, // "berserk"
+    pub game_id: String,
+    pub color: String,
+    /// The game's clocks after the berserk
+    pub clocks: StartingClocks,
+}
+
+/// Starts an arena that is registering, or finishes one whose time is up
+pub fn advance_arena(tournament: &mut Tournament, now: u64) -> Result<(), ServiceError> {
+    let TournamentFormat::Arena { minutes } = tournament.format else {
+        return Err(ServiceError::Invalid("Not an arena".to_string()));
+    };
+    match tournament.status {
+        TournamentStatus::Registering => {
+            if tournament.players.len() < 2 {
+                return Err(ServiceError::Conflict(
+                    "At least two players are needed".to_string(),
+                ));
+            }
+            tournament.status = TournamentStatus::InProgress;
+            tournament.ends_at = Some(now + u64::from(minutes) * 60);
+        }
+        TournamentStatus::InProgress if tournament.arena_running(now) => {
+            return Err(ServiceError::Conflict(
+                "The arena is still running".to_string(),
+            ));
+        }
+        TournamentStatus::InProgress => tournament.status = TournamentStatus::Finished,
+        TournamentStatus::Finished => {
+            return Err(ServiceError::Conflict("The arena has finished".to_string()));
+        }
+    }
+    Ok(())
+}
+
+/// Queue key of a player's entry in the arena's pool
+///
+/// Uses the rating the player registered with, so the entry can be found again without
+/// reading the user.
+fn arena_queue_key(tournament_id: &str, rating: i32) -> String {
+    format!(
+        "{}#{}",
+        pool_name(tournament_id),
+        MatchParams::default().normalize_rating(rating)
+    )
+}
+
+/// The queue entry that puts `user_id` into the arena's pool, if they are registered
+///
+/// The entry expires when the arena ends, so nobody is paired after that.
+pub fn arena_queue_entry(
+    tournament: &Tournament,
+    user_id: &str,
+    blocked_user_ids: Vec<String>,
+    now: u64,
+) -> Option<QueueEntry> {
+    let player = tournament.players.get(user_id)?;
+    Some(QueueEntry {
+        queue_key: arena_queue_key(&tournament.tournament_id, player.rating),
+        user_id: user_id.to_string(),
+        time_control: pool_name(&tournament.tournament_id),
+        rating: player.rating,
+        joined_at: now.to_string(),
+        status: "waiting".to_string(),
+        min_rating: None,
+        max_rating: None,
+        expires_at: tournament.ends_at,
+        blocked_user_ids,
+        linked_queue_keys: Vec::new(),
+        bot_fallback: false,
+        rated: tournament.rated,
+        variant: tournament.variant,
+        tournament: Some(TournamentEntry {
+            tournament_id: tournament.tournament_id.clone(),
+            time_control: tournament.time_control.clone(),
+            rated: tournament.rated,
+        }),
+    })
+}
+
+/// Checks that `user_id` may berserk in `game` at `now` and returns their colour
+///
+/// Berserking is only allowed at the very start of a game, before the clocks matter.
+pub fn check_berserk(game: &Game, user_id: &str, now: u64) -> Result<Color, ServiceError> {
+    let (color, already) = if game.white_player_id == user_id {
+        (Color::White, game.white_berserk)
+    } else if game.black_player_id == user_id {
+        (Color::Black, game.black_berserk)
+    } else {
+        return Err(ServiceError::Forbidden("You are not playing in this game"));
+    };
+    if !matches!(game.status, GameStatus::Active) {
+        return Err(ServiceError::Conflict("The game is over".to_string()));
+    }
+    if already {
+        return Err(ServiceError::Conflict(
+            "You have already berserked".to_string(),
+        ));
+    }
+    let created_at = game.created_at.parse::<u64>().unwrap_or(0);
+    if now > created_at + BERSERK_WINDOW_SECS {
+        return Err(ServiceError::Conflict(
+            "It is too late to berserk".to_string(),
+        ));
+    }
+    Ok(color)
+}
+
+/// Starting clocks once `color` has berserked: their time is halved and their increment
+/// dropped
+pub fn berserk_clocks(game: &Game, color: Color) -> Result<StartingClocks, ServiceError> {
+    let mut clocks = match game.clocks {
+        Some(clocks) => clocks,
+        None => {
+            let time_control = find_time_control(&game.time_control).ok_or_else(|| {
+                ServiceError::Invalid(unsupported_time_control(&game.time_control))
+            })?;
+            StartingClocks {
+                white_secs: time_control.initial_secs,
+                black_secs: time_control.initial_secs,
+                white_increment_secs: None,
+                black_increment_secs: None,
+            }
+        }
+    };
+    let (secs, increment_secs) = match color {
+        Color::White => (&mut clocks.white_secs, &mut clocks.white_increment_secs),
+        Color::Black => (&mut clocks.black_secs, &mut clocks.black_increment_secs),
+    };
+    *secs = (*secs / 2).max(1);
+    *increment_secs = Some(0);
+    Ok(clocks)
+}
+
+impl TournamentContext {
+    /// Registers `user_id` for an arena if needed and, while it runs, puts them in its pool
+    pub async fn join_arena(
+        &self,
+        tournament_id: &str,
+        user_id: &str,
+    ) -> Result<Tournament, ServiceError> {
+        let mut tournament = self.get(tournament_id).await?;
+        if !tournament.is_arena() {
+            return Err(ServiceError::Invalid("Not an arena".to_string()));
+        }
+        if tournament.status == TournamentStatus::Finished {
+            return Err(ServiceError::Conflict("The arena has finished".to_string()));
+        }
+        if !tournament.players.contains_key(user_id) {
+            tournament = self.register(tournament_id, user_id).await?;
+        }
+        if tournament.arena_running(unix_now()?) {
+            self.enqueue_arena_player(&tournament, user_id).await?;
+        }
+        Ok(tournament)
+    }
+
+    /// Takes `user_id` out of the arena's pool; their games so far still count
+    pub async fn leave_arena(
+        &self,
+        tournament_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        let tournament = self.get(tournament_id).await?;
+        let player = tournament
+            .players
+            .get(user_id)
+            .ok_or_else(|| ServiceError::Conflict("You are not registered".to_string()))?;
+        self.dynamodb
+            .delete_item()
+            .table_name(&self.queue_table)
+            .key(
+                "queue_key",
+                AttributeValue::S(arena_queue_key(tournament_id, player.rating)),
+            )
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        info!("{} left the pool of arena {}", user_id, tournament_id);
+        Ok(())
+    }
+
+    /// Puts a registered player into the arena's pool, where the matchmaker pairs them
+    pub async fn enqueue_arena_player(
+        &self,
+        tournament: &Tournament,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        let blocked_user_ids = self
+            .load_user(user_id)
+            .await?
+            .map(|user| user.blocked_user_ids)
+            .unwrap_or_default();
+        let Some(entry) = arena_queue_entry(tournament, user_id, blocked_user_ids, unix_now()?)
+        else {
+            return Ok(());
+        };
+        info!(
+            "Queueing {} in arena {} ({})",
+            user_id, tournament.tournament_id, entry.queue_key
+        );
+        self.dynamodb
+            .put_item()
+            .table_name(&self.queue_table)
+            .set_item(Some(serde_dynamo::to_item(&entry)?))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn load_game(&self, game_id: &str) -> Result<Game, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.games_table)
+            .key("game_id", AttributeValue::S(game_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(serde_dynamo::from_item(item)?),
+            None => Err(ServiceError::Invalid("Unknown game".to_string())),
+        }
+    }
+
+    /// Halves `user_id`'s clock and drops their increment in an arena game, in exchange for
+    /// an extra point on a win
+    ///
+    /// The clocks are written only if the opponent hasn't berserked since the game was
+    /// read, so two berserks at once can't overwrite each other's clock.
+    pub async fn berserk(&self, game_id: &str, user_id: &str) -> Result<Game, ServiceError> {
+        let mut attempt = 1;
+        let (mut game, color, clocks) = loop {
+            let mut game = self.load_game(game_id).await?;
+            let not_arena =
+                || ServiceError::Invalid("Only arena games can be berserked".to_string());
+            let tournament_id = game.tournament_id.clone().ok_or_else(not_arena)?;
+            if !self.get(&tournament_id).await?.is_arena() {
+                return Err(not_arena());
+            }
+            let color = check_berserk(&game, user_id, unix_now()?)?;
+            let clocks = berserk_clocks(&game, color)?;
+            let opponent_berserked = match color {
+                Color::White => game.black_berserk,
+                Color::Black => game.white_berserk,
+            };
+
+            let result = self
+                .dynamodb
+                .update_item()
+                .table_name(&self.games_table)
+                .key("game_id", AttributeValue::S(game_id.to_string()))
+                .update_expression("SET #berserk = :true, clocks = :clocks")
+                .condition_expression(
+                    "#status = :active \
+                     AND (attribute_not_exists(#berserk) OR #berserk = :false) \
+                     AND (attribute_not_exists(#other) OR #other = :other_berserked)",
+                )
+                .expression_attribute_names("#berserk", format!("{}_berserk", color.as_str()))
+                .expression_attribute_names(
+                    "#other",
+                    format!("{}_berserk", color.opposite().as_str()),
+                )
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_values(":true", AttributeValue::Bool(true))
+                .expression_attribute_values(":false", AttributeValue::Bool(false))
+                .expression_attribute_values(
+                    ":other_berserked",
+                    AttributeValue::Bool(opponent_berserked),
+                )
+                .expression_attribute_values(":active", AttributeValue::S("active".to_string()))
+                .expression_attribute_values(":clocks", serde_dynamo::to_attribute_value(clocks)?)
+                .send()
+                .await;
+            match result {
+                Ok(_) => {
+                    game.clocks = Some(clocks);
+                    break (game, color, clocks);
+                }
+                Err(e)
+                    if e.as_service_error()
+                        .is_some_and(|se| se.is_conditional_check_failed_exception()) =>
+                {
+                    // Read the game again: it may have ended, or the opponent berserked
+                    if attempt == MAX_BERSERK_ATTEMPTS {
+                        return Err(ServiceError::Conflict(
+                            "The game changed in the meantime; try again".to_string(),
+                        ));
+                    }
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+        match color {
+            Color::White => game.white_berserk = true,
+            Color::Black => game.black_berserk = true,
+        }
+
+        info!("{} berserked in game {}", user_id, game_id);
+        let message = BerserkMessage {
+            action: "berserk".to_string(),
+            game_id: game_id.to_string(),
+            color: color.as_str().to_string(),
+            clocks,
+        };
+        for player_id in [&game.white_player_id, &game.black_player_id] {
+            send_to_user(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                player_id,
+                &message,
+            )
+            .await;
+        }
+        Ok(game)
+    }
+
+    /// Records a finished arena game, puts both players back in the pool and pushes the
+    /// standings to everyone in the arena
+    ///
+    /// Games are keyed by id, so a result delivered twice is only counted once. The first
+    /// result after the time is up finishes the arena.
+    pub async fn record_arena_result(&self, game: &Game) -> Result<(), ServiceError> {
+        let Some(tournament_id) = &game.tournament_id else {
+            return Ok(());
+        };
+        let now = unix_now()?;
+        let Some(record) = ArenaGame::from_game(game, now) else {
+            return Ok(());
+        };
+        if !self.get(tournament_id).await?.is_arena() {
+            return Ok(());
+        }
+
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.tournaments_table)
+            .key("tournament_id", AttributeValue::S(tournament_id.clone()))
+            .update_expression("SET arena_games.#gid = :game ADD version :one")
+            .condition_expression("attribute_not_exists(arena_games.#gid)")
+            .expression_attribute_names("#gid", &game.game_id)
+            .expression_attribute_values(
+                ":game",
+                AttributeValue::M(serde_dynamo::to_item(&record)?),
+            )
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                info!("Arena game {} was already recorded", game.game_id);
+                return Ok(());
+            }
+            return Err(e.into());
+        }
+        info!(
+            "Recorded arena game {} in {}: {:?}",
+            game.game_id, tournament_id, record.result
+        );
+
+        let mut tournament = self.get(tournament_id).await?;
+        if tournament.arena_running(now) {
+            for user_id in [&game.white_player_id, &game.black_player_id] {
+                if let Err(e) = self.enqueue_arena_player(&tournament, user_id).await {
+                    warn!(
+                        "Failed to queue {} in arena {}: {}",
+                        user_id, tournament_id, e
+                    );
+                }
+            }
+        } else if tournament.status == TournamentStatus::InProgress {
+            let expected_version = tournament.version;
+            tournament.status = TournamentStatus::Finished;
+            match self.save(&mut tournament, expected_version).await {
+                Ok(()) => info!("Arena {} finished", tournament_id),
+                // Another result got there first and will finish it
+                Err(e) => warn!("Failed to finish arena {}: {}", tournament_id, e),
+            }
+        }
+        self.broadcast_standings(&tournament).await;
+        Ok(())
+    }
+
+    /// Sends the arena's current standings to every registered player
+    pub async fn broadcast_standings(&self, tournament: &Tournament) {
+        let message = ArenaStandingsMessage {
+            action: "arena_standings".to_string(),
+            tournament_id: tournament.tournament_id.clone(),
+            ends_at: tournament.ends_at,
+            standings: tournaments::arena::standings(tournament),
+        };
+        for user_id in tournament.players.keys() {
+            send_to_user(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                user_id,
+                &message,
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::variant::Variant;
+    use shared::GameResult;
+    use tournaments::TournamentPlayer;
+
+    fn arena(players: &[&str]) -> Tournament {
+        let mut tournament = Tournament::new(
+            "t1".to_string(),
+            "Hourly Blitz".to_string(),
+            "org".to_string(),
+            "blitz".to_string(),
+            true,
+            TournamentFormat::Arena { minutes: 30 },
+            0,
+        );
+        for user_id in players {
+            tournament.players.insert(
+                user_id.to_string(),
+                TournamentPlayer {
+                    user_id: user_id.to_string(),
+                    rating: 1234,
+                    registered_at: 0,
+                },
+            );
+        }
+        tournament
+    }
+
+    fn game(created_at: u64) -> Game {
+        Game {
+            white_player_id: "alice".to_string(),
+            black_player_id: "bob".to_string(),
+            created_at: created_at.to_string(),
+            tournament_id: Some("t1".to_string()),
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_arena_starts_runs_and_finishes() {
+        let mut tournament = arena(&["alice"]);
+        assert!(matches!(
+            advance_arena(&mut tournament, 1_000),
+            Err(ServiceError::Conflict(_))
+        ));
+
+        let mut tournament = arena(&["alice", "bob"]);
+        advance_arena(&mut tournament, 1_000).unwrap();
+        assert_eq!(tournament.status, TournamentStatus::InProgress);
+        assert_eq!(tournament.ends_at, Some(1_000 + 30 * 60));
+        assert!(tournament.arena_running(1_000));
+
+        assert!(advance_arena(&mut tournament, 1_500).is_err());
+        advance_arena(&mut tournament, 1_000 + 30 * 60).unwrap();
+        assert_eq!(tournament.status, TournamentStatus::Finished);
+    }
+
+    #[test]
+    fn test_queue_entry_pairs_into_the_arena_pool() {
+        let mut tournament = arena(&["alice", "bob"]);
+        advance_arena(&mut tournament, 1_000).unwrap();
+        let entry = arena_queue_entry(&tournament, "alice", Vec::new(), 1_010).unwrap();
+        assert_eq!(entry.queue_key, "arena:t1#1200");
+        assert_eq!(entry.time_control, "arena:t1");
+        assert_eq!(entry.expires_at, tournament.ends_at);
+        assert_eq!(entry.variant, Variant::Standard);
+        assert!(!entry.bot_fallback);
+        let settings = entry.tournament.unwrap();
+        assert_eq!(settings.tournament_id, "t1");
+        assert_eq!(settings.time_control, "blitz");
+        assert!(settings.rated);
+
+        assert!(arena_queue_entry(&tournament, "carol", Vec::new(), 1_010).is_none());
+
+        tournament.variant = Variant::Chess960;
+        let entry = arena_queue_entry(&tournament, "bob", Vec::new(), 1_010).unwrap();
+        assert_eq!(entry.variant, Variant::Chess960);
+    }
+
+    #[test]
+    fn test_berserk_halves_the_clock_and_drops_the_increment() {
+        let mut game = game(100);
+        game.time_control = "3+2".to_string();
+        let clocks = berserk_clocks(&game, Color::White).unwrap();
+        assert_eq!((clocks.white_secs, clocks.black_secs), (90, 180));
+        assert_eq!(clocks.white_increment_secs, Some(0));
+        assert_eq!(clocks.black_increment_secs, None);
+
+        // Both players berserking each lose half their time and their increment
+        game.clocks = Some(clocks);
+        let clocks = berserk_clocks(&game, Color::Black).unwrap();
+        assert_eq!((clocks.white_secs, clocks.black_secs), (90, 90));
+        assert_eq!(clocks.black_increment_secs, Some(0));
+
+        game.clocks = None;
+        game.time_control = "3+1".to_string();
+        assert!(matches!(
+            berserk_clocks(&game, Color::White),
+            Err(ServiceError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_berserk_only_at_the_start_of_your_own_game() {
+        assert_eq!(check_berserk(&game(100), "bob", 110).unwrap(), Color::Black);
+        assert!(matches!(
+            check_berserk(&game(100), "carol", 110),
+            Err(ServiceError::Forbidden(_))
+        ));
+        assert!(check_berserk(&game(100), "alice", 100 + BERSERK_WINDOW_SECS + 1).is_err());
+
+        let berserked = Game {
+            white_berserk: true,
+            ..game(100)
+        };
+        assert!(check_berserk(&berserked, "alice", 110).is_err());
+        let finished = Game {
+            status: GameStatus::Completed,
+            result: Some(GameResult::Draw),
+            ..game(100)
+        };
+        assert!(check_berserk(&finished, "alice", 110).is_err());
+    }
+}
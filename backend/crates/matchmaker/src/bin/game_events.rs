@@ -0,0 +1,94 @@
+//! Game events handler
+//!
+//! Reads the GamesTable stream. Every change to a simul board is forwarded to the host, so
+//! they follow all their boards over one connection, and a finished board is recorded on
+//! the simul. Every arena game is recorded as it finishes, which puts both players back in
+//! the arena's pool and pushes the new standings to its players. Every rated game moves
+//! both players' ratings as it finishes; casual games leave them alone.
+
+use aws_config::BehaviorVersion;
+use aws_lambda_events::event::dynamodb::{Event as DynamoDbEvent, EventRecord};
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::{run, service_fn, Error, LambdaEvent};
+use matchmaker::ratings::RatingContext;
+use matchmaker::simuls::SimulContext;
+use matchmaker::tournaments::TournamentContext;
+use shared::Game;
+use tracing::{error, info};
+
+struct AppState {
+    ratings: RatingContext,
+    tournaments: TournamentContext,
+    simuls: SimulContext,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .with_target(false)
+        .without_time()
+        .init();
+
+    let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+    let dynamodb = DynamoClient::new(&config);
+    let websocket_api_endpoint =
+        std::env::var("WEBSOCKET_API_ENDPOINT").expect("WEBSOCKET_API_ENDPOINT must be set");
+    let api_config = aws_sdk_apigatewaymanagement::config::Builder::from(&config)
+        .endpoint_url(&websocket_api_endpoint)
+        .build();
+    let api_gateway = ApiGatewayClient::from_conf(api_config);
+    let state = AppState {
+        ratings: RatingContext::from_env(dynamodb.clone()).expect("Invalid rating configuration"),
+        tournaments: TournamentContext::from_env(dynamodb.clone(), api_gateway.clone())
+            .expect("Invalid tournament configuration"),
+        simuls: SimulContext::from_env(dynamodb, api_gateway).expect("Invalid simul configuration"),
+    };
+
+    run(service_fn(|event| handler(event, &state))).await
+}
+
+async fn handler(event: LambdaEvent<DynamoDbEvent>, state: &AppState) -> Result<(), Error> {
+    info!(
+        "Received GamesTable stream event with {} records",
+        event.payload.records.len()
+    );
+    for record in event.payload.records {
+        if let Err(e) = process_record(state, record).await {
+            error!("Failed to process record: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+async fn process_record(state: &AppState, record: EventRecord) -> Result<(), Error> {
+    if record.event_name != "INSERT" && record.event_name != "MODIFY" {
+        return Ok(());
+    }
+    if record.change.new_image.is_empty() {
+        return Ok(());
+    }
+    let game: Game = serde_dynamo::from_item(record.change.new_image.clone())?;
+    // Only the write that set the result finishes the game; later updates don't
+    let finished = game.result.is_some()
+        && (record.change.old_image.is_empty()
+            || serde_dynamo::from_item::<_, Game>(record.change.old_image)?
+                .result
+                .is_none());
+
+    if let Some(board) = &game.simul {
+        let raw: serde_json::Value = serde_dynamo::from_item(record.change.new_image)?;
+        state.simuls.forward_board(board, raw).await;
+        if finished {
+            state.simuls.record_result(&game).await?;
+        }
+    }
+    if finished && game.tournament_id.is_some() {
+        state.tournaments.record_arena_result(&game).await?;
+    }
+    if finished {
+        state.ratings.apply(&game).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Offline matchmaking simulator
+//!
+//! Replays a synthetic arrival stream through the matching strategies without touching
+//! DynamoDB, and reports wait times, rating gaps and unmatched rates per pool.
+//!
+//! Example:
+//!   cargo run --bin matchmaker-sim -- --rate 0.2 --strategy bucket_walk,global_min_gap
+
+use matchmaker::matching::MatchParams;
+use matchmaker::simulation::{self, PoolReport, SimConfig};
+use matchmaker::strategy::StrategyConfig;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: matchmaker-sim [OPTIONS]
+
+Workload:
+  --duration <SECS>          Simulated time (default 3600)
+  --rate <PER_SEC>           Mean arrivals per second (default 0.5)
+  --rating-mean <N>          Mean player rating (default 1200)
+  --rating-sd <N>            Rating standard deviation (default 250)
+  --time-controls <LIST>     Pools and weights, e.g. 3+0=0.6,1+0=0.3,10+0=0.1
+  --ranged-fraction <F>      Fraction of players setting min/max rating (default 0.1)
+  --ranged-width <N>         Width of those ranges, ± own rating (default 200)
+  --patience <SECS>          Players leave after waiting this long (default 120)
+  --seed <N>                 Random seed (default 1)
+
+Matching:
+  --strategy <LIST>          Strategies to compare, comma-separated (default bucket_walk)
+  --pool-strategies <LIST>   Per-pool overrides, e.g. 1+0=closest_rating
+  --sweep-interval <SECS>    Re-match pass interval, 0 to disable (default 10)
+  --bucket-size <N>          Rating bucket size (default 50)
+  --max-range <N>            Hard cap on the search range (default 500)
+  --initial-range <N>        Search range on joining (default 50)
+  --widened-range <N>        Search range after --widen-after seconds (default 300)
+  --widen-after <SECS>       Seconds to widen to --widened-range (default 30)
+
+  -h, --help                 Print this help
+";
+
+struct Options {
+    config: SimConfig,
+    strategies: Vec<String>,
+    pool_strategies: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    for strategy in &options.strategies {
+        let strategies =
+            match StrategyConfig::parse(Some(strategy), options.pool_strategies.as_deref()) {
+                Ok(strategies) => strategies,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    return ExitCode::from(2);
+                }
+            };
+
+        let report = match simulation::run(&options.config, &strategies) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+        print_header(&options, strategy);
+        print_pool(
+            "all pools",
+            &report.total,
+            options.config.params.bucket_size,
+        );
+        for (time_control, pool) in &report.pools {
+            print_pool(time_control, pool, options.config.params.bucket_size);
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        config: SimConfig::default(),
+        strategies: vec!["bucket_walk".to_string()],
+        pool_strategies: None,
+    };
+    let config = &mut options.config;
+    let mut args = args.peekable();
+
+    while let Some(flag) = args.next() {
+        if flag == "-h" || flag == "--help" {
+            return Ok(None);
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+
+        match flag.as_str() {
+            "--duration" => config.duration_secs = parse(&flag, &value)?,
+            "--rate" => config.arrivals_per_sec = parse(&flag, &value)?,
+            "--rating-mean" => config.rating_mean = parse(&flag, &value)?,
+            "--rating-sd" => config.rating_sd = parse(&flag, &value)?,
+            "--time-controls" => config.time_controls = parse_weights(&value)?,
+            "--ranged-fraction" => config.ranged_fraction = parse(&flag, &value)?,
+            "--ranged-width" => config.ranged_width = parse(&flag, &value)?,
+            "--patience" => config.patience_secs = parse(&flag, &value)?,
+            "--seed" => config.seed = parse(&flag, &value)?,
+            "--strategy" => {
+                options.strategies = value.split(',').map(|s| s.trim().to_string()).collect()
+            }
+            "--pool-strategies" => options.pool_strategies = Some(value),
+            "--sweep-interval" => config.sweep_interval_secs = parse(&flag, &value)?,
+            "--bucket-size" => config.params.bucket_size = parse(&flag, &value)?,
+            "--max-range" => config.params.max_range = parse(&flag, &value)?,
+            "--initial-range" => config.params.initial_range = parse(&flag, &value)?,
+            "--widened-range" => config.params.widened_range = parse(&flag, &value)?,
+            "--widen-after" => config.params.widen_after_secs = parse(&flag, &value)?,
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+
+    if config.params.bucket_size <= 0 {
+        return Err("--bucket-size must be positive".to_string());
+    }
+    Ok(Some(options))
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, flag))
+}
+
+/// Parses "3+0=0.6,1+0=0.3" into (time control, weight) pairs
+fn parse_weights(value: &str) -> Result<Vec<(String, f64)>, String> {
+    let weights = value
+        .split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| {
+            let (name, weight) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected pool=weight, got '{}'", part))?;
+            Ok((name.trim().to_string(), parse("--time-controls", weight)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if weights.is_empty() {
+        return Err("--time-controls needs at least one pool".to_string());
+    }
+    Ok(weights)
+}
+
+fn print_header(options: &Options, strategy: &str) {
+    let config = &options.config;
+    let params: &MatchParams = &config.params;
+    println!(
+        "\n=== Strategy: {} ===\n{}s simulated, {} arrivals/s, patience {}s, sweep every {}s",
+        strategy,
+        config.duration_secs,
+        config.arrivals_per_sec,
+        config.patience_secs,
+        config.sweep_interval_secs
+    );
+    println!(
+        "Buckets of {}, range ±{} widening to ±{} over {}s, capped at ±{}",
+        params.bucket_size,
+        params.initial_range,
+        params.widened_range,
+        params.widen_after_secs,
+        params.max_range
+    );
+    if let Some(pools) = &options.pool_strategies {
+        println!("Pool overrides: {}", pools);
+    }
+}
+
+fn print_pool(name: &str, report: &PoolReport, band_width: i32) {
+    let percent = |count: usize| {
+        if report.arrivals == 0 {
+            0.0
+        } else {
+            100.0 * count as f64 / report.arrivals as f64
+        }
+    };
+
+    println!("\n[{}]", name);
+    println!(
+        "  arrivals {}  matched {} ({:.1}%)  abandoned {} ({:.1}%)  still waiting {}  unmatched {:.1}%",
+        report.arrivals,
+        report.matched,
+        percent(report.matched),
+        report.abandoned,
+        percent(report.abandoned),
+        report.still_waiting,
+        100.0 * report.unmatched_rate()
+    );
+
+    if report.wait_times.is_empty() {
+        println!("  no games");
+        return;
+    }
+
+    let wait = |p: f64| report.wait_percentile(p).unwrap_or_default();
+    let gap = |p: f64| report.gap_percentile(p).unwrap_or_default();
+    println!(
+        "  wait (s)    p50 {:>4}  p90 {:>4}  p95 {:>4}  p99 {:>4}  max {:>4}",
+        wait(50.0),
+        wait(90.0),
+        wait(95.0),
+        wait(99.0),
+        wait(100.0)
+    );
+    println!(
+        "  rating gap  p50 {:>4}  p90 {:>4}  p95 {:>4}  p99 {:>4}  max {:>4}",
+        gap(50.0),
+        gap(90.0),
+        gap(95.0),
+        gap(99.0),
+        gap(100.0)
+    );
+
+    let games = report.rating_gaps.len();
+    println!("  rating gap distribution:");
+    for (band, count) in report.gap_histogram(band_width) {
+        let share = count as f64 / games as f64;
+        println!(
+            "    {:>4}-{:<4} {:>6} {:>5.1}%  {}",
+            band,
+            band + band_width - 1,
+            count,
+            100.0 * share,
+            "#".repeat((share * 40.0).round() as usize)
+        );
+    }
+}
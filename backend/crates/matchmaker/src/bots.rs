@@ -0,0 +1,124 @@
+use crate::models::QueueEntry;
+
+/// Prefix of the user ids given to built-in engine opponents
+pub const BOT_ID_PREFIX: &str = "bot:";
+/// Strength levels on offer, as ratings
+const BOT_RATINGS: [i32; 9] = [800, 1000, 1200, 1400, 1600, 1800, 2000, 2200, 2400];
+
+/// A built-in engine opponent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotProfile {
+    pub user_id: String,
+    pub rating: i32,
+}
+
+impl BotProfile {
+    /// The bot level closest to `rating`
+    pub fn for_rating(rating: i32) -> Self {
+        let rating = BOT_RATINGS
+            .iter()
+            .copied()
+            .min_by_key(|level| (level - rating).abs())
+            .unwrap_or(BOT_RATINGS[0]);
+        Self {
+            user_id: format!("{}{}", BOT_ID_PREFIX, rating),
+            rating,
+        }
+    }
+}
+
+/// Returns true if `user_id` belongs to a built-in engine opponent
+pub fn is_bot(user_id: &str) -> bool {
+    user_id.starts_with(BOT_ID_PREFIX)
+}
+
+/// When players who opted in are handed a bot instead of waiting on
+///
+/// Off unless BOT_FALLBACK_AFTER_SECS is set. Nothing plays the bots' moves on the server
+/// yet, so a bot game would stall after the player's first move; until a move loop drives
+/// `engine::BotEngine`, join_queue refuses `bot_fallback` and this stays unset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BotFallback {
+    /// None while bot opponents are switched off
+    pub after_secs: Option<u64>,
+}
+
+impl BotFallback {
+    /// Reads BOT_FALLBACK_AFTER_SECS
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(std::env::var("BOT_FALLBACK_AFTER_SECS").ok().as_deref())
+    }
+
+    pub fn parse(after_secs: Option<&str>) -> Result<Self, String> {
+        let after_secs = match after_secs.filter(|s| !s.trim().is_empty()) {
+            Some(s) => Some(
+                s.trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid BOT_FALLBACK_AFTER_SECS: {}", s))?,
+            ),
+            None => None,
+        };
+        Ok(Self { after_secs })
+    }
+
+    /// Returns true if `player` asked for a bot and has waited long enough for a human
+    pub fn is_due(&self, player: &QueueEntry, now: u64) -> bool {
+        self.after_secs
+            .is_some_and(|after_secs| player.bot_fallback && player.waited_secs(now) >= after_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(bot_fallback: bool, joined_at: u64) -> QueueEntry {
+        QueueEntry {
+            user_id: "alice".to_string(),
+            joined_at: joined_at.to_string(),
+            bot_fallback,
+            ..QueueEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_bot_level_is_nearest_rating() {
+        assert_eq!(BotProfile::for_rating(1290).rating, 1200);
+        assert_eq!(BotProfile::for_rating(1310).rating, 1400);
+        assert_eq!(BotProfile::for_rating(100).rating, 800);
+        assert_eq!(BotProfile::for_rating(3000).rating, 2400);
+        assert_eq!(BotProfile::for_rating(1500).user_id, "bot:1400");
+    }
+
+    #[test]
+    fn test_is_bot() {
+        assert!(is_bot("bot:1200"));
+        assert!(!is_bot("alice"));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(BotFallback::parse(None).unwrap().after_secs, None);
+        assert_eq!(
+            BotFallback::parse(Some(" 30 ")).unwrap().after_secs,
+            Some(30)
+        );
+        assert!(BotFallback::parse(Some("soon")).is_err());
+    }
+
+    #[test]
+    fn test_fallback_needs_opt_in_and_wait() {
+        let fallback = BotFallback {
+            after_secs: Some(60),
+        };
+        assert!(fallback.is_due(&player(true, 1_000), 1_060));
+        assert!(!fallback.is_due(&player(true, 1_000), 1_059));
+        assert!(!fallback.is_due(&player(false, 1_000), 2_000));
+    }
+
+    #[test]
+    fn test_fallback_is_off_by_default() {
+        let fallback = BotFallback::parse(None).unwrap();
+        assert!(!fallback.is_due(&player(true, 1_000), 1_000_000));
+    }
+}
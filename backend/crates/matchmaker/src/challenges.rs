@@ -0,0 +1,666 @@
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use shared::fen::validate_fen;
+use shared::odds::Odds;
+use shared::variant::Variant;
+use shared::{ColorPreference, Game, User};
+use tracing::{info, warn};
+
+use crate::errors::ServiceError;
+use crate::game::{create_game, GameSetup};
+use crate::history::PairingHistory;
+use crate::matching::unix_now;
+use crate::notifications::{notify_player, send_to_user};
+use crate::terms::{check_custom_time_control, GameTerms};
+
+/// How long a challenge to a specific user stays open
+pub const DIRECT_CHALLENGE_TTL_SECS: u64 = 5 * 60;
+/// How long an open challenge link stays valid
+pub const OPEN_CHALLENGE_TTL_SECS: u64 = 60 * 60;
+
+/// A game offer from one player, either to a specific user or to anyone with the link
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Challenge {
+    pub challenge_id: String,
+    pub challenger_id: String,
+    /// Challenged user; None for an open challenge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<String>,
+    pub time_control: String,
+    pub rated: bool,
+    /// Colour the challenger asked for
+    #[serde(default)]
+    pub color: ColorPreference,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_fen: Option<String>,
+    /// Odds the challenger gives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub odds: Option<Odds>,
+    #[serde(default)]
+    pub variant: Variant,
+    pub status: ChallengeStatus,
+    pub created_at: u64,
+    /// DynamoDB TTL attribute; the challenge can't be accepted from this time on
+    pub expires_at: u64,
+    /// Set once accepted; the game reuses the challenge id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengeStatus {
+    Open,
+    Accepted,
+}
+
+/// Body of a create-challenge request, over REST or websocket
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChallengeRequest {
+    /// User to challenge; leave out to create an open challenge link
+    #[serde(default)]
+    pub target_id: Option<String>,
+    /// Any time control, not just the catalogue's; Chess960 games start from a generated
+    /// position unless initial_fen is set
+    #[serde(flatten)]
+    pub terms: GameTerms,
+    #[serde(default)]
+    pub initial_fen: Option<String>,
+    /// Material and/or time odds the challenger gives; odds games are unrated
+    #[serde(default)]
+    pub odds: Option<Odds>,
+}
+
+/// A challenge together with the link used to share it
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChallengeView {
+    #[serde(flatten)]
+    pub challenge: Challenge,
+    pub url: String,
+}
+
+/// Websocket message carrying a challenge ("challenge_created" or "challenge_received")
+#[derive(Debug, Serialize)]
+pub struct ChallengeMessage {
+    pub action: String,
+    pub challenge: ChallengeView,
+}
+
+/// Websocket message telling the other party a challenge was withdrawn or declined
+#[derive(Debug, Serialize)]
+pub struct ChallengeCancelledMessage {
+    pub action: String, // "challenge_cancelled"
+    pub challenge_id: String,
+}
+
+impl Challenge {
+    /// Builds a new open challenge from `request`, validating it
+    pub fn new(
+        challenge_id: String,
+        challenger_id: &str,
+        request: ChallengeRequest,
+        now: u64,
+    ) -> Result<Self, ServiceError> {
+        check_custom_time_control(&request.terms.time_control)?;
+        if request.target_id.as_deref() == Some(challenger_id) {
+            return Err(ServiceError::Invalid(
+                "You cannot challenge yourself".to_string(),
+            ));
+        }
+        if let Some(fen) = &request.initial_fen {
+            validate_fen(fen).map_err(ServiceError::Invalid)?;
+            // Ratings only mean something for games from the standard position
+            if request.terms.rated {
+                return Err(ServiceError::Invalid(
+                    "Games from a custom position cannot be rated".to_string(),
+                ));
+            }
+        }
+        if let Some(odds) = &request.odds {
+            odds.validate().map_err(ServiceError::Invalid)?;
+            let standard_start =
+                request.initial_fen.is_none() && request.terms.variant == Variant::Standard;
+            if odds.material.is_some() && !standard_start {
+                return Err(ServiceError::Invalid(
+                    "Material odds are given from the standard position".to_string(),
+                ));
+            }
+            if request.terms.rated {
+                return Err(ServiceError::Invalid(
+                    "Odds games cannot be rated".to_string(),
+                ));
+            }
+        }
+
+        let ttl = if request.target_id.is_some() {
+            DIRECT_CHALLENGE_TTL_SECS
+        } else {
+            OPEN_CHALLENGE_TTL_SECS
+        };
+
+        Ok(Self {
+            challenge_id,
+            challenger_id: challenger_id.to_string(),
+            target_id: request.target_id,
+            time_control: request.terms.time_control,
+            rated: request.terms.rated,
+            color: request.terms.color,
+            initial_fen: request.initial_fen,
+            odds: request.odds,
+            variant: request.terms.variant,
+            status: ChallengeStatus::Open,
+            created_at: now,
+            expires_at: now + ttl,
+            game_id: None,
+        })
+    }
+
+    pub fn is_open(&self, now: u64) -> bool {
+        self.status == ChallengeStatus::Open && self.expires_at > now
+    }
+
+    /// Checks that `user_id` may accept this challenge
+    pub fn check_can_accept(&self, user_id: &str, now: u64) -> Result<(), ServiceError> {
+        if self.challenger_id == user_id {
+            return Err(ServiceError::Forbidden(
+                "You cannot accept your own challenge",
+            ));
+        }
+        if self
+            .target_id
+            .as_deref()
+            .is_some_and(|target_id| target_id != user_id)
+        {
+            return Err(ServiceError::Forbidden(
+                "This challenge is for someone else",
+            ));
+        }
+        if !self.is_open(now) {
+            return Err(ServiceError::unavailable("Challenge"));
+        }
+        Ok(())
+    }
+
+    /// The other party to notify when `user_id` cancels or declines, if they may
+    pub fn counterparty(&self, user_id: &str) -> Result<Option<&str>, ServiceError> {
+        if self.challenger_id == user_id {
+            Ok(self.target_id.as_deref())
+        } else if self.target_id.as_deref() == Some(user_id) {
+            Ok(Some(&self.challenger_id))
+        } else {
+            Err(ServiceError::Forbidden(
+                "Only the challenger or the challenged user can cancel a challenge",
+            ))
+        }
+    }
+}
+
+/// Clients and tables needed to create, accept and announce challenges
+#[derive(Clone)]
+pub struct ChallengeContext {
+    pub dynamodb: DynamoClient,
+    pub api_gateway: ApiGatewayClient,
+    pub users_table: String,
+    pub challenges_table: String,
+    /// Rematch offers, keyed by the finished game's id
+    pub rematches_table: String,
+    pub games_table: String,
+    pub connections_table: String,
+    /// Private game invites, keyed by code
+    pub invites_table: String,
+    pub history: PairingHistory,
+    /// Prefix of shareable challenge links, e.g. "https://example.com"
+    pub url_base: String,
+}
+
+impl ChallengeContext {
+    /// Reads USERS_TABLE, CHALLENGES_TABLE, REMATCHES_TABLE, GAMES_TABLE,
+    /// CONNECTIONS_TABLE, INVITES_TABLE, CHALLENGE_URL_BASE and the pairing history settings
+    pub fn from_env(dynamodb: DynamoClient, api_gateway: ApiGatewayClient) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{} must be set", name));
+        Ok(Self {
+            dynamodb,
+            api_gateway,
+            users_table: var("USERS_TABLE")?,
+            challenges_table: var("CHALLENGES_TABLE")?,
+            rematches_table: var("REMATCHES_TABLE")?,
+            games_table: var("GAMES_TABLE")?,
+            connections_table: var("CONNECTIONS_TABLE")?,
+            invites_table: var("INVITES_TABLE")?,
+            history: PairingHistory::from_env()?,
+            url_base: std::env::var("CHALLENGE_URL_BASE").unwrap_or_default(),
+        })
+    }
+
+    pub fn view(&self, challenge: Challenge) -> ChallengeView {
+        let url = format!(
+            "{}/challenges/{}",
+            self.url_base.trim_end_matches('/'),
+            challenge.challenge_id
+        );
+        ChallengeView { challenge, url }
+    }
+
+    /// Stores a new challenge from `challenger_id` and tells the challenged user about it
+    pub async fn create(
+        &self,
+        challenger_id: &str,
+        request: ChallengeRequest,
+    ) -> Result<ChallengeView, ServiceError> {
+        let challenge_id = uuid::Uuid::new_v4().simple().to_string();
+        let now = unix_now()?;
+        let challenge = Challenge::new(challenge_id, challenger_id, request, now)?;
+
+        if let Some(target_id) = &challenge.target_id {
+            self.check_not_blocked(challenger_id, target_id).await?;
+        }
+
+        info!(
+            "Creating challenge {} from {} to {:?} ({}, rated: {})",
+            challenge.challenge_id,
+            challenger_id,
+            challenge.target_id,
+            challenge.time_control,
+            challenge.rated
+        );
+        self.dynamodb
+            .put_item()
+            .table_name(&self.challenges_table)
+            .set_item(Some(serde_dynamo::to_item(&challenge)?))
+            .condition_expression("attribute_not_exists(challenge_id)")
+            .send()
+            .await?;
+
+        let view = self.view(challenge);
+        if let Some(target_id) = &view.challenge.target_id {
+            let message = ChallengeMessage {
+                action: "challenge_received".to_string(),
+                challenge: view.clone(),
+            };
+            send_to_user(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                target_id,
+                &message,
+            )
+            .await;
+        }
+        Ok(view)
+    }
+
+    /// Loads a challenge, treating expired ones as gone
+    pub async fn get(&self, challenge_id: &str) -> Result<Challenge, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.challenges_table)
+            .key("challenge_id", AttributeValue::S(challenge_id.to_string()))
+            .send()
+            .await?;
+
+        let challenge: Challenge = match response.item {
+            Some(item) => serde_dynamo::from_item(item)?,
+            None => return Err(ServiceError::NotFound("Challenge")),
+        };
+        if challenge.status == ChallengeStatus::Open && challenge.expires_at <= unix_now()? {
+            return Err(ServiceError::NotFound("Challenge"));
+        }
+        Ok(challenge)
+    }
+
+    /// Accepts a challenge for `user_id` and creates the game
+    ///
+    /// The challenge is marked accepted in the same transaction that creates the game, so
+    /// two players racing for an open challenge can't both get a game.
+    pub async fn accept(&self, challenge_id: &str, user_id: &str) -> Result<Game, ServiceError> {
+        let now = unix_now()?;
+        let challenge = self.get(challenge_id).await?;
+        challenge.check_can_accept(user_id, now)?;
+        // Direct challenges were checked when created, but either side may have blocked
+        // the other since
+        self.check_not_blocked(&challenge.challenger_id, user_id)
+            .await?;
+
+        let setup = GameSetup {
+            game_id: Some(challenge.challenge_id.clone()),
+            player1_id: challenge.challenger_id.clone(),
+            player2_id: user_id.to_string(),
+            time_control: challenge.time_control.clone(),
+            rated: challenge.rated,
+            initial_fen: challenge.initial_fen.clone(),
+            tournament_id: None,
+            simul: None,
+            invite: None,
+            odds: challenge.odds,
+            variant: challenge.variant,
+            preference: challenge.color,
+        };
+        let claim = self.build_accept_item(&challenge, user_id, now)?;
+        let game = create_game(
+            &self.dynamodb,
+            &self.games_table,
+            &self.history,
+            &setup,
+            vec![claim],
+        )
+        .await?
+        .ok_or_else(|| ServiceError::unavailable("Challenge"))?;
+
+        info!(
+            "Challenge {} accepted by {}, created game {}",
+            challenge_id, user_id, game.game_id
+        );
+        for player_id in [&game.white_player_id, &game.black_player_id] {
+            notify_player(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                player_id,
+                &game,
+            )
+            .await;
+        }
+        Ok(game)
+    }
+
+    /// Withdraws (challenger) or declines (challenged user) an open challenge
+    pub async fn cancel(&self, challenge_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        let challenge = self.get(challenge_id).await?;
+        let counterparty = challenge.counterparty(user_id)?;
+
+        let result = self
+            .dynamodb
+            .delete_item()
+            .table_name(&self.challenges_table)
+            .key("challenge_id", AttributeValue::S(challenge_id.to_string()))
+            .condition_expression("#status = :open")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                return Err(ServiceError::unavailable("Challenge"));
+            }
+            return Err(e.into());
+        }
+
+        info!("Challenge {} cancelled by {}", challenge_id, user_id);
+        if let Some(counterparty) = counterparty {
+            let message = ChallengeCancelledMessage {
+                action: "challenge_cancelled".to_string(),
+                challenge_id: challenge_id.to_string(),
+            };
+            send_to_user(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                counterparty,
+                &message,
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Fails if either user has blocked the other
+    pub async fn check_not_blocked(&self, user_a: &str, user_b: &str) -> Result<(), ServiceError> {
+        let a = self.load_user(user_a).await?;
+        let b = self.load_user(user_b).await?;
+        let blocked = |user: &Option<User>, other: &str| {
+            user.as_ref()
+                .is_some_and(|u| u.blocked_user_ids.iter().any(|id| id == other))
+        };
+        if blocked(&a, user_b) || blocked(&b, user_a) {
+            warn!("Challenge between {} and {} blocked", user_a, user_b);
+            return Err(ServiceError::Forbidden("You cannot challenge this user"));
+        }
+        Ok(())
+    }
+
+    async fn load_user(&self, user_id: &str) -> Result<Option<User>, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.users_table)
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Builds the TransactWriteItem marking the challenge accepted, if it's still open
+    fn build_accept_item(
+        &self,
+        challenge: &Challenge,
+        user_id: &str,
+        now: u64,
+    ) -> Result<TransactWriteItem, Error> {
+        let update = Update::builder()
+            .table_name(&self.challenges_table)
+            .key(
+                "challenge_id",
+                AttributeValue::S(challenge.challenge_id.clone()),
+            )
+            .update_expression("SET #status = :accepted, game_id = :game_id, accepted_by = :uid")
+            .condition_expression("#status = :open AND expires_at > :now")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":accepted", AttributeValue::S("accepted".to_string()))
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(
+                ":game_id",
+                AttributeValue::S(challenge.challenge_id.clone()),
+            )
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .build()
+            .map_err(|e| format!("Failed to build update: {:?}", e))?;
+
+        Ok(TransactWriteItem::builder().update(update).build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::odds::{MaterialOdds, TimeOdds};
+
+    fn request(target_id: Option<&str>) -> ChallengeRequest {
+        ChallengeRequest {
+            target_id: target_id.map(str::to_string),
+            terms: GameTerms::casual("5+3"),
+            initial_fen: None,
+            odds: None,
+        }
+    }
+
+    fn challenge(target_id: Option<&str>) -> Challenge {
+        Challenge::new("c1".to_string(), "alice", request(target_id), 1_000).unwrap()
+    }
+
+    #[test]
+    fn test_direct_and_open_challenges_expire_differently() {
+        assert_eq!(
+            challenge(Some("bob")).expires_at,
+            1_000 + DIRECT_CHALLENGE_TTL_SECS
+        );
+        assert_eq!(challenge(None).expires_at, 1_000 + OPEN_CHALLENGE_TTL_SECS);
+    }
+
+    #[test]
+    fn test_invalid_requests_are_rejected() {
+        let self_challenge = Challenge::new("c".to_string(), "alice", request(Some("alice")), 0);
+        assert!(matches!(self_challenge, Err(ServiceError::Invalid(_))));
+
+        let bad_fen = ChallengeRequest {
+            initial_fen: Some("not a fen".to_string()),
+            ..request(None)
+        };
+        assert!(Challenge::new("c".to_string(), "alice", bad_fen, 0).is_err());
+
+        let mut rated_custom = ChallengeRequest {
+            initial_fen: Some(shared::fen::STARTING_FEN.to_string()),
+            ..request(None)
+        };
+        rated_custom.terms.rated = true;
+        assert!(Challenge::new("c".to_string(), "alice", rated_custom, 0).is_err());
+    }
+
+    #[test]
+    fn test_custom_time_controls_are_parsed() {
+        let with_time_control = |time_control: &str| ChallengeRequest {
+            terms: GameTerms::casual(time_control),
+            ..request(None)
+        };
+        let custom = Challenge::new("c".to_string(), "alice", with_time_control("7+4"), 0);
+        assert_eq!(custom.unwrap().time_control, "7+4");
+        for time_control in ["", "blitz", "abc+", "0+0", "5+600"] {
+            let result =
+                Challenge::new("c".to_string(), "alice", with_time_control(time_control), 0);
+            assert!(
+                matches!(result, Err(ServiceError::Invalid(_))),
+                "{:?}",
+                time_control
+            );
+        }
+    }
+
+    #[test]
+    fn test_chess960_challenge_keeps_its_variant() {
+        let mut request = request(Some("bob"));
+        request.terms.variant = Variant::Chess960;
+        let challenge = Challenge::new("c".to_string(), "alice", request, 0).unwrap();
+        assert_eq!(challenge.variant, Variant::Chess960);
+    }
+
+    #[test]
+    fn test_odds_challenges() {
+        let queen_odds = Odds {
+            material: Some(MaterialOdds::Queen),
+            time: None,
+        };
+        let odds = ChallengeRequest {
+            odds: Some(queen_odds),
+            ..request(Some("bob"))
+        };
+        let challenge = Challenge::new("c".to_string(), "alice", odds.clone(), 0).unwrap();
+        assert_eq!(challenge.odds, Some(queen_odds));
+
+        let mut rated = odds.clone();
+        rated.terms.rated = true;
+        assert!(matches!(
+            Challenge::new("c".to_string(), "alice", rated, 0),
+            Err(ServiceError::Invalid(_))
+        ));
+
+        let mut chess960 = odds.clone();
+        chess960.terms.variant = Variant::Chess960;
+        assert!(Challenge::new("c".to_string(), "alice", chess960, 0).is_err());
+
+        let custom_position = ChallengeRequest {
+            initial_fen: Some(shared::fen::STARTING_FEN.to_string()),
+            ..odds
+        };
+        assert!(Challenge::new("c".to_string(), "alice", custom_position, 0).is_err());
+
+        // A custom position is fine with time odds
+        let time_odds = ChallengeRequest {
+            initial_fen: Some(shared::fen::STARTING_FEN.to_string()),
+            odds: Some(Odds {
+                material: None,
+                time: Some(TimeOdds {
+                    giver_secs: 120,
+                    receiver_secs: 300,
+                }),
+            }),
+            ..request(None)
+        };
+        assert!(Challenge::new("c".to_string(), "alice", time_odds, 0).is_ok());
+
+        let nothing = ChallengeRequest {
+            odds: Some(Odds::default()),
+            ..request(None)
+        };
+        assert!(Challenge::new("c".to_string(), "alice", nothing, 0).is_err());
+    }
+
+    #[test]
+    fn test_only_the_target_can_accept_a_direct_challenge() {
+        let direct = challenge(Some("bob"));
+        assert!(direct.check_can_accept("bob", 1_001).is_ok());
+        assert!(matches!(
+            direct.check_can_accept("carol", 1_001),
+            Err(ServiceError::Forbidden(_))
+        ));
+        assert!(matches!(
+            direct.check_can_accept("alice", 1_001),
+            Err(ServiceError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_anyone_but_the_challenger_can_accept_an_open_challenge() {
+        let open = challenge(None);
+        assert!(open.check_can_accept("carol", 1_001).is_ok());
+        assert!(open.check_can_accept("alice", 1_001).is_err());
+    }
+
+    #[test]
+    fn test_expired_or_accepted_challenges_cannot_be_accepted() {
+        let open = challenge(None);
+        assert!(matches!(
+            open.check_can_accept("bob", open.expires_at),
+            Err(ServiceError::Conflict(_))
+        ));
+
+        let accepted = Challenge {
+            status: ChallengeStatus::Accepted,
+            ..challenge(None)
+        };
+        assert!(matches!(
+            accepted.check_can_accept("bob", 1_001),
+            Err(ServiceError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_counterparty() {
+        let direct = challenge(Some("bob"));
+        assert_eq!(direct.counterparty("alice").unwrap(), Some("bob"));
+        assert_eq!(direct.counterparty("bob").unwrap(), Some("alice"));
+        assert!(direct.counterparty("carol").is_err());
+        assert_eq!(challenge(None).counterparty("alice").unwrap(), None);
+    }
+
+    #[test]
+    fn test_request_defaults() {
+        let request: ChallengeRequest =
+            serde_json::from_value(serde_json::json!({ "time_control": "rapid" })).unwrap();
+        assert_eq!(request.target_id, None);
+        assert_eq!(request.terms, GameTerms::casual("rapid"));
+        assert_eq!(request.initial_fen, None);
+        assert_eq!(request.odds, None);
+    }
+
+    #[test]
+    fn test_view_serializes_flat_with_url() {
+        let view = ChallengeView {
+            challenge: challenge(None),
+            url: "https://example.com/challenges/c1".to_string(),
+        };
+        let json = serde_json::to_value(&view).unwrap();
+        assert_eq!(json["challenge_id"], "c1");
+        assert_eq!(json["status"], "open");
+        assert_eq!(json["url"], "https://example.com/challenges/c1");
+        assert!(json.get("target_id").is_none());
+    }
+}
@@ -0,0 +1,128 @@
+use rand::{Rng, RngCore};
+use shared::{Color, ColorPreference};
+use std::cmp::Ordering;
+
+/// Number of recent games considered when balancing colours
+pub const COLOR_HISTORY_GAMES: usize = 10;
+
+/// How strongly a player is owed White, from their recent colours (most recent first)
+///
+/// Compares first on how many more Blacks than Whites they had, then on the length of
+/// their current run of Blacks (a run of Whites counts negatively).
+fn white_claim(history: &[Color]) -> (i32, i32) {
+    let recent = &history[..history.len().min(COLOR_HISTORY_GAMES)];
+    let balance = recent
+        .iter()
+        .map(|color| match color {
+            Color::White => -1,
+            Color::Black => 1,
+        })
+        .sum();
+
+    let streak = match recent.first() {
+        Some(&last) => {
+            let length = recent.iter().take_while(|&&c| c == last).count() as i32;
+            if last == Color::Black {
+                length
+            } else {
+                -length
+            }
+        }
+        None => 0,
+    };
+
+    (balance, streak)
+}
+
+/// Picks player1's colour
+///
+/// An explicit preference wins. Otherwise the player with the stronger claim to White
+/// (see `white_claim`) gets it, and ties are broken at random.
+pub fn assign_colors(
+    preference: ColorPreference,
+    player1_history: &[Color],
+    player2_history: &[Color],
+    rng: &mut dyn RngCore,
+) -> Color {
+    if let Some(color) = preference.fixed() {
+        return color;
+    }
+
+    match white_claim(player1_history).cmp(&white_claim(player2_history)) {
+        Ordering::Greater => Color::White,
+        Ordering::Less => Color::Black,
+        Ordering::Equal if rng.gen_bool(0.5) => Color::White,
+        Ordering::Equal => Color::Black,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use Color::{Black, White};
+
+    fn assign(p1: &[Color], p2: &[Color]) -> Color {
+        assign_colors(ColorPreference::Auto, p1, p2, &mut StdRng::seed_from_u64(7))
+    }
+
+    #[test]
+    fn test_player_with_more_blacks_gets_white() {
+        assert_eq!(
+            assign(&[Black, Black, White], &[White, Black, White]),
+            White
+        );
+        assert_eq!(
+            assign(&[White, Black, White], &[Black, Black, White]),
+            Black
+        );
+    }
+
+    #[test]
+    fn test_streak_breaks_balance_ties() {
+        // Both balanced, but player2 has had Black twice in a row
+        assert_eq!(
+            assign(&[White, Black], &[Black, Black, White, White]),
+            Black
+        );
+    }
+
+    #[test]
+    fn test_five_blacks_in_a_row_gets_white() {
+        assert_eq!(assign(&[Black; 5], &[]), White);
+        assert_eq!(assign(&[], &[Black; 5]), Black);
+    }
+
+    #[test]
+    fn test_only_recent_games_count() {
+        let mut history = vec![White, Black];
+        history.extend([Black; 20]);
+        // Beyond COLOR_HISTORY_GAMES the long run of Blacks is ignored
+        let claim = white_claim(&history);
+        assert_eq!(claim.0, COLOR_HISTORY_GAMES as i32 - 2);
+        assert_eq!(claim.1, -1);
+    }
+
+    #[test]
+    fn test_ties_are_random() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let whites = (0..200)
+            .filter(|_| assign_colors(ColorPreference::Auto, &[], &[], &mut rng) == White)
+            .count();
+        assert!((60..140).contains(&whites), "{} whites", whites);
+    }
+
+    #[test]
+    fn test_explicit_preference_overrides_history() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(
+            assign_colors(ColorPreference::Black, &[Black; 5], &[], &mut rng),
+            Black
+        );
+        assert_eq!(
+            assign_colors(ColorPreference::White, &[], &[Black; 5], &mut rng),
+            White
+        );
+    }
+}
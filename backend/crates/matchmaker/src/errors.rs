@@ -0,0 +1,70 @@
+use aws_sdk_dynamodb::error::SdkError;
+use lambda_runtime::Error;
+use std::fmt;
+use tournaments::PairingError;
+
+/// Why a challenge, seek, invite, simul or tournament action was refused
+#[derive(Debug)]
+pub enum ServiceError {
+    /// The request itself is malformed
+    Invalid(String),
+    /// No such record, or it has expired; names what was looked up
+    NotFound(&'static str),
+    /// The user isn't allowed to do this
+    Forbidden(&'static str),
+    /// The record isn't in a state that allows this, e.g. it was accepted in the meantime
+    Conflict(String),
+    /// The user has made too many failed attempts and has to wait
+    Throttled(&'static str),
+    Internal(Error),
+}
+
+impl ServiceError {
+    /// `what` was accepted, withdrawn or started in the meantime
+    pub fn unavailable(what: &str) -> Self {
+        ServiceError::Conflict(format!("{} is no longer available", what))
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::Invalid(message) => write!(f, "{}", message),
+            ServiceError::NotFound(what) => write!(f, "{} not found", what),
+            ServiceError::Forbidden(message) => write!(f, "{}", message),
+            ServiceError::Conflict(message) => write!(f, "{}", message),
+            ServiceError::Throttled(message) => write!(f, "{}", message),
+            ServiceError::Internal(e) => write!(f, "Internal error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+impl From<Error> for ServiceError {
+    fn from(e: Error) -> Self {
+        ServiceError::Internal(e)
+    }
+}
+
+impl From<serde_dynamo::Error> for ServiceError {
+    fn from(e: serde_dynamo::Error) -> Self {
+        ServiceError::Internal(e.into())
+    }
+}
+
+impl From<PairingError> for ServiceError {
+    fn from(e: PairingError) -> Self {
+        ServiceError::Conflict(e.to_string())
+    }
+}
+
+impl<E, R> From<SdkError<E, R>> for ServiceError
+where
+    E: std::error::Error + Send + Sync + 'static,
+    R: fmt::Debug + Send + Sync + 'static,
+{
+    fn from(e: SdkError<E, R>) -> Self {
+        ServiceError::Internal(e.into())
+    }
+}
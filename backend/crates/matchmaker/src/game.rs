@@ -1,11 +1,16 @@
 use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
-use aws_sdk_dynamodb::types::{AttributeValue, Put, TransactWriteItem, Update};
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, TransactWriteItem};
 use lambda_runtime::Error;
-use serde_dynamo;
-use shared::{Game, GameStatus};
+use rand::Rng;
+use shared::odds::Odds;
+use shared::variant::{Variant, CHESS960_POSITIONS};
+use shared::{Color, ColorPreference, Game, GameInvite, GameOdds, GameStatus, SimulBoard};
 use std::collections::HashMap;
 use tracing::{info, warn};
 
+use crate::bots::BotProfile;
+use crate::colors::assign_colors;
+use crate::history::PairingHistory;
 use crate::models::QueueEntry;
 
 /// Creates a deterministic game ID based on player IDs and timestamp
@@ -14,7 +19,7 @@ fn create_deterministic_game_id(player1_id: &str, player2_id: &str, timestamp: &
     use sha2::{Digest, Sha256};
 
     // Sort player IDs to ensure consistency regardless of order
-    let mut players = vec![player1_id, player2_id];
+    let mut players = [player1_id, player2_id];
     players.sort();
 
     let input = format!("{}#{}#{}", players[0], players[1], timestamp);
@@ -22,59 +27,230 @@ fn create_deterministic_game_id(player1_id: &str, player2_id: &str, timestamp: &
     hex::encode(&hash[0..16]) // Use first 16 bytes (32 hex characters)
 }
 
+/// Everything needed to create a game between two players
+#[derive(Debug, Clone)]
+pub struct GameSetup {
+    /// Fixed game id; a deterministic one is derived from the players if None
+    pub game_id: Option<String>,
+    pub player1_id: String,
+    pub player2_id: String,
+    pub time_control: String,
+    pub rated: bool,
+    pub initial_fen: Option<String>,
+    /// Tournament the game is paired in, if any
+    pub tournament_id: Option<String>,
+    /// Simul the game is a board of, if any
+    pub simul: Option<SimulBoard>,
+    /// Invite the game was started from, if any
+    pub invite: Option<GameInvite>,
+    /// Odds player1 gives player2, if any
+    pub odds: Option<Odds>,
+    /// Variants other than standard get a fresh starting position unless initial_fen is set
+    pub variant: Variant,
+    /// Player1's colour preference
+    pub preference: ColorPreference,
+}
+
 /// Attempts to match two players atomically using DynamoDB transactions
 ///
 /// Transaction includes:
-/// 1. Update player1: set status="matched", matched_at=now (with condition: status="waiting")
-/// 2. Update player2: set status="matched", matched_at=now (with condition: status="waiting")
+/// 1. Delete player1's queue entry (with condition: status="waiting")
+/// 2. Delete player2's queue entry (with condition: status="waiting")
 /// 3. Create game record
+/// 4. Record the pairing in both players' pairing history
+/// 5. Delete both players' linked entries in other time-control pools
+///
+/// Deleting the entries rather than marking them matched frees the players to join the
+/// queue again as soon as the game exists. Linked entries are deleted unconditionally:
+/// they may already be gone (left or expired), and a player matched concurrently through
+/// one of them fails this transaction on the conditional delete of that entry instead.
+///
+/// Colours follow `preference` for player1 if it names one; otherwise they are balanced
+/// from both players' recent games.
 ///
 /// Returns Ok(Game) if successful, Err if transaction fails (e.g., opponent already matched)
 pub async fn attempt_match(
     dynamodb: &aws_sdk_dynamodb::Client,
     queue_table: &str,
     games_table: &str,
+    history: &PairingHistory,
     player1: &QueueEntry,
     player2: &QueueEntry,
+    preference: ColorPreference,
 ) -> Result<Game, Error> {
-    let now = std::time::SystemTime::now()
+    let mut queue_items = build_dequeue_items(queue_table, player1)?;
+    queue_items.extend(build_dequeue_items(queue_table, player2)?);
+
+    // Both entries are in the same pool, so player1's tournament settings apply to both
+    let setup = match &player1.tournament {
+        Some(tournament) => GameSetup {
+            game_id: None,
+            player1_id: player1.user_id.clone(),
+            player2_id: player2.user_id.clone(),
+            time_control: tournament.time_control.clone(),
+            rated: tournament.rated,
+            initial_fen: None,
+            tournament_id: Some(tournament.tournament_id.clone()),
+            simul: None,
+            invite: None,
+            odds: None,
+            variant: player1.variant,
+            preference,
+        },
+        None => GameSetup {
+            game_id: None,
+            player1_id: player1.user_id.clone(),
+            player2_id: player2.user_id.clone(),
+            time_control: player1.time_control.clone(),
+            rated: player1.rated,
+            initial_fen: None,
+            tournament_id: None,
+            simul: None,
+            invite: None,
+            odds: None,
+            variant: player1.variant,
+            preference,
+        },
+    };
+
+    match create_game(dynamodb, games_table, history, &setup, queue_items).await? {
+        Some(game) => Ok(game),
+        None => {
+            warn!(
+                "Transaction cancelled - player already matched: {} or {}",
+                player1.user_id, player2.user_id
+            );
+            Err("Player already matched".into())
+        }
+    }
+}
+
+/// Pairs a player who has waited too long with a built-in engine opponent
+///
+/// Works like `attempt_match` with the bot in place of the second queue entry. Bot games
+/// are unrated, so they never move a player's rating.
+pub async fn attempt_bot_match(
+    dynamodb: &aws_sdk_dynamodb::Client,
+    queue_table: &str,
+    games_table: &str,
+    history: &PairingHistory,
+    player: &QueueEntry,
+    bot: &BotProfile,
+) -> Result<Game, Error> {
+    let setup = GameSetup {
+        game_id: None,
+        player1_id: player.user_id.clone(),
+        player2_id: bot.user_id.clone(),
+        time_control: player.time_control.clone(),
+        rated: false,
+        initial_fen: None,
+        tournament_id: None,
+        simul: None,
+        invite: None,
+        odds: None,
+        variant: player.variant,
+        preference: ColorPreference::Auto,
+    };
+    let queue_items = build_dequeue_items(queue_table, player)?;
+
+    match create_game(dynamodb, games_table, history, &setup, queue_items).await? {
+        Some(game) => Ok(game),
+        None => {
+            warn!(
+                "Transaction cancelled - player {} already matched",
+                player.user_id
+            );
+            Err("Player already matched".into())
+        }
+    }
+}
+
+/// Creates a game and records the pairing in one transaction together with `claim_items`
+///
+/// `claim_items` are the writes that take the players out of wherever they were waiting,
+/// such as deleting their queue entries or accepting a challenge; their conditions decide
+/// whether the game may be created. Returns Ok(None) if the transaction was cancelled
+/// because one of those conditions failed.
+pub async fn create_game(
+    dynamodb: &aws_sdk_dynamodb::Client,
+    games_table: &str,
+    history: &PairingHistory,
+    setup: &GameSetup,
+    claim_items: Vec<TransactWriteItem>,
+) -> Result<Option<Game>, Error> {
+    let now_secs = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs()
-        .to_string();
+        .as_secs();
+    let now = now_secs.to_string();
 
-    // Create deterministic game ID
-    let game_id = create_deterministic_game_id(&player1.user_id, &player2.user_id, &now);
+    let game_id = match &setup.game_id {
+        Some(game_id) => game_id.clone(),
+        // Create deterministic game ID
+        None => create_deterministic_game_id(&setup.player1_id, &setup.player2_id, &now),
+    };
 
     info!(
-        "Attempting to match {} and {} (game_id: {})",
-        player1.user_id, player2.user_id, game_id
+        "Attempting to create game {} between {} and {}",
+        game_id, setup.player1_id, setup.player2_id
     );
 
-    // Randomly assign colors
-    let (white_player_id, black_player_id) = if rand::random::<bool>() {
-        (player1.user_id.clone(), player2.user_id.clone())
+    let player1_color = match setup.preference.fixed() {
+        Some(color) => color,
+        None => {
+            let player1_history = history.recent_colors(dynamodb, &setup.player1_id).await?;
+            let player2_history = history.recent_colors(dynamodb, &setup.player2_id).await?;
+            assign_colors(
+                setup.preference,
+                &player1_history,
+                &player2_history,
+                &mut rand::thread_rng(),
+            )
+        }
+    };
+
+    let (white_player_id, black_player_id) = if player1_color == Color::White {
+        (setup.player1_id.clone(), setup.player2_id.clone())
     } else {
-        (player2.user_id.clone(), player1.user_id.clone())
+        (setup.player2_id.clone(), setup.player1_id.clone())
     };
 
     let game = Game {
         game_id: game_id.clone(),
-        white_player_id: white_player_id.clone(),
-        black_player_id: black_player_id.clone(),
-        time_control: player1.time_control.clone(),
+        white_player_id,
+        black_player_id,
+        time_control: setup.time_control.clone(),
         status: GameStatus::Active,
-        created_at: now.clone(),
+        created_at: now,
+        variant: setup.variant,
+        rated: setup.rated,
+        // Material odds depend on which colour the giver got
+        initial_fen: setup
+            .odds
+            .and_then(|odds| odds.starting_fen(player1_color))
+            .or_else(|| setup.initial_fen.clone())
+            .or_else(|| {
+                let position = rand::thread_rng().gen_range(0..CHESS960_POSITIONS);
+                setup.variant.starting_fen(position)
+            }),
+        result: None,
+        tournament_id: setup.tournament_id.clone(),
+        white_berserk: false,
+        black_berserk: false,
+        simul: setup.simul.clone(),
+        invite: setup.invite.clone(),
+        odds: setup.odds.map(|odds| GameOdds {
+            giver_id: setup.player1_id.clone(),
+            odds,
+        }),
+        clocks: setup.odds.and_then(|odds| odds.clocks(player1_color)),
     };
 
     // Build transaction items
-    let transact_items = vec![
-        // Update player1 to "matched"
-        build_update_player_item(queue_table, player1, &now)?,
-        // Update player2 to "matched"
-        build_update_player_item(queue_table, player2, &now)?,
-        // Create game
-        build_create_game_item(games_table, &game)?,
-    ];
+    let mut transact_items = claim_items;
+    // Create game
+    transact_items.push(build_create_game_item(games_table, &game)?);
+    // Record the pairing
+    transact_items.extend(history.record_items(&game, now_secs)?);
 
     // Execute transaction
     match dynamodb
@@ -85,23 +261,21 @@ pub async fn attempt_match(
     {
         Ok(_) => {
             info!(
-                "Successfully matched {} and {} in game {}",
-                player1.user_id, player2.user_id, game_id
+                "Successfully created game {} between {} and {}",
+                game_id, setup.player1_id, setup.player2_id
             );
-            Ok(game)
+            Ok(Some(game))
         }
         Err(e) => {
-            // Check if it's a conditional check failure (player already matched)
+            // A cancelled transaction means a condition failed, e.g. a player was
+            // already matched
             if let Some(service_error) = e.as_service_error() {
                 if matches!(
                     service_error,
                     TransactWriteItemsError::TransactionCanceledException(_)
                 ) {
-                    warn!(
-                        "Transaction cancelled - player already matched: {} or {}",
-                        player1.user_id, player2.user_id
-                    );
-                    return Err("Player already matched".into());
+                    warn!("Transaction cancelled for game {}", game_id);
+                    return Ok(None);
                 }
             }
 
@@ -111,36 +285,105 @@ pub async fn attempt_match(
     }
 }
 
-/// Builds a TransactWriteItem to update a player's status to "matched"
-fn build_update_player_item(
+/// Removes a waiting player's queue entry outside of a match
+///
+/// Used for players who can no longer be notified, so their linked entries in other pools
+/// go too. The delete is conditional so an entry that has meanwhile been matched is left
+/// to the matching transaction.
+pub async fn remove_queue_entry(
+    dynamodb: &aws_sdk_dynamodb::Client,
     queue_table: &str,
     player: &QueueEntry,
-    matched_at: &str,
-) -> Result<TransactWriteItem, Error> {
-    let key = HashMap::from([
+) -> Result<(), Error> {
+    info!(
+        "Removing queue entry {} for player {}",
+        player.queue_key, player.user_id
+    );
+    dynamodb
+        .delete_item()
+        .table_name(queue_table)
+        .set_key(Some(queue_entry_key(&player.queue_key, &player.user_id)))
+        .condition_expression("#status = :waiting")
+        .expression_attribute_names("#status", "status")
+        .expression_attribute_values(":waiting", AttributeValue::S("waiting".to_string()))
+        .send()
+        .await?;
+
+    for queue_key in &player.linked_queue_keys {
+        info!(
+            "Removing linked queue entry {} for player {}",
+            queue_key, player.user_id
+        );
+        dynamodb
+            .delete_item()
+            .table_name(queue_table)
+            .set_key(Some(queue_entry_key(queue_key, &player.user_id)))
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+fn queue_entry_key(queue_key: &str, user_id: &str) -> HashMap<String, AttributeValue> {
+    HashMap::from([
         (
             "queue_key".to_string(),
-            AttributeValue::S(player.queue_key.clone()),
+            AttributeValue::S(queue_key.to_string()),
         ),
         (
             "user_id".to_string(),
-            AttributeValue::S(player.user_id.clone()),
+            AttributeValue::S(user_id.to_string()),
         ),
-    ]);
+    ])
+}
 
-    let update = Update::builder()
+/// Builds the TransactWriteItems taking a waiting player out of the queue, including any
+/// linked entries in other pools
+fn build_dequeue_items(
+    queue_table: &str,
+    player: &QueueEntry,
+) -> Result<Vec<TransactWriteItem>, Error> {
+    let mut items = vec![build_remove_player_item(queue_table, player)?];
+    for queue_key in &player.linked_queue_keys {
+        items.push(build_remove_linked_item(
+            queue_table,
+            queue_key,
+            &player.user_id,
+        )?);
+    }
+    Ok(items)
+}
+
+/// Builds a TransactWriteItem to remove a waiting player from the queue
+fn build_remove_player_item(
+    queue_table: &str,
+    player: &QueueEntry,
+) -> Result<TransactWriteItem, Error> {
+    let delete = Delete::builder()
         .table_name(queue_table)
-        .set_key(Some(key))
-        .update_expression("SET #status = :matched, matched_at = :matched_at")
-        .condition_expression("#status = :waiting AND attribute_not_exists(matched_at)")
+        .set_key(Some(queue_entry_key(&player.queue_key, &player.user_id)))
+        .condition_expression("#status = :waiting")
         .expression_attribute_names("#status", "status")
-        .expression_attribute_values(":matched", AttributeValue::S("matched".to_string()))
         .expression_attribute_values(":waiting", AttributeValue::S("waiting".to_string()))
-        .expression_attribute_values(":matched_at", AttributeValue::S(matched_at.to_string()))
         .build()
-        .map_err(|e| format!("Failed to build update: {:?}", e))?;
+        .map_err(|e| format!("Failed to build delete: {:?}", e))?;
+
+    Ok(TransactWriteItem::builder().delete(delete).build())
+}
+
+/// Builds a TransactWriteItem to remove one of a player's linked entries, if still there
+fn build_remove_linked_item(
+    queue_table: &str,
+    queue_key: &str,
+    user_id: &str,
+) -> Result<TransactWriteItem, Error> {
+    let delete = Delete::builder()
+        .table_name(queue_table)
+        .set_key(Some(queue_entry_key(queue_key, user_id)))
+        .build()
+        .map_err(|e| format!("Failed to build delete: {:?}", e))?;
 
-    Ok(TransactWriteItem::builder().update(update).build())
+    Ok(TransactWriteItem::builder().delete(delete).build())
 }
 
 /// Builds a TransactWriteItem to create a game record
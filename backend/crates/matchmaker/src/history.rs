@@ -0,0 +1,224 @@
+use aws_sdk_dynamodb::types::{AttributeValue, Put, TransactWriteItem};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use shared::{Color, Game};
+use tracing::{info, warn};
+
+use crate::bots::is_bot;
+use crate::colors::COLOR_HISTORY_GAMES;
+use crate::matching::RecentOpponents;
+
+/// Default number of games two players may play against each other within the window
+const DEFAULT_REPEAT_LIMIT: usize = 2;
+/// Default length of the repeat-pairing window
+const DEFAULT_REPEAT_WINDOW_SECS: u64 = 30 * 60;
+/// Minimum time rows are kept, so colour balancing sees more than the last few minutes
+const COLOR_HISTORY_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// One row per player per game, so a player's recent opponents are a single query
+#[derive(Debug, Serialize, Deserialize)]
+struct PairingRecord {
+    user_id: String,
+    /// Sort key: zero-padded unix time followed by the game id, so rows sort by time
+    paired_at: String,
+    opponent_id: String,
+    game_id: String,
+    /// Colour the player had; missing on rows written before colours were tracked
+    #[serde(default)]
+    color: Option<Color>,
+    /// DynamoDB TTL attribute
+    expires_at: u64,
+}
+
+/// Recent pairings, used to avoid matching the same two players over and over and to
+/// balance colours
+#[derive(Debug, Clone)]
+pub struct PairingHistory {
+    pub table: String,
+    /// Pairings allowed within the window before the opponent is set aside (0 disables)
+    pub repeat_limit: usize,
+    pub window_secs: u64,
+}
+
+impl PairingHistory {
+    /// Reads PAIRINGS_TABLE, REPEAT_PAIRING_LIMIT and REPEAT_PAIRING_WINDOW_SECS
+    pub fn from_env() -> Result<Self, String> {
+        let table = std::env::var("PAIRINGS_TABLE").map_err(|_| "PAIRINGS_TABLE must be set")?;
+        Self::parse(
+            table,
+            std::env::var("REPEAT_PAIRING_LIMIT").ok().as_deref(),
+            std::env::var("REPEAT_PAIRING_WINDOW_SECS").ok().as_deref(),
+        )
+    }
+
+    pub fn parse(table: String, limit: Option<&str>, window: Option<&str>) -> Result<Self, String> {
+        let repeat_limit = match limit.filter(|l| !l.trim().is_empty()) {
+            Some(l) => l
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid REPEAT_PAIRING_LIMIT: {}", l))?,
+            None => DEFAULT_REPEAT_LIMIT,
+        };
+        let window_secs = match window.filter(|w| !w.trim().is_empty()) {
+            Some(w) => w
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid REPEAT_PAIRING_WINDOW_SECS: {}", w))?,
+            None => DEFAULT_REPEAT_WINDOW_SECS,
+        };
+
+        Ok(Self {
+            table,
+            repeat_limit,
+            window_secs,
+        })
+    }
+
+    /// Loads the opponents `user_id` has faced too often within the window
+    pub async fn recent_opponents(
+        &self,
+        dynamodb: &DynamoClient,
+        user_id: &str,
+        now: u64,
+    ) -> Result<RecentOpponents, Error> {
+        if self.repeat_limit == 0 {
+            return Ok(RecentOpponents::default());
+        }
+
+        let since = sort_key_prefix(now.saturating_sub(self.window_secs));
+        let mut opponent_ids = Vec::new();
+        let mut exclusive_start_key = None;
+
+        loop {
+            let query_result = dynamodb
+                .query()
+                .table_name(&self.table)
+                .key_condition_expression("user_id = :uid AND paired_at >= :since")
+                .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+                .expression_attribute_values(":since", AttributeValue::S(since.clone()))
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+
+            for item in query_result.items.unwrap_or_default() {
+                match serde_dynamo::from_item::<_, PairingRecord>(item) {
+                    Ok(record) => opponent_ids.push(record.opponent_id),
+                    Err(e) => warn!("Failed to parse pairing record: {:?}", e),
+                }
+            }
+
+            exclusive_start_key = query_result.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+
+        info!(
+            "Player {} played {} games in the last {}s",
+            user_id,
+            opponent_ids.len(),
+            self.window_secs
+        );
+        Ok(RecentOpponents::from_games(opponent_ids, self.repeat_limit))
+    }
+
+    /// Loads the colours `user_id` played in their most recent games, most recent first
+    pub async fn recent_colors(
+        &self,
+        dynamodb: &DynamoClient,
+        user_id: &str,
+    ) -> Result<Vec<Color>, Error> {
+        let query_result = dynamodb
+            .query()
+            .table_name(&self.table)
+            .key_condition_expression("user_id = :uid")
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .scan_index_forward(false)
+            .limit(COLOR_HISTORY_GAMES as i32)
+            .send()
+            .await?;
+
+        let colors: Vec<Color> = query_result
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(
+                |item| match serde_dynamo::from_item::<_, PairingRecord>(item) {
+                    Ok(record) => record.color,
+                    Err(e) => {
+                        warn!("Failed to parse pairing record: {:?}", e);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        info!("Player {} recent colours: {:?}", user_id, colors);
+        Ok(colors)
+    }
+
+    /// Builds the TransactWriteItems recording `game` for both players
+    ///
+    /// Bots get no rows: they play everyone, so neither limit applies to them.
+    pub fn record_items(&self, game: &Game, now: u64) -> Result<Vec<TransactWriteItem>, Error> {
+        [
+            (&game.white_player_id, &game.black_player_id, Color::White),
+            (&game.black_player_id, &game.white_player_id, Color::Black),
+        ]
+        .into_iter()
+        .filter(|(user_id, _, _)| !is_bot(user_id))
+        .map(|(user_id, opponent_id, color)| {
+            let record = PairingRecord {
+                user_id: user_id.clone(),
+                paired_at: format!("{}#{}", sort_key_prefix(now), game.game_id),
+                opponent_id: opponent_id.clone(),
+                game_id: game.game_id.clone(),
+                color: Some(color),
+                expires_at: now + self.window_secs.max(COLOR_HISTORY_RETENTION_SECS),
+            };
+            let put = Put::builder()
+                .table_name(&self.table)
+                .set_item(Some(serde_dynamo::to_item(record)?))
+                .build()
+                .map_err(|e| format!("Failed to build put: {:?}", e))?;
+            Ok(TransactWriteItem::builder().put(put).build())
+        })
+        .collect()
+    }
+}
+
+/// Zero-pads unix seconds so that string order matches time order
+fn sort_key_prefix(unix_secs: u64) -> String {
+    format!("{:010}", unix_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults() {
+        let history = PairingHistory::parse("t".to_string(), None, Some("")).unwrap();
+        assert_eq!(history.repeat_limit, DEFAULT_REPEAT_LIMIT);
+        assert_eq!(history.window_secs, DEFAULT_REPEAT_WINDOW_SECS);
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let history = PairingHistory::parse("t".to_string(), Some("0"), Some(" 600 ")).unwrap();
+        assert_eq!(history.repeat_limit, 0);
+        assert_eq!(history.window_secs, 600);
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!(PairingHistory::parse("t".to_string(), Some("lots"), None).is_err());
+        assert!(PairingHistory::parse("t".to_string(), None, Some("-1")).is_err());
+    }
+
+    #[test]
+    fn test_sort_keys_order_by_time() {
+        assert!(sort_key_prefix(999_999_999) < sort_key_prefix(1_000_000_000));
+    }
+}
@@ -0,0 +1,593 @@
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use lambda_runtime::Error;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
+use shared::{ColorPreference, Game, GameInvite};
+use tracing::{info, warn};
+
+use crate::challenges::{ChallengeContext, ChallengeStatus};
+use crate::errors::ServiceError;
+use crate::game::{create_game, GameSetup};
+use crate::matching::unix_now;
+use crate::notifications::notify_player;
+use crate::terms::{check_catalogue_time_control, GameTerms};
+
+/// How long an invite code can be used to join
+pub const INVITE_TTL_SECS: u64 = 30 * 60;
+/// Codes are drawn at random, so a new invite may hit one that's still live
+const CODE_ATTEMPTS: usize = 5;
+/// Wrong codes a user may try before being locked out until their window closes
+pub const MAX_FAILED_CODES: u32 = 10;
+/// How long wrong codes count against a user, from the first one
+pub const FAILED_CODE_WINDOW_SECS: u64 = 15 * 60;
+/// Characters of a code's random part; no 0, 1, I or O, which are easily confused
+const CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+/// Random characters after the word, in groups of four
+const CODE_RANDOM_CHARS: usize = 8;
+/// Easy to read out and type; the random part keeps codes from being guessed
+const CODE_WORDS: &[&str] = &[
+    "KING",
+    "QUEEN",
+    "ROOK",
+    "BISHOP",
+    "KNIGHT",
+    "PAWN",
+    "CASTLE",
+    "GAMBIT",
+    "CHECK",
+    "FORK",
+    "PIN",
+    "SKEWER",
+    "TEMPO",
+    "FIANCHETTO",
+    "ZUGZWANG",
+    "ENDGAME",
+];
+
+/// A private game waiting for a friend to join with its code
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Invite {
+    /// Code the host shares, e.g. "KNIGHT-7KQ3-M9XP"
+    pub code: String,
+    pub host_id: String,
+    pub time_control: String,
+    pub rated: bool,
+    /// Colour the host asked for
+    #[serde(default)]
+    pub color: ColorPreference,
+    #[serde(default)]
+    pub variant: Variant,
+    pub status: ChallengeStatus,
+    pub created_at: u64,
+    /// DynamoDB TTL attribute; the code can't be used from this time on
+    pub expires_at: u64,
+    /// Set once a friend joined
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+}
+
+/// Body of a create-invite request
+pub type InviteRequest = GameTerms;
+
+/// Wrong codes a user tried recently
+///
+/// Kept in the invites table under a key with lowercase letters, which no normalized
+/// code can have.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FailedCodes {
+    pub code: String,
+    pub failures: u32,
+    /// DynamoDB TTL attribute; the window closes at this time
+    pub expires_at: u64,
+}
+
+impl FailedCodes {
+    pub fn key(user_id: &str) -> String {
+        format!("failed-codes#{}", user_id)
+    }
+
+    pub fn is_locked_out(&self, now: u64) -> bool {
+        self.expires_at > now && self.failures >= MAX_FAILED_CODES
+    }
+}
+
+/// A random code like "KNIGHT-7KQ3-M9XP"
+pub fn generate_code(rng: &mut impl Rng) -> String {
+    let mut code = CODE_WORDS[rng.gen_range(0..CODE_WORDS.len())].to_string();
+    for i in 0..CODE_RANDOM_CHARS {
+        if i % 4 == 0 {
+            code.push('-');
+        }
+        code.push(CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char);
+    }
+    code
+}
+
+/// Accepts codes typed in lowercase, with spaces or underscores instead of the dash
+pub fn normalize_code(code: &str) -> String {
+    code.trim()
+        .to_uppercase()
+        .split(|c: char| c == '-' || c == '_' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl Invite {
+    /// Builds a new open invite from `request`, validating it
+    pub fn new(
+        code: String,
+        host_id: &str,
+        request: InviteRequest,
+        now: u64,
+    ) -> Result<Self, ServiceError> {
+        check_catalogue_time_control(&request.time_control)?;
+        Ok(Self {
+            code,
+            host_id: host_id.to_string(),
+            time_control: request.time_control,
+            rated: request.rated,
+            color: request.color,
+            variant: request.variant,
+            status: ChallengeStatus::Open,
+            created_at: now,
+            expires_at: now + INVITE_TTL_SECS,
+            game_id: None,
+        })
+    }
+
+    pub fn is_open(&self, now: u64) -> bool {
+        self.status == ChallengeStatus::Open && self.expires_at > now
+    }
+
+    /// Checks that `user_id` may join with this invite's code
+    pub fn check_can_join(&self, user_id: &str, now: u64) -> Result<(), ServiceError> {
+        if self.host_id == user_id {
+            return Err(ServiceError::Forbidden("You cannot join your own game"));
+        }
+        if !self.is_open(now) {
+            return Err(ServiceError::unavailable("Invite"));
+        }
+        Ok(())
+    }
+}
+
+impl ChallengeContext {
+    /// Stores a new invite from `host_id` under a fresh code
+    pub async fn create_invite(
+        &self,
+        host_id: &str,
+        request: InviteRequest,
+    ) -> Result<Invite, ServiceError> {
+        let now = unix_now()?;
+        for _ in 0..CODE_ATTEMPTS {
+            let code = generate_code(&mut rand::thread_rng());
+            let invite = Invite::new(code, host_id, request.clone(), now)?;
+
+            // A live invite keeps its code; an expired one not yet swept by the TTL can
+            // be replaced
+            let result = self
+                .dynamodb
+                .put_item()
+                .table_name(&self.invites_table)
+                .set_item(Some(serde_dynamo::to_item(&invite)?))
+                .condition_expression(
+                    "attribute_not_exists(code) OR (#status = :open AND expires_at <= :now)",
+                )
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+                .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+                .send()
+                .await;
+            match result {
+                Ok(_) => {
+                    info!(
+                        "Created invite {} for {} ({}, rated: {})",
+                        invite.code, host_id, invite.time_control, invite.rated
+                    );
+                    return Ok(invite);
+                }
+                Err(e)
+                    if e.as_service_error()
+                        .is_some_and(|se| se.is_conditional_check_failed_exception()) =>
+                {
+                    warn!("Invite code {} is taken, drawing another", invite.code);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(ServiceError::Internal(
+            "Could not find a free invite code".into(),
+        ))
+    }
+
+    /// Loads an invite by code for `user_id`, treating expired ones as gone
+    ///
+    /// Wrong codes count against the user, and after MAX_FAILED_CODES of them within
+    /// FAILED_CODE_WINDOW_SECS every lookup is refused until the window closes, so codes
+    /// can't be found by guessing.
+    pub async fn get_invite(&self, code: &str, user_id: &str) -> Result<Invite, ServiceError> {
+        let now = unix_now()?;
+        if let Some(failed) = self.load_failed_codes(user_id).await? {
+            if failed.is_locked_out(now) {
+                warn!("{} is locked out of invite codes", user_id);
+                return Err(ServiceError::Throttled(
+                    "Too many wrong invite codes; try again later",
+                ));
+            }
+        }
+        match self.load_invite(code, now).await {
+            Err(ServiceError::NotFound(what)) => {
+                self.record_failed_code(user_id, now).await?;
+                Err(ServiceError::NotFound(what))
+            }
+            result => result,
+        }
+    }
+
+    async fn load_invite(&self, code: &str, now: u64) -> Result<Invite, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.invites_table)
+            .key("code", AttributeValue::S(normalize_code(code)))
+            .send()
+            .await?;
+
+        let invite: Invite = match response.item {
+            Some(item) => serde_dynamo::from_item(item)?,
+            None => return Err(ServiceError::NotFound("Invite")),
+        };
+        if invite.status == ChallengeStatus::Open && invite.expires_at <= now {
+            return Err(ServiceError::NotFound("Invite"));
+        }
+        Ok(invite)
+    }
+
+    async fn load_failed_codes(&self, user_id: &str) -> Result<Option<FailedCodes>, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.invites_table)
+            .key("code", AttributeValue::S(FailedCodes::key(user_id)))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Counts a wrong code against `user_id`, opening a new window if the last one closed
+    async fn record_failed_code(&self, user_id: &str, now: u64) -> Result<(), ServiceError> {
+        let key = FailedCodes::key(user_id);
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.invites_table)
+            .key("code", AttributeValue::S(key.clone()))
+            .update_expression(
+                "ADD failures :one SET expires_at = if_not_exists(expires_at, :expires)",
+            )
+            .condition_expression("attribute_not_exists(code) OR expires_at > :now")
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .expression_attribute_values(
+                ":expires",
+                AttributeValue::N((now + FAILED_CODE_WINDOW_SECS).to_string()),
+            )
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(()),
+            // The window closed but the TTL hasn't removed the record yet
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|se| se.is_conditional_check_failed_exception()) =>
+            {
+                let failed = FailedCodes {
+                    code: key,
+                    failures: 1,
+                    expires_at: now + FAILED_CODE_WINDOW_SECS,
+                };
+                self.dynamodb
+                    .put_item()
+                    .table_name(&self.invites_table)
+                    .set_item(Some(serde_dynamo::to_item(&failed)?))
+                    .send()
+                    .await?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Joins the invite with `code` as `user_id` and creates the private game
+    ///
+    /// The invite is used up in the same transaction that creates the game, so a code
+    /// gives exactly one game however many people try it.
+    pub async fn join_invite(&self, code: &str, user_id: &str) -> Result<Game, ServiceError> {
+        let invite = self.get_invite(code, user_id).await?;
+        let now = unix_now()?;
+        invite.check_can_join(user_id, now)?;
+        self.check_not_blocked(&invite.host_id, user_id).await?;
+
+        let game_id = uuid::Uuid::new_v4().simple().to_string();
+        let setup = GameSetup {
+            game_id: Some(game_id.clone()),
+            player1_id: invite.host_id.clone(),
+            player2_id: user_id.to_string(),
+            time_control: invite.time_control.clone(),
+            rated: invite.rated,
+            initial_fen: None,
+            tournament_id: None,
+            simul: None,
+            invite: Some(GameInvite {
+                code: invite.code.clone(),
+                host_id: invite.host_id.clone(),
+                private: true,
+            }),
+            odds: None,
+            variant: invite.variant,
+            preference: invite.color,
+        };
+        let claim = self.build_join_item(&invite, &game_id, user_id, now)?;
+        let game = create_game(
+            &self.dynamodb,
+            &self.games_table,
+            &self.history,
+            &setup,
+            vec![claim],
+        )
+        .await?
+        .ok_or_else(|| ServiceError::unavailable("Invite"))?;
+
+        info!(
+            "Invite {} used by {}, created private game {}",
+            invite.code, user_id, game.game_id
+        );
+        for player_id in [&game.white_player_id, &game.black_player_id] {
+            notify_player(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                player_id,
+                &game,
+            )
+            .await;
+        }
+        Ok(game)
+    }
+
+    /// Withdraws an unused invite; only its host may
+    pub async fn cancel_invite(&self, code: &str, user_id: &str) -> Result<(), ServiceError> {
+        let invite = self.get_invite(code, user_id).await?;
+        if invite.host_id != user_id {
+            return Err(ServiceError::Forbidden(
+                "Only the host can cancel an invite",
+            ));
+        }
+
+        let result = self
+            .dynamodb
+            .delete_item()
+            .table_name(&self.invites_table)
+            .key("code", AttributeValue::S(invite.code.clone()))
+            .condition_expression("#status = :open")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                return Err(ServiceError::unavailable("Invite"));
+            }
+            return Err(e.into());
+        }
+
+        info!("Invite {} cancelled by {}", invite.code, user_id);
+        Ok(())
+    }
+
+    /// Loads a game for `user_id` to watch
+    ///
+    /// A private game looks missing to anyone but its two players, so its id can't be
+    /// used to confirm it exists.
+    pub async fn spectate_game(&self, game_id: &str, user_id: &str) -> Result<Game, ServiceError> {
+        let game = self.load_game(game_id).await?;
+        if !game.is_visible_to(user_id) {
+            return Err(ServiceError::NotFound("Game"));
+        }
+        Ok(game)
+    }
+
+    /// Makes a private game visible to lobbies, spectators and leaderboards
+    ///
+    /// Only the host can, and there's no way back; making a public game public again is
+    /// a no-op.
+    pub async fn make_game_public(
+        &self,
+        game_id: &str,
+        user_id: &str,
+    ) -> Result<Game, ServiceError> {
+        let mut game = self.load_game(game_id).await?;
+        let invite = game.invite.as_mut().ok_or(ServiceError::Invalid(
+            "This game was not started from an invite".to_string(),
+        ))?;
+        if invite.host_id != user_id {
+            return Err(ServiceError::Forbidden(
+                "Only the host can make this game public",
+            ));
+        }
+        if !invite.private {
+            return Ok(game);
+        }
+
+        self.dynamodb
+            .update_item()
+            .table_name(&self.games_table)
+            .key("game_id", AttributeValue::S(game_id.to_string()))
+            .update_expression("SET invite.#private = :false")
+            .condition_expression("invite.host_id = :uid")
+            .expression_attribute_names("#private", "private")
+            .expression_attribute_values(":false", AttributeValue::Bool(false))
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        invite.private = false;
+
+        info!("Game {} made public by {}", game_id, user_id);
+        Ok(game)
+    }
+
+    /// Builds the TransactWriteItem using up the invite, if it's still open
+    fn build_join_item(
+        &self,
+        invite: &Invite,
+        game_id: &str,
+        user_id: &str,
+        now: u64,
+    ) -> Result<TransactWriteItem, Error> {
+        let update = Update::builder()
+            .table_name(&self.invites_table)
+            .key("code", AttributeValue::S(invite.code.clone()))
+            .update_expression("SET #status = :accepted, game_id = :game_id, accepted_by = :uid")
+            .condition_expression(
+                "#status = :open AND expires_at > :now AND created_at = :created_at",
+            )
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":accepted", AttributeValue::S("accepted".to_string()))
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(":game_id", AttributeValue::S(game_id.to_string()))
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .expression_attribute_values(
+                ":created_at",
+                AttributeValue::N(invite.created_at.to_string()),
+            )
+            .build()
+            .map_err(|e| format!("Failed to build update: {:?}", e))?;
+
+        Ok(TransactWriteItem::builder().update(update).build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn invite() -> Invite {
+        Invite::new(
+            "KNIGHT-7KQ3-M9XP".to_string(),
+            "alice",
+            GameTerms::casual("5+3"),
+            1_000,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_codes_are_a_word_and_two_random_groups() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let code = generate_code(&mut rng);
+            let parts: Vec<&str> = code.split('-').collect();
+            assert_eq!(parts.len(), 3, "{}", code);
+            assert!(CODE_WORDS.contains(&parts[0]), "{}", code);
+            for group in &parts[1..] {
+                assert_eq!(group.len(), 4, "{}", code);
+                assert!(
+                    group.bytes().all(|b| CODE_ALPHABET.contains(&b)),
+                    "{}",
+                    code
+                );
+            }
+            assert_eq!(normalize_code(&code), code);
+        }
+        // Far too many codes to find a live one by guessing
+        let space = CODE_WORDS.len() as f64 * (CODE_ALPHABET.len() as f64).powi(8);
+        assert!(space > 1e13);
+    }
+
+    #[test]
+    fn test_repeated_wrong_codes_lock_the_user_out() {
+        let failed = |failures| FailedCodes {
+            code: FailedCodes::key("mallory"),
+            failures,
+            expires_at: 2_000,
+        };
+        assert!(!failed(MAX_FAILED_CODES - 1).is_locked_out(1_000));
+        assert!(failed(MAX_FAILED_CODES).is_locked_out(1_000));
+        assert!(!failed(MAX_FAILED_CODES).is_locked_out(2_000));
+        // No typed code can reach the record
+        let key = FailedCodes::key("mallory");
+        assert_ne!(normalize_code(&key), key);
+    }
+
+    #[test]
+    fn test_normalize_code() {
+        assert_eq!(normalize_code("knight-4821"), "KNIGHT-4821");
+        assert_eq!(normalize_code("  Knight 4821 "), "KNIGHT-4821");
+        assert_eq!(normalize_code("knight_4821"), "KNIGHT-4821");
+        assert_eq!(normalize_code("KNIGHT--4821"), "KNIGHT-4821");
+    }
+
+    #[test]
+    fn test_new_invite() {
+        let invite = invite();
+        assert_eq!(invite.status, ChallengeStatus::Open);
+        assert_eq!(invite.expires_at, 1_000 + INVITE_TTL_SECS);
+        assert!(invite.is_open(1_001));
+
+        let chess960 = InviteRequest {
+            variant: Variant::Chess960,
+            ..GameTerms::casual("5+3")
+        };
+        let invite = Invite::new("KING-0001".to_string(), "alice", chess960, 0).unwrap();
+        assert_eq!(invite.variant, Variant::Chess960);
+
+        assert!(matches!(
+            Invite::new(
+                "KING-0001".to_string(),
+                "alice",
+                GameTerms::casual("7+4"),
+                0
+            ),
+            Err(ServiceError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_who_can_join() {
+        let invite = invite();
+        assert!(invite.check_can_join("bob", 1_001).is_ok());
+        assert!(matches!(
+            invite.check_can_join("alice", 1_001),
+            Err(ServiceError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_codes_are_single_use_and_expire() {
+        let expired = invite();
+        assert!(matches!(
+            expired.check_can_join("bob", expired.expires_at),
+            Err(ServiceError::Conflict(_))
+        ));
+
+        let used = Invite {
+            status: ChallengeStatus::Accepted,
+            game_id: Some("g1".to_string()),
+            ..invite()
+        };
+        assert!(matches!(
+            used.check_can_join("carol", 1_001),
+            Err(ServiceError::Conflict(_))
+        ));
+    }
+}
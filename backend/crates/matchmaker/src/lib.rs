@@ -1,3 +1,21 @@
 // Public API for testing
+pub mod arena;
+pub mod bots;
+pub mod challenges;
+pub mod colors;
+pub mod errors;
+pub mod game;
+pub mod history;
+pub mod invites;
 pub mod matching;
 pub mod models;
+pub mod notifications;
+pub mod ratings;
+pub mod rematch;
+pub mod seeks;
+pub mod simulation;
+pub mod simuls;
+pub mod status;
+pub mod strategy;
+pub mod terms;
+pub mod tournaments;
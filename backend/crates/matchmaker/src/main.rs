@@ -1,20 +1,46 @@
+mod bots;
+mod colors;
 mod game;
+mod history;
 mod matching;
 mod models;
 mod notifications;
+mod status;
+mod strategy;
+mod sweep;
 
 use aws_config::BehaviorVersion;
+use aws_lambda_events::event::cloudwatch_events::CloudWatchEvent;
 use aws_lambda_events::event::dynamodb::{Event as DynamoDbEvent, EventRecord};
 use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
 use lambda_runtime::{run, service_fn, Error, LambdaEvent};
-use serde_dynamo;
+use serde::Deserialize;
+use shared::{ColorPreference, Game};
+use std::collections::HashSet;
 use tracing::{error, info, warn};
 
-use crate::game::attempt_match;
-use crate::matching::find_match_for_player;
+use crate::bots::{BotFallback, BotProfile};
+use crate::game::{attempt_bot_match, attempt_match, remove_queue_entry};
+use crate::history::PairingHistory;
+use crate::matching::{
+    match_player, query_bucket, search_range_for_wait, unix_now, MatchParams, QueueStore,
+    RecentOpponents,
+};
 use crate::models::QueueEntry;
-use crate::notifications::notify_player;
+use crate::notifications::{get_connection_id, notify_player};
+use crate::status::record_match_waits;
+use crate::strategy::StrategyConfig;
+use crate::sweep::{pair_with_bots, push_queue_status, run_sweep, scan_waiting_players};
+
+/// The matchmaker is invoked both by the QueueTable stream (new players) and by a
+/// schedule (re-matching players who are still waiting)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MatchmakerEvent {
+    Stream(DynamoDbEvent),
+    Schedule(CloudWatchEvent),
+}
 
 #[derive(Clone)]
 struct AppState {
@@ -23,6 +49,10 @@ struct AppState {
     queue_table: String,
     games_table: String,
     connections_table: String,
+    stats_table: String,
+    strategies: StrategyConfig,
+    history: PairingHistory,
+    bot_fallback: BotFallback,
 }
 
 impl AppState {
@@ -34,6 +64,7 @@ impl AppState {
         let games_table = std::env::var("GAMES_TABLE").expect("GAMES_TABLE must be set");
         let connections_table =
             std::env::var("CONNECTIONS_TABLE").expect("CONNECTIONS_TABLE must be set");
+        let stats_table = std::env::var("STATS_TABLE").expect("STATS_TABLE must be set");
         let websocket_api_endpoint =
             std::env::var("WEBSOCKET_API_ENDPOINT").expect("WEBSOCKET_API_ENDPOINT must be set");
 
@@ -42,6 +73,11 @@ impl AppState {
             .build();
         let api_gateway = ApiGatewayClient::from_conf(api_config);
 
+        let strategies =
+            StrategyConfig::from_env().expect("Invalid matchmaking strategy configuration");
+        let history = PairingHistory::from_env().expect("Invalid repeat pairing configuration");
+        let bot_fallback = BotFallback::from_env().expect("Invalid bot fallback configuration");
+
         info!(
             "Initialized AppState with queue_table={}, games_table={}, connections_table={}",
             queue_table, games_table, connections_table
@@ -53,6 +89,10 @@ impl AppState {
             queue_table,
             games_table,
             connections_table,
+            stats_table,
+            strategies,
+            history,
+            bot_fallback,
         }
     }
 }
@@ -69,16 +109,27 @@ async fn main() -> Result<(), Error> {
     run(service_fn(|event| handler(event, state.clone()))).await
 }
 
-async fn handler(event: LambdaEvent<DynamoDbEvent>, state: AppState) -> Result<(), Error> {
-    info!(
-        "Received DynamoDB Stream event with {} records",
-        event.payload.records.len()
-    );
+async fn handler(event: LambdaEvent<MatchmakerEvent>, state: AppState) -> Result<(), Error> {
+    match event.payload {
+        MatchmakerEvent::Stream(stream_event) => {
+            info!(
+                "Received DynamoDB Stream event with {} records",
+                stream_event.records.len()
+            );
 
-    for record in event.payload.records {
-        if let Err(e) = process_record(&state, record).await {
-            error!("Failed to process record: {:?}", e);
-            // Continue processing other records even if one fails
+            for record in stream_event.records {
+                if let Err(e) = process_record(&state, record).await {
+                    error!("Failed to process record: {:?}", e);
+                    // Continue processing other records even if one fails
+                }
+            }
+        }
+        MatchmakerEvent::Schedule(schedule_event) => {
+            info!(
+                "Received scheduled event {:?}, running re-match sweep",
+                schedule_event.id
+            );
+            run_sweep(&state).await?;
         }
     }
 
@@ -86,9 +137,10 @@ async fn handler(event: LambdaEvent<DynamoDbEvent>, state: AppState) -> Result<(
 }
 
 async fn process_record(state: &AppState, record: EventRecord) -> Result<(), Error> {
-    // Only process INSERT events (when a new player joins the queue)
-    if record.event_name != "INSERT" {
-        info!("Skipping non-INSERT event: {}", record.event_name);
+    // Only process joins: INSERT for a new entry, MODIFY when a rejoin overwrites a stale
+    // one. REMOVE events (matches, leaves and TTL expiry) need no action.
+    if record.event_name != "INSERT" && record.event_name != "MODIFY" {
+        info!("Skipping {} event", record.event_name);
         return Ok(());
     }
 
@@ -100,7 +152,7 @@ async fn process_record(state: &AppState, record: EventRecord) -> Result<(), Err
 
     let new_player: QueueEntry = serde_dynamo::from_item(new_image)?;
 
-    // Skip if player is already matched (in case of duplicate events)
+    // Skip if player is no longer waiting (in case of duplicate events)
     if new_player.status != "waiting" {
         info!(
             "Player {} is not waiting (status: {}), skipping",
@@ -109,94 +161,275 @@ async fn process_record(state: &AppState, record: EventRecord) -> Result<(), Err
         return Ok(());
     }
 
+    let now = unix_now()?;
+    if new_player.is_expired(now) {
+        info!(
+            "Queue entry for player {} has expired, skipping",
+            new_player.user_id
+        );
+        return Ok(());
+    }
+
     info!(
-        "Processing new player in queue: {} (rating: {}, time_control: {}, status: {})",
-        new_player.user_id, new_player.rating, new_player.time_control, new_player.status
+        "Processing new player in queue: {} (rating: {}, time_control: {}, status: {}, range: {:?}..{:?})",
+        new_player.user_id,
+        new_player.rating,
+        new_player.time_control,
+        new_player.status,
+        new_player.min_rating,
+        new_player.max_rating
     );
 
-    // Try to find a match for this player using the new bucket-based algorithm
-    // The algorithm will search in expanding ranges (±50, ±100, ..., ±500) with random direction
-    loop {
-        match find_match_for_player(&state.dynamodb, &state.queue_table, &new_player).await? {
-            Some(opponent) => {
-                info!(
-                    "Found potential opponent: {} (rating: {})",
-                    opponent.user_id, opponent.rating
-                );
-
-                // Attempt to atomically match both players using DynamoDB transaction
-                match attempt_match(
-                    &state.dynamodb,
-                    &state.queue_table,
-                    &state.games_table,
-                    &new_player,
-                    &opponent,
-                )
-                .await
-                {
-                    Ok(game) => {
-                        info!(
-                            "Successfully matched {} vs {} in game {}",
-                            new_player.user_id, opponent.user_id, game.game_id
-                        );
-
-                        // Determine colors for each player
-                        let (player1_color, player2_color) =
-                            if game.white_player_id == new_player.user_id {
-                                ("white", "black")
-                            } else {
-                                ("black", "white")
-                            };
-
-                        // Send game_matched notification to both players
-                        notify_player(
-                            &state.api_gateway,
-                            &state.dynamodb,
-                            &state.connections_table,
-                            &new_player.user_id,
-                            &game.game_id,
-                            &opponent.user_id,
-                            player1_color,
-                            &game.time_control,
-                        )
-                        .await;
-
-                        notify_player(
-                            &state.api_gateway,
-                            &state.dynamodb,
-                            &state.connections_table,
-                            &opponent.user_id,
-                            &game.game_id,
-                            &new_player.user_id,
-                            player2_color,
-                            &game.time_control,
-                        )
-                        .await;
-
-                        info!("Match complete, both players notified");
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        // Transaction failed - opponent was already matched by another concurrent matchmaking run
-                        warn!(
-                            "Failed to match with {} (they may have been matched already): {:?}",
-                            opponent.user_id, e
-                        );
-
-                        // Continue the loop to try finding another opponent
-                        info!("Retrying matchmaking for player {}", new_player.user_id);
-                        continue;
-                    }
-                }
-            }
-            None => {
-                // No match found within ±500 points - player stays in queue with status="waiting"
-                info!(
-                    "No match found for player {} within rating range, they will remain in queue",
-                    new_player.user_id
-                );
-                return Ok(());
+    // New players start with a narrow search range; the scheduled sweep widens it
+    // the longer they wait
+    let max_range = search_range_for_wait(new_player.waited_secs(now));
+    let strategy = state.strategies.for_pool(&new_player.time_control);
+    let mut rng = rand::thread_rng();
+    if match_player(
+        &mut &*state,
+        &new_player,
+        max_range,
+        strategy,
+        now,
+        &mut rng,
+    )
+    .await?
+    .is_none()
+    {
+        info!(
+            "No match found for player {} within ±{}, they will remain in queue",
+            new_player.user_id, max_range
+        );
+    }
+
+    Ok(())
+}
+
+/// The DynamoDB queue table, with games announced over the websocket
+impl QueueStore for &AppState {
+    fn params(&self) -> MatchParams {
+        MatchParams::default()
+    }
+
+    async fn bucket(
+        &mut self,
+        pool: &str,
+        bucket: i32,
+        now: u64,
+    ) -> Result<Vec<QueueEntry>, Error> {
+        let queue_key = format!("{}#{}", pool, bucket);
+        info!("Querying bucket: {}", queue_key);
+        query_bucket(&self.dynamodb, &self.queue_table, &queue_key, now).await
+    }
+
+    async fn waiting_players(&mut self, now: u64) -> Result<Vec<QueueEntry>, Error> {
+        scan_waiting_players(&self.dynamodb, &self.queue_table, now).await
+    }
+
+    async fn recent_opponents(
+        &mut self,
+        player: &QueueEntry,
+        now: u64,
+    ) -> Result<RecentOpponents, Error> {
+        self.history
+            .recent_opponents(&self.dynamodb, &player.user_id, now)
+            .await
+    }
+
+    async fn ensure_connected(&mut self, player: &QueueEntry) -> Result<bool, Error> {
+        ensure_connected(self, player).await
+    }
+
+    async fn complete_match(
+        &mut self,
+        player: &QueueEntry,
+        opponent: &QueueEntry,
+        _now: u64,
+    ) -> Result<(), Error> {
+        complete_match(self, player, opponent).await?;
+        Ok(())
+    }
+
+    async fn finish_pool(
+        &mut self,
+        pool_name: &str,
+        pool: &[QueueEntry],
+        matched_users: &HashSet<String>,
+        now: u64,
+    ) -> HashSet<String> {
+        let bot_matched = pair_with_bots(self, pool, matched_users, now).await;
+        let still_waiting: Vec<&QueueEntry> = pool
+            .iter()
+            .filter(|player| {
+                !matched_users.contains(&player.user_id) && !bot_matched.contains(&player.user_id)
+            })
+            .collect();
+        push_queue_status(self, pool_name, &still_waiting, now).await;
+        bot_matched
+    }
+}
+
+/// Returns true if `player` has a live websocket connection
+///
+/// Players without one would never hear about their game, so their queue entry is
+/// removed instead. This catches entries the disconnect handler failed to clean up.
+async fn ensure_connected(state: &AppState, player: &QueueEntry) -> Result<bool, Error> {
+    if get_connection_id(&state.dynamodb, &state.connections_table, &player.user_id)
+        .await?
+        .is_some()
+    {
+        return Ok(true);
+    }
+
+    warn!(
+        "Player {} has no connection, removing them from the queue",
+        player.user_id
+    );
+    if let Err(e) = remove_queue_entry(&state.dynamodb, &state.queue_table, player).await {
+        warn!(
+            "Failed to remove queue entry for player {}: {:?}",
+            player.user_id, e
+        );
+    }
+    Ok(false)
+}
+
+/// Atomically pairs two players with `attempt_match` and notifies both of them
+async fn complete_match(
+    state: &AppState,
+    player: &QueueEntry,
+    opponent: &QueueEntry,
+) -> Result<Game, Error> {
+    let game = attempt_match(
+        &state.dynamodb,
+        &state.queue_table,
+        &state.games_table,
+        &state.history,
+        player,
+        opponent,
+        ColorPreference::Auto,
+    )
+    .await?;
+
+    info!(
+        "Successfully matched {} vs {} in game {}",
+        player.user_id, opponent.user_id, game.game_id
+    );
+
+    // Feed the wait estimates sent with queue_status; a failure here must not undo the match
+    let now = unix_now()?;
+    if let Err(e) = record_match_waits(
+        &state.dynamodb,
+        &state.stats_table,
+        &player.pool(),
+        &[player.waited_secs(now), opponent.waited_secs(now)],
+        now,
+    )
+    .await
+    {
+        warn!("Failed to record match waits: {:?}", e);
+    }
+
+    // Send game_matched notification to both players
+    for user_id in [&player.user_id, &opponent.user_id] {
+        notify_player(
+            &state.api_gateway,
+            &state.dynamodb,
+            &state.connections_table,
+            user_id,
+            &game,
+        )
+        .await;
+    }
+
+    info!("Match complete, both players notified");
+    Ok(game)
+}
+
+/// Pairs `player` with a bot of similar strength and notifies them
+async fn complete_bot_match(state: &AppState, player: &QueueEntry) -> Result<Game, Error> {
+    let bot = BotProfile::for_rating(player.rating);
+    info!(
+        "No human opponent found for {} in time, pairing with {}",
+        player.user_id, bot.user_id
+    );
+    let game = attempt_bot_match(
+        &state.dynamodb,
+        &state.queue_table,
+        &state.games_table,
+        &state.history,
+        player,
+        &bot,
+    )
+    .await?;
+
+    notify_player(
+        &state.api_gateway,
+        &state.dynamodb,
+        &state.connections_table,
+        &player.user_id,
+        &game,
+    )
+    .await;
+    Ok(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scheduled_event_is_routed_to_sweep() {
+        let json = serde_json::json!({
+            "version": "0",
+            "id": "53dc4d37-cffa-4f76-80c9-8b7d4a4d2eaa",
+            "detail-type": "Scheduled Event",
+            "source": "aws.events",
+            "account": "123456789012",
+            "time": "2024-01-01T00:00:00Z",
+            "region": "eu-west-1",
+            "resources": ["arn:aws:events:eu-west-1:123456789012:rule/matchmaker"],
+            "detail": {}
+        });
+
+        let event: MatchmakerEvent = serde_json::from_value(json).unwrap();
+        assert!(matches!(event, MatchmakerEvent::Schedule(_)));
+    }
+
+    #[test]
+    fn test_stream_event_is_routed_to_stream() {
+        let json = serde_json::json!({
+            "Records": [{
+                "eventID": "1",
+                "eventName": "INSERT",
+                "eventVersion": "1.1",
+                "eventSource": "aws:dynamodb",
+                "awsRegion": "eu-west-1",
+                "dynamodb": {
+                    "ApproximateCreationDateTime": 1700000000,
+                    "Keys": {
+                        "queue_key": {"S": "blitz#1200"},
+                        "user_id": {"S": "user-1"}
+                    },
+                    "NewImage": {
+                        "queue_key": {"S": "blitz#1200"},
+                        "user_id": {"S": "user-1"}
+                    },
+                    "SequenceNumber": "111",
+                    "SizeBytes": 26,
+                    "StreamViewType": "NEW_IMAGE"
+                },
+                "eventSourceARN": "arn:aws:dynamodb:eu-west-1:123456789012:table/queue/stream/2024"
+            }]
+        });
+
+        let event: MatchmakerEvent = serde_json::from_value(json).unwrap();
+        match event {
+            MatchmakerEvent::Stream(stream_event) => {
+                assert_eq!(stream_event.records.len(), 1);
+                assert_eq!(stream_event.records[0].event_name, "INSERT");
             }
+            MatchmakerEvent::Schedule(_) => panic!("Expected a stream event"),
         }
     }
 }
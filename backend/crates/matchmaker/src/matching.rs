@@ -1,156 +1,575 @@
 use aws_sdk_dynamodb::types::AttributeValue;
 use aws_sdk_dynamodb::Client as DynamoClient;
 use lambda_runtime::Error;
-use rand::seq::SliceRandom;
-use rand::Rng;
-use serde_dynamo;
+use rand::RngCore;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
 use tracing::{info, warn};
 
 use crate::models::QueueEntry;
+use crate::strategy::{MatchStrategy, StrategyConfig};
 
-const RANGE_STEP: i32 = 50;
-const MAX_RANGE: i32 = 500;
+/// Maximum number of opponents to try for one player before giving up until the next pass
+const MAX_MATCH_ATTEMPTS: usize = 5;
 
-/// Normalizes a rating to the nearest bucket (floors to nearest 50)
-pub fn normalize_rating(rating: i32) -> i32 {
-    (rating / RANGE_STEP) * RANGE_STEP
+/// Filter selecting waiting entries that haven't outlived their TTL
+///
+/// DynamoDB TTL deletes expired items lazily (often hours later), so reads must skip them
+/// explicitly. Expects `#status`, `:waiting` and `:now` to be bound.
+pub const WAITING_FILTER: &str =
+    "#status = :waiting AND (attribute_not_exists(expires_at) OR expires_at > :now)";
+
+/// Tunable parameters of the bucket search
+///
+/// Production uses `MatchParams::default()`; the simulator varies them to compare
+/// bucket sizes and widening schedules before they are deployed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchParams {
+    /// Width of a rating bucket (and of each search step)
+    pub bucket_size: i32,
+    /// Widest search range, however long a player waits
+    pub max_range: i32,
+    /// Search range for a player who has only just joined the queue
+    pub initial_range: i32,
+    /// Search range reached after `widen_after_secs` seconds in the queue
+    pub widened_range: i32,
+    pub widen_after_secs: u64,
+}
+
+impl Default for MatchParams {
+    fn default() -> Self {
+        Self {
+            bucket_size: 50,
+            max_range: 500,
+            initial_range: 50,
+            widened_range: 300,
+            widen_after_secs: 30,
+        }
+    }
+}
+
+/// A rating bucket to search, relative to the searching player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CandidateBucket {
+    /// Lowest rating in the bucket, as used in the queue key
+    pub bucket: i32,
+    /// Distance from the player's own bucket (0 for the own bucket)
+    pub offset: i32,
+    /// Smallest possible rating difference to anyone in the bucket
+    pub min_gap: i32,
+}
+
+impl MatchParams {
+    /// Normalizes a rating to its bucket (floors to the nearest bucket boundary)
+    pub fn normalize_rating(&self, rating: i32) -> i32 {
+        (rating / self.bucket_size) * self.bucket_size
+    }
+
+    /// Returns how far (in rating points) to search for a player who has waited `waited_secs`
+    ///
+    /// The range grows linearly from `initial_range` on joining to `widened_range` after
+    /// `widen_after_secs` and keeps widening at the same rate until it reaches `max_range`.
+    /// It is rounded down to whole buckets.
+    pub fn search_range_for_wait(&self, waited_secs: u64) -> i32 {
+        let growth = ((self.widened_range - self.initial_range).max(0) as u64)
+            .saturating_mul(waited_secs)
+            / self.widen_after_secs.max(1);
+        let range = (self.initial_range as u64)
+            .saturating_add(growth)
+            .min(self.max_range as u64) as i32;
+        (range / self.bucket_size) * self.bucket_size
+    }
+
+    /// Returns true if any rating in the given bucket could satisfy the player's requested range
+    ///
+    /// Buckets that cannot contain an acceptable opponent are skipped without querying DynamoDB.
+    pub fn bucket_in_range(&self, player: &QueueEntry, bucket: i32) -> bool {
+        let bucket_max = bucket + self.bucket_size - 1;
+        player.min_rating.is_none_or(|min| bucket_max >= min)
+            && player.max_rating.is_none_or(|max| bucket <= max)
+    }
+
+    /// Returns the smallest rating difference between `rating` and any rating in `bucket`
+    pub fn min_gap_to_bucket(&self, rating: i32, bucket: i32) -> i32 {
+        let bucket_max = bucket + self.bucket_size - 1;
+        if rating < bucket {
+            bucket - rating
+        } else if rating > bucket_max {
+            rating - bucket_max
+        } else {
+            0
+        }
+    }
+
+    /// Returns the rating buckets within `max_range` of the player's own bucket, nearest first
+    ///
+    /// The player's own bucket comes first, followed by the buckets above and below at each
+    /// distance. Buckets outside the player's min_rating/max_rating are left out.
+    pub fn candidate_buckets(&self, player: &QueueEntry, max_range: i32) -> Vec<CandidateBucket> {
+        let player_bucket = self.normalize_rating(player.rating);
+        let mut offsets = vec![0];
+        for range in
+            (self.bucket_size..=max_range.min(self.max_range)).step_by(self.bucket_size as usize)
+        {
+            offsets.push(range);
+            offsets.push(-range);
+        }
+
+        offsets
+            .into_iter()
+            .map(|offset| player_bucket + offset)
+            .filter(|&bucket| self.bucket_in_range(player, bucket))
+            .map(|bucket| CandidateBucket {
+                bucket,
+                offset: bucket - player_bucket,
+                min_gap: self.min_gap_to_bucket(player.rating, bucket),
+            })
+            .collect()
+    }
+
+    /// Returns true if either player's current search range reaches the other's bucket
+    pub fn within_search_range(&self, a: &QueueEntry, b: &QueueEntry, now: u64) -> bool {
+        let range = self
+            .search_range_for_wait(a.waited_secs(now))
+            .max(self.search_range_for_wait(b.waited_secs(now)));
+        (self.normalize_rating(a.rating) - self.normalize_rating(b.rating)).abs() <= range
+    }
+}
+
+/// Returns how far (in rating points) to search for a player who has waited `waited_secs`
+///
+/// The range grows linearly from ±50 on joining to ±300 after 30 seconds and keeps
+/// widening at the same rate until it reaches ±500.
+pub fn search_range_for_wait(waited_secs: u64) -> i32 {
+    MatchParams::default().search_range_for_wait(waited_secs)
+}
+
+/// Current unix time in seconds
+pub fn unix_now() -> Result<u64, Error> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs())
+}
+
+/// Returns true if both play the same variant, each player's rating falls within the
+/// other's requested range and neither has blocked the other
+pub fn is_mutual_match(a: &QueueEntry, b: &QueueEntry) -> bool {
+    a.variant == b.variant
+        && a.accepts_rating(b.rating)
+        && b.accepts_rating(a.rating)
+        && !a.has_blocked(&b.user_id)
+        && !b.has_blocked(&a.user_id)
+}
+
+/// Opponents a player has already faced `limit` or more times within the repeat window
+///
+/// They are only offered as opponents when nobody else is available.
+#[derive(Debug, Clone, Default)]
+pub struct RecentOpponents {
+    overplayed: HashSet<String>,
+}
+
+impl RecentOpponents {
+    /// Builds the set from the opponent of each recent game; a `limit` of 0 disables it
+    pub fn from_games(opponent_ids: impl IntoIterator<Item = String>, limit: usize) -> Self {
+        if limit == 0 {
+            return Self::default();
+        }
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for opponent_id in opponent_ids {
+            *counts.entry(opponent_id).or_insert(0) += 1;
+        }
+        Self {
+            overplayed: counts
+                .into_iter()
+                .filter(|(_, count)| *count >= limit)
+                .map(|(opponent_id, _)| opponent_id)
+                .collect(),
+        }
+    }
+
+    pub fn is_overplayed(&self, user_id: &str) -> bool {
+        self.overplayed.contains(user_id)
+    }
+}
+
+/// Returns the entries that are acceptable opponents for `player`
+///
+/// Filters out the player themselves, anyone outside either player's rating range and
+/// anyone either player has blocked.
+pub fn acceptable_candidates<'a>(
+    player: &'a QueueEntry,
+    entries: impl IntoIterator<Item = QueueEntry> + 'a,
+) -> impl Iterator<Item = QueueEntry> + 'a {
+    entries
+        .into_iter()
+        .filter(move |entry| entry.user_id != player.user_id)
+        .filter(move |entry| is_mutual_match(player, entry))
+}
+
+/// The queue the matchmaker searches and pairs players from
+///
+/// Production runs against the DynamoDB queue table; the simulator runs the same search
+/// and re-match pass against an in-memory queue.
+pub trait QueueStore {
+    /// Bucket layout the queue keys are built with
+    fn params(&self) -> MatchParams;
+
+    /// Waiting entries in one rating bucket of `pool` that haven't expired by `now`
+    fn bucket(
+        &mut self,
+        pool: &str,
+        bucket: i32,
+        now: u64,
+    ) -> impl Future<Output = Result<Vec<QueueEntry>, Error>>;
+
+    /// Every waiting entry that hasn't expired by `now`, in any order
+    fn waiting_players(&mut self, now: u64)
+        -> impl Future<Output = Result<Vec<QueueEntry>, Error>>;
+
+    /// Opponents `player` has faced too often lately
+    fn recent_opponents(
+        &mut self,
+        player: &QueueEntry,
+        now: u64,
+    ) -> impl Future<Output = Result<RecentOpponents, Error>>;
+
+    /// Returns true if `player` can still be told about a game; drops their entry if not
+    fn ensure_connected(
+        &mut self,
+        player: &QueueEntry,
+    ) -> impl Future<Output = Result<bool, Error>>;
+
+    /// Creates the game for two players and takes both out of the queue
+    ///
+    /// Fails if either of them was paired elsewhere in the meantime.
+    fn complete_match(
+        &mut self,
+        player: &QueueEntry,
+        opponent: &QueueEntry,
+        now: u64,
+    ) -> impl Future<Output = Result<(), Error>>;
+
+    /// Runs once a re-match pass is done with `pool`, whose paired players are
+    /// `matched_users`; returns anyone it paired some other way
+    fn finish_pool(
+        &mut self,
+        _pool_name: &str,
+        _pool: &[QueueEntry],
+        _matched_users: &HashSet<String>,
+        _now: u64,
+    ) -> impl Future<Output = HashSet<String>> {
+        async { HashSet::new() }
+    }
 }
 
-/// Finds a match for a player using the new bucket-based algorithm with random search direction
+/// Finds a match for a player by walking rating buckets in the order chosen by `strategy`
 ///
 /// Algorithm:
-/// 1. Compute player's rating bucket (rating // 50) * 50
-/// 2. Randomly decide whether to start searching upward or downward
-/// 3. Expand search range gradually: 50 → 100 → 150 → ... → 500
-/// 4. For each expansion level, shuffle offsets and try both directions
-/// 5. Query DynamoDB for candidates with status = "waiting"
-/// 6. Randomly pick an opponent from results
-/// 7. Return the first valid candidate found
+/// 1. Compute the buckets within `max_range` of the player's bucket, skipping buckets that
+///    lie entirely outside the player's min_rating/max_rating
+/// 2. Let the strategy order the buckets
+/// 3. Read unexpired waiting candidates bucket by bucket, keeping only candidates who are
+///    mutually acceptable, and setting aside candidates in `recent` that the player has
+///    faced too often lately
+/// 4. Stop early once the strategy has enough candidates to decide
+/// 5. Let the strategy select the opponent from the collected candidates, falling back to
+///    the set-aside repeat opponents only if there is nobody else
 pub async fn find_match_for_player(
-    dynamodb: &DynamoClient,
-    queue_table: &str,
+    queue: &mut impl QueueStore,
     new_player: &QueueEntry,
+    max_range: i32,
+    strategy: &dyn MatchStrategy,
+    recent: &RecentOpponents,
+    now: u64,
+    rng: &mut dyn RngCore,
 ) -> Result<Option<QueueEntry>, Error> {
     info!(
-        "Finding match for player {} (rating: {}, time_control: {})",
-        new_player.user_id, new_player.rating, new_player.time_control
+        "Finding match for player {} (rating: {}, time_control: {}, range: ±{}, strategy: {})",
+        new_player.user_id,
+        new_player.rating,
+        new_player.time_control,
+        max_range,
+        strategy.name()
     );
 
-    let player_bucket = normalize_rating(new_player.rating);
-    info!("Player bucket: {}", player_bucket);
-
-    // First, check the player's own bucket (offset 0)
-    let queue_key = format!("{}#{}", new_player.time_control, player_bucket);
-    info!("First checking own bucket: {}", queue_key);
+    let params = queue.params();
+    let buckets = strategy.bucket_order(
+        new_player,
+        params.candidate_buckets(new_player, max_range),
+        rng,
+    );
+    info!(
+        "Bucket search order: {:?}",
+        buckets.iter().map(|b| b.bucket).collect::<Vec<_>>()
+    );
 
-    match query_bucket(dynamodb, queue_table, &queue_key, &new_player.user_id).await? {
-        Some(candidates) if !candidates.is_empty() => {
+    let pool = new_player.pool();
+    let mut candidates = Vec::new();
+    let mut repeat_candidates = Vec::new();
+    for (i, bucket) in buckets.iter().enumerate() {
+        let entries = queue.bucket(&pool, bucket.bucket, now).await?;
+        let (found, repeats): (Vec<_>, Vec<_>) = acceptable_candidates(new_player, entries)
+            .partition(|entry| !recent.is_overplayed(&entry.user_id));
+        if found.is_empty() {
+            info!("No candidates in bucket {}#{}", pool, bucket.bucket);
+        } else {
             info!(
-                "Found {} candidates in own bucket {}",
-                candidates.len(),
-                queue_key
+                "Found {} candidates in bucket {}#{}",
+                found.len(),
+                pool,
+                bucket.bucket
             );
-            let mut rng = rand::thread_rng();
-            if let Some(opponent) = candidates.choose(&mut rng) {
-                info!(
-                    "Selected opponent: {} (rating: {}) from own bucket",
-                    opponent.user_id, opponent.rating
-                );
-                return Ok(Some(opponent.clone()));
-            }
+            candidates.extend(found);
         }
-        _ => {
-            info!("No candidates in own bucket {}", queue_key);
+        repeat_candidates.extend(repeats);
+
+        if strategy.enough_candidates(new_player, &candidates, &buckets[i + 1..]) {
+            break;
         }
     }
 
-    // Randomly decide whether to start searching upward (+1) or downward (-1)
-    let mut rng = rand::thread_rng();
-    let start_direction = if rng.gen_bool(0.5) { 1 } else { -1 };
-    info!(
-        "Search direction: {}",
-        if start_direction == 1 {
-            "upward"
-        } else {
-            "downward"
+    if candidates.is_empty() && !repeat_candidates.is_empty() {
+        info!(
+            "Only repeat opponents available for player {}, considering {} of them",
+            new_player.user_id,
+            repeat_candidates.len()
+        );
+        candidates = repeat_candidates;
+    }
+
+    match strategy.select_opponent(new_player, &candidates, rng) {
+        Some(index) => {
+            let opponent = candidates.swap_remove(index);
+            info!(
+                "Selected opponent: {} (rating: {})",
+                opponent.user_id, opponent.rating
+            );
+            Ok(Some(opponent))
         }
-    );
+        None => {
+            info!(
+                "No match found for player {} within ±{} points",
+                new_player.user_id, max_range
+            );
+            Ok(None)
+        }
+    }
+}
 
-    // Expand search range gradually: 50 → 100 → 150 → ... → 500
-    for range in (RANGE_STEP..=MAX_RANGE).step_by(RANGE_STEP as usize) {
-        info!("Searching at range ±{}", range);
+/// Tries to find and lock in an opponent for `player` within `max_range` rating points
+///
+/// Searches with `find_match_for_player` and pairs with `complete_match`, retrying with
+/// another opponent when a concurrent matchmaking run got there first. Returns the
+/// opponent, or None if the player stays queued.
+pub async fn match_player(
+    queue: &mut impl QueueStore,
+    player: &QueueEntry,
+    max_range: i32,
+    strategy: &dyn MatchStrategy,
+    now: u64,
+    rng: &mut dyn RngCore,
+) -> Result<Option<QueueEntry>, Error> {
+    if !queue.ensure_connected(player).await? {
+        return Ok(None);
+    }
 
-        // Create offsets for this range
-        let mut offsets = vec![range * start_direction, range * -start_direction];
-        offsets.shuffle(&mut rng);
+    let recent = queue.recent_opponents(player, now).await?;
 
-        for offset in offsets {
-            let candidate_bucket = normalize_rating(new_player.rating + offset);
-            let queue_key = format!("{}#{}", new_player.time_control, candidate_bucket);
+    for attempt in 1..=MAX_MATCH_ATTEMPTS {
+        let opponent =
+            match find_match_for_player(queue, player, max_range, strategy, &recent, now, rng)
+                .await?
+            {
+                Some(opponent) => opponent,
+                None => return Ok(None),
+            };
 
-            info!(
-                "Querying bucket: {} (offset: {}, candidate_bucket: {})",
-                queue_key, offset, candidate_bucket
-            );
+        info!(
+            "Found potential opponent: {} (rating: {})",
+            opponent.user_id, opponent.rating
+        );
+
+        // Removing a disconnected candidate lets the next attempt find someone else
+        if !queue.ensure_connected(&opponent).await? {
+            continue;
+        }
 
-            // Query for waiting players in this bucket
-            match query_bucket(dynamodb, queue_table, &queue_key, &new_player.user_id).await? {
-                Some(candidates) if !candidates.is_empty() => {
-                    info!(
-                        "Found {} candidates in bucket {}",
-                        candidates.len(),
-                        queue_key
-                    );
-
-                    // Randomly pick one opponent
-                    if let Some(opponent) = candidates.choose(&mut rng) {
-                        info!(
-                            "Selected opponent: {} (rating: {})",
-                            opponent.user_id, opponent.rating
-                        );
-                        return Ok(Some(opponent.clone()));
-                    }
-                }
-                _ => {
-                    info!("No candidates in bucket {}", queue_key);
-                }
+        match queue.complete_match(player, &opponent, now).await {
+            Ok(()) => return Ok(Some(opponent)),
+            Err(e) => {
+                // Transaction failed - either player may have been matched by another
+                // concurrent matchmaking run
+                warn!(
+                    "Failed to match {} with {} (attempt {}/{}): {:?}",
+                    player.user_id, opponent.user_id, attempt, MAX_MATCH_ATTEMPTS, e
+                );
             }
         }
     }
 
-    info!(
-        "No match found for player {} within ±{} points",
-        new_player.user_id, MAX_RANGE
+    warn!(
+        "Giving up on player {} after {} attempts, they will be retried on the next sweep",
+        player.user_id, MAX_MATCH_ATTEMPTS
     );
     Ok(None)
 }
 
-/// Queries a specific rating bucket for waiting players
-async fn query_bucket(
+/// Runs a single re-match pass over the queue, pool by pool
+///
+/// Pools whose strategy pairs in batches are paired in one go; otherwise players are
+/// re-matched one at a time, longest-waiting first, with a search range widened by their
+/// time in queue. Returns the number of games created.
+pub async fn rematch_pass(
+    queue: &mut impl QueueStore,
+    strategies: &StrategyConfig,
+    now: u64,
+    rng: &mut dyn RngCore,
+) -> Result<usize, Error> {
+    let players = queue.waiting_players(now).await?;
+    info!("Found {} waiting players", players.len());
+
+    let mut pools: BTreeMap<String, Vec<QueueEntry>> = BTreeMap::new();
+    for player in players {
+        pools.entry(player.pool()).or_default().push(player);
+    }
+
+    let mut games_created = 0;
+    // Players who joined several pools appear in each of them; once matched in one, their
+    // other entries are gone even though this pass's snapshot still lists them
+    let mut matched_this_pass = HashSet::new();
+    for (pool_name, mut pool) in pools {
+        pool.retain(|player| !matched_this_pass.contains(&player.user_id));
+        if pool.is_empty() {
+            continue;
+        }
+        pool.sort_by_key(|player| player.joined_at.parse::<u64>().unwrap_or(u64::MAX));
+
+        // Casual pools are paired the same way as the rated pool of their time control
+        let strategy = strategies.for_pool(&pool[0].time_control);
+        let mut matched_users = match strategy.pair_pool(&pool, now, &queue.params()) {
+            Some(pairs) => {
+                info!(
+                    "Strategy {} paired {} games in pool {}",
+                    strategy.name(),
+                    pairs.len(),
+                    pool_name
+                );
+                complete_pairs(queue, &pool, &pairs, now).await
+            }
+            None => rematch_each(queue, &pool, strategy, now, rng).await,
+        };
+        games_created += matched_users.len() / 2;
+
+        let paired_otherwise = queue
+            .finish_pool(&pool_name, &pool, &matched_users, now)
+            .await;
+        games_created += paired_otherwise.len();
+        matched_users.extend(paired_otherwise);
+
+        matched_this_pass.extend(matched_users);
+    }
+
+    Ok(games_created)
+}
+
+/// Creates games for pairs chosen up front by a batch strategy
+///
+/// A disconnected player is dropped from the queue and their partner is left for the
+/// next pass. Returns the ids of the players who were matched.
+async fn complete_pairs(
+    queue: &mut impl QueueStore,
+    pool: &[QueueEntry],
+    pairs: &[(usize, usize)],
+    now: u64,
+) -> HashSet<String> {
+    let mut matched_users = HashSet::new();
+    for &(a, b) in pairs {
+        let (a, b) = (&pool[a], &pool[b]);
+        let paired = async {
+            if !queue.ensure_connected(a).await? || !queue.ensure_connected(b).await? {
+                return Ok::<_, Error>(false);
+            }
+            queue.complete_match(a, b, now).await?;
+            Ok(true)
+        };
+        match paired.await {
+            Ok(true) => {
+                matched_users.insert(a.user_id.clone());
+                matched_users.insert(b.user_id.clone());
+            }
+            Ok(false) => {}
+            Err(e) => warn!("Failed to pair {} with {}: {:?}", a.user_id, b.user_id, e),
+        }
+    }
+    matched_users
+}
+
+/// Re-matches each player in turn with a search range widened by their time in queue
+///
+/// Returns the ids of the players who were matched.
+async fn rematch_each(
+    queue: &mut impl QueueStore,
+    pool: &[QueueEntry],
+    strategy: &dyn MatchStrategy,
+    now: u64,
+    rng: &mut dyn RngCore,
+) -> HashSet<String> {
+    let params = queue.params();
+    let mut matched_users = HashSet::new();
+
+    for player in pool {
+        // The player may already have been paired earlier in this pass
+        if matched_users.contains(&player.user_id) {
+            continue;
+        }
+
+        let max_range = params.search_range_for_wait(player.waited_secs(now));
+        info!(
+            "Re-matching player {} (waited {}s, range ±{})",
+            player.user_id,
+            player.waited_secs(now),
+            max_range
+        );
+
+        match match_player(queue, player, max_range, strategy, now, rng).await {
+            Ok(Some(opponent)) => {
+                matched_users.insert(player.user_id.clone());
+                matched_users.insert(opponent.user_id);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                // Keep going so one bad entry doesn't stall the rest of the queue
+                warn!("Failed to re-match player {}: {:?}", player.user_id, e);
+            }
+        }
+    }
+
+    matched_users
+}
+
+/// Queries one queue key for waiting entries that haven't expired by `now`
+pub async fn query_bucket(
     dynamodb: &DynamoClient,
     queue_table: &str,
     queue_key: &str,
-    exclude_user_id: &str,
-) -> Result<Option<Vec<QueueEntry>>, Error> {
+    now: u64,
+) -> Result<Vec<QueueEntry>, Error> {
     let query_result = dynamodb
         .query()
         .table_name(queue_table)
         .key_condition_expression("queue_key = :qk")
-        .filter_expression("#status = :waiting")
+        .filter_expression(WAITING_FILTER)
         .expression_attribute_names("#status", "status")
         .expression_attribute_values(":qk", AttributeValue::S(queue_key.to_string()))
         .expression_attribute_values(":waiting", AttributeValue::S("waiting".to_string()))
+        .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
         .send()
         .await?;
 
-    let items = query_result.items.unwrap_or_default();
-
-    if items.is_empty() {
-        return Ok(None);
-    }
-
-    // Parse and filter out the current player
-    let candidates: Vec<QueueEntry> = items
+    Ok(query_result
+        .items
+        .unwrap_or_default()
         .into_iter()
         .filter_map(|item| match serde_dynamo::from_item(item) {
             Ok(entry) => Some(entry),
@@ -159,12 +578,260 @@ async fn query_bucket(
                 None
             }
         })
-        .filter(|entry: &QueueEntry| entry.user_id != exclude_user_id)
-        .collect();
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::variant::Variant;
+
+    fn entry(user_id: &str, rating: i32, min: Option<i32>, max: Option<i32>) -> QueueEntry {
+        QueueEntry {
+            queue_key: format!("blitz#{}", MatchParams::default().normalize_rating(rating)),
+            user_id: user_id.to_string(),
+            time_control: "blitz".to_string(),
+            rating,
+            min_rating: min,
+            max_rating: max,
+            ..QueueEntry::default()
+        }
+    }
+
+    #[test]
+    fn test_normalize_rating_floors_to_bucket() {
+        let params = MatchParams::default();
+        assert_eq!(params.normalize_rating(1200), 1200);
+        assert_eq!(params.normalize_rating(1249), 1200);
+        assert_eq!(params.normalize_rating(1250), 1250);
+    }
+
+    #[test]
+    fn test_search_range_starts_narrow() {
+        assert_eq!(search_range_for_wait(0), 50);
+        assert_eq!(search_range_for_wait(5), 50);
+    }
+
+    #[test]
+    fn test_search_range_widens_with_wait() {
+        assert_eq!(search_range_for_wait(6), 100);
+        assert_eq!(search_range_for_wait(15), 150);
+        assert_eq!(search_range_for_wait(30), 300);
+    }
+
+    #[test]
+    fn test_search_range_is_capped() {
+        assert_eq!(search_range_for_wait(54), 500);
+        assert_eq!(search_range_for_wait(600), 500);
+        assert_eq!(search_range_for_wait(u64::MAX), 500);
+    }
+
+    #[test]
+    fn test_waited_secs() {
+        let mut a = entry("a", 1200, None, None);
+        a.joined_at = "1000".to_string();
+        assert_eq!(a.waited_secs(1030), 30);
+        // Clock skew between Lambdas must not underflow
+        assert_eq!(a.waited_secs(990), 0);
+    }
+
+    #[test]
+    fn test_casual_players_wait_in_their_own_pool() {
+        let mut a = entry("a", 1200, None, None);
+        assert_eq!(a.pool(), "blitz");
+        a.rated = false;
+        assert_eq!(a.pool(), "casual:blitz");
+        // A casual arena still has the arena's pool to itself
+        a.time_control = "arena:t1".to_string();
+        a.tournament = Some(crate::models::TournamentEntry {
+            tournament_id: "t1".to_string(),
+            time_control: "blitz".to_string(),
+            rated: false,
+        });
+        assert_eq!(a.pool(), "arena:t1");
+    }
+
+    #[test]
+    fn test_chess960_players_wait_in_their_own_pools() {
+        let mut a = entry("a", 1200, None, None);
+        a.variant = Variant::Chess960;
+        assert_eq!(a.pool(), "chess960:blitz");
+        a.rated = false;
+        assert_eq!(a.pool(), "casual:chess960:blitz");
+    }
+
+    #[test]
+    fn test_variants_never_match() {
+        let a = entry("a", 1200, None, None);
+        let mut b = entry("b", 1200, None, None);
+        b.variant = Variant::Chess960;
+        assert!(!is_mutual_match(&a, &b));
+        assert!(!is_mutual_match(&b, &a));
+    }
+
+    #[test]
+    fn test_unbounded_players_match() {
+        let a = entry("a", 1200, None, None);
+        let b = entry("b", 1650, None, None);
+        assert!(is_mutual_match(&a, &b));
+    }
+
+    #[test]
+    fn test_opponent_outside_own_range_is_rejected() {
+        let a = entry("a", 1200, Some(1100), Some(1300));
+        let b = entry("b", 1400, None, None);
+        assert!(!is_mutual_match(&a, &b));
+    }
+
+    #[test]
+    fn test_player_outside_opponent_range_is_rejected() {
+        // a is happy to play b, but b only wants opponents rated 1500+
+        let a = entry("a", 1200, None, None);
+        let b = entry("b", 1300, Some(1500), None);
+        assert!(a.accepts_rating(b.rating));
+        assert!(!is_mutual_match(&a, &b));
+        assert!(!is_mutual_match(&b, &a));
+    }
+
+    #[test]
+    fn test_range_bounds_are_inclusive() {
+        let a = entry("a", 1200, Some(1150), Some(1250));
+        assert!(a.accepts_rating(1150));
+        assert!(a.accepts_rating(1250));
+        assert!(!a.accepts_rating(1149));
+        assert!(!a.accepts_rating(1251));
+    }
+
+    fn buckets(player: &QueueEntry, max_range: i32) -> Vec<i32> {
+        MatchParams::default()
+            .candidate_buckets(player, max_range)
+            .iter()
+            .map(|b| b.bucket)
+            .collect()
+    }
+
+    #[test]
+    fn test_bucket_in_range_without_limits() {
+        let params = MatchParams::default();
+        let a = entry("a", 1200, None, None);
+        assert!(params.bucket_in_range(&a, 700));
+        assert!(params.bucket_in_range(&a, 1700));
+    }
+
+    #[test]
+    fn test_bucket_in_range_prunes_buckets_outside_limits() {
+        let params = MatchParams::default();
+        let a = entry("a", 1200, Some(1120), Some(1260));
+        assert!(!params.bucket_in_range(&a, 1050));
+        // 1100..=1149 overlaps 1120
+        assert!(params.bucket_in_range(&a, 1100));
+        assert!(params.bucket_in_range(&a, 1200));
+        // 1250..=1299 overlaps 1260
+        assert!(params.bucket_in_range(&a, 1250));
+        assert!(!params.bucket_in_range(&a, 1300));
+    }
+
+    #[test]
+    fn test_candidate_buckets_nearest_first() {
+        let a = entry("a", 1234, None, None);
+        assert_eq!(buckets(&a, 50), vec![1200, 1250, 1150]);
+        assert_eq!(buckets(&a, 100), vec![1200, 1250, 1150, 1300, 1100]);
+    }
+
+    #[test]
+    fn test_candidate_buckets_carry_offset_and_gap() {
+        let a = entry("a", 1234, None, None);
+        let found = MatchParams::default().candidate_buckets(&a, 50);
+        assert_eq!(
+            found[1],
+            CandidateBucket {
+                bucket: 1250,
+                offset: 50,
+                min_gap: 16
+            }
+        );
+        assert_eq!(found[2].offset, -50);
+        assert_eq!(found[2].min_gap, 35);
+    }
+
+    #[test]
+    fn test_candidate_buckets_respects_requested_range() {
+        let a = entry("a", 1234, Some(1200), None);
+        assert_eq!(buckets(&a, 100), vec![1200, 1250, 1300]);
+    }
+
+    #[test]
+    fn test_candidate_buckets_is_capped_at_max_range() {
+        let a = entry("a", 1500, None, None);
+        assert_eq!(buckets(&a, 10_000).len(), 21);
+    }
+
+    #[test]
+    fn test_candidate_buckets_with_custom_bucket_size() {
+        let params = MatchParams {
+            bucket_size: 100,
+            ..MatchParams::default()
+        };
+        let a = entry("a", 1234, None, None);
+        let found: Vec<i32> = params
+            .candidate_buckets(&a, 200)
+            .iter()
+            .map(|b| b.bucket)
+            .collect();
+        assert_eq!(found, vec![1200, 1300, 1100, 1400, 1000]);
+    }
+
+    #[test]
+    fn test_own_bucket_can_be_pruned() {
+        // A player may ask only for stronger opponents than themselves
+        let params = MatchParams::default();
+        let a = entry("a", 1210, Some(1300), None);
+        assert!(!params.bucket_in_range(&a, params.normalize_rating(a.rating)));
+        assert!(params.bucket_in_range(&a, 1300));
+    }
+
+    #[test]
+    fn test_acceptable_candidates_filters_self_and_ranges() {
+        let player = entry("p", 1200, None, Some(1300));
+        let entries = vec![
+            entry("p", 1200, None, None),
+            entry("a", 1250, None, None),
+            entry("b", 1350, None, None),
+            entry("c", 1210, Some(1250), None),
+        ];
+        let found: Vec<String> = acceptable_candidates(&player, entries)
+            .map(|e| e.user_id)
+            .collect();
+        assert_eq!(found, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_blocked_players_never_match() {
+        let mut a = entry("a", 1200, None, None);
+        let b = entry("b", 1200, None, None);
+        assert!(is_mutual_match(&a, &b));
+
+        a.blocked_user_ids = vec!["b".to_string()];
+        assert!(!is_mutual_match(&a, &b));
+        assert!(!is_mutual_match(&b, &a));
+    }
+
+    #[test]
+    fn test_recent_opponents_counts_up_to_limit() {
+        let games = ["a", "b", "a", "c", "a", "b"].map(String::from);
+        let recent = RecentOpponents::from_games(games.clone(), 2);
+        assert!(recent.is_overplayed("a"));
+        assert!(recent.is_overplayed("b"));
+        assert!(!recent.is_overplayed("c"));
+
+        let recent = RecentOpponents::from_games(games, 3);
+        assert!(recent.is_overplayed("a"));
+        assert!(!recent.is_overplayed("b"));
+    }
 
-    if candidates.is_empty() {
-        Ok(None)
-    } else {
-        Ok(Some(candidates))
+    #[test]
+    fn test_recent_opponents_limit_zero_disables() {
+        let recent = RecentOpponents::from_games(vec!["a".to_string(); 10], 0);
+        assert!(!recent.is_overplayed("a"));
     }
 }
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct QueueEntry {
@@ -8,8 +9,122 @@ pub struct QueueEntry {
     pub rating: i32,
     pub joined_at: String,
     pub status: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub matched_at: Option<String>,
+    #[serde(default)]
+    pub min_rating: Option<i32>,
+    #[serde(default)]
+    pub max_rating: Option<i32>,
+    /// Unix time after which the entry is stale; DynamoDB TTL deletes it some time later
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Snapshot of the player's block list taken when they joined
+    #[serde(default)]
+    pub blocked_user_ids: Vec<String>,
+    /// Queue keys of the entries created alongside this one when the player joined
+    /// several time controls at once; matching any of them cancels the rest
+    #[serde(default)]
+    pub linked_queue_keys: Vec<String>,
+    /// Player accepts a bot opponent if no human is found in time
+    #[serde(default)]
+    pub bot_fallback: bool,
+    /// Casual players wait in their own pools, so they are never paired with rated ones
+    #[serde(default = "default_rated")]
+    pub rated: bool,
+    /// Each variant has its own pools, so standard and Chess960 players never meet
+    #[serde(default)]
+    pub variant: Variant,
+    /// Set on entries in a tournament's own pool, whose time_control names the pool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tournament: Option<TournamentEntry>,
+}
+
+/// A waiting entry in a rated standard pool with everything else empty, for tests to fill in
+impl Default for QueueEntry {
+    fn default() -> Self {
+        Self {
+            queue_key: String::new(),
+            user_id: String::new(),
+            time_control: String::new(),
+            rating: 0,
+            joined_at: "0".to_string(),
+            status: "waiting".to_string(),
+            min_rating: None,
+            max_rating: None,
+            expires_at: None,
+            blocked_user_ids: Vec::new(),
+            linked_queue_keys: Vec::new(),
+            bot_fallback: false,
+            rated: true,
+            variant: Variant::Standard,
+            tournament: None,
+        }
+    }
+}
+
+/// What a game paired from a tournament pool is played as
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct TournamentEntry {
+    pub tournament_id: String,
+    pub time_control: String,
+    pub rated: bool,
+}
+
+/// Entries written before the flag existed all came from the rated queue
+fn default_rated() -> bool {
+    true
+}
+
+/// Name of the pool casual players wait in for `time_control`
+pub fn casual_pool_name(time_control: &str) -> String {
+    format!("casual:{}", time_control)
+}
+
+/// Name of the rated pool for `time_control` in `variant`; standard pools are just the
+/// time control
+pub fn variant_pool_name(variant: Variant, time_control: &str) -> String {
+    match variant.pool_prefix() {
+        Some(prefix) => format!("{}:{}", prefix, time_control),
+        None => time_control.to_string(),
+    }
+}
+
+impl QueueEntry {
+    /// Name of the pool the entry waits in, which prefixes its queue key
+    ///
+    /// Rated players wait in the time control's pool for their variant and casual players
+    /// in its casual pool. Tournament entries already name their tournament's pool.
+    pub fn pool(&self) -> String {
+        if self.tournament.is_some() {
+            return self.time_control.clone();
+        }
+        let pool = variant_pool_name(self.variant, &self.time_control);
+        if self.rated {
+            pool
+        } else {
+            casual_pool_name(&pool)
+        }
+    }
+
+    /// Seconds this player has been waiting in the queue as of `now` (unix seconds)
+    pub fn waited_secs(&self, now: u64) -> u64 {
+        let joined_at = self.joined_at.parse::<u64>().unwrap_or(now);
+        now.saturating_sub(joined_at)
+    }
+
+    /// Returns true once the entry has outlived its TTL but DynamoDB hasn't removed it yet
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    /// Returns true if this player has blocked `user_id`
+    pub fn has_blocked(&self, user_id: &str) -> bool {
+        self.blocked_user_ids.iter().any(|id| id == user_id)
+    }
+
+    /// Returns true if `rating` falls within this player's requested rating range
+    pub fn accepts_rating(&self, rating: i32) -> bool {
+        self.min_rating.is_none_or(|min| rating >= min)
+            && self.max_rating.is_none_or(|max| rating <= max)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -19,6 +134,10 @@ pub struct GameMatchedMessage {
     pub opponent_id: String,
     pub color: String,
     pub time_control: String,
+    pub opponent_is_bot: bool,
+    pub rated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_fen: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1,50 +1,87 @@
 use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
 use lambda_runtime::Error;
-use serde_dynamo;
+use serde::Serialize;
+use shared::{Color, Game};
 use tracing::{error, info};
 
+use crate::bots::is_bot;
 use crate::models::{Connection, GameMatchedMessage};
+use crate::status::QueueStatusMessage;
 
-/// Sends a game_matched notification to a player via WebSocket
+/// Sends a game_matched notification to one of the game's players via WebSocket
 pub async fn notify_player(
     api_gateway: &ApiGatewayClient,
     dynamodb: &DynamoClient,
     connections_table: &str,
     user_id: &str,
-    game_id: &str,
-    opponent_id: &str,
-    color: &str,
-    time_control: &str,
+    game: &Game,
 ) {
-    info!("Notifying player {} of new game {}", user_id, game_id);
+    info!("Notifying player {} of new game {}", user_id, game.game_id);
 
+    let (color, opponent_id) = if game.white_player_id == user_id {
+        (Color::White, &game.black_player_id)
+    } else {
+        (Color::Black, &game.white_player_id)
+    };
+    let message = GameMatchedMessage {
+        action: "game_matched".to_string(),
+        game_id: game.game_id.clone(),
+        opponent_id: opponent_id.clone(),
+        color: color.as_str().to_string(),
+        time_control: game.time_control.clone(),
+        opponent_is_bot: is_bot(opponent_id),
+        rated: game.rated,
+        initial_fen: game.initial_fen.clone(),
+    };
+
+    if send_to_user(api_gateway, dynamodb, connections_table, user_id, &message).await {
+        info!(
+            "Successfully notified player {} of game {}",
+            user_id, game.game_id
+        );
+    }
+}
+
+/// Sends a queue_status update to a waiting player via WebSocket
+pub async fn send_queue_status(
+    api_gateway: &ApiGatewayClient,
+    dynamodb: &DynamoClient,
+    connections_table: &str,
+    user_id: &str,
+    status: &QueueStatusMessage,
+) {
+    send_to_user(api_gateway, dynamodb, connections_table, user_id, status).await;
+}
+
+/// Posts `message` as JSON to the user's connection, logging any failure
+///
+/// Returns true if the message was delivered.
+pub async fn send_to_user(
+    api_gateway: &ApiGatewayClient,
+    dynamodb: &DynamoClient,
+    connections_table: &str,
+    user_id: &str,
+    message: &impl Serialize,
+) -> bool {
     // Get the connection_id for this user
     let connection_id = match get_connection_id(dynamodb, connections_table, user_id).await {
         Ok(Some(conn_id)) => conn_id,
         Ok(None) => {
             error!("No active connection found for user {}", user_id);
-            return;
+            return false;
         }
         Err(e) => {
             error!("Failed to get connection for user {}: {:?}", user_id, e);
-            return;
+            return false;
         }
     };
 
-    let message = GameMatchedMessage {
-        action: "game_matched".to_string(),
-        game_id: game_id.to_string(),
-        opponent_id: opponent_id.to_string(),
-        color: color.to_string(),
-        time_control: time_control.to_string(),
-    };
-
-    let data = match serde_json::to_string(&message) {
+    let data = match serde_json::to_string(message) {
         Ok(d) => d,
         Err(e) => {
             error!("Failed to serialize message: {:?}", e);
-            return;
+            return false;
         }
     };
 
@@ -55,16 +92,16 @@ pub async fn notify_player(
         .send()
         .await
     {
-        Ok(_) => info!(
-            "Successfully notified player {} of game {}",
-            user_id, game_id
-        ),
-        Err(e) => error!("Failed to send notification to player {}: {:?}", user_id, e),
+        Ok(_) => true,
+        Err(e) => {
+            error!("Failed to send message to player {}: {:?}", user_id, e);
+            false
+        }
     }
 }
 
 /// Retrieves the WebSocket connection ID for a user by querying the UserIdIndex GSI
-async fn get_connection_id(
+pub async fn get_connection_id(
     dynamodb: &DynamoClient,
     connections_table: &str,
     user_id: &str,
@@ -90,7 +127,7 @@ async fn get_connection_id(
             let connection: Connection = serde_dynamo::from_item(item.clone())?;
             info!(
                 "Found connection {} for user {}",
-                connection.connection_id, user_id
+                connection.connection_id, connection.user_id
             );
             return Ok(Some(connection.connection_id));
         }
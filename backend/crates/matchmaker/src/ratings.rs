@@ -0,0 +1,261 @@
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::{AttributeValue, TransactWriteItem, Update};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use shared::rating::rate_game;
+use shared::variant::Variant;
+use shared::{Game, User};
+use tracing::{info, warn};
+
+/// Attempts at rating a game before giving up, when another game's update got in between
+const MAX_RATING_ATTEMPTS: usize = 3;
+
+/// One player's rating change from a game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RatingChange {
+    pub user_id: String,
+    /// The value stored before the game; None if the player had no rating in the variant
+    /// yet and started from their standard one
+    pub stored: Option<i32>,
+    pub new_rating: i32,
+}
+
+/// Rating changes a finished game makes, in the user attribute of its variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RatingUpdate {
+    pub attribute: &'static str,
+    pub white: RatingChange,
+    pub black: RatingChange,
+}
+
+/// The rating changes `game` makes, or None if it is casual or hasn't finished
+pub fn rating_update(game: &Game, white: &User, black: &User) -> Option<RatingUpdate> {
+    if !game.rated {
+        return None;
+    }
+    let result = game.result?;
+    let (white_rating, black_rating) = rate_game(
+        white.rating_in(game.variant),
+        black.rating_in(game.variant),
+        result,
+    );
+    let stored = |user: &User| match game.variant {
+        Variant::Standard => Some(user.rating),
+        Variant::Chess960 => user.chess960_rating,
+    };
+    Some(RatingUpdate {
+        attribute: game.variant.rating_attribute(),
+        white: RatingChange {
+            user_id: white.user_id.clone(),
+            stored: stored(white),
+            new_rating: white_rating,
+        },
+        black: RatingChange {
+            user_id: black.user_id.clone(),
+            stored: stored(black),
+            new_rating: black_rating,
+        },
+    })
+}
+
+/// Clients and tables needed to apply rating updates
+#[derive(Clone)]
+pub struct RatingContext {
+    pub dynamodb: DynamoClient,
+    pub users_table: String,
+    pub games_table: String,
+}
+
+impl RatingContext {
+    /// Reads USERS_TABLE and GAMES_TABLE
+    pub fn from_env(dynamodb: DynamoClient) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{} must be set", name));
+        Ok(Self {
+            dynamodb,
+            users_table: var("USERS_TABLE")?,
+            games_table: var("GAMES_TABLE")?,
+        })
+    }
+
+    /// Moves both players' ratings in the game's variant once a rated game has finished
+    ///
+    /// Casual games are left alone. The game is marked as rated in the same transaction,
+    /// so a redelivered stream record can't rate it twice, and each rating is only written
+    /// if it still holds the value it was computed from.
+    pub async fn apply(&self, game: &Game) -> Result<Option<RatingUpdate>, Error> {
+        if !game.rated {
+            info!("Game {} is casual, ratings are unchanged", game.game_id);
+            return Ok(None);
+        }
+
+        for attempt in 1..=MAX_RATING_ATTEMPTS {
+            let (Some(white), Some(black)) = (
+                self.load_user(&game.white_player_id).await?,
+                self.load_user(&game.black_player_id).await?,
+            ) else {
+                warn!("A player of game {} no longer exists", game.game_id);
+                return Ok(None);
+            };
+            let Some(update) = rating_update(game, &white, &black) else {
+                return Ok(None);
+            };
+
+            let items = vec![
+                self.build_rating_item(update.attribute, &update.white)?,
+                self.build_rating_item(update.attribute, &update.black)?,
+                self.build_rated_marker_item(&game.game_id)?,
+            ];
+            let result = self
+                .dynamodb
+                .transact_write_items()
+                .set_transact_items(Some(items))
+                .send()
+                .await;
+            let reasons = match result {
+                Ok(_) => {
+                    info!(
+                        "Rated game {}: {} {} -> {}, {} {} -> {}",
+                        game.game_id,
+                        white.user_id,
+                        white.rating_in(game.variant),
+                        update.white.new_rating,
+                        black.user_id,
+                        black.rating_in(game.variant),
+                        update.black.new_rating
+                    );
+                    return Ok(Some(update));
+                }
+                Err(e) => match e.as_service_error() {
+                    Some(TransactWriteItemsError::TransactionCanceledException(cancelled)) => {
+                        cancelled.cancellation_reasons().to_vec()
+                    }
+                    _ => return Err(e.into()),
+                },
+            };
+            if reasons.get(2).and_then(|reason| reason.code()) == Some("ConditionalCheckFailed") {
+                info!("Game {} was already rated", game.game_id);
+                return Ok(None);
+            }
+            warn!(
+                "A rating changed while rating game {} (attempt {}/{})",
+                game.game_id, attempt, MAX_RATING_ATTEMPTS
+            );
+        }
+
+        Err(format!("Gave up rating game {}", game.game_id).into())
+    }
+
+    async fn load_user(&self, user_id: &str) -> Result<Option<User>, Error> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.users_table)
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Builds the TransactWriteItem storing a new rating, if the old one is still there
+    fn build_rating_item(
+        &self,
+        attribute: &str,
+        change: &RatingChange,
+    ) -> Result<TransactWriteItem, Error> {
+        let update = Update::builder()
+            .table_name(&self.users_table)
+            .key("user_id", AttributeValue::S(change.user_id.clone()))
+            .update_expression("SET #rating = :new")
+            .expression_attribute_names("#rating", attribute)
+            .expression_attribute_values(":new", AttributeValue::N(change.new_rating.to_string()));
+        let update = match change.stored {
+            Some(stored) => update
+                .condition_expression("#rating = :stored")
+                .expression_attribute_values(":stored", AttributeValue::N(stored.to_string())),
+            None => update.condition_expression(
+                "attribute_exists(user_id) AND attribute_not_exists(#rating)",
+            ),
+        }
+        .build()
+        .map_err(|e| format!("Failed to build update: {:?}", e))?;
+
+        Ok(TransactWriteItem::builder().update(update).build())
+    }
+
+    /// Builds the TransactWriteItem marking the game as rated, unless it already is
+    fn build_rated_marker_item(&self, game_id: &str) -> Result<TransactWriteItem, Error> {
+        let update = Update::builder()
+            .table_name(&self.games_table)
+            .key("game_id", AttributeValue::S(game_id.to_string()))
+            .update_expression("SET ratings_applied = :true")
+            .condition_expression("attribute_not_exists(ratings_applied)")
+            .expression_attribute_values(":true", AttributeValue::Bool(true))
+            .build()
+            .map_err(|e| format!("Failed to build update: {:?}", e))?;
+
+        Ok(TransactWriteItem::builder().update(update).build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::{GameResult, GameStatus};
+
+    fn user(user_id: &str, rating: i32, chess960_rating: Option<i32>) -> User {
+        User {
+            user_id: user_id.to_string(),
+            rating,
+            chess960_rating,
+            blocked_user_ids: Vec::new(),
+        }
+    }
+
+    fn game(rated: bool, variant: Variant, result: Option<GameResult>) -> Game {
+        Game {
+            status: GameStatus::Completed,
+            variant,
+            rated,
+            result,
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_casual_and_unfinished_games_leave_ratings_alone() {
+        let (alice, bob) = (user("alice", 1500, None), user("bob", 1500, None));
+        let casual = game(false, Variant::Standard, Some(GameResult::WhiteWins));
+        assert_eq!(rating_update(&casual, &alice, &bob), None);
+        let unfinished = game(true, Variant::Standard, None);
+        assert_eq!(rating_update(&unfinished, &alice, &bob), None);
+    }
+
+    #[test]
+    fn test_rated_game_moves_standard_ratings() {
+        let (alice, bob) = (user("alice", 1500, Some(1700)), user("bob", 1500, None));
+        let rated = game(true, Variant::Standard, Some(GameResult::WhiteWins));
+        let update = rating_update(&rated, &alice, &bob).unwrap();
+        assert_eq!(update.attribute, "rating");
+        assert_eq!(update.white.stored, Some(1500));
+        assert_eq!(update.white.new_rating, 1510);
+        assert_eq!(update.black.new_rating, 1490);
+    }
+
+    #[test]
+    fn test_chess960_games_move_the_chess960_rating() {
+        let (alice, bob) = (user("alice", 1500, Some(1700)), user("bob", 1700, None));
+        let rated = game(true, Variant::Chess960, Some(GameResult::Draw));
+        let update = rating_update(&rated, &alice, &bob).unwrap();
+        assert_eq!(update.attribute, "chess960_rating");
+        assert_eq!(update.white.stored, Some(1700));
+        // Bob's first 960 game starts from his standard rating
+        assert_eq!(update.black.stored, None);
+        assert_eq!(
+            (update.white.new_rating, update.black.new_rating),
+            (1700, 1700)
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use aws_sdk_dynamodb::types::{AttributeValue, ReturnValue, TransactWriteItem, Update};
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use shared::{Color, ColorPreference, Game, GameStatus};
+use tracing::info;
+
+use crate::challenges::{ChallengeContext, ChallengeStatus};
+use crate::errors::ServiceError;
+use crate::game::{create_game, GameSetup};
+use crate::matching::unix_now;
+use crate::notifications::{notify_player, send_to_user};
+
+/// How long a rematch offer waits for the other player
+pub const REMATCH_WINDOW_SECS: u64 = 60;
+
+/// Rematch offers for one finished game, keyed by its game id
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct RematchOffer {
+    game_id: String,
+    status: ChallengeStatus,
+    #[serde(default)]
+    white_offered: bool,
+    #[serde(default)]
+    black_offered: bool,
+    /// DynamoDB TTL attribute; the offer lapses from this time on
+    expires_at: u64,
+}
+
+impl RematchOffer {
+    fn both_offered(&self) -> bool {
+        self.white_offered && self.black_offered
+    }
+}
+
+/// Websocket message telling a player their opponent wants a rematch
+#[derive(Debug, Serialize)]
+pub struct RematchOfferedMessage {
+    pub action: String, // "rematch_offered"
+    pub game_id: String,
+    pub offered_by: String,
+    pub expires_at: u64,
+}
+
+#[derive(Debug)]
+pub enum RematchOutcome {
+    /// Waiting for the opponent to offer too
+    Offered,
+    /// Both players offered and the new game was created
+    Started(Box<Game>),
+}
+
+/// The colour `user_id` played in `game`, if they played in it
+fn side_in(game: &Game, user_id: &str) -> Option<Color> {
+    if game.white_player_id == user_id {
+        Some(Color::White)
+    } else if game.black_player_id == user_id {
+        Some(Color::Black)
+    } else {
+        None
+    }
+}
+
+/// Same players, time control and starting position as `game`, with colours swapped
+///
+/// A rematch of a private game stays private. In an odds game the same player gives the
+/// odds again, from their new colour.
+fn rematch_setup(game: &Game) -> GameSetup {
+    // Odds are given by player1
+    let (player1_id, player2_id, preference) = match &game.odds {
+        Some(odds) if odds.giver_id == game.white_player_id => (
+            &game.white_player_id,
+            &game.black_player_id,
+            ColorPreference::Black,
+        ),
+        _ => (
+            &game.black_player_id,
+            &game.white_player_id,
+            ColorPreference::White,
+        ),
+    };
+    GameSetup {
+        game_id: None,
+        player1_id: player1_id.clone(),
+        player2_id: player2_id.clone(),
+        time_control: game.time_control.clone(),
+        rated: game.rated,
+        initial_fen: game.initial_fen.clone(),
+        tournament_id: None,
+        simul: None,
+        invite: game.invite.clone(),
+        odds: game.odds.as_ref().map(|odds| odds.odds),
+        variant: game.variant,
+        preference,
+    }
+}
+
+impl ChallengeContext {
+    /// Records that `user_id` wants a rematch of the finished game `game_id`
+    ///
+    /// The first offer notifies the opponent. An offer from the opponent within
+    /// REMATCH_WINDOW_SECS accepts it: the new game is created in the same transaction
+    /// that closes the offer, so two simultaneous accepts yield exactly one game.
+    pub async fn offer_rematch(
+        &self,
+        game_id: &str,
+        user_id: &str,
+    ) -> Result<RematchOutcome, ServiceError> {
+        let game = self.load_game(game_id).await?;
+        let side = side_in(&game, user_id).ok_or(ServiceError::Forbidden(
+            "Only the players of a game can ask for a rematch",
+        ))?;
+        if matches!(game.status, GameStatus::Active) {
+            return Err(ServiceError::Invalid(
+                "The game is still in progress".to_string(),
+            ));
+        }
+
+        let offer = self.record_offer(game_id, side).await?;
+        if !offer.both_offered() {
+            info!("User {} offered a rematch of game {}", user_id, game_id);
+            let opponent_id = match side {
+                Color::White => &game.black_player_id,
+                Color::Black => &game.white_player_id,
+            };
+            let message = RematchOfferedMessage {
+                action: "rematch_offered".to_string(),
+                game_id: game_id.to_string(),
+                offered_by: user_id.to_string(),
+                expires_at: offer.expires_at,
+            };
+            send_to_user(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                opponent_id,
+                &message,
+            )
+            .await;
+            return Ok(RematchOutcome::Offered);
+        }
+
+        let setup = rematch_setup(&game);
+        let claim = self.build_rematch_claim_item(game_id, unix_now()?)?;
+        let rematch = create_game(
+            &self.dynamodb,
+            &self.games_table,
+            &self.history,
+            &setup,
+            vec![claim],
+        )
+        .await?
+        .ok_or_else(|| ServiceError::unavailable("Rematch"))?;
+
+        info!(
+            "Rematch of game {} started as game {}",
+            game_id, rematch.game_id
+        );
+        for player_id in [&rematch.white_player_id, &rematch.black_player_id] {
+            notify_player(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                player_id,
+                &rematch,
+            )
+            .await;
+        }
+        Ok(RematchOutcome::Started(Box::new(rematch)))
+    }
+
+    /// Loads a game by id
+    pub async fn load_game(&self, game_id: &str) -> Result<Game, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.games_table)
+            .key("game_id", AttributeValue::S(game_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(serde_dynamo::from_item(item)?),
+            None => Err(ServiceError::NotFound("Game")),
+        }
+    }
+
+    /// Sets `side`'s flag on the game's offer, starting a new offer window if there is no
+    /// live one, and returns the offer as it now stands
+    async fn record_offer(&self, game_id: &str, side: Color) -> Result<RematchOffer, ServiceError> {
+        let now = unix_now()?;
+        let flag = match side {
+            Color::White => "white_offered",
+            Color::Black => "black_offered",
+        };
+
+        // An offer past its window that TTL hasn't removed yet is replaced; one that was
+        // accepted can't be offered again
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.rematches_table)
+            .key("game_id", AttributeValue::S(game_id.to_string()))
+            .update_expression(format!(
+                "SET {flag} = :true, \
+                 #status = if_not_exists(#status, :open), \
+                 expires_at = if_not_exists(expires_at, :expires)"
+            ))
+            .condition_expression(
+                "attribute_not_exists(game_id) OR (#status = :open AND expires_at > :now)",
+            )
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":true", AttributeValue::Bool(true))
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(
+                ":expires",
+                AttributeValue::N((now + REMATCH_WINDOW_SECS).to_string()),
+            )
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .return_values(ReturnValue::AllNew)
+            .send()
+            .await;
+
+        match result {
+            Ok(output) => Ok(serde_dynamo::from_item(
+                output.attributes.unwrap_or_default(),
+            )?),
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|se| se.is_conditional_check_failed_exception()) =>
+            {
+                self.restart_offer(game_id, side, now).await
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces an expired offer with a new one from `side`
+    async fn restart_offer(
+        &self,
+        game_id: &str,
+        side: Color,
+        now: u64,
+    ) -> Result<RematchOffer, ServiceError> {
+        let offer = RematchOffer {
+            game_id: game_id.to_string(),
+            status: ChallengeStatus::Open,
+            white_offered: side == Color::White,
+            black_offered: side == Color::Black,
+            expires_at: now + REMATCH_WINDOW_SECS,
+        };
+        let result = self
+            .dynamodb
+            .put_item()
+            .table_name(&self.rematches_table)
+            .set_item(Some(serde_dynamo::to_item(&offer)?))
+            .condition_expression("#status = :open AND expires_at <= :now")
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(offer),
+            // Already accepted, or another offer arrived in the meantime
+            Err(e)
+                if e.as_service_error()
+                    .is_some_and(|se| se.is_conditional_check_failed_exception()) =>
+            {
+                Err(ServiceError::unavailable("Rematch"))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Builds the TransactWriteItem closing the offer, if it's still open with both flags
+    fn build_rematch_claim_item(
+        &self,
+        game_id: &str,
+        now: u64,
+    ) -> Result<TransactWriteItem, Error> {
+        let update = Update::builder()
+            .table_name(&self.rematches_table)
+            .key("game_id", AttributeValue::S(game_id.to_string()))
+            .update_expression("SET #status = :accepted")
+            .condition_expression(
+                "#status = :open AND expires_at > :now AND white_offered = :true \
+                 AND black_offered = :true",
+            )
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":accepted", AttributeValue::S("accepted".to_string()))
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(":true", AttributeValue::Bool(true))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .build()
+            .map_err(|e| format!("Failed to build update: {:?}", e))?;
+
+        Ok(TransactWriteItem::builder().update(update).build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::odds::{MaterialOdds, Odds};
+    use shared::GameOdds;
+
+    fn game() -> Game {
+        Game {
+            white_player_id: "alice".to_string(),
+            black_player_id: "bob".to_string(),
+            time_control: "5+3".to_string(),
+            status: GameStatus::Completed,
+            initial_fen: Some(shared::fen::STARTING_FEN.to_string()),
+            result: Some(shared::GameResult::Draw),
+            ..Game::default()
+        }
+    }
+
+    #[test]
+    fn test_side_in() {
+        assert_eq!(side_in(&game(), "alice"), Some(Color::White));
+        assert_eq!(side_in(&game(), "bob"), Some(Color::Black));
+        assert_eq!(side_in(&game(), "carol"), None);
+    }
+
+    #[test]
+    fn test_rematch_swaps_colours_and_keeps_settings() {
+        let setup = rematch_setup(&game());
+        assert_eq!(setup.player1_id, "bob");
+        assert_eq!(setup.player2_id, "alice");
+        assert_eq!(setup.preference.fixed(), Some(Color::White));
+        assert_eq!(setup.time_control, "5+3");
+        assert!(!setup.rated);
+        assert_eq!(setup.initial_fen, game().initial_fen);
+        assert_eq!(setup.game_id, None);
+    }
+
+    #[test]
+    fn test_odds_rematch_keeps_the_giver() {
+        let odds = Odds {
+            material: Some(MaterialOdds::Queen),
+            time: None,
+        };
+        let game = Game {
+            odds: Some(GameOdds {
+                giver_id: "alice".to_string(),
+                odds,
+            }),
+            ..game()
+        };
+        let setup = rematch_setup(&game);
+        assert_eq!(setup.player1_id, "alice");
+        assert_eq!(setup.player2_id, "bob");
+        assert_eq!(setup.preference.fixed(), Some(Color::Black));
+        assert_eq!(setup.odds, Some(odds));
+    }
+
+    #[test]
+    fn test_offer_needs_both_players() {
+        let mut offer = RematchOffer {
+            game_id: "g1".to_string(),
+            status: ChallengeStatus::Open,
+            white_offered: true,
+            black_offered: false,
+            expires_at: 100,
+        };
+        assert!(!offer.both_offered());
+        offer.black_offered = true;
+        assert!(offer.both_offered());
+    }
+
+    #[test]
+    fn test_offer_flags_default_to_false() {
+        let item = std::collections::HashMap::from([
+            ("game_id".to_string(), AttributeValue::S("g1".to_string())),
+            ("status".to_string(), AttributeValue::S("open".to_string())),
+            ("white_offered".to_string(), AttributeValue::Bool(true)),
+            (
+                "expires_at".to_string(),
+                AttributeValue::N("100".to_string()),
+            ),
+        ]);
+        let offer: RematchOffer = serde_dynamo::from_item(item).unwrap();
+        assert!(offer.white_offered);
+        assert!(!offer.black_offered);
+    }
+}
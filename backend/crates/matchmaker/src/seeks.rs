@@ -0,0 +1,552 @@
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
+use aws_sdk_dynamodb::types::{AttributeValue, Delete, TransactWriteItem};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
+use shared::{ColorPreference, Game, User};
+use tracing::{error, info, warn};
+
+use crate::errors::ServiceError;
+use crate::game::{create_game, GameSetup};
+use crate::history::PairingHistory;
+use crate::matching::unix_now;
+use crate::notifications::notify_player;
+use crate::terms::{check_catalogue_time_control, GameTerms};
+
+/// How long a seek stays in the lobby before DynamoDB TTL removes it
+pub const SEEK_TTL_SECS: u64 = 30 * 60;
+/// Most seeks one user can have in the lobby at once
+pub const MAX_SEEKS_PER_USER: usize = 3;
+/// Rating shown for users without a stored rating, as in the queue
+const DEFAULT_RATING: i32 = 1200;
+
+/// An open offer to play, listed in the lobby until someone accepts it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Seek {
+    pub seek_id: String,
+    pub user_id: String,
+    /// The seeker's rating when the seek was created
+    pub rating: i32,
+    pub time_control: String,
+    pub rated: bool,
+    /// Colour the seeker asked for
+    #[serde(default)]
+    pub color: ColorPreference,
+    #[serde(default)]
+    pub variant: Variant,
+    pub created_at: u64,
+    /// DynamoDB TTL attribute; the seek can't be accepted from this time on
+    pub expires_at: u64,
+}
+
+/// Body of a create_seek message
+pub type SeekRequest = GameTerms;
+
+/// Websocket message confirming a new seek to its creator ("seek_created")
+#[derive(Debug, Serialize)]
+pub struct SeekMessage {
+    pub action: String,
+    pub seek: Seek,
+}
+
+/// Websocket message pushed to every connection whenever the lobby changes
+///
+/// Carries the whole lobby, oldest seek first, so a client never has to patch its copy.
+#[derive(Debug, Serialize)]
+pub struct LobbyUpdateMessage {
+    pub action: String, // "lobby_update"
+    pub seeks: Vec<Seek>,
+}
+
+impl Seek {
+    /// Builds a new seek from `request`, validating it
+    pub fn new(
+        seek_id: String,
+        user_id: &str,
+        rating: i32,
+        request: SeekRequest,
+        now: u64,
+    ) -> Result<Self, ServiceError> {
+        check_catalogue_time_control(&request.time_control)?;
+        Ok(Self {
+            seek_id,
+            user_id: user_id.to_string(),
+            rating,
+            time_control: request.time_control,
+            rated: request.rated,
+            color: request.color,
+            variant: request.variant,
+            created_at: now,
+            expires_at: now + SEEK_TTL_SECS,
+        })
+    }
+
+    pub fn is_open(&self, now: u64) -> bool {
+        self.expires_at > now
+    }
+
+    /// Checks that `user_id` may accept this seek
+    pub fn check_can_accept(&self, user_id: &str, now: u64) -> Result<(), ServiceError> {
+        if self.user_id == user_id {
+            return Err(ServiceError::Forbidden("You cannot accept your own seek"));
+        }
+        if !self.is_open(now) {
+            return Err(ServiceError::unavailable("Seek"));
+        }
+        Ok(())
+    }
+
+    /// The game played when `user_id` accepts, on the seeker's colour preference
+    pub fn game_setup(&self, user_id: &str) -> GameSetup {
+        GameSetup {
+            game_id: Some(self.seek_id.clone()),
+            player1_id: self.user_id.clone(),
+            player2_id: user_id.to_string(),
+            time_control: self.time_control.clone(),
+            rated: self.rated,
+            initial_fen: None,
+            tournament_id: None,
+            simul: None,
+            invite: None,
+            odds: None,
+            variant: self.variant,
+            preference: self.color,
+        }
+    }
+}
+
+/// The lobby as shown to clients: seeks still open, oldest first
+pub fn lobby(mut seeks: Vec<Seek>, now: u64) -> Vec<Seek> {
+    seeks.retain(|seek| seek.is_open(now));
+    seeks.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then(a.seek_id.cmp(&b.seek_id))
+    });
+    seeks
+}
+
+/// Clients and tables needed to run the lobby
+#[derive(Clone)]
+pub struct SeekContext {
+    pub dynamodb: DynamoClient,
+    pub api_gateway: ApiGatewayClient,
+    pub users_table: String,
+    pub seeks_table: String,
+    pub games_table: String,
+    pub connections_table: String,
+    pub history: PairingHistory,
+}
+
+impl SeekContext {
+    /// Reads USERS_TABLE, SEEKS_TABLE, GAMES_TABLE, CONNECTIONS_TABLE and the pairing
+    /// history settings
+    pub fn from_env(dynamodb: DynamoClient, api_gateway: ApiGatewayClient) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{} must be set", name));
+        Ok(Self {
+            dynamodb,
+            api_gateway,
+            users_table: var("USERS_TABLE")?,
+            seeks_table: var("SEEKS_TABLE")?,
+            games_table: var("GAMES_TABLE")?,
+            connections_table: var("CONNECTIONS_TABLE")?,
+            history: PairingHistory::from_env()?,
+        })
+    }
+
+    /// Adds a seek from `user_id` to the lobby and pushes the new lobby to everyone
+    pub async fn create(&self, user_id: &str, request: SeekRequest) -> Result<Seek, ServiceError> {
+        let now = unix_now()?;
+        let open = self
+            .user_seeks(user_id)
+            .await?
+            .into_iter()
+            .filter(|seek| seek.is_open(now))
+            .count();
+        if open >= MAX_SEEKS_PER_USER {
+            return Err(ServiceError::Invalid(format!(
+                "At most {} seeks can be open at once",
+                MAX_SEEKS_PER_USER
+            )));
+        }
+
+        let rating = self
+            .load_user(user_id)
+            .await?
+            .map_or(DEFAULT_RATING, |user| user.rating_in(request.variant));
+        let seek_id = uuid::Uuid::new_v4().simple().to_string();
+        let seek = Seek::new(seek_id, user_id, rating, request, now)?;
+
+        info!(
+            "Creating seek {} from {} ({}, rated: {})",
+            seek.seek_id, user_id, seek.time_control, seek.rated
+        );
+        self.dynamodb
+            .put_item()
+            .table_name(&self.seeks_table)
+            .set_item(Some(serde_dynamo::to_item(&seek)?))
+            .condition_expression("attribute_not_exists(seek_id)")
+            .send()
+            .await?;
+
+        self.broadcast_lobby().await;
+        Ok(seek)
+    }
+
+    /// Loads a seek, treating expired ones as gone
+    pub async fn get(&self, seek_id: &str) -> Result<Seek, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.seeks_table)
+            .key("seek_id", AttributeValue::S(seek_id.to_string()))
+            .send()
+            .await?;
+
+        let seek: Seek = match response.item {
+            Some(item) => serde_dynamo::from_item(item)?,
+            None => return Err(ServiceError::NotFound("Seek")),
+        };
+        if !seek.is_open(unix_now()?) {
+            return Err(ServiceError::NotFound("Seek"));
+        }
+        Ok(seek)
+    }
+
+    /// Every seek still open, oldest first
+    pub async fn list(&self) -> Result<Vec<Seek>, ServiceError> {
+        let mut seeks = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let response = self
+                .dynamodb
+                .scan()
+                .table_name(&self.seeks_table)
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            for item in response.items.unwrap_or_default() {
+                seeks.push(serde_dynamo::from_item(item)?);
+            }
+            exclusive_start_key = response.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+        Ok(lobby(seeks, unix_now()?))
+    }
+
+    /// Accepts a seek for `user_id` and creates the game
+    ///
+    /// The seek is deleted in the same transaction that creates the game, so two players
+    /// racing for it can't both get a game. Both players' other seeks are withdrawn
+    /// once they have a game to play.
+    pub async fn accept(&self, seek_id: &str, user_id: &str) -> Result<Game, ServiceError> {
+        let now = unix_now()?;
+        let seek = self.get(seek_id).await?;
+        seek.check_can_accept(user_id, now)?;
+        self.check_not_blocked(&seek.user_id, user_id).await?;
+
+        let claim = self.build_claim_item(&seek, now)?;
+        let game = create_game(
+            &self.dynamodb,
+            &self.games_table,
+            &self.history,
+            &seek.game_setup(user_id),
+            vec![claim],
+        )
+        .await?
+        .ok_or_else(|| ServiceError::unavailable("Seek"))?;
+
+        info!(
+            "Seek {} accepted by {}, created game {}",
+            seek_id, user_id, game.game_id
+        );
+        for player_id in [&game.white_player_id, &game.black_player_id] {
+            notify_player(
+                &self.api_gateway,
+                &self.dynamodb,
+                &self.connections_table,
+                player_id,
+                &game,
+            )
+            .await;
+        }
+        for player_id in [&seek.user_id, user_id] {
+            if let Err(e) = self.delete_user_seeks(player_id).await {
+                warn!("Failed to withdraw seeks of {}: {:?}", player_id, e);
+            }
+        }
+        self.broadcast_lobby().await;
+        Ok(game)
+    }
+
+    /// Withdraws one of the user's own seeks
+    pub async fn cancel(&self, seek_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        let result = self
+            .dynamodb
+            .delete_item()
+            .table_name(&self.seeks_table)
+            .key("seek_id", AttributeValue::S(seek_id.to_string()))
+            .condition_expression("user_id = :uid")
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                // Tell a missing seek apart from someone else's
+                self.get(seek_id).await?;
+                return Err(ServiceError::Forbidden("Only the seeker can cancel a seek"));
+            }
+            return Err(e.into());
+        }
+
+        info!("Seek {} cancelled by {}", seek_id, user_id);
+        self.broadcast_lobby().await;
+        Ok(())
+    }
+
+    /// Withdraws every seek the user has, e.g. when they disconnect
+    ///
+    /// Returns the number of seeks removed; the lobby is only pushed if it changed.
+    pub async fn remove_user_seeks(&self, user_id: &str) -> Result<usize, ServiceError> {
+        let removed = self.delete_user_seeks(user_id).await?;
+        if removed > 0 {
+            info!("Removed {} seeks of {}", removed, user_id);
+            self.broadcast_lobby().await;
+        }
+        Ok(removed)
+    }
+
+    /// Pushes the current lobby to every open connection, logging any failure
+    pub async fn broadcast_lobby(&self) {
+        let seeks = match self.list().await {
+            Ok(seeks) => seeks,
+            Err(e) => {
+                error!("Failed to load the lobby: {:?}", e);
+                return;
+            }
+        };
+        let message = LobbyUpdateMessage {
+            action: "lobby_update".to_string(),
+            seeks,
+        };
+        let data = match serde_json::to_string(&message) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to serialize lobby update: {:?}", e);
+                return;
+            }
+        };
+        let connection_ids = match self.connection_ids().await {
+            Ok(ids) => ids,
+            Err(e) => {
+                error!("Failed to list connections: {:?}", e);
+                return;
+            }
+        };
+
+        info!(
+            "Pushing lobby of {} seeks to {} connections",
+            message.seeks.len(),
+            connection_ids.len()
+        );
+        for connection_id in connection_ids {
+            // Connections closing right now are expected to fail
+            if let Err(e) = self
+                .api_gateway
+                .post_to_connection()
+                .connection_id(&connection_id)
+                .data(aws_sdk_apigatewaymanagement::primitives::Blob::new(
+                    data.clone(),
+                ))
+                .send()
+                .await
+            {
+                warn!(
+                    "Failed to send lobby update to connection {}: {:?}",
+                    connection_id, e
+                );
+            }
+        }
+    }
+
+    /// Deletes the user's seeks without pushing the lobby, returning how many went
+    async fn delete_user_seeks(&self, user_id: &str) -> Result<usize, ServiceError> {
+        let seeks = self.user_seeks(user_id).await?;
+        for seek in &seeks {
+            self.dynamodb
+                .delete_item()
+                .table_name(&self.seeks_table)
+                .key("seek_id", AttributeValue::S(seek.seek_id.clone()))
+                .send()
+                .await?;
+        }
+        Ok(seeks.len())
+    }
+
+    /// The user's seeks, found through the SeeksTable UserIdIndex GSI
+    async fn user_seeks(&self, user_id: &str) -> Result<Vec<Seek>, ServiceError> {
+        let response = self
+            .dynamodb
+            .query()
+            .table_name(&self.seeks_table)
+            .index_name("UserIdIndex")
+            .key_condition_expression("user_id = :uid")
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        response
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| Ok(serde_dynamo::from_item(item)?))
+            .collect()
+    }
+
+    /// Ids of every open websocket connection
+    async fn connection_ids(&self) -> Result<Vec<String>, ServiceError> {
+        let mut ids = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let response = self
+                .dynamodb
+                .scan()
+                .table_name(&self.connections_table)
+                .projection_expression("connection_id")
+                .set_exclusive_start_key(exclusive_start_key)
+                .send()
+                .await?;
+            for item in response.items.unwrap_or_default() {
+                if let Some(AttributeValue::S(id)) = item.get("connection_id") {
+                    ids.push(id.clone());
+                }
+            }
+            exclusive_start_key = response.last_evaluated_key;
+            if exclusive_start_key.is_none() {
+                break;
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Fails if either user has blocked the other
+    async fn check_not_blocked(&self, user_a: &str, user_b: &str) -> Result<(), ServiceError> {
+        let a = self.load_user(user_a).await?;
+        let b = self.load_user(user_b).await?;
+        let blocked = |user: &Option<User>, other: &str| {
+            user.as_ref()
+                .is_some_and(|u| u.blocked_user_ids.iter().any(|id| id == other))
+        };
+        if blocked(&a, user_b) || blocked(&b, user_a) {
+            warn!("Seek between {} and {} blocked", user_a, user_b);
+            return Err(ServiceError::Forbidden("You cannot play this user"));
+        }
+        Ok(())
+    }
+
+    async fn load_user(&self, user_id: &str) -> Result<Option<User>, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.users_table)
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Builds the TransactWriteItem removing the seek, if it's still open
+    fn build_claim_item(&self, seek: &Seek, now: u64) -> Result<TransactWriteItem, Error> {
+        let delete = Delete::builder()
+            .table_name(&self.seeks_table)
+            .key("seek_id", AttributeValue::S(seek.seek_id.clone()))
+            .condition_expression("attribute_exists(seek_id) AND expires_at > :now")
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .build()
+            .map_err(|e| format!("Failed to build delete: {:?}", e))?;
+
+        Ok(TransactWriteItem::builder().delete(delete).build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seek(seek_id: &str, created_at: u64) -> Seek {
+        let request = SeekRequest {
+            rated: true,
+            ..GameTerms::casual("3+0")
+        };
+        Seek::new(seek_id.to_string(), "alice", 1500, request, created_at).unwrap()
+    }
+
+    #[test]
+    fn test_new_seek_is_validated_and_expires_after_ttl() {
+        let seek = seek("s1", 1_000);
+        assert_eq!(seek.expires_at, 1_000 + SEEK_TTL_SECS);
+        assert_eq!(seek.rating, 1500);
+        assert!(matches!(
+            Seek::new("s".to_string(), "alice", 1500, GameTerms::casual("3+1"), 0),
+            Err(ServiceError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_only_others_can_accept_an_open_seek() {
+        let seek = seek("s1", 1_000);
+        assert!(seek.check_can_accept("bob", 1_001).is_ok());
+        assert!(matches!(
+            seek.check_can_accept("alice", 1_001),
+            Err(ServiceError::Forbidden(_))
+        ));
+        assert!(matches!(
+            seek.check_can_accept("bob", seek.expires_at),
+            Err(ServiceError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_game_setup_keeps_seekers_preference() {
+        let seek = Seek {
+            color: ColorPreference::Black,
+            ..seek("s1", 1_000)
+        };
+        let setup = seek.game_setup("bob");
+        assert_eq!(setup.game_id.as_deref(), Some("s1"));
+        assert_eq!(setup.player1_id, "alice");
+        assert_eq!(setup.player2_id, "bob");
+        assert_eq!(setup.preference, ColorPreference::Black);
+        assert!(setup.rated);
+        assert_eq!(setup.variant, Variant::Standard);
+
+        let chess960 = SeekRequest {
+            variant: Variant::Chess960,
+            ..GameTerms::casual("3+0")
+        };
+        let seek = Seek::new("s2".to_string(), "alice", 1500, chess960, 1_000).unwrap();
+        assert_eq!(seek.game_setup("bob").variant, Variant::Chess960);
+    }
+
+    #[test]
+    fn test_lobby_drops_expired_seeks_and_sorts_oldest_first() {
+        let seeks = vec![
+            seek("b", 2_000),
+            seek("expired", 0),
+            seek("a", 2_000),
+            seek("c", 1_000),
+        ];
+        let ids: Vec<String> = lobby(seeks, SEEK_TTL_SECS + 1)
+            .into_iter()
+            .map(|s| s.seek_id)
+            .collect();
+        assert_eq!(ids, ["c", "a", "b"]);
+    }
+}
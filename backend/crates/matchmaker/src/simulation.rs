@@ -0,0 +1,519 @@
+use lambda_runtime::Error;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use shared::variant::Variant;
+use std::collections::BTreeMap;
+
+use crate::matching::{match_player, rematch_pass, MatchParams, QueueStore, RecentOpponents};
+use crate::models::QueueEntry;
+use crate::strategy::StrategyConfig;
+
+/// Synthetic workload for an offline matchmaking run
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    /// Simulated time in seconds
+    pub duration_secs: u64,
+    /// Mean number of players joining the queue per second (Poisson arrivals)
+    pub arrivals_per_sec: f64,
+    /// Player ratings are drawn from a normal distribution with this mean and deviation
+    pub rating_mean: f64,
+    pub rating_sd: f64,
+    /// Time controls players join with, and their relative weights
+    pub time_controls: Vec<(String, f64)>,
+    /// Fraction of players who set min_rating/max_rating, and how wide that range is (±)
+    pub ranged_fraction: f64,
+    pub ranged_width: i32,
+    /// Players leave the queue after waiting this long without a match
+    pub patience_secs: u64,
+    /// How often the scheduled re-match pass runs
+    pub sweep_interval_secs: u64,
+    pub params: MatchParams,
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            duration_secs: 3600,
+            arrivals_per_sec: 0.5,
+            rating_mean: 1200.0,
+            rating_sd: 250.0,
+            time_controls: vec![
+                ("3+0".to_string(), 0.6),
+                ("1+0".to_string(), 0.3),
+                ("10+0".to_string(), 0.1),
+            ],
+            ranged_fraction: 0.1,
+            ranged_width: 200,
+            patience_secs: 120,
+            sweep_interval_secs: 10,
+            params: MatchParams::default(),
+            seed: 1,
+        }
+    }
+}
+
+/// Outcome of a simulation run for one time-control pool (or all of them)
+#[derive(Debug, Clone, Default)]
+pub struct PoolReport {
+    pub arrivals: usize,
+    /// Players who were matched (two per game)
+    pub matched: usize,
+    /// Players who gave up after `patience_secs`
+    pub abandoned: usize,
+    /// Players still queued when the simulation ended
+    pub still_waiting: usize,
+    /// Seconds each matched player spent in the queue
+    pub wait_times: Vec<u64>,
+    /// Rating difference of each game
+    pub rating_gaps: Vec<i32>,
+}
+
+impl PoolReport {
+    /// Fraction of players who left or were left in the queue without a game
+    pub fn unmatched_rate(&self) -> f64 {
+        if self.arrivals == 0 {
+            0.0
+        } else {
+            (self.abandoned + self.still_waiting) as f64 / self.arrivals as f64
+        }
+    }
+
+    pub fn wait_percentile(&self, percentile: f64) -> Option<u64> {
+        percentile_of(&self.wait_times, percentile)
+    }
+
+    pub fn gap_percentile(&self, percentile: f64) -> Option<i32> {
+        percentile_of(&self.rating_gaps, percentile)
+    }
+
+    /// Counts games per rating-gap band of `band_width` points, lowest band first
+    pub fn gap_histogram(&self, band_width: i32) -> BTreeMap<i32, usize> {
+        let mut histogram = BTreeMap::new();
+        for gap in &self.rating_gaps {
+            *histogram
+                .entry((gap / band_width) * band_width)
+                .or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    fn record_match(&mut self, now: u64, a: &QueueEntry, b: &QueueEntry) {
+        self.matched += 2;
+        self.wait_times.push(a.waited_secs(now));
+        self.wait_times.push(b.waited_secs(now));
+        self.rating_gaps.push((a.rating - b.rating).abs());
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimReport {
+    pub total: PoolReport,
+    pub pools: BTreeMap<String, PoolReport>,
+}
+
+impl SimReport {
+    fn pool(&mut self, time_control: &str) -> &mut PoolReport {
+        self.pools.entry(time_control.to_string()).or_default()
+    }
+
+    fn record_arrival(&mut self, time_control: &str) {
+        self.total.arrivals += 1;
+        self.pool(time_control).arrivals += 1;
+    }
+
+    fn record_match(&mut self, now: u64, a: &QueueEntry, b: &QueueEntry) {
+        self.total.record_match(now, a, b);
+        self.pool(&a.time_control).record_match(now, a, b);
+    }
+
+    fn record_abandoned(&mut self, time_control: &str) {
+        self.total.abandoned += 1;
+        self.pool(time_control).abandoned += 1;
+    }
+
+    fn record_still_waiting(&mut self, time_control: &str) {
+        self.total.still_waiting += 1;
+        self.pool(time_control).still_waiting += 1;
+    }
+}
+
+/// Returns the nearest-rank percentile (0-100) of `values`
+pub fn percentile_of<T: Copy + Ord>(values: &[T], percentile: f64) -> Option<T> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = ((percentile / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// In-memory stand-in for the queue table, keyed by (pool, rating bucket)
+///
+/// Everyone is connected and nobody has history, so every pair the matchmaker settles on
+/// becomes a game, which is recorded in `report`.
+struct MemoryQueue {
+    params: MatchParams,
+    buckets: BTreeMap<(String, i32), Vec<QueueEntry>>,
+    report: SimReport,
+}
+
+impl MemoryQueue {
+    fn new(params: MatchParams) -> Self {
+        Self {
+            params,
+            buckets: BTreeMap::new(),
+            report: SimReport::default(),
+        }
+    }
+
+    fn key(&self, entry: &QueueEntry) -> (String, i32) {
+        (entry.pool(), self.params.normalize_rating(entry.rating))
+    }
+
+    fn insert(&mut self, entry: QueueEntry) {
+        let key = self.key(&entry);
+        self.buckets.entry(key).or_default().push(entry);
+    }
+
+    fn remove(&mut self, entry: &QueueEntry) {
+        let key = self.key(entry);
+        if let Some(bucket) = self.buckets.get_mut(&key) {
+            bucket.retain(|e| e.user_id != entry.user_id);
+        }
+    }
+
+    /// Removes and returns everyone who has waited longer than `patience_secs`
+    fn expire(&mut self, now: u64, patience_secs: u64) -> Vec<QueueEntry> {
+        let mut expired = Vec::new();
+        for entries in self.buckets.values_mut() {
+            entries.retain(|e| {
+                let keep = e.waited_secs(now) <= patience_secs;
+                if !keep {
+                    expired.push(e.clone());
+                }
+                keep
+            });
+        }
+        expired
+    }
+
+    fn drain(&mut self) -> Vec<QueueEntry> {
+        std::mem::take(&mut self.buckets)
+            .into_values()
+            .flatten()
+            .collect()
+    }
+}
+
+impl QueueStore for MemoryQueue {
+    fn params(&self) -> MatchParams {
+        self.params
+    }
+
+    async fn bucket(
+        &mut self,
+        pool: &str,
+        bucket: i32,
+        _now: u64,
+    ) -> Result<Vec<QueueEntry>, Error> {
+        Ok(self
+            .buckets
+            .get(&(pool.to_string(), bucket))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn waiting_players(&mut self, _now: u64) -> Result<Vec<QueueEntry>, Error> {
+        Ok(self.buckets.values().flatten().cloned().collect())
+    }
+
+    async fn recent_opponents(
+        &mut self,
+        _player: &QueueEntry,
+        _now: u64,
+    ) -> Result<RecentOpponents, Error> {
+        Ok(RecentOpponents::default())
+    }
+
+    async fn ensure_connected(&mut self, _player: &QueueEntry) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    async fn complete_match(
+        &mut self,
+        player: &QueueEntry,
+        opponent: &QueueEntry,
+        now: u64,
+    ) -> Result<(), Error> {
+        self.remove(player);
+        self.remove(opponent);
+        self.report.record_match(now, player, opponent);
+        Ok(())
+    }
+}
+
+/// Runs the matching logic against a synthetic arrival stream
+///
+/// Each simulated second, new arrivals are matched immediately with the initial search
+/// range (as the stream-triggered matchmaker does), every `sweep_interval_secs` the
+/// re-match pass runs over the whole queue, and players who exceed their patience leave.
+/// Both use the production `match_player` and `rematch_pass` against a `MemoryQueue`.
+pub fn run(config: &SimConfig, strategies: &StrategyConfig) -> Result<SimReport, Error> {
+    // The in-memory queue never waits, so a single thread drives every step to completion
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(simulate(config, strategies))
+}
+
+async fn simulate(config: &SimConfig, strategies: &StrategyConfig) -> Result<SimReport, Error> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let params = config.params;
+    let mut queue = MemoryQueue::new(params);
+    let mut next_user = 0usize;
+
+    for now in 0..config.duration_secs {
+        for _ in 0..sample_poisson(&mut rng, config.arrivals_per_sec) {
+            next_user += 1;
+            let player = synthetic_player(config, &mut rng, next_user, now);
+            queue.report.record_arrival(&player.time_control);
+
+            let strategy = strategies.for_pool(&player.time_control);
+            let max_range = params.search_range_for_wait(0);
+            if match_player(&mut queue, &player, max_range, strategy, now, &mut rng)
+                .await?
+                .is_none()
+            {
+                queue.insert(player);
+            }
+        }
+
+        if config.sweep_interval_secs > 0 && now % config.sweep_interval_secs == 0 {
+            rematch_pass(&mut queue, strategies, now, &mut rng).await?;
+        }
+
+        for player in queue.expire(now, config.patience_secs) {
+            queue.report.record_abandoned(&player.time_control);
+        }
+    }
+
+    for player in queue.drain() {
+        queue.report.record_still_waiting(&player.time_control);
+    }
+
+    Ok(queue.report)
+}
+
+fn synthetic_player(config: &SimConfig, rng: &mut StdRng, n: usize, now: u64) -> QueueEntry {
+    let rating = (config.rating_mean + config.rating_sd * sample_standard_normal(rng))
+        .round()
+        .clamp(100.0, 3000.0) as i32;
+    let time_control = pick_weighted(rng, &config.time_controls);
+    let (min_rating, max_rating) = if rng.gen_bool(config.ranged_fraction.clamp(0.0, 1.0)) {
+        (
+            Some(rating - config.ranged_width),
+            Some(rating + config.ranged_width),
+        )
+    } else {
+        (None, None)
+    };
+
+    QueueEntry {
+        queue_key: format!(
+            "{}#{}",
+            time_control,
+            config.params.normalize_rating(rating)
+        ),
+        user_id: format!("sim-{}", n),
+        time_control,
+        rating,
+        joined_at: now.to_string(),
+        status: "waiting".to_string(),
+        expires_at: None,
+        blocked_user_ids: Vec::new(),
+        linked_queue_keys: Vec::new(),
+        bot_fallback: false,
+        rated: true,
+        variant: Variant::Standard,
+        tournament: None,
+        min_rating,
+        max_rating,
+    }
+}
+
+fn pick_weighted(rng: &mut StdRng, choices: &[(String, f64)]) -> String {
+    let total: f64 = choices.iter().map(|(_, weight)| weight.max(0.0)).sum();
+    let mut target = rng.gen::<f64>() * total;
+    for (name, weight) in choices {
+        target -= weight.max(0.0);
+        if target < 0.0 {
+            return name.clone();
+        }
+    }
+    choices
+        .last()
+        .map(|(name, _)| name.clone())
+        .unwrap_or_default()
+}
+
+/// Samples a standard normal variate using the Box-Muller transform
+fn sample_standard_normal(rng: &mut StdRng) -> f64 {
+    let u1 = rng.gen::<f64>().max(f64::MIN_POSITIVE);
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Samples a Poisson-distributed count with mean `lambda`
+fn sample_poisson(rng: &mut StdRng, lambda: f64) -> u64 {
+    if lambda <= 0.0 {
+        return 0;
+    }
+    if lambda > 30.0 {
+        // Normal approximation; Knuth's method underflows for large means
+        let sample = lambda + lambda.sqrt() * sample_standard_normal(rng);
+        return sample.round().max(0.0) as u64;
+    }
+    let limit = (-lambda).exp();
+    let mut count = 0;
+    let mut product = rng.gen::<f64>();
+    while product > limit {
+        count += 1;
+        product *= rng.gen::<f64>();
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SimConfig {
+        SimConfig {
+            duration_secs: 600,
+            arrivals_per_sec: 1.0,
+            ..SimConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_percentile_of() {
+        let values = vec![5, 1, 4, 2, 3];
+        assert_eq!(percentile_of(&values, 50.0), Some(3));
+        assert_eq!(percentile_of(&values, 100.0), Some(5));
+        assert_eq!(percentile_of(&values, 0.0), Some(1));
+        assert_eq!(percentile_of::<u64>(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_gap_histogram_bands() {
+        let report = PoolReport {
+            rating_gaps: vec![0, 49, 50, 120],
+            ..PoolReport::default()
+        };
+        let histogram = report.gap_histogram(50);
+        assert_eq!(histogram.get(&0), Some(&2));
+        assert_eq!(histogram.get(&50), Some(&1));
+        assert_eq!(histogram.get(&100), Some(&1));
+    }
+
+    #[test]
+    fn test_poisson_mean() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let samples = 20_000;
+        let total: u64 = (0..samples).map(|_| sample_poisson(&mut rng, 2.5)).sum();
+        let mean = total as f64 / samples as f64;
+        assert!((mean - 2.5).abs() < 0.1, "mean was {}", mean);
+    }
+
+    #[test]
+    fn test_every_arrival_is_accounted_for() {
+        let report = run(&config(), &StrategyConfig::default()).unwrap();
+        let total = &report.total;
+        assert!(total.arrivals > 0);
+        assert_eq!(
+            total.arrivals,
+            total.matched + total.abandoned + total.still_waiting
+        );
+        assert_eq!(total.wait_times.len(), total.matched);
+        assert_eq!(total.rating_gaps.len() * 2, total.matched);
+
+        let pool_arrivals: usize = report.pools.values().map(|p| p.arrivals).sum();
+        assert_eq!(pool_arrivals, total.arrivals);
+    }
+
+    #[test]
+    fn test_runs_are_reproducible_for_a_seed() {
+        let a = run(&config(), &StrategyConfig::default()).unwrap();
+        let b = run(&config(), &StrategyConfig::default()).unwrap();
+        assert_eq!(a.total.wait_times, b.total.wait_times);
+        assert_eq!(a.total.rating_gaps, b.total.rating_gaps);
+    }
+
+    #[test]
+    fn test_busy_queue_matches_nearly_everyone() {
+        let config = SimConfig {
+            arrivals_per_sec: 5.0,
+            ..config()
+        };
+        let report = run(&config, &StrategyConfig::default()).unwrap();
+        assert!(report.total.unmatched_rate() < 0.05);
+        // Nobody is paired outside the widest search range plus a bucket
+        assert!(report.total.rating_gaps.iter().all(|&gap| gap < 550));
+    }
+
+    #[test]
+    fn test_empty_queue_produces_empty_report() {
+        let config = SimConfig {
+            arrivals_per_sec: 0.0,
+            ..config()
+        };
+        let report = run(&config, &StrategyConfig::default()).unwrap();
+        assert_eq!(report.total.arrivals, 0);
+        assert_eq!(report.total.unmatched_rate(), 0.0);
+        assert_eq!(report.total.wait_percentile(50.0), None);
+    }
+
+    #[test]
+    fn test_batch_strategy_runs() {
+        let strategies = StrategyConfig::parse(Some("global_min_gap"), None).unwrap();
+        let report = run(&config(), &strategies).unwrap();
+        assert!(report.total.matched > 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_runs_the_production_search() {
+        let config = config();
+        let mut rng = StdRng::seed_from_u64(5);
+        let player = |n, rating| QueueEntry {
+            rating,
+            min_rating: None,
+            max_rating: None,
+            ..synthetic_player(&config, &mut StdRng::seed_from_u64(n), n as usize, 0)
+        };
+        let alice = player(1, 1200);
+        let blocked = QueueEntry {
+            blocked_user_ids: vec![alice.user_id.clone()],
+            ..player(2, 1210)
+        };
+        let bob = player(3, 1230);
+        let far = player(4, 1900);
+
+        let mut queue = MemoryQueue::new(config.params);
+        queue.insert(blocked.clone());
+        queue.insert(far.clone());
+        let strategy = StrategyConfig::default();
+        let strategy = strategy.for_pool(&alice.time_control);
+        let opponent = match_player(&mut queue, &alice, 50, strategy, 10, &mut rng)
+            .await
+            .unwrap();
+        assert!(opponent.is_none());
+
+        queue.insert(bob.clone());
+        let opponent = match_player(&mut queue, &alice, 50, strategy, 10, &mut rng)
+            .await
+            .unwrap();
+        assert_eq!(opponent.map(|e| e.user_id), Some(bob.user_id));
+        assert_eq!(queue.report.total.rating_gaps, vec![30]);
+        assert_eq!(queue.drain().len(), 2);
+    }
+}
@@ -0,0 +1,610 @@
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
+use shared::{Color, ColorPreference, Game, GameResult, HostClock, SimulBoard, User};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use crate::errors::ServiceError;
+use crate::game::{create_game, GameSetup};
+use crate::history::PairingHistory;
+use crate::matching::unix_now;
+use crate::notifications::{notify_player, send_to_user};
+use crate::terms::check_catalogue_time_control;
+
+/// Most opponents a host may take on at once
+pub const MAX_SIMUL_BOARDS: usize = 50;
+
+/// A host playing every participant at once, one game each
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Simul {
+    pub simul_id: String,
+    pub host_id: String,
+    pub name: String,
+    pub time_control: String,
+    /// Colour the host has on every board
+    pub host_color: Color,
+    #[serde(default)]
+    pub host_clock: HostClock,
+    pub rated: bool,
+    pub status: SimulStatus,
+    #[serde(default)]
+    pub participants: HashMap<String, SimulParticipant>,
+    pub created_at: u64,
+    /// Bumped on every write, so starting can't race a join
+    #[serde(default)]
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimulStatus {
+    /// Open for participants to join
+    Open,
+    InProgress,
+    /// Every board has a result
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SimulParticipant {
+    pub user_id: String,
+    pub rating: i32,
+    pub joined_at: u64,
+    /// Set when the simul starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+    /// Copied from the game once it has finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<GameResult>,
+}
+
+/// Body of a create-simul request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SimulRequest {
+    pub name: String,
+    pub time_control: String,
+    /// The host plays white unless asked otherwise
+    #[serde(default = "default_host_color")]
+    pub host_color: Color,
+    #[serde(default)]
+    pub host_clock: HostClock,
+    /// Simuls are casual unless asked otherwise
+    #[serde(default)]
+    pub rated: bool,
+}
+
+fn default_host_color() -> Color {
+    Color::White
+}
+
+/// Websocket message giving the host the whole simul ("simul_started" or "simul_updated")
+#[derive(Debug, Serialize)]
+pub struct SimulMessage {
+    pub action: String,
+    pub simul: Simul,
+}
+
+/// Websocket message forwarding one board's latest state to the host
+///
+/// The game is passed on as stored, so the host sees whatever the game server records.
+#[derive(Debug, Serialize)]
+pub struct SimulBoardMessage {
+    pub action: String, // "simul_board"
+    pub simul_id: String,
+    pub game: serde_json::Value,
+}
+
+impl SimulRequest {
+    /// Builds a new simul open for participants, validating the request
+    pub fn into_simul(
+        self,
+        simul_id: String,
+        host_id: &str,
+        now: u64,
+    ) -> Result<Simul, ServiceError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(ServiceError::Invalid("Name must not be empty".to_string()));
+        }
+        check_catalogue_time_control(&self.time_control)?;
+        Ok(Simul {
+            simul_id,
+            host_id: host_id.to_string(),
+            name: name.to_string(),
+            time_control: self.time_control,
+            host_color: self.host_color,
+            host_clock: self.host_clock,
+            rated: self.rated,
+            status: SimulStatus::Open,
+            participants: HashMap::new(),
+            created_at: now,
+            version: 0,
+        })
+    }
+}
+
+impl Simul {
+    /// Game id of a participant's board, fixed so a retried start can't create it twice
+    pub fn board_game_id(&self, user_id: &str) -> String {
+        format!("{}-{}", self.simul_id, user_id)
+    }
+
+    /// Participants in board order: highest rating first, ties by user id
+    pub fn board_order(&self) -> Vec<&SimulParticipant> {
+        let mut participants: Vec<&SimulParticipant> = self.participants.values().collect();
+        participants.sort_by(|a, b| b.rating.cmp(&a.rating).then(a.user_id.cmp(&b.user_id)));
+        participants
+    }
+
+    /// The game for one participant's board, with the host on their fixed colour
+    pub fn board_setup(&self, participant: &SimulParticipant) -> GameSetup {
+        GameSetup {
+            game_id: Some(self.board_game_id(&participant.user_id)),
+            player1_id: self.host_id.clone(),
+            player2_id: participant.user_id.clone(),
+            time_control: self.time_control.clone(),
+            rated: self.rated,
+            initial_fen: None,
+            tournament_id: None,
+            simul: Some(SimulBoard {
+                simul_id: self.simul_id.clone(),
+                host_id: self.host_id.clone(),
+                host_clock: self.host_clock,
+            }),
+            invite: None,
+            odds: None,
+            variant: Variant::Standard,
+            preference: match self.host_color {
+                Color::White => ColorPreference::White,
+                Color::Black => ColorPreference::Black,
+            },
+        }
+    }
+
+    /// True once every board has a result
+    pub fn all_boards_decided(&self) -> bool {
+        self.participants
+            .values()
+            .all(|p| p.game_id.is_none() || p.result.is_some())
+    }
+}
+
+/// Clients and tables needed to run simuls
+#[derive(Clone)]
+pub struct SimulContext {
+    pub dynamodb: DynamoClient,
+    pub api_gateway: ApiGatewayClient,
+    pub users_table: String,
+    pub simuls_table: String,
+    pub games_table: String,
+    pub connections_table: String,
+    pub history: PairingHistory,
+}
+
+impl SimulContext {
+    /// Reads USERS_TABLE, SIMULS_TABLE, GAMES_TABLE, CONNECTIONS_TABLE and the pairing
+    /// history settings
+    pub fn from_env(dynamodb: DynamoClient, api_gateway: ApiGatewayClient) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{} must be set", name));
+        Ok(Self {
+            dynamodb,
+            api_gateway,
+            users_table: var("USERS_TABLE")?,
+            simuls_table: var("SIMULS_TABLE")?,
+            games_table: var("GAMES_TABLE")?,
+            connections_table: var("CONNECTIONS_TABLE")?,
+            history: PairingHistory::from_env()?,
+        })
+    }
+
+    pub async fn create(
+        &self,
+        host_id: &str,
+        request: SimulRequest,
+    ) -> Result<Simul, ServiceError> {
+        let simul_id = uuid::Uuid::new_v4().simple().to_string();
+        let simul = request.into_simul(simul_id, host_id, unix_now()?)?;
+
+        info!("Creating simul {} hosted by {}", simul.simul_id, host_id);
+        self.dynamodb
+            .put_item()
+            .table_name(&self.simuls_table)
+            .set_item(Some(serde_dynamo::to_item(&simul)?))
+            .condition_expression("attribute_not_exists(simul_id)")
+            .send()
+            .await?;
+        Ok(simul)
+    }
+
+    pub async fn get(&self, simul_id: &str) -> Result<Simul, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.simuls_table)
+            .key("simul_id", AttributeValue::S(simul_id.to_string()))
+            .consistent_read(true)
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(serde_dynamo::from_item(item)?),
+            None => Err(ServiceError::NotFound("Simul")),
+        }
+    }
+
+    /// Adds `user_id` as a participant while the simul is open and has a free board
+    pub async fn join(&self, simul_id: &str, user_id: &str) -> Result<Simul, ServiceError> {
+        let simul = self.get(simul_id).await?;
+        if simul.host_id == user_id {
+            return Err(ServiceError::Forbidden("You can't play in your own simul"));
+        }
+        let user = self
+            .load_user(user_id)
+            .await?
+            .ok_or_else(|| ServiceError::Invalid("Create a profile before joining".to_string()))?;
+        let host = self.load_user(&simul.host_id).await?;
+        let blocked = user.blocked_user_ids.contains(&simul.host_id)
+            || host.is_some_and(|host| host.blocked_user_ids.iter().any(|id| id == user_id));
+        if blocked {
+            return Err(ServiceError::Forbidden("You can't join this simul"));
+        }
+        let participant = SimulParticipant {
+            user_id: user_id.to_string(),
+            rating: user.rating,
+            joined_at: unix_now()?,
+            game_id: None,
+            result: None,
+        };
+
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.simuls_table)
+            .key("simul_id", AttributeValue::S(simul_id.to_string()))
+            .update_expression("SET participants.#uid = :participant ADD version :one")
+            .condition_expression(
+                "#status = :open AND attribute_not_exists(participants.#uid) \
+                 AND size(participants) < :max",
+            )
+            .expression_attribute_names("#uid", user_id)
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(
+                ":participant",
+                AttributeValue::M(serde_dynamo::to_item(&participant)?),
+            )
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(":max", AttributeValue::N(MAX_SIMUL_BOARDS.to_string()))
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                let simul = self.get(simul_id).await?;
+                let message = if simul.participants.contains_key(user_id) {
+                    "You have already joined"
+                } else if simul.status != SimulStatus::Open {
+                    "The simul has already started"
+                } else {
+                    "The simul is full"
+                };
+                return Err(ServiceError::Conflict(message.to_string()));
+            }
+            return Err(e.into());
+        }
+
+        info!("{} joined simul {}", user_id, simul_id);
+        self.get(simul_id).await
+    }
+
+    /// Removes `user_id` before the simul starts
+    pub async fn leave(&self, simul_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.simuls_table)
+            .key("simul_id", AttributeValue::S(simul_id.to_string()))
+            .update_expression("REMOVE participants.#uid ADD version :one")
+            .condition_expression("#status = :open AND attribute_exists(participants.#uid)")
+            .expression_attribute_names("#uid", user_id)
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":open", AttributeValue::S("open".to_string()))
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                let simul = self.get(simul_id).await?;
+                if !simul.participants.contains_key(user_id) {
+                    return Err(ServiceError::Conflict("You have not joined".to_string()));
+                }
+                return Err(ServiceError::Conflict(
+                    "The simul has already started".to_string(),
+                ));
+            }
+            return Err(e.into());
+        }
+
+        info!("{} left simul {}", user_id, simul_id);
+        Ok(())
+    }
+
+    /// Creates one game per participant and sends the host every board at once (host only)
+    pub async fn start(&self, simul_id: &str, user_id: &str) -> Result<Simul, ServiceError> {
+        let mut simul = self.get(simul_id).await?;
+        if simul.host_id != user_id {
+            return Err(ServiceError::Forbidden("Only the host can start the simul"));
+        }
+        if simul.status != SimulStatus::Open {
+            return Err(ServiceError::Conflict(
+                "The simul has already started".to_string(),
+            ));
+        }
+        if simul.participants.is_empty() {
+            return Err(ServiceError::Conflict("Nobody has joined yet".to_string()));
+        }
+
+        let expected_version = simul.version;
+        let setups: Vec<GameSetup> = simul
+            .board_order()
+            .into_iter()
+            .map(|participant| simul.board_setup(participant))
+            .collect();
+        for setup in &setups {
+            match create_game(
+                &self.dynamodb,
+                &self.games_table,
+                &self.history,
+                setup,
+                Vec::new(),
+            )
+            .await?
+            {
+                // The host hears about every board in one message below
+                Some(game) => {
+                    notify_player(
+                        &self.api_gateway,
+                        &self.dynamodb,
+                        &self.connections_table,
+                        &setup.player2_id,
+                        &game,
+                    )
+                    .await
+                }
+                // Created by an earlier attempt at starting
+                None => warn!("Game {:?} already exists", setup.game_id),
+            }
+            if let Some(participant) = simul.participants.get_mut(&setup.player2_id) {
+                participant.game_id = setup.game_id.clone();
+            }
+        }
+        simul.status = SimulStatus::InProgress;
+        self.save(&mut simul, expected_version).await?;
+
+        info!("Simul {} started on {} boards", simul_id, setups.len());
+        self.send_to_host("simul_started", &simul).await;
+        Ok(simul)
+    }
+
+    /// Passes a board's latest state on to the host
+    pub async fn forward_board(&self, board: &SimulBoard, game: serde_json::Value) {
+        let message = SimulBoardMessage {
+            action: "simul_board".to_string(),
+            simul_id: board.simul_id.clone(),
+            game,
+        };
+        send_to_user(
+            &self.api_gateway,
+            &self.dynamodb,
+            &self.connections_table,
+            &board.host_id,
+            &message,
+        )
+        .await;
+    }
+
+    /// Records a finished board and finishes the simul once every board is decided
+    pub async fn record_result(&self, game: &Game) -> Result<(), ServiceError> {
+        let (Some(board), Some(result)) = (&game.simul, game.result) else {
+            return Ok(());
+        };
+        let participant_id = if game.white_player_id == board.host_id {
+            &game.black_player_id
+        } else {
+            &game.white_player_id
+        };
+
+        self.dynamodb
+            .update_item()
+            .table_name(&self.simuls_table)
+            .key("simul_id", AttributeValue::S(board.simul_id.clone()))
+            .update_expression("SET participants.#uid.#result = :result ADD version :one")
+            .condition_expression("attribute_exists(participants.#uid)")
+            .expression_attribute_names("#uid", participant_id)
+            .expression_attribute_names("#result", "result")
+            .expression_attribute_values(":result", serde_dynamo::to_attribute_value(result)?)
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .send()
+            .await?;
+        info!(
+            "Board {} of simul {} finished: {:?}",
+            game.game_id, board.simul_id, result
+        );
+
+        let mut simul = self.get(&board.simul_id).await?;
+        if simul.status == SimulStatus::InProgress && simul.all_boards_decided() {
+            let expected_version = simul.version;
+            simul.status = SimulStatus::Finished;
+            match self.save(&mut simul, expected_version).await {
+                Ok(()) => info!("Simul {} finished", simul.simul_id),
+                // Another result got there first and will finish it
+                Err(e) => warn!("Failed to finish simul {}: {}", simul.simul_id, e),
+            }
+        }
+        self.send_to_host("simul_updated", &simul).await;
+        Ok(())
+    }
+
+    async fn send_to_host(&self, action: &str, simul: &Simul) {
+        let message = SimulMessage {
+            action: action.to_string(),
+            simul: simul.clone(),
+        };
+        send_to_user(
+            &self.api_gateway,
+            &self.dynamodb,
+            &self.connections_table,
+            &simul.host_id,
+            &message,
+        )
+        .await;
+    }
+
+    /// Writes the simul back if nobody else has changed it since it was read
+    async fn save(&self, simul: &mut Simul, expected_version: u64) -> Result<(), ServiceError> {
+        simul.version = expected_version + 1;
+        let result = self
+            .dynamodb
+            .put_item()
+            .table_name(&self.simuls_table)
+            .set_item(Some(serde_dynamo::to_item(&*simul)?))
+            .condition_expression("version = :expected")
+            .expression_attribute_values(
+                ":expected",
+                AttributeValue::N(expected_version.to_string()),
+            )
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                return Err(ServiceError::Conflict(
+                    "The simul was changed in the meantime; try again".to_string(),
+                ));
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn load_user(&self, user_id: &str) -> Result<Option<User>, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.users_table)
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> SimulRequest {
+        serde_json::from_value(serde_json::json!({
+            "name": " Friday Simul ",
+            "time_control": "15+10"
+        }))
+        .unwrap()
+    }
+
+    fn simul(participants: &[(&str, i32)]) -> Simul {
+        let mut simul = request()
+            .into_simul("s1".to_string(), "host", 1_000)
+            .unwrap();
+        for (user_id, rating) in participants {
+            simul.participants.insert(
+                user_id.to_string(),
+                SimulParticipant {
+                    user_id: user_id.to_string(),
+                    rating: *rating,
+                    joined_at: 1_000,
+                    game_id: None,
+                    result: None,
+                },
+            );
+        }
+        simul
+    }
+
+    #[test]
+    fn test_request_defaults() {
+        let simul = simul(&[]);
+        assert_eq!(simul.name, "Friday Simul");
+        assert_eq!(simul.host_color, Color::White);
+        assert_eq!(simul.host_clock, HostClock::PerBoard);
+        assert!(!simul.rated);
+        assert_eq!(simul.status, SimulStatus::Open);
+    }
+
+    #[test]
+    fn test_invalid_requests_are_rejected() {
+        for bad in [
+            SimulRequest {
+                name: " ".to_string(),
+                ..request()
+            },
+            SimulRequest {
+                time_control: "rapid".to_string(),
+                ..request()
+            },
+        ] {
+            assert!(matches!(
+                bad.into_simul("s1".to_string(), "host", 0),
+                Err(ServiceError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_boards_put_the_host_on_a_fixed_colour() {
+        let mut simul = simul(&[("bob", 1400), ("alice", 1600), ("carol", 1400)]);
+        simul.host_color = Color::Black;
+        simul.host_clock = HostClock::Shared;
+        let order: Vec<&str> = simul
+            .board_order()
+            .iter()
+            .map(|p| p.user_id.as_str())
+            .collect();
+        assert_eq!(order, ["alice", "bob", "carol"]);
+
+        let setup = simul.board_setup(&simul.participants["bob"]);
+        assert_eq!(setup.game_id.as_deref(), Some("s1-bob"));
+        assert_eq!(setup.player1_id, "host");
+        assert_eq!(setup.player2_id, "bob");
+        assert_eq!(setup.preference, ColorPreference::Black);
+        assert_eq!(
+            setup.simul,
+            Some(SimulBoard {
+                simul_id: "s1".to_string(),
+                host_id: "host".to_string(),
+                host_clock: HostClock::Shared,
+            })
+        );
+    }
+
+    #[test]
+    fn test_finished_once_every_board_is_decided() {
+        let mut simul = simul(&[("alice", 1600), ("bob", 1400)]);
+        for participant in simul.participants.values_mut() {
+            participant.game_id = Some(format!("s1-{}", participant.user_id));
+        }
+        assert!(!simul.all_boards_decided());
+        simul.participants.get_mut("alice").unwrap().result = Some(GameResult::Draw);
+        assert!(!simul.all_boards_decided());
+        simul.participants.get_mut("bob").unwrap().result = Some(GameResult::WhiteWins);
+        assert!(simul.all_boards_decided());
+    }
+}
@@ -0,0 +1,211 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::matching::MatchParams;
+use shared::variant::Variant;
+
+/// Hours of recent matches used to estimate waits
+const ESTIMATE_WINDOW_HOURS: u64 = 2;
+/// Below this many matched players an estimate would mostly be noise
+const MIN_ESTIMATE_SAMPLE: u64 = 4;
+/// Hourly counters are kept a little longer than the estimate window needs
+const STATS_RETENTION_SECS: u64 = 2 * 24 * 60 * 60;
+
+/// Periodic update sent to a waiting player
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueueStatusMessage {
+    pub action: String, // "queue_status"
+    pub time_control: String,
+    #[serde(default)]
+    pub variant: Variant,
+    /// Whether the player is waiting for a rated or a casual game
+    pub rated: bool,
+    pub waited_secs: u64,
+    /// Current search range (± rating points)
+    pub search_range: i32,
+    /// Average wait of players matched in this pool recently, if there were enough
+    pub estimated_wait_secs: Option<u64>,
+}
+
+/// Matches recorded for one pool over the estimate window
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolStats {
+    pub players_matched: u64,
+    pub total_wait_secs: u64,
+}
+
+impl PoolStats {
+    pub fn estimated_wait_secs(&self) -> Option<u64> {
+        if self.players_matched < MIN_ESTIMATE_SAMPLE {
+            return None;
+        }
+        Some(self.total_wait_secs / self.players_matched)
+    }
+}
+
+/// Builds the status message for a player who has waited `waited_secs` in `time_control`
+pub fn queue_status(
+    time_control: &str,
+    variant: Variant,
+    rated: bool,
+    waited_secs: u64,
+    params: &MatchParams,
+    stats: &PoolStats,
+) -> QueueStatusMessage {
+    QueueStatusMessage {
+        action: "queue_status".to_string(),
+        time_control: time_control.to_string(),
+        variant,
+        rated,
+        waited_secs,
+        search_range: params.search_range_for_wait(waited_secs),
+        estimated_wait_secs: stats.estimated_wait_secs(),
+    }
+}
+
+/// Sums the hourly counters for a pool over the estimate window
+///
+/// Counters are kept per pool (see `QueueEntry::pool`), so casual and rated waits are
+/// estimated separately. The table's partition key predates pools and is still named
+/// time_control.
+pub async fn load_pool_stats(
+    dynamodb: &DynamoClient,
+    stats_table: &str,
+    pool: &str,
+    now: u64,
+) -> Result<PoolStats, Error> {
+    let since = (now / 3600).saturating_sub(ESTIMATE_WINDOW_HOURS - 1);
+    let query_result = dynamodb
+        .query()
+        .table_name(stats_table)
+        .key_condition_expression("time_control = :tc AND #hour >= :since")
+        .expression_attribute_names("#hour", "hour")
+        .expression_attribute_values(":tc", AttributeValue::S(pool.to_string()))
+        .expression_attribute_values(":since", AttributeValue::N(since.to_string()))
+        .send()
+        .await?;
+
+    let mut stats = PoolStats::default();
+    for item in query_result.items.unwrap_or_default() {
+        stats.players_matched += number(&item, "players_matched");
+        stats.total_wait_secs += number(&item, "total_wait_secs");
+    }
+
+    info!(
+        "Pool {} stats: {} players matched, {}s total wait",
+        pool, stats.players_matched, stats.total_wait_secs
+    );
+    Ok(stats)
+}
+
+/// Adds the waits of newly matched players to the pool's counter for the current hour
+pub async fn record_match_waits(
+    dynamodb: &DynamoClient,
+    stats_table: &str,
+    pool: &str,
+    waits: &[u64],
+    now: u64,
+) -> Result<(), Error> {
+    dynamodb
+        .update_item()
+        .table_name(stats_table)
+        .key("time_control", AttributeValue::S(pool.to_string()))
+        .key("hour", AttributeValue::N((now / 3600).to_string()))
+        .update_expression(
+            "ADD players_matched :players, total_wait_secs :wait SET expires_at = :expires",
+        )
+        .expression_attribute_values(":players", AttributeValue::N(waits.len().to_string()))
+        .expression_attribute_values(
+            ":wait",
+            AttributeValue::N(waits.iter().sum::<u64>().to_string()),
+        )
+        .expression_attribute_values(
+            ":expires",
+            AttributeValue::N((now + STATS_RETENTION_SECS).to_string()),
+        )
+        .send()
+        .await?;
+    Ok(())
+}
+
+fn number(item: &std::collections::HashMap<String, AttributeValue>, name: &str) -> u64 {
+    match item.get(name) {
+        Some(AttributeValue::N(n)) => n.parse().unwrap_or_else(|_| {
+            warn!("Invalid {} in pool stats: {}", name, n);
+            0
+        }),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_needs_enough_matches() {
+        let stats = PoolStats {
+            players_matched: MIN_ESTIMATE_SAMPLE - 1,
+            total_wait_secs: 30,
+        };
+        assert_eq!(stats.estimated_wait_secs(), None);
+    }
+
+    #[test]
+    fn test_estimate_is_average_wait() {
+        let stats = PoolStats {
+            players_matched: 10,
+            total_wait_secs: 125,
+        };
+        assert_eq!(stats.estimated_wait_secs(), Some(12));
+    }
+
+    #[test]
+    fn test_queue_status_uses_search_range_for_wait() {
+        let params = MatchParams::default();
+        let status = queue_status(
+            "blitz",
+            Variant::Chess960,
+            false,
+            30,
+            &params,
+            &PoolStats::default(),
+        );
+        assert_eq!(status.action, "queue_status");
+        assert_eq!(status.time_control, "blitz");
+        assert_eq!(status.variant, Variant::Chess960);
+        assert!(!status.rated);
+        assert_eq!(status.waited_secs, 30);
+        assert_eq!(status.search_range, params.search_range_for_wait(30));
+        assert_eq!(status.estimated_wait_secs, None);
+    }
+
+    #[test]
+    fn test_queue_status_serialization() {
+        let status = QueueStatusMessage {
+            action: "queue_status".to_string(),
+            time_control: "bullet".to_string(),
+            variant: Variant::Standard,
+            rated: true,
+            waited_secs: 5,
+            search_range: 50,
+            estimated_wait_secs: Some(20),
+        };
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "action": "queue_status",
+                "time_control": "bullet",
+                "variant": "standard",
+                "rated": true,
+                "waited_secs": 5,
+                "search_range": 50,
+                "estimated_wait_secs": 20
+            })
+        );
+    }
+}
@@ -0,0 +1,544 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::matching::{is_mutual_match, CandidateBucket, MatchParams};
+use crate::models::QueueEntry;
+
+/// Strategy used when no configuration is provided
+pub const DEFAULT_STRATEGY: &str = "bucket_walk";
+
+/// Decides which waiting players get paired together
+///
+/// `find_match_for_player` walks rating buckets in the order returned by `bucket_order`,
+/// collecting mutually acceptable candidates until `enough_candidates` says to stop, and
+/// then asks `select_opponent` to choose one. Strategies that can do better by looking at
+/// a whole pool at once override `pair_pool`, which the scheduled sweep uses.
+pub trait MatchStrategy: Send + Sync {
+    /// Name used to select the strategy in configuration
+    fn name(&self) -> &'static str;
+
+    /// Orders the buckets to query. `buckets` arrive nearest-first, own bucket first.
+    fn bucket_order(
+        &self,
+        _player: &QueueEntry,
+        buckets: Vec<CandidateBucket>,
+        _rng: &mut dyn RngCore,
+    ) -> Vec<CandidateBucket> {
+        buckets
+    }
+
+    /// Returns true once `candidates` are sufficient to pick an opponent without
+    /// querying the `remaining` buckets
+    fn enough_candidates(
+        &self,
+        _player: &QueueEntry,
+        _candidates: &[QueueEntry],
+        _remaining: &[CandidateBucket],
+    ) -> bool {
+        false
+    }
+
+    /// Picks the index of the opponent for `player` among `candidates`
+    fn select_opponent(
+        &self,
+        player: &QueueEntry,
+        candidates: &[QueueEntry],
+        rng: &mut dyn RngCore,
+    ) -> Option<usize>;
+
+    /// Pairs players of one time-control pool in a single batch
+    ///
+    /// Returns index pairs into `pool`, or None to fall back to matching each player in
+    /// turn with `find_match_for_player`. Batch pairing honours block lists through
+    /// `is_mutual_match` but does not consult the repeat-pairing history.
+    fn pair_pool(
+        &self,
+        _pool: &[QueueEntry],
+        _now: u64,
+        _params: &MatchParams,
+    ) -> Option<Vec<(usize, usize)>> {
+        None
+    }
+}
+
+/// The original algorithm: expand outwards bucket by bucket, trying up and down in random
+/// order, and pick a random opponent from the first bucket that has one
+pub struct BucketWalk;
+
+impl MatchStrategy for BucketWalk {
+    fn name(&self) -> &'static str {
+        "bucket_walk"
+    }
+
+    fn bucket_order(
+        &self,
+        _player: &QueueEntry,
+        buckets: Vec<CandidateBucket>,
+        rng: &mut dyn RngCore,
+    ) -> Vec<CandidateBucket> {
+        let mut by_distance: BTreeMap<i32, Vec<CandidateBucket>> = BTreeMap::new();
+        for bucket in buckets {
+            by_distance
+                .entry(bucket.offset.abs())
+                .or_default()
+                .push(bucket);
+        }
+
+        let mut ordered = Vec::new();
+        for (_, mut group) in by_distance {
+            group.shuffle(rng);
+            ordered.extend(group);
+        }
+        ordered
+    }
+
+    fn enough_candidates(
+        &self,
+        _player: &QueueEntry,
+        candidates: &[QueueEntry],
+        _remaining: &[CandidateBucket],
+    ) -> bool {
+        !candidates.is_empty()
+    }
+
+    fn select_opponent(
+        &self,
+        _player: &QueueEntry,
+        candidates: &[QueueEntry],
+        rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        if candidates.is_empty() {
+            None
+        } else {
+            Some(rng.gen_range(0..candidates.len()))
+        }
+    }
+}
+
+/// Picks the opponent with the smallest rating difference, longest-waiting on ties
+pub struct ClosestRating;
+
+impl MatchStrategy for ClosestRating {
+    fn name(&self) -> &'static str {
+        "closest_rating"
+    }
+
+    fn enough_candidates(
+        &self,
+        player: &QueueEntry,
+        candidates: &[QueueEntry],
+        remaining: &[CandidateBucket],
+    ) -> bool {
+        let best_gap = match candidates
+            .iter()
+            .map(|c| (c.rating - player.rating).abs())
+            .min()
+        {
+            Some(gap) => gap,
+            None => return false,
+        };
+        // Stop once no remaining bucket could hold anyone strictly closer
+        remaining.iter().all(|bucket| bucket.min_gap >= best_gap)
+    }
+
+    fn select_opponent(
+        &self,
+        player: &QueueEntry,
+        candidates: &[QueueEntry],
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| ((c.rating - player.rating).abs(), joined_at(c)))
+            .map(|(index, _)| index)
+    }
+}
+
+/// Picks the opponent who has been waiting longest, closest rating on ties
+pub struct LongestWaiting;
+
+impl MatchStrategy for LongestWaiting {
+    fn name(&self) -> &'static str {
+        "longest_waiting"
+    }
+
+    fn select_opponent(
+        &self,
+        player: &QueueEntry,
+        candidates: &[QueueEntry],
+        _rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| (joined_at(c), (c.rating - player.rating).abs()))
+            .map(|(index, _)| index)
+    }
+}
+
+/// Pairs a whole pool at once, maximising the number of games and then minimising the
+/// total rating gap across them
+///
+/// Players are sorted by rating and each is only paired with someone at most
+/// `PAIRING_WINDOW` places below them in that order, which keeps the search linear while
+/// still letting players skip over neighbours whose rating range excludes them. New
+/// arrivals are matched individually with the closest available rating.
+pub struct GlobalMinGap;
+
+const PAIRING_WINDOW: usize = 8;
+
+impl MatchStrategy for GlobalMinGap {
+    fn name(&self) -> &'static str {
+        "global_min_gap"
+    }
+
+    fn enough_candidates(
+        &self,
+        player: &QueueEntry,
+        candidates: &[QueueEntry],
+        remaining: &[CandidateBucket],
+    ) -> bool {
+        ClosestRating.enough_candidates(player, candidates, remaining)
+    }
+
+    fn select_opponent(
+        &self,
+        player: &QueueEntry,
+        candidates: &[QueueEntry],
+        rng: &mut dyn RngCore,
+    ) -> Option<usize> {
+        ClosestRating.select_opponent(player, candidates, rng)
+    }
+
+    fn pair_pool(
+        &self,
+        pool: &[QueueEntry],
+        now: u64,
+        params: &MatchParams,
+    ) -> Option<Vec<(usize, usize)>> {
+        let mut order: Vec<usize> = (0..pool.len()).collect();
+        order.sort_by_key(|&i| (pool[i].rating, joined_at(&pool[i])));
+
+        // best[k] = (games, total gap) using only the k lowest-rated players, and
+        // partner[k] = the position the k-th player was paired with, if any
+        let n = order.len();
+        let mut best = vec![(0usize, 0i64); n + 1];
+        let mut partner = vec![None; n + 1];
+        for k in 1..=n {
+            best[k] = best[k - 1];
+            let b = &pool[order[k - 1]];
+            for j in k.saturating_sub(PAIRING_WINDOW + 1)..k - 1 {
+                let a = &pool[order[j]];
+                if !can_pair(a, b, now, params) {
+                    continue;
+                }
+                let games = best[j].0 + 1;
+                let gap = best[j].1 + (b.rating - a.rating).abs() as i64;
+                if games > best[k].0 || (games == best[k].0 && gap < best[k].1) {
+                    best[k] = (games, gap);
+                    partner[k] = Some(j);
+                }
+            }
+        }
+
+        let mut pairs = Vec::new();
+        let mut k = n;
+        while k > 0 {
+            match partner[k] {
+                Some(j) => {
+                    pairs.push((order[j], order[k - 1]));
+                    k = j;
+                }
+                None => k -= 1,
+            }
+        }
+        pairs.reverse();
+        Some(pairs)
+    }
+}
+
+/// Returns true if the players accept each other's rating and either player's current
+/// search range reaches the other
+fn can_pair(a: &QueueEntry, b: &QueueEntry, now: u64, params: &MatchParams) -> bool {
+    a.user_id != b.user_id && is_mutual_match(a, b) && params.within_search_range(a, b, now)
+}
+
+fn joined_at(entry: &QueueEntry) -> u64 {
+    entry.joined_at.parse().unwrap_or(u64::MAX)
+}
+
+/// Looks up a strategy by its configuration name
+pub fn strategy_by_name(name: &str) -> Option<Arc<dyn MatchStrategy>> {
+    match name {
+        "bucket_walk" => Some(Arc::new(BucketWalk)),
+        "closest_rating" => Some(Arc::new(ClosestRating)),
+        "longest_waiting" => Some(Arc::new(LongestWaiting)),
+        "global_min_gap" => Some(Arc::new(GlobalMinGap)),
+        _ => None,
+    }
+}
+
+/// Which strategy to use for each time-control pool
+#[derive(Clone)]
+pub struct StrategyConfig {
+    default: Arc<dyn MatchStrategy>,
+    pools: HashMap<String, Arc<dyn MatchStrategy>>,
+}
+
+impl StrategyConfig {
+    /// Reads `MATCH_STRATEGY` (the default strategy) and `MATCH_STRATEGY_POOLS`
+    /// (per-pool overrides such as `1+0=closest_rating,3+0=global_min_gap`)
+    pub fn from_env() -> Result<Self, String> {
+        let default = std::env::var("MATCH_STRATEGY").ok();
+        let pools = std::env::var("MATCH_STRATEGY_POOLS").ok();
+        Self::parse(default.as_deref(), pools.as_deref())
+    }
+
+    pub fn parse(default: Option<&str>, pools: Option<&str>) -> Result<Self, String> {
+        let default_name = default
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .unwrap_or(DEFAULT_STRATEGY);
+        let default = strategy_by_name(default_name)
+            .ok_or_else(|| format!("Unknown match strategy: {}", default_name))?;
+
+        let mut pool_strategies = HashMap::new();
+        for assignment in pools.unwrap_or_default().split(',') {
+            let assignment = assignment.trim();
+            if assignment.is_empty() {
+                continue;
+            }
+            let (pool, name) = assignment
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid pool strategy assignment: {}", assignment))?;
+            let strategy = strategy_by_name(name.trim())
+                .ok_or_else(|| format!("Unknown match strategy: {}", name.trim()))?;
+            pool_strategies.insert(pool.trim().to_string(), strategy);
+        }
+
+        Ok(Self {
+            default,
+            pools: pool_strategies,
+        })
+    }
+
+    /// Returns the strategy configured for the given time-control pool
+    pub fn for_pool(&self, time_control: &str) -> &dyn MatchStrategy {
+        self.pools
+            .get(time_control)
+            .unwrap_or(&self.default)
+            .as_ref()
+    }
+}
+
+impl Default for StrategyConfig {
+    fn default() -> Self {
+        Self {
+            default: Arc::new(BucketWalk),
+            pools: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn entry(user_id: &str, rating: i32, joined_at: u64) -> QueueEntry {
+        QueueEntry {
+            queue_key: format!("blitz#{}", MatchParams::default().normalize_rating(rating)),
+            user_id: user_id.to_string(),
+            time_control: "blitz".to_string(),
+            rating,
+            joined_at: joined_at.to_string(),
+            ..QueueEntry::default()
+        }
+    }
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(7)
+    }
+
+    #[test]
+    fn test_bucket_walk_keeps_distance_order() {
+        let player = entry("p", 1210, 0);
+        let buckets = MatchParams::default().candidate_buckets(&player, 100);
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let ordered: Vec<i32> = BucketWalk
+                .bucket_order(&player, buckets.clone(), &mut rng)
+                .iter()
+                .map(|b| b.bucket)
+                .collect();
+            assert_eq!(ordered[0], 1200);
+            let mut second: Vec<i32> = ordered[1..3].to_vec();
+            second.sort();
+            assert_eq!(second, vec![1150, 1250]);
+            let mut third: Vec<i32> = ordered[3..5].to_vec();
+            third.sort();
+            assert_eq!(third, vec![1100, 1300]);
+        }
+    }
+
+    #[test]
+    fn test_bucket_walk_stops_at_first_candidate() {
+        let player = entry("p", 1210, 0);
+        let remaining = MatchParams::default().candidate_buckets(&player, 50);
+        assert!(!BucketWalk.enough_candidates(&player, &[], &remaining));
+        assert!(BucketWalk.enough_candidates(&player, &[entry("a", 1500, 0)], &remaining));
+    }
+
+    #[test]
+    fn test_closest_rating_selects_smallest_gap() {
+        let player = entry("p", 1210, 0);
+        let candidates = vec![
+            entry("a", 1290, 1),
+            entry("b", 1180, 2),
+            entry("c", 1350, 0),
+        ];
+        assert_eq!(
+            ClosestRating.select_opponent(&player, &candidates, &mut rng()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_closest_rating_breaks_ties_by_wait() {
+        let player = entry("p", 1200, 0);
+        let candidates = vec![entry("a", 1250, 20), entry("b", 1150, 10)];
+        assert_eq!(
+            ClosestRating.select_opponent(&player, &candidates, &mut rng()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_closest_rating_keeps_searching_while_closer_is_possible() {
+        let player = entry("p", 1240, 0);
+        let buckets = MatchParams::default().candidate_buckets(&player, 50);
+        assert_eq!(buckets[1].bucket, 1250);
+        // Found someone 30 points away in the own bucket; bucket 1250 could still hold
+        // someone only 10 points away
+        let found = vec![entry("a", 1210, 0)];
+        assert!(!ClosestRating.enough_candidates(&player, &found, &buckets[1..]));
+        // Bucket 1150 can't beat a 30 point gap
+        assert!(ClosestRating.enough_candidates(&player, &found, &buckets[2..]));
+    }
+
+    #[test]
+    fn test_longest_waiting_selects_earliest_join() {
+        let player = entry("p", 1200, 100);
+        let candidates = vec![
+            entry("a", 1200, 50),
+            entry("b", 1400, 10),
+            entry("c", 1210, 70),
+        ];
+        assert_eq!(
+            LongestWaiting.select_opponent(&player, &candidates, &mut rng()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_no_candidates_selects_nothing() {
+        let player = entry("p", 1200, 0);
+        for name in [
+            "bucket_walk",
+            "closest_rating",
+            "longest_waiting",
+            "global_min_gap",
+        ] {
+            let strategy = strategy_by_name(name).unwrap();
+            assert_eq!(strategy.select_opponent(&player, &[], &mut rng()), None);
+        }
+    }
+
+    #[test]
+    fn test_global_min_gap_pairs_neighbours() {
+        // Greedy closest-first would pair 1230 with 1260 and strand the others
+        let pool = vec![
+            entry("a", 1200, 0),
+            entry("b", 1230, 0),
+            entry("c", 1260, 0),
+            entry("d", 1290, 0),
+        ];
+        let pairs = GlobalMinGap
+            .pair_pool(&pool, 60, &MatchParams::default())
+            .unwrap();
+        assert_eq!(pairs, vec![(0, 1), (2, 3)]);
+    }
+
+    #[test]
+    fn test_global_min_gap_leaves_odd_player_out() {
+        let pool = vec![
+            entry("a", 1200, 0),
+            entry("b", 1210, 0),
+            entry("c", 1290, 0),
+        ];
+        let pairs = GlobalMinGap
+            .pair_pool(&pool, 60, &MatchParams::default())
+            .unwrap();
+        assert_eq!(pairs, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn test_global_min_gap_respects_search_range() {
+        // Both players just joined, so their range is only ±50
+        let pool = vec![entry("a", 1200, 100), entry("b", 1400, 100)];
+        assert_eq!(
+            GlobalMinGap
+                .pair_pool(&pool, 100, &MatchParams::default())
+                .unwrap(),
+            vec![]
+        );
+        // After a long wait they are within range
+        assert_eq!(
+            GlobalMinGap
+                .pair_pool(&pool, 200, &MatchParams::default())
+                .unwrap(),
+            vec![(0, 1)]
+        );
+    }
+
+    #[test]
+    fn test_global_min_gap_respects_requested_ratings() {
+        let mut b = entry("b", 1220, 0);
+        b.min_rating = Some(1300);
+        let pool = vec![entry("a", 1200, 0), b, entry("c", 1240, 0)];
+        let pairs = GlobalMinGap
+            .pair_pool(&pool, 60, &MatchParams::default())
+            .unwrap();
+        // b only wants stronger opponents, so a and c pair around them
+        assert_eq!(pairs, vec![(0, 2)]);
+    }
+
+    #[test]
+    fn test_config_defaults_to_bucket_walk() {
+        let config = StrategyConfig::parse(None, None).unwrap();
+        assert_eq!(config.for_pool("3+0").name(), "bucket_walk");
+    }
+
+    #[test]
+    fn test_config_per_pool_overrides() {
+        let config = StrategyConfig::parse(
+            Some("closest_rating"),
+            Some("1+0=global_min_gap, 10+0 = longest_waiting"),
+        )
+        .unwrap();
+        assert_eq!(config.for_pool("1+0").name(), "global_min_gap");
+        assert_eq!(config.for_pool("10+0").name(), "longest_waiting");
+        assert_eq!(config.for_pool("3+0").name(), "closest_rating");
+    }
+
+    #[test]
+    fn test_config_rejects_unknown_strategy() {
+        assert!(StrategyConfig::parse(Some("fastest"), None).is_err());
+        assert!(StrategyConfig::parse(None, Some("3+0=fastest")).is_err());
+        assert!(StrategyConfig::parse(None, Some("3+0")).is_err());
+    }
+}
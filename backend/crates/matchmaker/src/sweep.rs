@@ -0,0 +1,144 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use std::collections::HashSet;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::matching::{rematch_pass, unix_now, MatchParams, WAITING_FILTER};
+use crate::models::QueueEntry;
+use crate::notifications::send_queue_status;
+use crate::status::{load_pool_stats, queue_status, PoolStats};
+use crate::{complete_bot_match, ensure_connected, AppState};
+
+/// Number of re-match passes per scheduled invocation
+///
+/// The schedule can fire at most once a minute, so each invocation runs several passes
+/// to let search ranges widen in smaller steps than the schedule allows.
+const SWEEP_PASSES: u32 = 5;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Re-evaluates every waiting player with a search range widened by their time in queue
+pub async fn run_sweep(state: &AppState) -> Result<(), Error> {
+    for pass in 1..=SWEEP_PASSES {
+        info!("Starting re-match pass {}/{}", pass, SWEEP_PASSES);
+        let now = unix_now()?;
+        let mut rng = rand::thread_rng();
+        let matched = rematch_pass(&mut &*state, &state.strategies, now, &mut rng).await?;
+        info!(
+            "Re-match pass {}/{} complete, {} games created",
+            pass, SWEEP_PASSES, matched
+        );
+
+        if pass < SWEEP_PASSES {
+            tokio::time::sleep(SWEEP_INTERVAL).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Gives a bot opponent to every player in `pool` still unmatched who opted in and has
+/// waited long enough
+///
+/// Returns the ids of the players who got a bot game.
+pub async fn pair_with_bots(
+    state: &AppState,
+    pool: &[QueueEntry],
+    matched_users: &HashSet<String>,
+    now: u64,
+) -> HashSet<String> {
+    let mut bot_matched = HashSet::new();
+    for player in pool.iter().filter(|player| {
+        !matched_users.contains(&player.user_id) && state.bot_fallback.is_due(player, now)
+    }) {
+        match ensure_connected(state, player).await {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                warn!("Failed to check connection of {}: {:?}", player.user_id, e);
+                continue;
+            }
+        }
+        match complete_bot_match(state, player).await {
+            Ok(_) => {
+                bot_matched.insert(player.user_id.clone());
+            }
+            Err(e) => warn!("Failed to pair {} with a bot: {:?}", player.user_id, e),
+        }
+    }
+    bot_matched
+}
+
+/// Sends a queue_status update to each of `players`, who are still waiting in `pool_name`
+pub async fn push_queue_status(
+    state: &AppState,
+    pool_name: &str,
+    players: &[&QueueEntry],
+    now: u64,
+) {
+    let stats = match load_pool_stats(&state.dynamodb, &state.stats_table, pool_name, now).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!("Failed to load stats for pool {}: {:?}", pool_name, e);
+            PoolStats::default()
+        }
+    };
+
+    let params = MatchParams::default();
+    for player in players {
+        let status = queue_status(
+            &player.time_control,
+            player.variant,
+            player.rated,
+            player.waited_secs(now),
+            &params,
+            &stats,
+        );
+        send_queue_status(
+            &state.api_gateway,
+            &state.dynamodb,
+            &state.connections_table,
+            &player.user_id,
+            &status,
+        )
+        .await;
+    }
+}
+
+/// Scans the whole queue table for waiting players whose entries haven't expired by `now`
+pub async fn scan_waiting_players(
+    dynamodb: &DynamoClient,
+    queue_table: &str,
+    now: u64,
+) -> Result<Vec<QueueEntry>, Error> {
+    let mut players = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let scan_result = dynamodb
+            .scan()
+            .table_name(queue_table)
+            .filter_expression(WAITING_FILTER)
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(":waiting", AttributeValue::S("waiting".to_string()))
+            .expression_attribute_values(":now", AttributeValue::N(now.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+
+        for item in scan_result.items.unwrap_or_default() {
+            match serde_dynamo::from_item(item) {
+                Ok(entry) => players.push(entry),
+                Err(e) => warn!("Failed to parse queue entry: {:?}", e),
+            }
+        }
+
+        exclusive_start_key = scan_result.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(players)
+}
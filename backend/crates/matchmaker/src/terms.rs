@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use shared::time_control::{
+    find_time_control, invalid_time_control, parse_time_control, unsupported_time_control,
+};
+use shared::variant::Variant;
+use shared::ColorPreference;
+
+use crate::errors::ServiceError;
+
+/// The game a challenge, seek or invite offers
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GameTerms {
+    pub time_control: String,
+    /// Casual unless asked otherwise
+    #[serde(default)]
+    pub rated: bool,
+    #[serde(default)]
+    pub color: ColorPreference,
+    /// Standard chess unless asked otherwise
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+impl GameTerms {
+    /// A casual standard game at `time_control`, either colour
+    pub fn casual(time_control: &str) -> Self {
+        Self {
+            time_control: time_control.to_string(),
+            rated: false,
+            color: ColorPreference::Auto,
+            variant: Variant::Standard,
+        }
+    }
+}
+
+/// Rejects time controls outside the catalogue; custom ones are only for challenges
+pub fn check_catalogue_time_control(time_control: &str) -> Result<(), ServiceError> {
+    match find_time_control(time_control) {
+        Some(_) => Ok(()),
+        None => Err(ServiceError::Invalid(unsupported_time_control(
+            time_control,
+        ))),
+    }
+}
+
+/// Rejects custom time controls that aren't `<minutes>+<increment>` within bounds
+pub fn check_custom_time_control(time_control: &str) -> Result<(), ServiceError> {
+    match parse_time_control(time_control) {
+        Some(_) => Ok(()),
+        None => Err(ServiceError::Invalid(invalid_time_control(time_control))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let terms: GameTerms =
+            serde_json::from_value(serde_json::json!({ "time_control": "10+0" })).unwrap();
+        assert_eq!(terms, GameTerms::casual("10+0"));
+    }
+
+    #[test]
+    fn test_only_catalogue_time_controls_pass() {
+        assert!(check_catalogue_time_control("3+2").is_ok());
+        for time_control in ["", "blitz", "3+1", "7+4"] {
+            assert!(matches!(
+                check_catalogue_time_control(time_control),
+                Err(ServiceError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_custom_time_controls_must_parse() {
+        assert!(check_custom_time_control("7+4").is_ok());
+        for time_control in ["", "blitz", "abc+", "0+0", "600+0"] {
+            assert!(matches!(
+                check_custom_time_control(time_control),
+                Err(ServiceError::Invalid(_))
+            ));
+        }
+    }
+}
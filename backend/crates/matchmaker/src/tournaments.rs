@@ -0,0 +1,677 @@
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
+use aws_sdk_dynamodb::types::{AttributeValue, KeysAndAttributes};
+use aws_sdk_dynamodb::Client as DynamoClient;
+use lambda_runtime::Error;
+use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
+use shared::{ColorPreference, Game, GameResult, User};
+use std::collections::HashMap;
+use tournaments::{
+    Pairing, Round, Standings, Tournament, TournamentFormat, TournamentPlayer, TournamentStatus,
+};
+use tracing::info;
+
+use crate::arena::advance_arena;
+use crate::errors::ServiceError;
+use crate::game::{create_game, GameSetup};
+use crate::history::PairingHistory;
+use crate::matching::unix_now;
+use crate::notifications::notify_player;
+use crate::terms::check_catalogue_time_control;
+
+/// Most rounds a Swiss tournament may have
+pub const MAX_SWISS_ROUNDS: u32 = 20;
+/// Single or double round-robin
+pub const MAX_ROUND_ROBIN_CYCLES: u32 = 2;
+/// Shortest and longest arena, in minutes
+pub const ARENA_MINUTES: std::ops::RangeInclusive<u32> = 10..=720;
+/// BatchGetItem reads at most this many keys per call
+const BATCH_GET_LIMIT: usize = 100;
+
+/// Body of a create-tournament request
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TournamentRequest {
+    pub name: String,
+    pub time_control: String,
+    pub format: TournamentFormat,
+    /// Tournaments are rated unless asked otherwise
+    #[serde(default = "default_rated")]
+    pub rated: bool,
+    /// Standard chess unless asked otherwise; players are seeded by their rating in it
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+fn default_rated() -> bool {
+    true
+}
+
+impl TournamentRequest {
+    /// Builds a new tournament open for registration, validating the request
+    pub fn into_tournament(
+        self,
+        tournament_id: String,
+        organizer_id: &str,
+        now: u64,
+    ) -> Result<Tournament, ServiceError> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(ServiceError::Invalid("Name must not be empty".to_string()));
+        }
+        check_catalogue_time_control(&self.time_control)?;
+        match self.format {
+            TournamentFormat::Swiss { rounds } if !(1..=MAX_SWISS_ROUNDS).contains(&rounds) => {
+                return Err(ServiceError::Invalid(format!(
+                    "A Swiss tournament has between 1 and {} rounds",
+                    MAX_SWISS_ROUNDS
+                )));
+            }
+            TournamentFormat::Arena { minutes } if !ARENA_MINUTES.contains(&minutes) => {
+                return Err(ServiceError::Invalid(format!(
+                    "An arena lasts between {} and {} minutes",
+                    ARENA_MINUTES.start(),
+                    ARENA_MINUTES.end()
+                )));
+            }
+            TournamentFormat::RoundRobin { cycles }
+                if !(1..=MAX_ROUND_ROBIN_CYCLES).contains(&cycles) =>
+            {
+                return Err(ServiceError::Invalid(format!(
+                    "A round-robin has between 1 and {} cycles",
+                    MAX_ROUND_ROBIN_CYCLES
+                )));
+            }
+            TournamentFormat::Swiss { .. }
+            | TournamentFormat::RoundRobin { .. }
+            | TournamentFormat::Knockout { .. }
+            | TournamentFormat::Arena { .. } => {}
+        }
+        Ok(Tournament {
+            variant: self.variant,
+            ..Tournament::new(
+                tournament_id,
+                name.to_string(),
+                organizer_id.to_string(),
+                self.time_control,
+                self.rated,
+                self.format,
+                now,
+            )
+        })
+    }
+}
+
+/// A tournament together with its current standings
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TournamentView {
+    #[serde(flatten)]
+    pub tournament: Tournament,
+    pub standings: Standings,
+}
+
+impl From<Tournament> for TournamentView {
+    fn from(tournament: Tournament) -> Self {
+        let standings = Standings::of(&tournament);
+        Self {
+            tournament,
+            standings,
+        }
+    }
+}
+
+/// Game id for a tournament board, fixed so creating a round's games again can't duplicate them
+pub fn tournament_game_id(tournament_id: &str, round: u32, board: u32) -> String {
+    format!("{}-r{}-b{}", tournament_id, round, board)
+}
+
+/// Copies finished games' results into the round's pairings
+pub fn apply_results(round: &mut Round, results: &HashMap<String, GameResult>) {
+    for pairing in round.pairings.iter_mut() {
+        if let Some(result) = pairing.game_id.as_ref().and_then(|id| results.get(id)) {
+            pairing.result = Some(*result);
+        }
+    }
+}
+
+/// Clients and tables needed to run tournaments
+#[derive(Clone)]
+pub struct TournamentContext {
+    pub dynamodb: DynamoClient,
+    pub api_gateway: ApiGatewayClient,
+    pub users_table: String,
+    pub tournaments_table: String,
+    pub games_table: String,
+    /// Arena players wait for their next game in the matchmaking queue
+    pub queue_table: String,
+    pub connections_table: String,
+    pub history: PairingHistory,
+}
+
+impl TournamentContext {
+    /// Reads USERS_TABLE, TOURNAMENTS_TABLE, GAMES_TABLE, QUEUE_TABLE, CONNECTIONS_TABLE
+    /// and the pairing history settings
+    pub fn from_env(dynamodb: DynamoClient, api_gateway: ApiGatewayClient) -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).map_err(|_| format!("{} must be set", name));
+        Ok(Self {
+            dynamodb,
+            api_gateway,
+            users_table: var("USERS_TABLE")?,
+            tournaments_table: var("TOURNAMENTS_TABLE")?,
+            games_table: var("GAMES_TABLE")?,
+            queue_table: var("QUEUE_TABLE")?,
+            connections_table: var("CONNECTIONS_TABLE")?,
+            history: PairingHistory::from_env()?,
+        })
+    }
+
+    pub async fn create(
+        &self,
+        organizer_id: &str,
+        request: TournamentRequest,
+    ) -> Result<Tournament, ServiceError> {
+        let tournament_id = uuid::Uuid::new_v4().simple().to_string();
+        let tournament = request.into_tournament(tournament_id, organizer_id, unix_now()?)?;
+
+        info!(
+            "Creating tournament {} ({:?}) by {}",
+            tournament.tournament_id, tournament.format, organizer_id
+        );
+        self.dynamodb
+            .put_item()
+            .table_name(&self.tournaments_table)
+            .set_item(Some(serde_dynamo::to_item(&tournament)?))
+            .condition_expression("attribute_not_exists(tournament_id)")
+            .send()
+            .await?;
+        Ok(tournament)
+    }
+
+    pub async fn get(&self, tournament_id: &str) -> Result<Tournament, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.tournaments_table)
+            .key(
+                "tournament_id",
+                AttributeValue::S(tournament_id.to_string()),
+            )
+            .consistent_read(true)
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(serde_dynamo::from_item(item)?),
+            None => Err(ServiceError::NotFound("Tournament")),
+        }
+    }
+
+    /// Registers `user_id` at their current rating, while registration is open
+    ///
+    /// Arenas stay open for registration while they run. Bumps the version so a round being paired concurrently is retried with the player.
+    pub async fn register(
+        &self,
+        tournament_id: &str,
+        user_id: &str,
+    ) -> Result<Tournament, ServiceError> {
+        let user = self.load_user(user_id).await?.ok_or_else(|| {
+            ServiceError::Invalid("Create a profile before registering".to_string())
+        })?;
+        let variant = self.get(tournament_id).await?.variant;
+        let player = TournamentPlayer {
+            user_id: user_id.to_string(),
+            rating: user.rating_in(variant),
+            registered_at: unix_now()?,
+        };
+
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.tournaments_table)
+            .key(
+                "tournament_id",
+                AttributeValue::S(tournament_id.to_string()),
+            )
+            .update_expression("SET players.#uid = :player ADD version :one")
+            .condition_expression(
+                "attribute_exists(tournament_id) AND attribute_not_exists(players.#uid) \
+                 AND (#status = :registering \
+                 OR (#status = :in_progress AND #format.#type = :arena))",
+            )
+            .expression_attribute_names("#uid", user_id)
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_names("#format", "format")
+            .expression_attribute_names("#type", "type")
+            .expression_attribute_values(
+                ":in_progress",
+                AttributeValue::S("in_progress".to_string()),
+            )
+            .expression_attribute_values(":arena", AttributeValue::S("arena".to_string()))
+            .expression_attribute_values(
+                ":player",
+                AttributeValue::M(serde_dynamo::to_item(&player)?),
+            )
+            .expression_attribute_values(
+                ":registering",
+                AttributeValue::S("registering".to_string()),
+            )
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                // Tell apart the reasons the condition can fail
+                let tournament = self.get(tournament_id).await?;
+                if tournament.players.contains_key(user_id) {
+                    return Err(ServiceError::Conflict(
+                        "You are already registered".to_string(),
+                    ));
+                }
+                return Err(ServiceError::Conflict("Registration is closed".to_string()));
+            }
+            return Err(e.into());
+        }
+
+        info!("{} registered for tournament {}", user_id, tournament_id);
+        self.get(tournament_id).await
+    }
+
+    /// Withdraws `user_id` before the first round is paired
+    pub async fn withdraw(&self, tournament_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        let result = self
+            .dynamodb
+            .update_item()
+            .table_name(&self.tournaments_table)
+            .key(
+                "tournament_id",
+                AttributeValue::S(tournament_id.to_string()),
+            )
+            .update_expression("REMOVE players.#uid ADD version :one")
+            .condition_expression("#status = :registering AND attribute_exists(players.#uid)")
+            .expression_attribute_names("#uid", user_id)
+            .expression_attribute_names("#status", "status")
+            .expression_attribute_values(
+                ":registering",
+                AttributeValue::S("registering".to_string()),
+            )
+            .expression_attribute_values(":one", AttributeValue::N("1".to_string()))
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                let tournament = self.get(tournament_id).await?;
+                if !tournament.players.contains_key(user_id) {
+                    return Err(ServiceError::Conflict("You are not registered".to_string()));
+                }
+                return Err(ServiceError::Conflict(
+                    "The tournament has already started".to_string(),
+                ));
+            }
+            return Err(e.into());
+        }
+
+        info!("{} withdrew from tournament {}", user_id, tournament_id);
+        Ok(())
+    }
+
+    /// Pairs the next round once every game of the current one has a result
+    ///
+    /// After the last round this finishes the tournament instead. Arenas have no rounds:
+    /// the first call starts the clock and one after the time is up finishes it. The round
+    /// is saved with a version check before its games are created, so if two requests race
+    /// only the winner's pairings get games. Games have fixed ids per board, and a call
+    /// while the round is unfinished creates any a failed start left out.
+    pub async fn start_next_round(
+        &self,
+        tournament_id: &str,
+        user_id: &str,
+    ) -> Result<Tournament, ServiceError> {
+        let mut tournament = self.get(tournament_id).await?;
+        if tournament.organizer_id != user_id {
+            return Err(ServiceError::Forbidden(
+                "Only the organizer can start rounds",
+            ));
+        }
+        if tournament.status == TournamentStatus::Finished {
+            return Err(ServiceError::Conflict(
+                "The tournament has finished".to_string(),
+            ));
+        }
+
+        let expected_version = tournament.version;
+        let Some(format) = tournament.format.round_format() else {
+            advance_arena(&mut tournament, unix_now()?)?;
+            self.save(&mut tournament, expected_version).await?;
+            if tournament.status == TournamentStatus::InProgress {
+                for user_id in tournament.ranked_player_ids() {
+                    self.enqueue_arena_player(&tournament, &user_id).await?;
+                }
+            }
+            return Ok(tournament);
+        };
+        if let Some(round) = tournament.rounds.last_mut() {
+            let results = self.load_results(round).await?;
+            apply_results(round, &results);
+        }
+        if let Some(round) = tournament.rounds.last() {
+            if !round.is_complete() {
+                self.create_round_games(&tournament, round).await?;
+                return Err(ServiceError::Conflict(format!(
+                    "Round {} still has games in progress",
+                    round.number
+                )));
+            }
+        }
+
+        let Some(mut pairings) = format.pair_next(&tournament)? else {
+            tournament.status = TournamentStatus::Finished;
+            info!("Tournament {} finished", tournament_id);
+            self.save(&mut tournament, expected_version).await?;
+            return Ok(tournament);
+        };
+
+        let number = tournament.rounds.len() as u32 + 1;
+        for pairing in pairings.iter_mut().filter(|p| !p.is_bye()) {
+            pairing.game_id = Some(tournament_game_id(tournament_id, number, pairing.board));
+        }
+        tournament.rounds.push(Round { number, pairings });
+        tournament.status = TournamentStatus::InProgress;
+        self.save(&mut tournament, expected_version).await?;
+
+        let round = &tournament.rounds[tournament.rounds.len() - 1];
+        self.create_round_games(&tournament, round).await?;
+        info!(
+            "Paired round {} of tournament {} on {} boards",
+            number,
+            tournament_id,
+            round.pairings.len()
+        );
+        Ok(tournament)
+    }
+
+    /// Creates the games of a saved round that don't exist yet
+    async fn create_round_games(
+        &self,
+        tournament: &Tournament,
+        round: &Round,
+    ) -> Result<(), ServiceError> {
+        for pairing in round.pairings.iter().filter(|p| !p.is_decided()) {
+            self.create_board_game(tournament, pairing).await?;
+        }
+        Ok(())
+    }
+
+    /// Creates the game for one board and tells both players about it
+    async fn create_board_game(
+        &self,
+        tournament: &Tournament,
+        pairing: &Pairing,
+    ) -> Result<(), ServiceError> {
+        let (Some(black_id), Some(game_id)) = (&pairing.black_id, &pairing.game_id) else {
+            return Ok(());
+        };
+        let setup = GameSetup {
+            game_id: Some(game_id.clone()),
+            player1_id: pairing.white_id.clone(),
+            player2_id: black_id.clone(),
+            time_control: tournament.time_control.clone(),
+            rated: tournament.rated,
+            initial_fen: None,
+            tournament_id: Some(tournament.tournament_id.clone()),
+            simul: None,
+            invite: None,
+            odds: None,
+            variant: tournament.variant,
+            // Colours come from the pairing
+            preference: ColorPreference::White,
+        };
+        match create_game(
+            &self.dynamodb,
+            &self.games_table,
+            &self.history,
+            &setup,
+            Vec::new(),
+        )
+        .await?
+        {
+            Some(game) => {
+                for player_id in [&game.white_player_id, &game.black_player_id] {
+                    notify_player(
+                        &self.api_gateway,
+                        &self.dynamodb,
+                        &self.connections_table,
+                        player_id,
+                        &game,
+                    )
+                    .await;
+                }
+            }
+            // Created by an earlier call for this round
+            None => info!("Game {} already exists", game_id),
+        }
+        Ok(())
+    }
+
+    /// Reads the results of the round's undecided games
+    async fn load_results(
+        &self,
+        round: &Round,
+    ) -> Result<HashMap<String, GameResult>, ServiceError> {
+        let game_ids: Vec<&String> = round
+            .pairings
+            .iter()
+            .filter(|p| !p.is_decided())
+            .filter_map(|p| p.game_id.as_ref())
+            .collect();
+
+        let mut results = HashMap::new();
+        for chunk in game_ids.chunks(BATCH_GET_LIMIT) {
+            let mut keys = KeysAndAttributes::builder();
+            for game_id in chunk {
+                keys = keys.keys(HashMap::from([(
+                    "game_id".to_string(),
+                    AttributeValue::S(game_id.to_string()),
+                )]));
+            }
+            let keys = keys
+                .build()
+                .map_err(|e| Error::from(format!("Failed to build keys: {:?}", e)))?;
+            let response = self
+                .dynamodb
+                .batch_get_item()
+                .request_items(&self.games_table, keys)
+                .send()
+                .await?;
+
+            // Games left unprocessed count as still in progress until the next attempt
+            let items = response
+                .responses
+                .and_then(|mut responses| responses.remove(&self.games_table))
+                .unwrap_or_default();
+            for item in items {
+                let game: Game = serde_dynamo::from_item(item)?;
+                if let Some(result) = game.result {
+                    results.insert(game.game_id, result);
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Writes the tournament back if nobody else has changed it since it was read
+    pub async fn save(
+        &self,
+        tournament: &mut Tournament,
+        expected_version: u64,
+    ) -> Result<(), ServiceError> {
+        tournament.version = expected_version + 1;
+        let result = self
+            .dynamodb
+            .put_item()
+            .table_name(&self.tournaments_table)
+            .set_item(Some(serde_dynamo::to_item(&*tournament)?))
+            .condition_expression("version = :expected")
+            .expression_attribute_values(
+                ":expected",
+                AttributeValue::N(expected_version.to_string()),
+            )
+            .send()
+            .await;
+        if let Err(e) = result {
+            if e.as_service_error()
+                .is_some_and(|se| se.is_conditional_check_failed_exception())
+            {
+                return Err(ServiceError::Conflict(
+                    "The tournament was changed in the meantime; try again".to_string(),
+                ));
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    pub async fn load_user(&self, user_id: &str) -> Result<Option<User>, ServiceError> {
+        let response = self
+            .dynamodb
+            .get_item()
+            .table_name(&self.users_table)
+            .key("user_id", AttributeValue::S(user_id.to_string()))
+            .send()
+            .await?;
+        match response.item {
+            Some(item) => Ok(Some(serde_dynamo::from_item(item)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tournaments::Elimination;
+
+    fn request(rounds: u32) -> TournamentRequest {
+        TournamentRequest {
+            name: " Club Swiss ".to_string(),
+            time_control: "3+2".to_string(),
+            format: TournamentFormat::Swiss { rounds },
+            rated: true,
+            variant: Variant::Standard,
+        }
+    }
+
+    #[test]
+    fn test_request_builds_a_registering_tournament() {
+        let tournament = request(5)
+            .into_tournament("t1".to_string(), "org", 1_000)
+            .unwrap();
+        assert_eq!(tournament.name, "Club Swiss");
+        assert_eq!(tournament.organizer_id, "org");
+        assert_eq!(tournament.status, TournamentStatus::Registering);
+        assert_eq!(tournament.total_rounds(), Some(5));
+        assert!(tournament.rounds.is_empty());
+        assert_eq!(tournament.variant, Variant::Standard);
+
+        let chess960 = TournamentRequest {
+            variant: Variant::Chess960,
+            ..request(5)
+        };
+        let tournament = chess960
+            .into_tournament("t2".to_string(), "org", 1_000)
+            .unwrap();
+        assert_eq!(tournament.variant, Variant::Chess960);
+    }
+
+    #[test]
+    fn test_invalid_requests_are_rejected() {
+        for bad in [
+            request(0),
+            request(MAX_SWISS_ROUNDS + 1),
+            TournamentRequest {
+                format: TournamentFormat::RoundRobin { cycles: 3 },
+                ..request(5)
+            },
+            TournamentRequest {
+                name: "  ".to_string(),
+                ..request(5)
+            },
+            TournamentRequest {
+                time_control: "3+1".to_string(),
+                ..request(5)
+            },
+        ] {
+            assert!(matches!(
+                bad.into_tournament("t1".to_string(), "org", 0),
+                Err(ServiceError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_request_defaults_to_rated() {
+        let request: TournamentRequest = serde_json::from_value(serde_json::json!({
+            "name": "Club Swiss",
+            "time_control": "rapid",
+            "format": { "type": "swiss", "rounds": 7 }
+        }))
+        .unwrap();
+        assert!(request.rated);
+        assert_eq!(request.format, TournamentFormat::Swiss { rounds: 7 });
+    }
+
+    #[test]
+    fn test_knockout_request() {
+        let request: TournamentRequest = serde_json::from_value(serde_json::json!({
+            "name": "Club Cup",
+            "time_control": "10+0",
+            "format": { "type": "knockout", "elimination": "double" }
+        }))
+        .unwrap();
+        let tournament = request.into_tournament("t1".to_string(), "org", 0).unwrap();
+        assert_eq!(
+            tournament.format,
+            TournamentFormat::Knockout {
+                elimination: Elimination::Double
+            }
+        );
+        assert_eq!(tournament.total_rounds(), None);
+    }
+
+    #[test]
+    fn test_results_are_copied_by_game_id() {
+        let mut round = Round {
+            number: 1,
+            pairings: vec![Pairing::game(1, "a", "b"), Pairing::game(2, "c", "d")],
+        };
+        for pairing in round.pairings.iter_mut() {
+            pairing.game_id = Some(tournament_game_id("t1", 1, pairing.board));
+        }
+        let results = HashMap::from([("t1-r1-b2".to_string(), GameResult::Draw)]);
+        apply_results(&mut round, &results);
+        assert_eq!(round.pairings[0].result, None);
+        assert_eq!(round.pairings[1].result, Some(GameResult::Draw));
+        assert!(!round.is_complete());
+    }
+
+    #[test]
+    fn test_view_serializes_flat_with_standings() {
+        let mut tournament = request(3)
+            .into_tournament("t1".to_string(), "org", 0)
+            .unwrap();
+        tournament.players.insert(
+            "alice".to_string(),
+            TournamentPlayer {
+                user_id: "alice".to_string(),
+                rating: 1500,
+                registered_at: 0,
+            },
+        );
+        let json = serde_json::to_value(TournamentView::from(tournament)).unwrap();
+        assert_eq!(json["tournament_id"], "t1");
+        assert_eq!(json["status"], "registering");
+        assert_eq!(json["standings"][0]["user_id"], "alice");
+        assert_eq!(json["standings"][0]["rank"], 1);
+    }
+}
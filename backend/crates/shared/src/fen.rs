@@ -0,0 +1,172 @@
+//! Structural checks for FEN strings supplied by players
+
+/// Standard starting position
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Checks that `fen` is a well-formed FEN with one king per side
+///
+/// This validates the notation, not chess legality: positions where the side not to move
+/// is in check are accepted.
+pub fn validate_fen(fen: &str) -> Result<(), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() != 6 {
+        return Err(format!("FEN must have 6 fields, found {}", fields.len()));
+    }
+
+    validate_board(fields[0])?;
+
+    if !matches!(fields[1], "w" | "b") {
+        return Err(format!("Invalid side to move: {}", fields[1]));
+    }
+
+    let castling = fields[2];
+    let castling_valid = castling == "-"
+        || (!castling.is_empty()
+            && castling.chars().all(|c| "KQkqABCDEFGHabcdefgh".contains(c))
+            && castling
+                .chars()
+                .enumerate()
+                .all(|(i, c)| !castling[..i].contains(c)));
+    if !castling_valid {
+        return Err(format!("Invalid castling rights: {}", castling));
+    }
+    // Shredder-FEN (used for Chess960) names the file of the rook that castles
+    for right in castling.chars().filter(|c| !"KQkq-".contains(*c)) {
+        let (back_rank, rook) = if right.is_ascii_uppercase() {
+            (7, 'R')
+        } else {
+            (0, 'r')
+        };
+        let file = (right.to_ascii_lowercase() as u8 - b'a') as usize;
+        if piece_at(fields[0], back_rank, file) != Some(rook) {
+            return Err(format!("No rook to castle with for right {}", right));
+        }
+    }
+
+    // The en passant square is behind a pawn that just moved, so it depends on who moves
+    let en_passant = fields[3];
+    let en_passant_rank = if fields[1] == "w" { b'6' } else { b'3' };
+    let en_passant_valid = en_passant == "-"
+        || matches!(en_passant.as_bytes(), [b'a'..=b'h', rank] if *rank == en_passant_rank);
+    if !en_passant_valid {
+        return Err(format!("Invalid en passant square: {}", en_passant));
+    }
+
+    if fields[4].parse::<u32>().is_err() {
+        return Err(format!("Invalid halfmove clock: {}", fields[4]));
+    }
+    match fields[5].parse::<u32>() {
+        Ok(n) if n >= 1 => Ok(()),
+        _ => Err(format!("Invalid fullmove number: {}", fields[5])),
+    }
+}
+
+/// Piece on `file` of the `rank`-th rank listed in `board` (0 is the eighth rank)
+fn piece_at(board: &str, rank: usize, file: usize) -> Option<char> {
+    let mut current = 0;
+    for c in board.split('/').nth(rank)?.chars() {
+        match c.to_digit(10) {
+            Some(n) => current += n as usize,
+            None if current == file => return Some(c),
+            None => current += 1,
+        }
+        if current > file {
+            return None;
+        }
+    }
+    None
+}
+
+fn validate_board(board: &str) -> Result<(), String> {
+    let ranks: Vec<&str> = board.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("Board must have 8 ranks, found {}", ranks.len()));
+    }
+
+    let (mut white_kings, mut black_kings) = (0, 0);
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut squares = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => squares += c.to_digit(10).unwrap_or(0),
+                'P' | 'p' if i == 0 || i == 7 => {
+                    return Err("Pawns cannot stand on the first or last rank".to_string())
+                }
+                'K' => {
+                    white_kings += 1;
+                    squares += 1;
+                }
+                'k' => {
+                    black_kings += 1;
+                    squares += 1;
+                }
+                'P' | 'N' | 'B' | 'R' | 'Q' | 'p' | 'n' | 'b' | 'r' | 'q' => squares += 1,
+                _ => return Err(format!("Invalid piece: {}", c)),
+            }
+        }
+        if squares != 8 {
+            return Err(format!("Rank {} does not have 8 squares", 8 - i));
+        }
+    }
+
+    if white_kings != 1 || black_kings != 1 {
+        return Err("Each side must have exactly one king".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starting_position_is_valid() {
+        assert_eq!(validate_fen(STARTING_FEN), Ok(()));
+    }
+
+    #[test]
+    fn test_positions_with_en_passant_and_no_castling() {
+        assert!(
+            validate_fen("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3").is_ok()
+        );
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k b - - 50 80").is_ok());
+        // Shredder-style castling rights, as used for Chess960
+        assert!(validate_fen("bnrqkrnb/pppppppp/8/8/8/8/PPPPPPPP/BNRQKRNB w FCfc - 0 1").is_ok());
+        // X-FEN keeps KQkq for the outermost rooks
+        assert!(validate_fen("bnrqkrnb/pppppppp/8/8/8/8/PPPPPPPP/BNRQKRNB w KQkq - 0 1").is_ok());
+    }
+
+    #[test]
+    fn test_shredder_castling_needs_a_rook_on_that_file() {
+        assert!(validate_fen("bnrqkrnb/pppppppp/8/8/8/8/PPPPPPPP/BNRQKRNB w GCfc - 0 1").is_err());
+        assert!(validate_fen("bnrqkrnb/pppppppp/8/8/8/8/PPPPPPPP/BNRQKRNB w FCfb - 0 1").is_err());
+        assert!(validate_fen("4k3/8/8/8/8/8/8/R3K3 w A - 0 1").is_ok());
+        assert!(validate_fen("4k3/8/8/8/8/8/8/4K2R w A - 0 1").is_err());
+    }
+
+    #[test]
+    fn test_malformed_fields_are_rejected() {
+        assert!(validate_fen("").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k w - -").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k x - - 0 1").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k w KK - 0 1").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k w - e4 0 1").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k w - e3 0 1").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k w - - x 1").is_err());
+        assert!(validate_fen("8/8/8/8/8/8/8/K6k w - - 0 0").is_err());
+    }
+
+    #[test]
+    fn test_bad_boards_are_rejected() {
+        // Seven ranks
+        assert!(validate_fen("8/8/8/8/8/8/K6k w - - 0 1").is_err());
+        // Rank with nine squares
+        assert!(validate_fen("8/8/8/8/8/8/8/K7k w - - 0 1").is_err());
+        // Missing black king
+        assert!(validate_fen("8/8/8/8/8/8/8/K7 w - - 0 1").is_err());
+        // Pawn on the back rank
+        assert!(validate_fen("P7/8/8/8/8/8/8/K6k w - - 0 1").is_err());
+        // Unknown piece
+        assert!(validate_fen("8/8/8/8/8/8/8/K5xk w - - 0 1").is_err());
+    }
+}
@@ -1,5 +1,13 @@
 pub mod auth;
+pub mod fen;
 pub mod models;
+pub mod odds;
+pub mod rating;
+pub mod time_control;
+pub mod variant;
 
-pub use models::game::{Game, GameStatus};
+pub use models::game::{
+    Color, ColorPreference, Game, GameInvite, GameOdds, GameResult, GameStatus, HostClock,
+    SimulBoard,
+};
 pub use models::user::User;
@@ -1,6 +1,9 @@
+use crate::odds::{Odds, StartingClocks};
+use crate::variant::Variant;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// The default is an empty, active, casual standard game, for tests to fill in
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Game {
     pub game_id: String,
     pub white_player_id: String,
@@ -8,12 +11,196 @@ pub struct Game {
     pub time_control: String,
     pub status: GameStatus,
     pub created_at: String,
+    /// Games created before variants existed are all standard
+    #[serde(default)]
+    pub variant: Variant,
+    /// Games created before this flag existed all came from the rated queue
+    #[serde(default = "default_rated")]
+    pub rated: bool,
+    /// Starting position for games that don't start from the standard one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_fen: Option<String>,
+    /// Set when the game ends with a result
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<GameResult>,
+    /// Tournament the game was paired in, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tournament_id: Option<String>,
+    /// Set when white berserks in an arena: the game server starts white's clock at half
+    /// the time control
+    #[serde(default)]
+    pub white_berserk: bool,
+    #[serde(default)]
+    pub black_berserk: bool,
+    /// Simul the game is a board of, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub simul: Option<SimulBoard>,
+    /// Invite code the game was started from, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite: Option<GameInvite>,
+    /// Handicap one player gives; material odds are already applied to initial_fen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub odds: Option<GameOdds>,
+    /// Starting clocks when the players start with different times or increments, after
+    /// time odds or a berserk; otherwise both come from the time control
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clocks: Option<StartingClocks>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl Game {
+    /// True while the game should be left out of lobbies, spectating and leaderboards
+    pub fn is_private(&self) -> bool {
+        self.invite.as_ref().is_some_and(|invite| invite.private)
+    }
+
+    /// Whether `user_id` may watch the game; private games are only shown to their players
+    pub fn is_visible_to(&self, user_id: &str) -> bool {
+        !self.is_private() || self.white_player_id == user_id || self.black_player_id == user_id
+    }
+}
+
+fn default_rated() -> bool {
+    true
+}
+
+/// How the host's clock runs in a simul
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HostClock {
+    /// The host has a clock on every board, like their opponent
+    #[default]
+    PerBoard,
+    /// One clock for the host across all boards, running while any board waits on them
+    Shared,
+    /// The host plays without a clock
+    Off,
+}
+
+/// What the game server needs to know about a simul board
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SimulBoard {
+    pub simul_id: String,
+    pub host_id: String,
+    pub host_clock: HostClock,
+}
+
+/// A game started from a private invite code
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GameInvite {
+    pub code: String,
+    /// The user who created the invite; only they can make the game public
+    pub host_id: String,
+    /// Hidden from lobbies, spectators and leaderboards until the host makes it public
+    pub private: bool,
+}
+
+/// Odds given in a game, and by whom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GameOdds {
+    pub giver_id: String,
+    #[serde(flatten)]
+    pub odds: Odds,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum GameStatus {
+    #[default]
     Active,
     Completed,
     Abandoned,
 }
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub fn opposite(self) -> Self {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Color::White => "white",
+            Color::Black => "black",
+        }
+    }
+}
+
+/// Colour a player asks for when a game is created
+///
+/// `Auto` lets the matchmaker balance colours from the players' recent games; challenges
+/// and tournaments pass an explicit colour to override that.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorPreference {
+    White,
+    Black,
+    #[default]
+    Auto,
+}
+
+impl ColorPreference {
+    /// The explicitly requested colour, if any
+    pub fn fixed(self) -> Option<Color> {
+        match self {
+            ColorPreference::White => Some(Color::White),
+            ColorPreference::Black => Some(Color::Black),
+            ColorPreference::Auto => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(invite: Option<GameInvite>) -> Game {
+        Game {
+            white_player_id: "alice".to_string(),
+            black_player_id: "bob".to_string(),
+            invite,
+            ..Game::default()
+        }
+    }
+
+    fn invite(private: bool) -> Option<GameInvite> {
+        Some(GameInvite {
+            code: "KING-0001".to_string(),
+            host_id: "alice".to_string(),
+            private,
+        })
+    }
+
+    #[test]
+    fn test_private_game_is_hidden_from_spectators() {
+        let private = game(invite(true));
+        assert!(private.is_private());
+        assert!(private.is_visible_to("alice"));
+        assert!(private.is_visible_to("bob"));
+        assert!(!private.is_visible_to("carol"));
+    }
+
+    #[test]
+    fn test_public_games_are_visible_to_everyone() {
+        assert!(game(None).is_visible_to("carol"));
+        let made_public = game(invite(false));
+        assert!(!made_public.is_private());
+        assert!(made_public.is_visible_to("carol"));
+    }
+}
@@ -1,7 +1,24 @@
+use crate::variant::Variant;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct User {
     pub user_id: String,
     pub rating: i32,
+    /// Chess960 is rated separately; unset until the user's first rated 960 game
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chess960_rating: Option<i32>,
+    /// Users this user never wants to be paired with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocked_user_ids: Vec<String>,
+}
+
+impl User {
+    /// The user's rating in `variant`, starting from their standard rating
+    pub fn rating_in(&self, variant: Variant) -> i32 {
+        match variant {
+            Variant::Standard => self.rating,
+            Variant::Chess960 => self.chess960_rating.unwrap_or(self.rating),
+        }
+    }
 }
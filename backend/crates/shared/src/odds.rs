@@ -0,0 +1,277 @@
+//! Handicaps a stronger player can give in a challenge
+//!
+//! Material odds change the starting position and time odds the starting clocks. Both are
+//! expressed from the giver's side and only turned into a position and clocks once colours
+//! are known.
+
+use crate::fen::STARTING_FEN;
+use crate::Color;
+use serde::{Deserialize, Serialize};
+
+/// Material the giver starts without
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MaterialOdds {
+    Queen,
+    /// The queen's rook, along with castling on that side
+    Rook,
+    /// The queen's knight
+    Knight,
+    /// The f-pawn, and the receiver moves first
+    PawnAndMove,
+}
+
+/// Starting clocks, in seconds, for the giver and the receiver
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeOdds {
+    pub giver_secs: u32,
+    pub receiver_secs: u32,
+}
+
+/// Handicap one player gives the other
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Odds {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<MaterialOdds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<TimeOdds>,
+}
+
+/// Starting clocks by colour for a game with time odds or a berserk; increments come from
+/// the time control unless overridden
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StartingClocks {
+    pub white_secs: u32,
+    pub black_secs: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub white_increment_secs: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub black_increment_secs: Option<u32>,
+}
+
+impl MaterialOdds {
+    /// Square emptied on the giver's side, as (file, rank from the giver's back rank)
+    fn removed_square(self) -> (usize, usize) {
+        match self {
+            MaterialOdds::Queen => (3, 0),
+            MaterialOdds::Rook => (0, 0),
+            MaterialOdds::Knight => (1, 0),
+            MaterialOdds::PawnAndMove => (5, 1),
+        }
+    }
+}
+
+impl Odds {
+    /// Checks that the odds give something and that time odds favour the receiver
+    pub fn validate(&self) -> Result<(), String> {
+        if self.material.is_none() && self.time.is_none() {
+            return Err("Odds must give material, time or both".to_string());
+        }
+        if let Some(time) = self.time {
+            if time.giver_secs == 0 || time.receiver_secs == 0 {
+                return Err("Both players need time on the clock".to_string());
+            }
+            if time.giver_secs >= time.receiver_secs {
+                return Err("Time odds must give the receiver more time".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Starting position with the material odds applied, if there are any
+    pub fn starting_fen(&self, giver: Color) -> Option<String> {
+        let material = self.material?;
+        let fields: Vec<&str> = STARTING_FEN.split_whitespace().collect();
+
+        let mut board: Vec<Vec<char>> = fields[0].split('/').map(expand_rank).collect();
+        let (file, rank) = material.removed_square();
+        // Ranks are listed from black's back rank down
+        let row = match giver {
+            Color::White => 7 - rank,
+            Color::Black => rank,
+        };
+        board[row][file] = '1';
+
+        let side_to_move = match (material, giver) {
+            (MaterialOdds::PawnAndMove, Color::White) => "b",
+            _ => fields[1],
+        };
+        let castling: String = match (material, giver) {
+            (MaterialOdds::Rook, Color::White) => fields[2].replace('Q', ""),
+            (MaterialOdds::Rook, Color::Black) => fields[2].replace('q', ""),
+            _ => fields[2].to_string(),
+        };
+
+        let board = board
+            .iter()
+            .map(|rank| compress_rank(rank))
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(format!(
+            "{} {} {} {} {} {}",
+            board, side_to_move, castling, fields[3], fields[4], fields[5]
+        ))
+    }
+
+    /// Starting clocks by colour, if there are time odds
+    pub fn clocks(&self, giver: Color) -> Option<StartingClocks> {
+        let time = self.time?;
+        Some(match giver {
+            Color::White => StartingClocks {
+                white_secs: time.giver_secs,
+                black_secs: time.receiver_secs,
+                white_increment_secs: None,
+                black_increment_secs: None,
+            },
+            Color::Black => StartingClocks {
+                white_secs: time.receiver_secs,
+                black_secs: time.giver_secs,
+                white_increment_secs: None,
+                black_increment_secs: None,
+            },
+        })
+    }
+}
+
+/// One FEN rank as 8 squares, with empty squares as '1'
+fn expand_rank(rank: &str) -> Vec<char> {
+    rank.chars()
+        .flat_map(|c| match c.to_digit(10) {
+            Some(n) => vec!['1'; n as usize],
+            None => vec![c],
+        })
+        .collect()
+}
+
+fn compress_rank(squares: &[char]) -> String {
+    let mut rank = String::new();
+    let mut empty = 0;
+    for &c in squares {
+        if c == '1' {
+            empty += 1;
+            continue;
+        }
+        if empty > 0 {
+            rank.push_str(&empty.to_string());
+            empty = 0;
+        }
+        rank.push(c);
+    }
+    if empty > 0 {
+        rank.push_str(&empty.to_string());
+    }
+    rank
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::validate_fen;
+
+    fn material(material: MaterialOdds) -> Odds {
+        Odds {
+            material: Some(material),
+            time: None,
+        }
+    }
+
+    #[test]
+    fn test_material_odds_positions() {
+        let cases = [
+            (
+                MaterialOdds::Queen,
+                Color::White,
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+            ),
+            (
+                MaterialOdds::Queen,
+                Color::Black,
+                "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            ),
+            (
+                MaterialOdds::Rook,
+                Color::White,
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w Kkq - 0 1",
+            ),
+            (
+                MaterialOdds::Rook,
+                Color::Black,
+                "1nbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQk - 0 1",
+            ),
+            (
+                MaterialOdds::Knight,
+                Color::White,
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1",
+            ),
+            (
+                MaterialOdds::PawnAndMove,
+                Color::White,
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPP1PP/RNBQKBNR b KQkq - 0 1",
+            ),
+            (
+                MaterialOdds::PawnAndMove,
+                Color::Black,
+                "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            ),
+        ];
+        for (odds, giver, expected) in cases {
+            let fen = material(odds).starting_fen(giver).unwrap();
+            assert_eq!(fen, expected, "{:?} given by {:?}", odds, giver);
+            assert_eq!(validate_fen(&fen), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_time_odds_clocks() {
+        let odds = Odds {
+            material: None,
+            time: Some(TimeOdds {
+                giver_secs: 120,
+                receiver_secs: 300,
+            }),
+        };
+        assert_eq!(odds.starting_fen(Color::White), None);
+        assert_eq!(
+            odds.clocks(Color::Black),
+            Some(StartingClocks {
+                white_secs: 300,
+                black_secs: 120,
+                white_increment_secs: None,
+                black_increment_secs: None,
+            })
+        );
+        assert_eq!(material(MaterialOdds::Queen).clocks(Color::White), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(material(MaterialOdds::Knight).validate().is_ok());
+        assert!(Odds::default().validate().is_err());
+
+        let time = |giver_secs, receiver_secs| Odds {
+            material: None,
+            time: Some(TimeOdds {
+                giver_secs,
+                receiver_secs,
+            }),
+        };
+        assert!(time(120, 300).validate().is_ok());
+        assert!(time(0, 300).validate().is_err());
+        assert!(time(300, 300).validate().is_err());
+        assert!(time(300, 120).validate().is_err());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let odds: Odds = serde_json::from_value(serde_json::json!({
+            "material": "pawn_and_move",
+            "time": { "giver_secs": 120, "receiver_secs": 300 }
+        }))
+        .unwrap();
+        assert_eq!(odds.material, Some(MaterialOdds::PawnAndMove));
+        assert_eq!(
+            serde_json::to_value(material(MaterialOdds::Rook)).unwrap(),
+            serde_json::json!({ "material": "rook" })
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Elo rating updates for finished rated games
+
+use crate::models::game::GameResult;
+
+/// Most points a single game can move a rating
+pub const K_FACTOR: f64 = 20.0;
+
+/// White's and black's ratings after a game between them ended with `result`
+pub fn rate_game(white: i32, black: i32, result: GameResult) -> (i32, i32) {
+    let white_score = match result {
+        GameResult::WhiteWins => 1.0,
+        GameResult::BlackWins => 0.0,
+        GameResult::Draw => 0.5,
+    };
+    // Rounding each side separately could create or destroy a point, so black's change
+    // mirrors white's
+    let change = (K_FACTOR * (white_score - expected_score(white, black))).round() as i32;
+    (white + change, black - change)
+}
+
+/// Score `rating` is expected to make against `opponent`, between 0 and 1
+pub fn expected_score(rating: i32, opponent: i32) -> f64 {
+    1.0 / (1.0 + 10f64.powf(f64::from(opponent - rating) / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal_players() {
+        assert_eq!(rate_game(1500, 1500, GameResult::WhiteWins), (1510, 1490));
+        assert_eq!(rate_game(1500, 1500, GameResult::BlackWins), (1490, 1510));
+        assert_eq!(rate_game(1500, 1500, GameResult::Draw), (1500, 1500));
+    }
+
+    #[test]
+    fn test_upsets_move_ratings_further() {
+        // 400 points apart the favourite is expected to score 10/11
+        assert!((expected_score(1900, 1500) - 10.0 / 11.0).abs() < 1e-9);
+        assert_eq!(rate_game(1900, 1500, GameResult::WhiteWins), (1902, 1498));
+        assert_eq!(rate_game(1900, 1500, GameResult::BlackWins), (1882, 1518));
+        assert_eq!(rate_game(1900, 1500, GameResult::Draw), (1892, 1508));
+    }
+}
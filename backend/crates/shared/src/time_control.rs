@@ -0,0 +1,194 @@
+//! Catalogue of the time controls players can queue, seek and hold events in
+//!
+//! Each entry is its own matchmaking pool. Challenges may use any other time control.
+
+use serde::{Deserialize, Serialize};
+
+/// Speed category of a time control, as shown in the client
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeControlCategory {
+    Bullet,
+    Blitz,
+    Rapid,
+    Classical,
+}
+
+/// A supported time control
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    /// What clients send as `time_control`: minutes on the clock plus increment seconds
+    pub id: &'static str,
+    pub category: TimeControlCategory,
+    pub display_name: &'static str,
+    pub initial_secs: u32,
+    pub increment_secs: u32,
+}
+
+const fn time_control(
+    id: &'static str,
+    category: TimeControlCategory,
+    display_name: &'static str,
+    initial_secs: u32,
+    increment_secs: u32,
+) -> TimeControl {
+    TimeControl {
+        id,
+        category,
+        display_name,
+        initial_secs,
+        increment_secs,
+    }
+}
+
+/// Every supported time control, fastest first
+#[rustfmt::skip]
+pub const TIME_CONTROLS: &[TimeControl] = &[
+    time_control("1+0",   TimeControlCategory::Bullet,     "Bullet 1+0",      60,   0),
+    time_control("2+1",   TimeControlCategory::Bullet,     "Bullet 2+1",      120,  1),
+    time_control("3+0",   TimeControlCategory::Blitz,      "Blitz 3+0",       180,  0),
+    time_control("3+2",   TimeControlCategory::Blitz,      "Blitz 3+2",       180,  2),
+    time_control("5+0",   TimeControlCategory::Blitz,      "Blitz 5+0",       300,  0),
+    time_control("5+3",   TimeControlCategory::Blitz,      "Blitz 5+3",       300,  3),
+    time_control("10+0",  TimeControlCategory::Rapid,      "Rapid 10+0",      600,  0),
+    time_control("10+5",  TimeControlCategory::Rapid,      "Rapid 10+5",      600,  5),
+    time_control("15+10", TimeControlCategory::Rapid,      "Rapid 15+10",     900,  10),
+    time_control("30+0",  TimeControlCategory::Classical,  "Classical 30+0",  1800, 0),
+    time_control("30+20", TimeControlCategory::Classical,  "Classical 30+20", 1800, 20),
+];
+
+/// Looks up a supported time control by id
+pub fn find_time_control(id: &str) -> Option<&'static TimeControl> {
+    TIME_CONTROLS.iter().find(|tc| tc.id == id)
+}
+
+/// Error for a time control that isn't in the catalogue
+pub fn unsupported_time_control(id: &str) -> String {
+    format!(
+        "Unsupported time control '{}'; expected one of {}",
+        id,
+        TIME_CONTROLS
+            .iter()
+            .map(|tc| tc.id)
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Longest clock a custom time control may start with, in minutes
+pub const MAX_CUSTOM_MINUTES: u32 = 180;
+/// Largest increment a custom time control may add, in seconds
+pub const MAX_CUSTOM_INCREMENT_SECS: u32 = 60;
+
+/// Reads a time control written like the catalogue ids, `<minutes>+<increment seconds>`,
+/// as (initial_secs, increment_secs); None unless both are whole numbers within bounds
+/// and the clock starts above zero
+pub fn parse_time_control(id: &str) -> Option<(u32, u32)> {
+    let (minutes, increment) = id.split_once('+')?;
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_number(minutes) || !is_number(increment) {
+        return None;
+    }
+    let (minutes, increment): (u32, u32) = (minutes.parse().ok()?, increment.parse().ok()?);
+    if minutes == 0 || minutes > MAX_CUSTOM_MINUTES || increment > MAX_CUSTOM_INCREMENT_SECS {
+        return None;
+    }
+    Some((minutes * 60, increment))
+}
+
+/// Error for a custom time control that can't be read or is out of bounds
+pub fn invalid_time_control(id: &str) -> String {
+    format!(
+        "Invalid time control '{}'; expected <minutes>+<increment> with 1 to {} minutes and \
+         at most {} seconds of increment, e.g. 5+3",
+        id, MAX_CUSTOM_MINUTES, MAX_CUSTOM_INCREMENT_SECS
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_match_clock_settings() {
+        for tc in TIME_CONTROLS {
+            assert_eq!(
+                tc.id,
+                format!("{}+{}", tc.initial_secs / 60, tc.increment_secs)
+            );
+            assert!(tc.display_name.ends_with(tc.id));
+        }
+    }
+
+    #[test]
+    fn test_categories_follow_estimated_duration() {
+        // Estimated game length assumes 40 moves, as most servers do
+        for tc in TIME_CONTROLS {
+            let estimate = tc.initial_secs + 40 * tc.increment_secs;
+            let expected = match estimate {
+                0..180 => TimeControlCategory::Bullet,
+                180..480 => TimeControlCategory::Blitz,
+                480..1500 => TimeControlCategory::Rapid,
+                _ => TimeControlCategory::Classical,
+            };
+            assert_eq!(tc.category, expected, "{}", tc.id);
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(
+            find_time_control("3+2").map(|tc| tc.category),
+            Some(TimeControlCategory::Blitz)
+        );
+        assert!(find_time_control("blitz").is_none());
+        assert!(find_time_control("3+1").is_none());
+        assert!(unsupported_time_control("3+1").contains("3+2"));
+    }
+
+    #[test]
+    fn test_custom_time_controls() {
+        assert_eq!(parse_time_control("7+4"), Some((420, 4)));
+        assert_eq!(parse_time_control("180+60"), Some((10_800, 60)));
+        for tc in TIME_CONTROLS {
+            assert_eq!(
+                parse_time_control(tc.id),
+                Some((tc.initial_secs, tc.increment_secs))
+            );
+        }
+        for id in [
+            "",
+            "blitz",
+            "abc+",
+            "5",
+            "5+",
+            "+3",
+            "0+5",
+            "181+0",
+            "5+61",
+            "-1+0",
+            "5+3+1",
+            " 5+3",
+            "5.5+0",
+            "99999999999+0",
+        ] {
+            assert_eq!(parse_time_control(id), None, "{:?}", id);
+        }
+        assert!(invalid_time_control("abc+").contains("abc+"));
+    }
+
+    #[test]
+    fn test_serialization() {
+        let json = serde_json::to_value(find_time_control("10+5").unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": "10+5",
+                "category": "rapid",
+                "display_name": "Rapid 10+5",
+                "initial_secs": 600,
+                "increment_secs": 5
+            })
+        );
+    }
+}
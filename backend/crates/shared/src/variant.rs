@@ -0,0 +1,172 @@
+//! Chess variants games can be played in
+//!
+//! Every variant has its own matchmaking pools and its own rating, so players of different
+//! variants never meet in the queue.
+
+use serde::{Deserialize, Serialize};
+
+/// Number of Chess960 starting positions
+pub const CHESS960_POSITIONS: u16 = 960;
+/// Scharnagl number of the standard starting position
+pub const CHESS960_STANDARD_POSITION: u16 = 518;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// Fischer Random: the back rank is shuffled, castling works from any start
+    Chess960,
+}
+
+impl Variant {
+    /// Prefix of the variant's matchmaking pools; standard pools have none
+    pub fn pool_prefix(self) -> Option<&'static str> {
+        match self {
+            Variant::Standard => None,
+            Variant::Chess960 => Some("chess960"),
+        }
+    }
+
+    /// User attribute holding the player's rating in this variant
+    pub fn rating_attribute(self) -> &'static str {
+        match self {
+            Variant::Standard => "rating",
+            Variant::Chess960 => "chess960_rating",
+        }
+    }
+
+    /// Starting position for a new game, given a random number below CHESS960_POSITIONS
+    ///
+    /// Standard games start from the usual position and need no FEN.
+    pub fn starting_fen(self, position: u16) -> Option<String> {
+        match self {
+            Variant::Standard => None,
+            Variant::Chess960 => Some(chess960_fen(position)),
+        }
+    }
+}
+
+/// Back rank of Chess960 position `number` (0 to 959), in Scharnagl's numbering
+pub fn chess960_back_rank(number: u16) -> [char; 8] {
+    // Knight placements over the five squares left after the bishops and queen
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let n = usize::from(number % CHESS960_POSITIONS);
+    let mut rank = [' '; 8];
+    // The light-squared bishop goes on b, d, f or h and the dark-squared one on a, c, e or g
+    rank[(n % 4) * 2 + 1] = 'B';
+    rank[(n / 4 % 4) * 2] = 'B';
+
+    let n = n / 16;
+    let empty = |rank: &[char; 8]| (0..8).filter(|&file| rank[file] == ' ').collect::<Vec<_>>();
+    rank[empty(&rank)[n % 6]] = 'Q';
+
+    let (first, second) = KNIGHTS[n / 6];
+    let squares = empty(&rank);
+    rank[squares[first]] = 'N';
+    rank[squares[second]] = 'N';
+
+    // The king always ends up between the rooks
+    for (file, piece) in empty(&rank).into_iter().zip(['R', 'K', 'R']) {
+        rank[file] = piece;
+    }
+    rank
+}
+
+/// Starting FEN of Chess960 position `number`, with Shredder-FEN castling rights
+///
+/// Naming the rook files keeps castling unambiguous, which KQkq is not in every position.
+pub fn chess960_fen(number: u16) -> String {
+    let white: String = chess960_back_rank(number).iter().collect();
+    let black = white.to_lowercase();
+    let rook_files: Vec<char> = white
+        .char_indices()
+        .filter(|&(_, piece)| piece == 'R')
+        .map(|(file, _)| (b'a' + file as u8) as char)
+        .rev()
+        .collect();
+    let castling: String = rook_files
+        .iter()
+        .map(|file| file.to_ascii_uppercase())
+        .chain(rook_files.iter().copied())
+        .collect();
+    format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {} - 0 1",
+        black, white, castling
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fen::{validate_fen, STARTING_FEN};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_known_positions() {
+        assert_eq!(
+            chess960_back_rank(CHESS960_STANDARD_POSITION)
+                .iter()
+                .collect::<String>(),
+            "RNBQKBNR"
+        );
+        assert_eq!(chess960_back_rank(0).iter().collect::<String>(), "BBQNNRKR");
+        assert_eq!(
+            chess960_back_rank(959).iter().collect::<String>(),
+            "RKRNNQBB"
+        );
+    }
+
+    #[test]
+    fn test_every_position_is_distinct_and_legal() {
+        let mut seen = HashSet::new();
+        for number in 0..CHESS960_POSITIONS {
+            let rank = chess960_back_rank(number);
+            assert!(seen.insert(rank), "position {} repeats", number);
+
+            let file_of = |piece| rank.iter().position(|&c| c == piece).unwrap();
+            let bishops: Vec<usize> = (0..8).filter(|&f| rank[f] == 'B').collect();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "position {}", number);
+            let rooks: Vec<usize> = (0..8).filter(|&f| rank[f] == 'R').collect();
+            assert!(rooks[0] < file_of('K') && file_of('K') < rooks[1]);
+
+            assert_eq!(validate_fen(&chess960_fen(number)), Ok(()));
+        }
+        assert_eq!(seen.len(), 960);
+    }
+
+    #[test]
+    fn test_fen_names_rook_files() {
+        assert_eq!(
+            chess960_fen(CHESS960_STANDARD_POSITION),
+            STARTING_FEN.replace("KQkq", "HAha")
+        );
+        assert_eq!(
+            chess960_fen(0),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+        );
+    }
+
+    #[test]
+    fn test_variant() {
+        assert_eq!(Variant::default(), Variant::Standard);
+        assert_eq!(Variant::Standard.starting_fen(0), None);
+        assert_eq!(Variant::Chess960.starting_fen(0), Some(chess960_fen(0)));
+        assert_eq!(
+            serde_json::to_value(Variant::Chess960).unwrap(),
+            serde_json::json!("chess960")
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use serde::{Deserialize, Serialize};
+use shared::{Color, Game, GameResult};
+use std::cmp::Reverse;
+
+use crate::model::Tournament;
+
+/// Wins in a row after which a player is on fire and scores double
+pub const FIRE_STREAK: u32 = 2;
+
+/// Queue pool an arena's players wait in between games
+pub fn pool_name(tournament_id: &str) -> String {
+    format!("arena:{}", tournament_id)
+}
+
+/// A finished arena game, as recorded on the tournament
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArenaGame {
+    pub game_id: String,
+    pub white_id: String,
+    pub black_id: String,
+    pub result: GameResult,
+    #[serde(default)]
+    pub white_berserk: bool,
+    #[serde(default)]
+    pub black_berserk: bool,
+    pub finished_at: u64,
+}
+
+impl ArenaGame {
+    /// The record for `game`, if it has a result
+    pub fn from_game(game: &Game, finished_at: u64) -> Option<Self> {
+        Some(Self {
+            game_id: game.game_id.clone(),
+            white_id: game.white_player_id.clone(),
+            black_id: game.black_player_id.clone(),
+            result: game.result?,
+            white_berserk: game.white_berserk,
+            black_berserk: game.black_berserk,
+            finished_at,
+        })
+    }
+
+    pub fn color_of(&self, user_id: &str) -> Option<Color> {
+        if self.white_id == user_id {
+            Some(Color::White)
+        } else if self.black_id == user_id {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    fn berserked(&self, color: Color) -> bool {
+        match color {
+            Color::White => self.white_berserk,
+            Color::Black => self.black_berserk,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArenaStanding {
+    pub rank: u32,
+    pub user_id: String,
+    pub score: u32,
+    pub games: u32,
+    pub wins: u32,
+    /// Won the last two games or more, so the next win or draw scores double
+    pub on_fire: bool,
+    /// Points scored in each game, oldest first
+    pub sheet: Vec<u32>,
+}
+
+/// Running score of one player, fed their games in the order they finished
+#[derive(Debug, Default)]
+struct ScoreSheet {
+    points: Vec<u32>,
+    wins: u32,
+    streak: u32,
+}
+
+impl ScoreSheet {
+    fn on_fire(&self) -> bool {
+        self.streak >= FIRE_STREAK
+    }
+
+    /// A win scores 2 and a draw 1, both doubled while on fire; a berserked win earns one
+    /// more point. Anything but a win ends the streak.
+    fn add(&mut self, won: bool, drawn: bool, berserk: bool) {
+        let multiplier = if self.on_fire() { 2 } else { 1 };
+        let points = if won {
+            2 * multiplier + u32::from(berserk)
+        } else if drawn {
+            multiplier
+        } else {
+            0
+        };
+        self.points.push(points);
+        if won {
+            self.wins += 1;
+            self.streak += 1;
+        } else {
+            self.streak = 0;
+        }
+    }
+
+    fn total(&self) -> u32 {
+        self.points.iter().sum()
+    }
+}
+
+/// Ranks an arena's players by score, then wins, then pairing order
+pub fn standings(tournament: &Tournament) -> Vec<ArenaStanding> {
+    let mut games: Vec<&ArenaGame> = tournament.arena_games.values().collect();
+    games.sort_by(|a, b| {
+        a.finished_at
+            .cmp(&b.finished_at)
+            .then(a.game_id.cmp(&b.game_id))
+    });
+
+    let mut rows: Vec<(usize, String, ScoreSheet)> = tournament
+        .ranked_player_ids()
+        .into_iter()
+        .enumerate()
+        .map(|(seed, user_id)| {
+            let mut sheet = ScoreSheet::default();
+            for game in &games {
+                let Some(color) = game.color_of(&user_id) else {
+                    continue;
+                };
+                let won = matches!(
+                    (game.result, color),
+                    (GameResult::WhiteWins, Color::White) | (GameResult::BlackWins, Color::Black)
+                );
+                sheet.add(won, game.result == GameResult::Draw, game.berserked(color));
+            }
+            (seed, user_id, sheet)
+        })
+        .collect();
+    rows.sort_by_key(|(seed, _, sheet)| (Reverse(sheet.total()), Reverse(sheet.wins), *seed));
+
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, (_, user_id, sheet))| ArenaStanding {
+            rank: index as u32 + 1,
+            user_id,
+            score: sheet.total(),
+            games: sheet.points.len() as u32,
+            wins: sheet.wins,
+            on_fire: sheet.on_fire(),
+            sheet: sheet.points,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{TournamentFormat, TournamentPlayer};
+
+    fn arena(players: &[(&str, i32)]) -> Tournament {
+        let mut tournament = Tournament::new(
+            "t1".to_string(),
+            "Hourly Blitz".to_string(),
+            "org".to_string(),
+            "blitz".to_string(),
+            true,
+            TournamentFormat::Arena { minutes: 60 },
+            0,
+        );
+        for (user_id, rating) in players {
+            tournament.players.insert(
+                user_id.to_string(),
+                TournamentPlayer {
+                    user_id: user_id.to_string(),
+                    rating: *rating,
+                    registered_at: 0,
+                },
+            );
+        }
+        tournament
+    }
+
+    fn record(tournament: &mut Tournament, white: &str, black: &str, result: GameResult) {
+        let n = tournament.arena_games.len() as u64;
+        let game_id = format!("g{}", n);
+        tournament.arena_games.insert(
+            game_id.clone(),
+            ArenaGame {
+                game_id,
+                white_id: white.to_string(),
+                black_id: black.to_string(),
+                result,
+                white_berserk: false,
+                black_berserk: false,
+                finished_at: 100 + n,
+            },
+        );
+    }
+
+    #[test]
+    fn test_streaks_double_points_and_berserk_adds_one() {
+        let mut sheet = ScoreSheet::default();
+        // W W W(berserk) D W L
+        for (won, drawn, berserk) in [
+            (true, false, false),
+            (true, false, false),
+            (true, false, true),
+            (false, true, false),
+            (true, false, false),
+            (false, false, true),
+        ] {
+            sheet.add(won, drawn, berserk);
+        }
+        assert_eq!(sheet.points, [2, 2, 5, 2, 2, 0]);
+        assert_eq!(sheet.total(), 13);
+        assert_eq!(sheet.wins, 4);
+        assert!(!sheet.on_fire());
+    }
+
+    #[test]
+    fn test_standings_follow_finish_order() {
+        let mut tournament = arena(&[("alice", 1600), ("bob", 1500), ("carol", 1400)]);
+        record(&mut tournament, "alice", "bob", GameResult::WhiteWins);
+        record(&mut tournament, "carol", "alice", GameResult::BlackWins);
+        record(&mut tournament, "bob", "carol", GameResult::Draw);
+        record(&mut tournament, "alice", "bob", GameResult::WhiteWins);
+
+        let standings = standings(&tournament);
+        let alice = &standings[0];
+        assert_eq!(alice.user_id, "alice");
+        assert_eq!(alice.sheet, [2, 2, 4]);
+        assert!(alice.on_fire);
+        assert_eq!(
+            standings
+                .iter()
+                .map(|s| (s.user_id.as_str(), s.score))
+                .collect::<Vec<_>>(),
+            [("alice", 8), ("bob", 1), ("carol", 1)]
+        );
+    }
+
+    #[test]
+    fn test_berserked_loss_scores_nothing() {
+        let mut tournament = arena(&[("alice", 1600), ("bob", 1500)]);
+        record(&mut tournament, "alice", "bob", GameResult::BlackWins);
+        tournament.arena_games.get_mut("g0").unwrap().white_berserk = true;
+        let standings = standings(&tournament);
+        assert_eq!(standings[0].user_id, "bob");
+        assert_eq!(standings[1].sheet, [0]);
+    }
+
+    #[test]
+    fn test_record_needs_a_result() {
+        let game = Game {
+            game_id: "g1".to_string(),
+            white_player_id: "alice".to_string(),
+            black_player_id: "bob".to_string(),
+            tournament_id: Some("t1".to_string()),
+            white_berserk: true,
+            ..Game::default()
+        };
+        assert_eq!(ArenaGame::from_game(&game, 10), None);
+        let finished = Game {
+            result: Some(GameResult::Draw),
+            ..game
+        };
+        let record = ArenaGame::from_game(&finished, 10).unwrap();
+        assert!(record.white_berserk);
+        assert_eq!(record.color_of("bob"), Some(Color::Black));
+    }
+}
@@ -0,0 +1,29 @@
+use crate::knockout::Knockout;
+use crate::model::{Pairing, Tournament, TournamentFormat};
+use crate::round_robin::RoundRobin;
+use crate::swiss::{PairingError, Swiss};
+use crate::tiebreaks::Standings;
+
+/// Rules of a format played in rounds: who meets whom next and how players are ranked
+///
+/// Every format shares the tournament model, so a new one only has to decide its pairings
+/// from the rounds played so far.
+pub trait RoundFormat: Send + Sync {
+    /// Pairings for the next round once the previous one is decided, or None when the
+    /// tournament is over
+    fn pair_next(&self, tournament: &Tournament) -> Result<Option<Vec<Pairing>>, PairingError>;
+
+    fn standings(&self, tournament: &Tournament) -> Standings;
+}
+
+impl TournamentFormat {
+    /// The format's round rules; None for arenas, which pair players continuously
+    pub fn round_format(&self) -> Option<Box<dyn RoundFormat>> {
+        match *self {
+            TournamentFormat::Swiss { rounds } => Some(Box::new(Swiss { rounds })),
+            TournamentFormat::RoundRobin { cycles } => Some(Box::new(RoundRobin { cycles })),
+            TournamentFormat::Knockout { elimination } => Some(Box::new(Knockout { elimination })),
+            TournamentFormat::Arena { .. } => None,
+        }
+    }
+}
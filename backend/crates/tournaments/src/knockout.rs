@@ -0,0 +1,419 @@
+use serde::{Deserialize, Serialize};
+use shared::GameResult;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::format::RoundFormat;
+use crate::model::{Pairing, Round, Tournament};
+use crate::swiss::PairingError;
+use crate::tiebreaks::Standings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Elimination {
+    Single,
+    /// A first loss drops a player into the losers bracket, a second knocks them out
+    Double,
+}
+
+impl Elimination {
+    /// Matches a player may lose before they are knocked out
+    pub fn lives(self) -> u32 {
+        match self {
+            Elimination::Single => 1,
+            Elimination::Double => 2,
+        }
+    }
+}
+
+/// Knockout played as one game per match
+///
+/// A drawn match is replayed at once as an Armageddon game with colours reversed, in
+/// which a draw counts as a win for black. Unbeaten players follow a seeded bracket, so
+/// the top seeds can only meet late and any byes go to them in the first round. In double
+/// elimination the once-beaten players meet each other, highest seed against lowest, and
+/// the last unbeaten player meets the last once-beaten one in the final; if the unbeaten
+/// player loses it, the final is played again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Knockout {
+    pub elimination: Elimination,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnockoutStanding {
+    pub rank: u32,
+    pub user_id: String,
+    pub losses: u32,
+    /// Round the player was knocked out in; None while they are still in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eliminated_in: Option<u32>,
+}
+
+#[derive(Debug, Default)]
+struct Record {
+    losses: u32,
+    whites: u32,
+    eliminated_in: Option<u32>,
+}
+
+/// Seeds in bracket order for a bracket of `size` (a power of two), seeds counted from
+/// zero: for eight, 1 v 8, 4 v 5, 2 v 7, 3 v 6
+pub fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let mirror = 2 * order.len() - 1;
+        order = order
+            .iter()
+            .flat_map(|&seed| [seed, mirror - seed])
+            .collect();
+    }
+    order
+}
+
+fn is_armageddon(round: &Round) -> bool {
+    round.pairings.iter().all(|p| p.armageddon)
+}
+
+impl Knockout {
+    fn records<'a>(&self, tournament: &'a Tournament) -> HashMap<&'a str, Record> {
+        let lives = self.elimination.lives();
+        let mut records: HashMap<&str, Record> = tournament
+            .players
+            .keys()
+            .map(|id| (id.as_str(), Record::default()))
+            .collect();
+        for round in &tournament.rounds {
+            for pairing in &round.pairings {
+                if let Some(record) = records.get_mut(pairing.white_id.as_str()) {
+                    record.whites += 1;
+                }
+                let Some((_, loser)) = pairing.winner_and_loser() else {
+                    continue;
+                };
+                if let Some(record) = records.get_mut(loser) {
+                    record.losses += 1;
+                    if record.losses == lives {
+                        record.eliminated_in = Some(round.number);
+                    }
+                }
+            }
+        }
+        records
+    }
+}
+
+impl RoundFormat for Knockout {
+    fn pair_next(&self, tournament: &Tournament) -> Result<Option<Vec<Pairing>>, PairingError> {
+        let ranked = tournament.ranked_player_ids();
+        if ranked.len() < 2 {
+            return Err(PairingError::NotEnoughPlayers);
+        }
+
+        // Drawn matches are settled before the bracket moves on
+        if let Some(round) = tournament.rounds.last() {
+            let replays: Vec<Pairing> = round
+                .pairings
+                .iter()
+                .filter(|p| p.result == Some(GameResult::Draw) && !p.armageddon)
+                .filter_map(|p| Some((p.black_id.as_deref()?, p.white_id.as_str())))
+                .enumerate()
+                .map(|(index, (white, black))| Pairing {
+                    armageddon: true,
+                    ..Pairing::game(index as u32 + 1, white, black)
+                })
+                .collect();
+            if !replays.is_empty() {
+                return Ok(Some(replays));
+            }
+        }
+
+        let records = self.records(tournament);
+        let alive: Vec<(usize, &str)> = ranked
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| records[id.as_str()].eliminated_in.is_none())
+            .map(|(seed, id)| (seed, id.as_str()))
+            .collect();
+        if alive.len() < 2 {
+            return Ok(None);
+        }
+
+        // Unbeaten players meet whoever is left in the same block of the bracket, the
+        // blocks doubling in size with every bracket round
+        let stage = tournament
+            .rounds
+            .iter()
+            .filter(|r| !is_armageddon(r))
+            .count();
+        let size = ranked.len().next_power_of_two();
+        let mut slots = vec![0; size];
+        for (slot, seed) in bracket_order(size).into_iter().enumerate() {
+            slots[seed] = slot;
+        }
+        let mut blocks: Vec<(usize, Vec<&str>)> = Vec::new();
+        for &(seed, id) in alive.iter().filter(|(_, id)| records[id].losses == 0) {
+            let block = slots[seed] >> (stage + 1);
+            match blocks.iter_mut().find(|(b, _)| *b == block) {
+                Some((_, players)) => players.push(id),
+                None => blocks.push((block, vec![id])),
+            }
+        }
+        let mut matches: Vec<(&str, &str)> = blocks
+            .into_iter()
+            .filter_map(|(_, players)| match players[..] {
+                [a, b] => Some((a, b)),
+                _ => None,
+            })
+            .collect();
+
+        // Losers bracket: top seed against bottom seed, the top seed resting if odd
+        let beaten: Vec<&str> = alive
+            .iter()
+            .filter(|(_, id)| records[id].losses == 1)
+            .map(|&(_, id)| id)
+            .collect();
+        let beaten = &beaten[beaten.len() % 2..];
+        for i in 0..beaten.len() / 2 {
+            matches.push((beaten[i], beaten[beaten.len() - 1 - i]));
+        }
+
+        if matches.is_empty() {
+            // The final: last unbeaten player against the last once-beaten one
+            matches.push((alive[0].1, alive[1].1));
+        }
+
+        let pairings = matches
+            .into_iter()
+            .enumerate()
+            .map(|(index, (higher, lower))| {
+                // White to whoever has had it less, the higher seed on a tie
+                let (white, black) = if records[lower].whites < records[higher].whites {
+                    (lower, higher)
+                } else {
+                    (higher, lower)
+                };
+                Pairing::game(index as u32 + 1, white, black)
+            })
+            .collect();
+        Ok(Some(pairings))
+    }
+
+    /// Players still in first, fewest losses first; then the knocked out, latest first.
+    /// Ties go to the higher seed.
+    fn standings(&self, tournament: &Tournament) -> Standings {
+        let records = self.records(tournament);
+        let mut rows: Vec<(usize, String, &Record)> = tournament
+            .ranked_player_ids()
+            .into_iter()
+            .enumerate()
+            .map(|(seed, id)| {
+                let record = &records[id.as_str()];
+                (seed, id, record)
+            })
+            .collect();
+        rows.sort_by_key(|(seed, _, record)| {
+            (
+                record.eliminated_in.is_some(),
+                Reverse(record.eliminated_in),
+                record.losses,
+                *seed,
+            )
+        });
+        Standings::Knockout(
+            rows.into_iter()
+                .enumerate()
+                .map(|(index, (_, user_id, record))| KnockoutStanding {
+                    rank: index as u32 + 1,
+                    user_id,
+                    losses: record.losses,
+                    eliminated_in: record.eliminated_in,
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{TournamentFormat, TournamentPlayer};
+    use std::cell::Cell;
+
+    fn knockout(players: usize, elimination: Elimination) -> Tournament {
+        let mut tournament = Tournament::new(
+            "t1".to_string(),
+            "Club Cup".to_string(),
+            "org".to_string(),
+            "rapid".to_string(),
+            true,
+            TournamentFormat::Knockout { elimination },
+            0,
+        );
+        for i in 1..=players {
+            let user_id = format!("p{}", i);
+            tournament.players.insert(
+                user_id.clone(),
+                TournamentPlayer {
+                    user_id,
+                    rating: 2000 - i as i32,
+                    registered_at: 0,
+                },
+            );
+        }
+        tournament
+    }
+
+    fn seed(id: &str) -> u32 {
+        id[1..].parse().unwrap()
+    }
+
+    /// Pairs and plays rounds until the format says it is over; `result` decides each game
+    fn play_out(
+        tournament: &mut Tournament,
+        result: impl Fn(&Pairing) -> GameResult,
+    ) -> Vec<Vec<(String, String)>> {
+        let format = tournament.format.round_format().unwrap();
+        let mut boards = Vec::new();
+        while let Some(pairings) = format.pair_next(tournament).unwrap() {
+            boards.push(
+                pairings
+                    .iter()
+                    .map(|p| (p.white_id.clone(), p.black_id.clone().unwrap()))
+                    .collect(),
+            );
+            let pairings = pairings
+                .into_iter()
+                .map(|p| Pairing {
+                    result: Some(result(&p)),
+                    ..p
+                })
+                .collect();
+            let number = tournament.rounds.len() as u32 + 1;
+            tournament.rounds.push(Round { number, pairings });
+        }
+        boards
+    }
+
+    fn higher_seed_wins(pairing: &Pairing) -> GameResult {
+        if seed(&pairing.white_id) < seed(pairing.black_id.as_deref().unwrap()) {
+            GameResult::WhiteWins
+        } else {
+            GameResult::BlackWins
+        }
+    }
+
+    fn ranking(tournament: &Tournament) -> Vec<(String, Option<u32>)> {
+        let Standings::Knockout(standings) = tournament
+            .format
+            .round_format()
+            .unwrap()
+            .standings(tournament)
+        else {
+            unreachable!()
+        };
+        standings
+            .into_iter()
+            .map(|s| (s.user_id, s.eliminated_in))
+            .collect()
+    }
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(w, b)| (w.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_bracket_order() {
+        assert_eq!(bracket_order(1), [0]);
+        assert_eq!(bracket_order(4), [0, 3, 1, 2]);
+        assert_eq!(bracket_order(8), [0, 7, 3, 4, 1, 6, 2, 5]);
+    }
+
+    #[test]
+    fn test_single_elimination_gives_top_seeds_the_byes() {
+        let mut tournament = knockout(6, Elimination::Single);
+        let rounds = play_out(&mut tournament, higher_seed_wins);
+        assert_eq!(
+            rounds,
+            [
+                pairs(&[("p3", "p6"), ("p4", "p5")]),
+                pairs(&[("p1", "p4"), ("p2", "p3")]),
+                pairs(&[("p1", "p2")]),
+            ]
+        );
+        assert_eq!(
+            ranking(&tournament),
+            [
+                ("p1".to_string(), None),
+                ("p2".to_string(), Some(3)),
+                ("p3".to_string(), Some(2)),
+                ("p4".to_string(), Some(2)),
+                ("p5".to_string(), Some(1)),
+                ("p6".to_string(), Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_draw_is_settled_by_armageddon() {
+        let mut tournament = knockout(2, Elimination::Single);
+        let rounds = play_out(&mut tournament, |_| GameResult::Draw);
+        // p2 had black, so gets white in the replay; the drawn Armageddon goes to p1
+        assert_eq!(rounds, [pairs(&[("p1", "p2")]), pairs(&[("p2", "p1")])]);
+        assert!(tournament.rounds[1].pairings[0].armageddon);
+        assert_eq!(ranking(&tournament)[0], ("p1".to_string(), None));
+        assert_eq!(ranking(&tournament)[1], ("p2".to_string(), Some(2)));
+    }
+
+    #[test]
+    fn test_double_elimination_replays_a_lost_final() {
+        let mut tournament = knockout(4, Elimination::Double);
+        // Higher seeds win, except that p2 beats p1 after losing to them once
+        let meetings = Cell::new(0);
+        let rounds = play_out(&mut tournament, |p| {
+            if !p.involves("p1") || !p.involves("p2") {
+                return higher_seed_wins(p);
+            }
+            meetings.set(meetings.get() + 1);
+            let p2_wins = meetings.get() > 1;
+            match (p.white_id == "p2") == p2_wins {
+                true => GameResult::WhiteWins,
+                false => GameResult::BlackWins,
+            }
+        });
+        assert_eq!(
+            rounds,
+            [
+                pairs(&[("p1", "p4"), ("p2", "p3")]),
+                pairs(&[("p1", "p2"), ("p3", "p4")]),
+                pairs(&[("p2", "p3")]),
+                // The final, then the replay after the unbeaten p1 loses it
+                pairs(&[("p1", "p2")]),
+                pairs(&[("p2", "p1")]),
+            ]
+        );
+        assert_eq!(
+            ranking(&tournament),
+            [
+                ("p2".to_string(), None),
+                ("p1".to_string(), Some(5)),
+                ("p3".to_string(), Some(3)),
+                ("p4".to_string(), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_needs_two_players() {
+        let tournament = knockout(1, Elimination::Single);
+        assert_eq!(
+            tournament
+                .format
+                .round_format()
+                .unwrap()
+                .pair_next(&tournament),
+            Err(PairingError::NotEnoughPlayers)
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Tournament model, pairing and standings
+//!
+//! Everything here is pure: the tournament is passed in and pairings or standings come
+//! back, so formats can be tested against worked examples without any storage. Points
+//! are counted in half points throughout so draws stay exact.
+
+pub mod arena;
+pub mod format;
+pub mod knockout;
+pub mod model;
+pub mod round_robin;
+pub mod swiss;
+pub mod tiebreaks;
+
+pub use arena::{ArenaGame, ArenaStanding};
+pub use format::RoundFormat;
+pub use knockout::{Elimination, KnockoutStanding};
+pub use model::{
+    HalfPoints, Pairing, Round, Tournament, TournamentFormat, TournamentPlayer, TournamentStatus,
+};
+pub use swiss::PairingError;
+pub use tiebreaks::{standings, Standing, Standings};
@@ -0,0 +1,362 @@
+use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
+use shared::{Color, GameResult};
+use std::collections::HashMap;
+
+use crate::arena::ArenaGame;
+use crate::knockout::Elimination;
+
+/// Tournament points times two, so a draw is 1 and a win 2
+pub type HalfPoints = u32;
+
+/// Points awarded for a pairing-allocated bye
+pub const BYE_POINTS: HalfPoints = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TournamentFormat {
+    Swiss {
+        rounds: u32,
+    },
+    /// Everyone plays everyone `cycles` times, on Berger tables
+    RoundRobin {
+        cycles: u32,
+    },
+    /// Losers drop out; drawn matches are settled by an Armageddon game
+    Knockout {
+        elimination: Elimination,
+    },
+    /// Players are paired continuously until the time runs out
+    Arena {
+        minutes: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentStatus {
+    /// Open for registration; no round has been paired
+    Registering,
+    InProgress,
+    Finished,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TournamentPlayer {
+    pub user_id: String,
+    /// Rating at registration, which fixes the pairing order
+    pub rating: i32,
+    pub registered_at: u64,
+}
+
+/// One board of a round, or a bye if there is no black player
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pairing {
+    pub board: u32,
+    pub white_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub black_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+    /// Copied from the game once it has finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<GameResult>,
+    /// Knockout tiebreak in which a draw counts as a win for black
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub armageddon: bool,
+}
+
+impl Pairing {
+    pub fn game(board: u32, white_id: &str, black_id: &str) -> Self {
+        Self {
+            board,
+            white_id: white_id.to_string(),
+            black_id: Some(black_id.to_string()),
+            game_id: None,
+            result: None,
+            armageddon: false,
+        }
+    }
+
+    pub fn bye(board: u32, user_id: &str) -> Self {
+        Self {
+            board,
+            white_id: user_id.to_string(),
+            black_id: None,
+            game_id: None,
+            result: None,
+            armageddon: false,
+        }
+    }
+
+    pub fn is_bye(&self) -> bool {
+        self.black_id.is_none()
+    }
+
+    pub fn involves(&self, user_id: &str) -> bool {
+        self.white_id == user_id || self.black_id.as_deref() == Some(user_id)
+    }
+
+    /// True once the points for this board are known
+    pub fn is_decided(&self) -> bool {
+        self.is_bye() || self.result.is_some()
+    }
+
+    pub fn opponent_of(&self, user_id: &str) -> Option<&str> {
+        if self.white_id == user_id {
+            self.black_id.as_deref()
+        } else if self.black_id.as_deref() == Some(user_id) {
+            Some(&self.white_id)
+        } else {
+            None
+        }
+    }
+
+    /// The colour `user_id` played; None for a bye
+    pub fn color_of(&self, user_id: &str) -> Option<Color> {
+        self.black_id.as_ref()?;
+        if self.white_id == user_id {
+            Some(Color::White)
+        } else if self.black_id.as_deref() == Some(user_id) {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    /// Result that counts for the tournament: an Armageddon draw is a win for black
+    pub fn decisive_result(&self) -> Option<GameResult> {
+        match self.result? {
+            GameResult::Draw if self.armageddon => Some(GameResult::BlackWins),
+            result => Some(result),
+        }
+    }
+
+    /// Points `user_id` scored on this board, if it is decided
+    pub fn points_for(&self, user_id: &str) -> Option<HalfPoints> {
+        if self.is_bye() {
+            return (self.white_id == user_id).then_some(BYE_POINTS);
+        }
+        let color = self.color_of(user_id)?;
+        Some(match (self.decisive_result()?, color) {
+            (GameResult::Draw, _) => 1,
+            (GameResult::WhiteWins, Color::White) | (GameResult::BlackWins, Color::Black) => 2,
+            _ => 0,
+        })
+    }
+
+    /// Winner and loser of a decided game; None for byes, draws and unfinished games
+    pub fn winner_and_loser(&self) -> Option<(&str, &str)> {
+        let black_id = self.black_id.as_deref()?;
+        match self.decisive_result()? {
+            GameResult::WhiteWins => Some((&self.white_id, black_id)),
+            GameResult::BlackWins => Some((black_id, &self.white_id)),
+            GameResult::Draw => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Round {
+    pub number: u32,
+    pub pairings: Vec<Pairing>,
+}
+
+impl Round {
+    pub fn is_complete(&self) -> bool {
+        self.pairings.iter().all(Pairing::is_decided)
+    }
+
+    pub fn pairing_of(&self, user_id: &str) -> Option<&Pairing> {
+        self.pairings.iter().find(|p| p.involves(user_id))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tournament {
+    pub tournament_id: String,
+    pub name: String,
+    pub organizer_id: String,
+    pub time_control: String,
+    pub rated: bool,
+    /// Tournaments created before variants existed are all standard
+    #[serde(default)]
+    pub variant: Variant,
+    pub format: TournamentFormat,
+    pub status: TournamentStatus,
+    #[serde(default)]
+    pub players: HashMap<String, TournamentPlayer>,
+    #[serde(default)]
+    pub rounds: Vec<Round>,
+    pub created_at: u64,
+    /// When an arena stops pairing; set when it starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<u64>,
+    /// Finished arena games by game id. Always written, even empty, so games can be added
+    /// to the map in place
+    #[serde(default)]
+    pub arena_games: HashMap<String, ArenaGame>,
+    /// Bumped on every write that changes rounds, so concurrent writers can't both win
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl Tournament {
+    pub fn new(
+        tournament_id: String,
+        name: String,
+        organizer_id: String,
+        time_control: String,
+        rated: bool,
+        format: TournamentFormat,
+        now: u64,
+    ) -> Self {
+        Self {
+            tournament_id,
+            name,
+            organizer_id,
+            time_control,
+            rated,
+            variant: Variant::Standard,
+            format,
+            status: TournamentStatus::Registering,
+            players: HashMap::new(),
+            rounds: Vec::new(),
+            created_at: now,
+            ends_at: None,
+            arena_games: HashMap::new(),
+            version: 0,
+        }
+    }
+
+    /// Player ids in pairing order: highest rating first, ties by user id
+    pub fn ranked_player_ids(&self) -> Vec<String> {
+        let mut players: Vec<&TournamentPlayer> = self.players.values().collect();
+        players.sort_by(|a, b| b.rating.cmp(&a.rating).then(a.user_id.cmp(&b.user_id)));
+        players.into_iter().map(|p| p.user_id.clone()).collect()
+    }
+
+    /// Rounds to be paired, when known in advance
+    ///
+    /// Knockouts go on until one player is left and arenas pair continuously, so neither
+    /// has a fixed number.
+    pub fn total_rounds(&self) -> Option<u32> {
+        match self.format {
+            TournamentFormat::Swiss { rounds } => Some(rounds),
+            TournamentFormat::RoundRobin { cycles } => {
+                Some(cycles * crate::round_robin::rounds_per_cycle(self.players.len()))
+            }
+            TournamentFormat::Knockout { .. } | TournamentFormat::Arena { .. } => None,
+        }
+    }
+
+    pub fn is_arena(&self) -> bool {
+        matches!(self.format, TournamentFormat::Arena { .. })
+    }
+
+    /// True while an arena pairs players at `now`
+    pub fn arena_running(&self, now: u64) -> bool {
+        self.status == TournamentStatus::InProgress && self.ends_at.is_some_and(|end| now < end)
+    }
+
+    pub fn current_round(&self) -> Option<&Round> {
+        self.rounds.last()
+    }
+
+    /// Decided points of `user_id` over all rounds so far
+    pub fn score_of(&self, user_id: &str) -> HalfPoints {
+        self.rounds
+            .iter()
+            .filter_map(|round| round.pairing_of(user_id)?.points_for(user_id))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decided(result: GameResult) -> Pairing {
+        Pairing {
+            result: Some(result),
+            ..Pairing::game(1, "alice", "bob")
+        }
+    }
+
+    #[test]
+    fn test_points() {
+        let win = decided(GameResult::WhiteWins);
+        assert_eq!(win.points_for("alice"), Some(2));
+        assert_eq!(win.points_for("bob"), Some(0));
+        assert_eq!(win.points_for("carol"), None);
+        assert_eq!(decided(GameResult::Draw).points_for("bob"), Some(1));
+        assert_eq!(Pairing::game(1, "alice", "bob").points_for("alice"), None);
+        assert_eq!(
+            Pairing::bye(4, "carol").points_for("carol"),
+            Some(BYE_POINTS)
+        );
+    }
+
+    #[test]
+    fn test_bye_has_no_colour_or_opponent() {
+        let bye = Pairing::bye(4, "carol");
+        assert!(bye.is_decided());
+        assert_eq!(bye.color_of("carol"), None);
+        assert_eq!(bye.opponent_of("carol"), None);
+        assert_eq!(
+            decided(GameResult::Draw).color_of("bob"),
+            Some(Color::Black)
+        );
+    }
+
+    #[test]
+    fn test_ranked_player_ids() {
+        let mut tournament = Tournament::new(
+            "t1".to_string(),
+            "Club Swiss".to_string(),
+            "org".to_string(),
+            "blitz".to_string(),
+            true,
+            TournamentFormat::Swiss { rounds: 5 },
+            0,
+        );
+        for (user_id, rating) in [("bob", 1500), ("carol", 1700), ("alice", 1500)] {
+            tournament.players.insert(
+                user_id.to_string(),
+                TournamentPlayer {
+                    user_id: user_id.to_string(),
+                    rating,
+                    registered_at: 0,
+                },
+            );
+        }
+        assert_eq!(tournament.ranked_player_ids(), ["carol", "alice", "bob"]);
+    }
+
+    #[test]
+    fn test_format_serialization() {
+        let json = serde_json::to_value(TournamentFormat::Swiss { rounds: 7 }).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "swiss", "rounds": 7 }));
+        let json = serde_json::to_value(TournamentFormat::Arena { minutes: 60 }).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "arena", "minutes": 60 }));
+        let json = serde_json::to_value(TournamentFormat::Knockout {
+            elimination: Elimination::Double,
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "type": "knockout", "elimination": "double" })
+        );
+    }
+
+    #[test]
+    fn test_armageddon_draw_goes_to_black() {
+        let armageddon = Pairing {
+            armageddon: true,
+            ..decided(GameResult::Draw)
+        };
+        assert_eq!(armageddon.points_for("bob"), Some(2));
+        assert_eq!(armageddon.winner_and_loser(), Some(("bob", "alice")));
+        assert_eq!(decided(GameResult::Draw).winner_and_loser(), None);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::format::RoundFormat;
+use crate::model::{Pairing, Tournament};
+use crate::swiss::PairingError;
+use crate::tiebreaks::{standings, Standings};
+
+/// Everyone plays everyone once per cycle; the second cycle of a double round-robin
+/// repeats the first with colours reversed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundRobin {
+    pub cycles: u32,
+}
+
+/// Rounds in one cycle; with an odd field every player rests once
+pub fn rounds_per_cycle(players: usize) -> u32 {
+    if players < 2 {
+        return 0;
+    }
+    (players + players % 2 - 1) as u32
+}
+
+/// Boards of a round of the Berger table for `players`, white first
+///
+/// Players and `round` are numbered from zero. The last pairing number stays put while
+/// the others rotate, and it alternates colours from round to round. With an odd field
+/// the player drawn against the missing last number rests and is left out.
+pub fn berger_round(players: usize, round: usize) -> Vec<(usize, usize)> {
+    let n = players + players % 2;
+    let rotating = n - 1;
+    let last = n - 1;
+    // The player facing the last number moves n/2 places each round
+    let pivot = (round * n / 2) % rotating;
+
+    let mut boards = vec![if round.is_multiple_of(2) {
+        (pivot, last)
+    } else {
+        (last, pivot)
+    }];
+    for k in 1..n / 2 {
+        boards.push(((pivot + k) % rotating, (pivot + rotating - k) % rotating));
+    }
+    boards.retain(|&(white, black)| white < players && black < players);
+    boards
+}
+
+impl RoundFormat for RoundRobin {
+    fn pair_next(&self, tournament: &Tournament) -> Result<Option<Vec<Pairing>>, PairingError> {
+        let ranked = tournament.ranked_player_ids();
+        if ranked.len() < 2 {
+            return Err(PairingError::NotEnoughPlayers);
+        }
+        let per_cycle = rounds_per_cycle(ranked.len()) as usize;
+        let played = tournament.rounds.len();
+        if played >= per_cycle * self.cycles as usize {
+            return Ok(None);
+        }
+
+        let reversed = (played / per_cycle) % 2 == 1;
+        let pairings = berger_round(ranked.len(), played % per_cycle)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (white, black))| {
+                let (white, black) = if reversed {
+                    (black, white)
+                } else {
+                    (white, black)
+                };
+                Pairing::game(index as u32 + 1, &ranked[white], &ranked[black])
+            })
+            .collect();
+        Ok(Some(pairings))
+    }
+
+    fn standings(&self, tournament: &Tournament) -> Standings {
+        Standings::Rounds(standings(tournament))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Round, TournamentFormat, TournamentPlayer};
+    use std::collections::HashMap;
+
+    fn tournament(players: usize, cycles: u32) -> Tournament {
+        let mut tournament = Tournament::new(
+            "t1".to_string(),
+            "Club Championship".to_string(),
+            "org".to_string(),
+            "rapid".to_string(),
+            true,
+            TournamentFormat::RoundRobin { cycles },
+            0,
+        );
+        for i in 1..=players {
+            let user_id = format!("p{}", i);
+            tournament.players.insert(
+                user_id.clone(),
+                TournamentPlayer {
+                    user_id,
+                    rating: 2000 - i as i32,
+                    registered_at: 0,
+                },
+            );
+        }
+        tournament
+    }
+
+    /// Pairs every round until the format says it is over
+    fn play_out(tournament: &mut Tournament) {
+        let format = RoundRobin {
+            cycles: match tournament.format {
+                TournamentFormat::RoundRobin { cycles } => cycles,
+                _ => unreachable!(),
+            },
+        };
+        while let Some(pairings) = format.pair_next(tournament).unwrap() {
+            let number = tournament.rounds.len() as u32 + 1;
+            tournament.rounds.push(Round { number, pairings });
+        }
+    }
+
+    #[test]
+    fn test_berger_tables() {
+        // FIDE Berger tables, pairing numbers from one
+        let one_based = |players, round| {
+            berger_round(players, round)
+                .into_iter()
+                .map(|(w, b)| (w + 1, b + 1))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(one_based(4, 0), [(1, 4), (2, 3)]);
+        assert_eq!(one_based(4, 1), [(4, 3), (1, 2)]);
+        assert_eq!(one_based(4, 2), [(2, 4), (3, 1)]);
+        assert_eq!(one_based(6, 1), [(6, 4), (5, 3), (1, 2)]);
+        assert_eq!(one_based(6, 4), [(3, 6), (4, 2), (5, 1)]);
+        // Player 3 would face the missing sixth player, so rests
+        assert_eq!(one_based(5, 4), [(4, 2), (5, 1)]);
+    }
+
+    #[test]
+    fn test_everyone_meets_everyone_once() {
+        for players in [4, 5, 8, 9] {
+            let mut tournament = tournament(players, 1);
+            play_out(&mut tournament);
+            assert_eq!(
+                tournament.rounds.len() as u32,
+                rounds_per_cycle(players),
+                "{} players",
+                players
+            );
+            assert_eq!(tournament.total_rounds(), Some(rounds_per_cycle(players)));
+
+            let mut meetings: HashMap<(String, String), u32> = HashMap::new();
+            let mut whites: HashMap<String, u32> = HashMap::new();
+            for pairing in tournament.rounds.iter().flat_map(|r| &r.pairings) {
+                let black = pairing.black_id.clone().unwrap();
+                let key = if pairing.white_id < black {
+                    (pairing.white_id.clone(), black)
+                } else {
+                    (black, pairing.white_id.clone())
+                };
+                *meetings.entry(key).or_default() += 1;
+                *whites.entry(pairing.white_id.clone()).or_default() += 1;
+            }
+            assert_eq!(meetings.len(), players * (players - 1) / 2);
+            assert!(meetings.values().all(|&n| n == 1));
+            // Berger tables keep colours within one of even
+            let games = players as u32 - 1;
+            assert!(whites
+                .values()
+                .all(|&w| w.abs_diff(games - w) <= 1 + (players % 2) as u32));
+        }
+    }
+
+    #[test]
+    fn test_second_cycle_reverses_colours() {
+        let mut tournament = tournament(4, 2);
+        play_out(&mut tournament);
+        assert_eq!(tournament.rounds.len(), 6);
+        for (first, second) in tournament.rounds[..3].iter().zip(&tournament.rounds[3..]) {
+            for (a, b) in first.pairings.iter().zip(&second.pairings) {
+                assert_eq!(Some(&a.white_id), b.black_id.as_ref());
+                assert_eq!(a.black_id.as_ref(), Some(&b.white_id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_needs_two_players() {
+        assert_eq!(
+            RoundRobin { cycles: 1 }.pair_next(&tournament(1, 1)),
+            Err(PairingError::NotEnoughPlayers)
+        );
+    }
+}
@@ -0,0 +1,829 @@
+use shared::Color;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::format::RoundFormat;
+use crate::model::{HalfPoints, Pairing, Round, Tournament};
+use crate::tiebreaks::{standings, Standings};
+
+/// Upper bound on search steps per attempt, so a field with no valid pairing fails quickly
+const SEARCH_BUDGET: usize = 200_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PairingError {
+    NotEnoughPlayers,
+    /// Every pairing repeats a game or clashes on colours
+    NoValidPairing,
+}
+
+impl fmt::Display for PairingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PairingError::NotEnoughPlayers => write!(f, "At least two players are needed"),
+            PairingError::NoValidPairing => write!(f, "No valid pairing exists for this round"),
+        }
+    }
+}
+
+impl std::error::Error for PairingError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Strength {
+    Mild,
+    Strong,
+    Absolute,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ColorPreference {
+    color: Color,
+    strength: Strength,
+}
+
+/// A player's record going into the round
+#[derive(Debug)]
+struct Entrant {
+    id: String,
+    /// Pairing number minus one
+    rank: usize,
+    score: HalfPoints,
+    opponents: HashSet<String>,
+    /// Colours of games actually played, oldest first
+    colors: Vec<Color>,
+    had_bye: bool,
+}
+
+impl Entrant {
+    fn new(id: &str, rank: usize, rounds: &[Round]) -> Self {
+        let mut entrant = Self {
+            id: id.to_string(),
+            rank,
+            score: 0,
+            opponents: HashSet::new(),
+            colors: Vec::new(),
+            had_bye: false,
+        };
+        for pairing in rounds.iter().filter_map(|round| round.pairing_of(id)) {
+            entrant.score += pairing.points_for(id).unwrap_or(0);
+            match (pairing.opponent_of(id), pairing.color_of(id)) {
+                (Some(opponent), Some(color)) => {
+                    entrant.opponents.insert(opponent.to_string());
+                    entrant.colors.push(color);
+                }
+                _ => entrant.had_bye = true,
+            }
+        }
+        entrant
+    }
+
+    fn color_difference(&self) -> i32 {
+        self.colors
+            .iter()
+            .map(|c| if *c == Color::White { 1 } else { -1 })
+            .sum()
+    }
+
+    /// FIDE colour preference: absolute after two more games with one colour or the same
+    /// colour twice running, strong after one more, mild to alternate otherwise
+    fn preference(&self) -> Option<ColorPreference> {
+        let difference = self.color_difference();
+        let last = self.colors.last().copied();
+        let twice_running = match self.colors.as_slice() {
+            [.., a, b] if a == b => Some(*b),
+            _ => None,
+        };
+        let (color, strength) = if difference > 1 {
+            (Color::Black, Strength::Absolute)
+        } else if difference < -1 {
+            (Color::White, Strength::Absolute)
+        } else if let Some(color) = twice_running {
+            (color.opposite(), Strength::Absolute)
+        } else if difference == 1 {
+            (Color::Black, Strength::Strong)
+        } else if difference == -1 {
+            (Color::White, Strength::Strong)
+        } else {
+            (last?.opposite(), Strength::Mild)
+        };
+        Some(ColorPreference { color, strength })
+    }
+
+    /// Sort key putting higher scores first, then lower pairing numbers
+    fn order(&self) -> (Reverse<HalfPoints>, usize) {
+        (Reverse(self.score), self.rank)
+    }
+}
+
+/// Swiss system over a fixed number of rounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Swiss {
+    pub rounds: u32,
+}
+
+impl RoundFormat for Swiss {
+    fn pair_next(&self, tournament: &Tournament) -> Result<Option<Vec<Pairing>>, PairingError> {
+        if tournament.rounds.len() as u32 >= self.rounds {
+            return Ok(None);
+        }
+        pair_round(&tournament.ranked_player_ids(), &tournament.rounds).map(Some)
+    }
+
+    fn standings(&self, tournament: &Tournament) -> Standings {
+        Standings::Rounds(standings(tournament))
+    }
+}
+
+/// Pairs the next round of a Swiss tournament using the FIDE Dutch system
+///
+/// `ranked` holds the player ids in pairing-number order and `rounds` the rounds played
+/// so far. Players are paired within score groups, top half against bottom half, with
+/// players who can't be paired in their group floating down to the next. Nobody meets
+/// the same opponent twice, two players who must both have the same colour never meet,
+/// and with an odd number of players the lowest-placed player without a bye gets one.
+pub fn pair_round(ranked: &[String], rounds: &[Round]) -> Result<Vec<Pairing>, PairingError> {
+    if ranked.len() < 2 {
+        return Err(PairingError::NotEnoughPlayers);
+    }
+    let entrants: Vec<Entrant> = ranked
+        .iter()
+        .enumerate()
+        .map(|(rank, id)| Entrant::new(id, rank, rounds))
+        .collect();
+    let mut pairer = Pairer {
+        entrants: &entrants,
+        steps: 0,
+        strict_first: true,
+    };
+    let everyone: Vec<usize> = (0..entrants.len()).collect();
+
+    let (bye, pairs) = if entrants.len() % 2 == 1 {
+        let mut candidates = everyone.clone();
+        candidates.sort_by_key(|&i| (entrants[i].had_bye, entrants[i].score, Reverse(i)));
+        candidates
+            .into_iter()
+            .find_map(|bye| {
+                let rest: Vec<usize> = everyone.iter().copied().filter(|&i| i != bye).collect();
+                pairer.pair_field(&rest).map(|pairs| (Some(bye), pairs))
+            })
+            .ok_or(PairingError::NoValidPairing)?
+    } else {
+        let pairs = pairer
+            .pair_field(&everyone)
+            .ok_or(PairingError::NoValidPairing)?;
+        (None, pairs)
+    };
+
+    Ok(pairer.boards(pairs, bye))
+}
+
+/// The bracket being paired and what follows it
+struct Bracket<'g> {
+    groups: &'g [Vec<usize>],
+    index: usize,
+    players: Vec<usize>,
+    pairs: usize,
+    strict: bool,
+}
+
+struct Pairer<'a> {
+    entrants: &'a [Entrant],
+    steps: usize,
+    /// Whether each bracket tries the strict colour rules before relaxing them
+    strict_first: bool,
+}
+
+impl Pairer<'_> {
+    /// Pairs every player in `players`, trying the strict colour rules first and then
+    /// only the relaxed ones, each attempt with its own search budget
+    fn pair_field(&mut self, players: &[usize]) -> Option<Vec<(usize, usize)>> {
+        [true, false].into_iter().find_map(|strict_first| {
+            self.steps = 0;
+            self.strict_first = strict_first;
+            self.pair_all(players)
+        })
+    }
+
+    /// Pairs every player in `players`, or None if that's impossible
+    fn pair_all(&mut self, players: &[usize]) -> Option<Vec<(usize, usize)>> {
+        let mut sorted = players.to_vec();
+        sorted.sort_by_key(|&i| self.entrants[i].order());
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for i in sorted {
+            match groups.last_mut() {
+                Some(group) if self.entrants[group[0]].score == self.entrants[i].score => {
+                    group.push(i)
+                }
+                _ => groups.push(vec![i]),
+            }
+        }
+        self.pair_groups(&groups, 0, Vec::new())
+    }
+
+    /// Pairs score group `index` together with the players floating down into it, then
+    /// the groups below; backtracks if what's left over can't be paired further down
+    fn pair_groups(
+        &mut self,
+        groups: &[Vec<usize>],
+        index: usize,
+        floaters: Vec<usize>,
+    ) -> Option<Vec<(usize, usize)>> {
+        let Some(group) = groups.get(index) else {
+            return floaters.is_empty().then(Vec::new);
+        };
+        let mut players = floaters;
+        players.extend(group);
+        players.sort_by_key(|&i| self.entrants[i].order());
+
+        let last = index + 1 == groups.len();
+        if last && players.len() % 2 == 1 {
+            return None;
+        }
+        let max_pairs = players.len() / 2;
+        let min_pairs = if last { max_pairs } else { 0 };
+        // As many pairs as possible, then as few colour clashes as possible
+        let modes: &[bool] = if self.strict_first {
+            &[true, false]
+        } else {
+            &[false]
+        };
+        for pairs in (min_pairs..=max_pairs).rev() {
+            for &strict in modes {
+                let bracket = Bracket {
+                    groups,
+                    index,
+                    players: players.clone(),
+                    pairs,
+                    strict,
+                };
+                let mut used = vec![false; players.len() - pairs];
+                if let Some(result) = self.transpose(&bracket, 0, &mut used, &mut Vec::new()) {
+                    return Some(result);
+                }
+                if let Some(result) =
+                    self.exchange(&bracket, &players, pairs, &mut Vec::new(), &mut Vec::new())
+                {
+                    return Some(result);
+                }
+            }
+        }
+        None
+    }
+
+    /// Tries the top `pairs` players (S1) against the rest (S2) with S2 reordered, in
+    /// the order the Dutch system prefers
+    fn transpose(
+        &mut self,
+        bracket: &Bracket,
+        i: usize,
+        used: &mut Vec<bool>,
+        pairs: &mut Vec<(usize, usize)>,
+    ) -> Option<Vec<(usize, usize)>> {
+        if !self.step() {
+            return None;
+        }
+        let (s1, s2) = bracket.players.split_at(bracket.pairs);
+        if i == s1.len() {
+            let leftover = s2
+                .iter()
+                .zip(used.iter())
+                .filter(|(_, used)| !**used)
+                .map(|(p, _)| *p)
+                .collect();
+            return self.finish(bracket, pairs, leftover);
+        }
+        for j in 0..s2.len() {
+            if used[j] || !self.can_meet(s1[i], s2[j], bracket.strict) {
+                continue;
+            }
+            used[j] = true;
+            pairs.push((s1[i], s2[j]));
+            if let Some(result) = self.transpose(bracket, i + 1, used, pairs) {
+                return Some(result);
+            }
+            pairs.pop();
+            used[j] = false;
+        }
+        None
+    }
+
+    /// Falls back to any `pairs` pairs within the bracket, which covers exchanges
+    /// between the halves
+    fn exchange(
+        &mut self,
+        bracket: &Bracket,
+        remaining: &[usize],
+        pairs_left: usize,
+        floats: &mut Vec<usize>,
+        pairs: &mut Vec<(usize, usize)>,
+    ) -> Option<Vec<(usize, usize)>> {
+        if !self.step() {
+            return None;
+        }
+        if pairs_left == 0 {
+            let mut leftover = floats.clone();
+            leftover.extend(remaining);
+            return self.finish(bracket, pairs, leftover);
+        }
+        if remaining.len() < 2 * pairs_left {
+            return None;
+        }
+        let (&first, rest) = remaining.split_first()?;
+        for k in 0..rest.len() {
+            if !self.can_meet(first, rest[k], bracket.strict) {
+                continue;
+            }
+            let others: Vec<usize> = rest
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != k)
+                .map(|(_, p)| *p)
+                .collect();
+            pairs.push((first, rest[k]));
+            if let Some(result) = self.exchange(bracket, &others, pairs_left - 1, floats, pairs) {
+                return Some(result);
+            }
+            pairs.pop();
+        }
+        floats.push(first);
+        let result = self.exchange(bracket, rest, pairs_left, floats, pairs);
+        floats.pop();
+        result
+    }
+
+    /// Completes a bracket by pairing the groups below with `leftover` floating down
+    fn finish(
+        &mut self,
+        bracket: &Bracket,
+        pairs: &[(usize, usize)],
+        leftover: Vec<usize>,
+    ) -> Option<Vec<(usize, usize)>> {
+        let rest = self.pair_groups(bracket.groups, bracket.index + 1, leftover)?;
+        let mut result = pairs.to_vec();
+        result.extend(rest);
+        Some(result)
+    }
+
+    fn step(&mut self) -> bool {
+        self.steps += 1;
+        self.steps <= SEARCH_BUDGET
+    }
+
+    /// Players who have met can't meet again, nor can two who must both have the same
+    /// colour. When `strict`, two strong preferences for the same colour also rule it out.
+    fn can_meet(&self, a: usize, b: usize, strict: bool) -> bool {
+        let (a, b) = (&self.entrants[a], &self.entrants[b]);
+        if a.opponents.contains(&b.id) {
+            return false;
+        }
+        match (a.preference(), b.preference()) {
+            (Some(p), Some(q)) if p.color == q.color => {
+                let both_absolute =
+                    p.strength == Strength::Absolute && q.strength == Strength::Absolute;
+                let both_strong = p.strength >= Strength::Strong && q.strength >= Strength::Strong;
+                !(both_absolute || strict && both_strong)
+            }
+            _ => true,
+        }
+    }
+
+    /// Orders the pairs into boards, allocates colours and appends the bye
+    fn boards(&self, mut pairs: Vec<(usize, usize)>, bye: Option<usize>) -> Vec<Pairing> {
+        for pair in pairs.iter_mut() {
+            if self.entrants[pair.1].order() < self.entrants[pair.0].order() {
+                *pair = (pair.1, pair.0);
+            }
+        }
+        pairs.sort_by_key(|&(higher, lower)| {
+            let (h, l) = (&self.entrants[higher], &self.entrants[lower]);
+            (Reverse(h.score), Reverse(h.score + l.score), h.rank)
+        });
+
+        let mut boards: Vec<Pairing> = pairs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (higher, lower))| {
+                let (white, black) = self.allocate_colors(higher, lower, i);
+                Pairing::game(
+                    i as u32 + 1,
+                    &self.entrants[white].id,
+                    &self.entrants[black].id,
+                )
+            })
+            .collect();
+        if let Some(bye) = bye {
+            boards.push(Pairing::bye(
+                boards.len() as u32 + 1,
+                &self.entrants[bye].id,
+            ));
+        }
+        boards
+    }
+
+    /// Returns (white, black) for `higher` and `lower`, the higher-placed player first
+    ///
+    /// Both preferences are granted if they differ; otherwise the stronger one wins, then
+    /// the one from the larger colour imbalance, then colours are alternated from the
+    /// last round the two had different colours, and finally the higher-placed player
+    /// gets theirs. With no history at all the top player of odd boards has white.
+    fn allocate_colors(&self, higher: usize, lower: usize, board_index: usize) -> (usize, usize) {
+        let (h, l) = (&self.entrants[higher], &self.entrants[lower]);
+        let higher_gets = |color: Color| match color {
+            Color::White => (higher, lower),
+            Color::Black => (lower, higher),
+        };
+        match (h.preference(), l.preference()) {
+            (None, None) => higher_gets(if board_index.is_multiple_of(2) {
+                Color::White
+            } else {
+                Color::Black
+            }),
+            (Some(p), None) => higher_gets(p.color),
+            (None, Some(q)) => higher_gets(q.color.opposite()),
+            (Some(p), Some(q)) if p.color != q.color => higher_gets(p.color),
+            (Some(p), Some(q)) if p.strength != q.strength => {
+                if p.strength > q.strength {
+                    higher_gets(p.color)
+                } else {
+                    higher_gets(q.color.opposite())
+                }
+            }
+            (Some(p), Some(q)) => {
+                let (dh, dl) = (h.color_difference().abs(), l.color_difference().abs());
+                if p.strength == Strength::Absolute && dh != dl {
+                    return if dh > dl {
+                        higher_gets(p.color)
+                    } else {
+                        higher_gets(q.color.opposite())
+                    };
+                }
+                let last_different = h
+                    .colors
+                    .iter()
+                    .rev()
+                    .zip(l.colors.iter().rev())
+                    .find(|(a, b)| a != b);
+                match last_different {
+                    Some((higher_had, _)) => higher_gets(higher_had.opposite()),
+                    None => higher_gets(p.color),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::GameResult;
+
+    // Worked examples following the FIDE Dutch rules (C.04.3), checked by hand
+
+    fn ids(n: usize) -> Vec<String> {
+        (1..=n).map(|i| format!("p{}", i)).collect()
+    }
+
+    fn boards(pairings: &[Pairing]) -> Vec<(String, String)> {
+        pairings
+            .iter()
+            .map(|p| {
+                (
+                    p.white_id.clone(),
+                    p.black_id.clone().unwrap_or_else(|| "bye".to_string()),
+                )
+            })
+            .collect()
+    }
+
+    fn expected(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(w, b)| (w.to_string(), b.to_string()))
+            .collect()
+    }
+
+    fn with_results(number: u32, pairings: Vec<Pairing>, results: &[GameResult]) -> Round {
+        let mut results = results.iter();
+        let pairings = pairings
+            .into_iter()
+            .map(|mut p| {
+                if !p.is_bye() {
+                    p.result = results.next().copied();
+                }
+                p
+            })
+            .collect();
+        Round { number, pairings }
+    }
+
+    #[test]
+    fn test_first_round_top_half_against_bottom_half() {
+        let pairings = pair_round(&ids(8), &[]).unwrap();
+        assert_eq!(
+            boards(&pairings),
+            expected(&[("p1", "p5"), ("p6", "p2"), ("p3", "p7"), ("p8", "p4")])
+        );
+        assert_eq!(
+            pairings.iter().map(|p| p.board).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_first_round_bye_goes_to_lowest_rated() {
+        let pairings = pair_round(&ids(7), &[]).unwrap();
+        assert_eq!(
+            boards(&pairings),
+            expected(&[("p1", "p4"), ("p5", "p2"), ("p3", "p6"), ("p7", "bye")])
+        );
+    }
+
+    #[test]
+    fn test_second_round_floats_and_alternates_colours() {
+        let players = ids(8);
+        let first = pair_round(&players, &[]).unwrap();
+        // p1 and p2 win, p3-p7 drawn, p4 wins with black
+        let rounds = [with_results(
+            1,
+            first,
+            &[
+                GameResult::WhiteWins,
+                GameResult::BlackWins,
+                GameResult::Draw,
+                GameResult::BlackWins,
+            ],
+        )];
+        let second = pair_round(&players, &rounds).unwrap();
+        // 1 point: p1, p2, p4 - p4 floats; ½ point: p3, p7 plus p4 - p7 floats
+        assert_eq!(
+            boards(&second),
+            expected(&[("p2", "p1"), ("p4", "p3"), ("p7", "p6"), ("p5", "p8")])
+        );
+    }
+
+    #[test]
+    fn test_rematch_is_avoided_by_transposition() {
+        let players = ids(4);
+        // Round 1: p1-p3, p4-p2, both won by white; p1 and p4 on 1 point
+        let round1 = with_results(
+            1,
+            vec![Pairing::game(1, "p1", "p3"), Pairing::game(2, "p4", "p2")],
+            &[GameResult::WhiteWins, GameResult::WhiteWins],
+        );
+        // Round 2: p1 beats p4, p2 beats p3
+        let round2 = with_results(
+            2,
+            vec![Pairing::game(1, "p4", "p1"), Pairing::game(2, "p3", "p2")],
+            &[GameResult::BlackWins, GameResult::BlackWins],
+        );
+        let third = pair_round(&players, &[round1, round2]).unwrap();
+        // p1 (2) has met p3 and p4, so meets p2, who must have white after two blacks;
+        // p4 has had white twice and takes black against p3
+        assert_eq!(boards(&third), expected(&[("p2", "p1"), ("p3", "p4")]));
+    }
+
+    #[test]
+    fn test_nobody_gets_two_byes() {
+        let players = ids(3);
+        let mut rounds = Vec::new();
+        for number in 1..=3 {
+            let pairings = pair_round(&players, &rounds).unwrap();
+            rounds.push(with_results(number, pairings, &[GameResult::Draw]));
+        }
+        let byes: Vec<&str> = rounds
+            .iter()
+            .flat_map(|r| r.pairings.iter())
+            .filter(|p| p.is_bye())
+            .map(|p| p.white_id.as_str())
+            .collect();
+        assert_eq!(byes.len(), 3);
+        assert_eq!(byes.iter().collect::<HashSet<_>>().len(), 3);
+    }
+
+    #[test]
+    fn test_simulated_tournament_keeps_the_rules() {
+        // 11 players, 6 rounds, the higher-rated player always wins
+        let players = ids(11);
+        let mut rounds: Vec<Round> = Vec::new();
+        for number in 1..=6 {
+            let pairings = pair_round(&players, &rounds).unwrap();
+            let results: Vec<GameResult> = pairings
+                .iter()
+                .filter(|p| !p.is_bye())
+                .map(|p| {
+                    let rank = |id: &str| players.iter().position(|p| p == id).unwrap();
+                    if rank(&p.white_id) < rank(p.black_id.as_deref().unwrap()) {
+                        GameResult::WhiteWins
+                    } else {
+                        GameResult::BlackWins
+                    }
+                })
+                .collect();
+            rounds.push(with_results(number, pairings, &results));
+        }
+
+        let mut games = HashSet::new();
+        for round in &rounds {
+            let mut seen = HashSet::new();
+            for pairing in &round.pairings {
+                assert!(seen.insert(pairing.white_id.clone()));
+                if let Some(black) = &pairing.black_id {
+                    assert!(seen.insert(black.clone()));
+                    let mut key = [pairing.white_id.clone(), black.clone()];
+                    key.sort();
+                    assert!(games.insert(key), "rematch in round {}", round.number);
+                }
+            }
+            assert_eq!(seen.len(), players.len());
+        }
+        for player in &players {
+            let entrant = Entrant::new(player, 0, &rounds);
+            assert!(entrant.color_difference().abs() <= 2, "{}", player);
+            assert!(!entrant
+                .colors
+                .windows(3)
+                .any(|w| w[0] == w[1] && w[1] == w[2]));
+        }
+    }
+
+    #[test]
+    fn test_large_fields_pair_every_round() {
+        // Pseudo-random results from a fixed seed, so a failure replays exactly
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next_result = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            [
+                GameResult::WhiteWins,
+                GameResult::BlackWins,
+                GameResult::Draw,
+            ][(seed % 3) as usize]
+        };
+        for size in [51, 64, 65, 100, 101] {
+            let players = ids(size);
+            let mut rounds: Vec<Round> = Vec::new();
+            for number in 1..=7 {
+                let pairings = pair_round(&players, &rounds)
+                    .unwrap_or_else(|e| panic!("{} players, round {}: {}", size, number, e));
+                let results: Vec<GameResult> = pairings
+                    .iter()
+                    .filter(|p| !p.is_bye())
+                    .map(|_| next_result())
+                    .collect();
+                rounds.push(with_results(number, pairings, &results));
+            }
+        }
+    }
+
+    #[test]
+    fn test_colour_preferences() {
+        let entrant = |colors: &[Color]| Entrant {
+            id: "p".to_string(),
+            rank: 0,
+            score: 0,
+            opponents: HashSet::new(),
+            colors: colors.to_vec(),
+            had_bye: false,
+        };
+        use Color::{Black as B, White as W};
+        assert_eq!(entrant(&[]).preference(), None);
+        let pref = |colors: &[Color]| entrant(colors).preference().unwrap();
+        assert_eq!(pref(&[W]).strength, Strength::Strong);
+        assert_eq!(pref(&[W, B]).color, W);
+        assert_eq!(pref(&[W, B]).strength, Strength::Mild);
+        assert_eq!(pref(&[B, W, W]).color, B);
+        assert_eq!(pref(&[B, W, W]).strength, Strength::Absolute);
+        assert_eq!(pref(&[B, B]).color, W);
+    }
+
+    /// Rounds recorded in a FIDE tournament report (TRF), players named p1, p2, ... by
+    /// starting rank
+    ///
+    /// Only pairing-allocated byes and played games are supported, since the engine has no
+    /// notion of absences or forfeits.
+    fn read_trf(trf: &str) -> (Vec<String>, Vec<Round>) {
+        let mut players = Vec::new();
+        let mut rounds: Vec<Round> = Vec::new();
+        for line in trf.lines().filter(|line| line.starts_with("001")) {
+            let rank: usize = line[4..8].trim().parse().unwrap();
+            players.push(rank);
+            // Each round is a 10 character block from column 92: opponent, colour, result
+            let blocks = line.get(91..).unwrap_or("").as_bytes().chunks(10);
+            for (index, block) in blocks.enumerate() {
+                let block = std::str::from_utf8(block).unwrap();
+                let opponent: usize = block[0..4].trim().parse().unwrap_or(0);
+                let (color, result) = (&block[5..6], &block[7..8]);
+                if rounds.len() <= index {
+                    rounds.push(Round {
+                        number: index as u32 + 1,
+                        pairings: Vec::new(),
+                    });
+                }
+                let pairings = &mut rounds[index].pairings;
+                let board = pairings.len() as u32 + 1;
+                match (opponent, color, result) {
+                    (0, _, "U") | (0, _, "+") => {
+                        pairings.push(Pairing::bye(board, &format!("p{}", rank)))
+                    }
+                    (_, "b", "1" | "0" | "=") => {}
+                    (_, "w", "1" | "0" | "=") => {
+                        let mut pairing =
+                            Pairing::game(board, &format!("p{}", rank), &format!("p{}", opponent));
+                        pairing.result = Some(match result {
+                            "1" => GameResult::WhiteWins,
+                            "0" => GameResult::BlackWins,
+                            _ => GameResult::Draw,
+                        });
+                        pairings.push(pairing);
+                    }
+                    _ => panic!(
+                        "Round {} of p{} is unsupported: {:?}",
+                        index + 1,
+                        rank,
+                        block
+                    ),
+                }
+            }
+        }
+        players.sort();
+        assert_eq!(players, (1..=players.len()).collect::<Vec<_>>());
+        (ids(players.len()), rounds)
+    }
+
+    /// Pairs every round of a recorded event from the rounds before it and compares the
+    /// boards with the ones that were played
+    fn replay(trf: &str) {
+        let (players, rounds) = read_trf(trf);
+        for (played, round) in rounds.iter().enumerate() {
+            let pairings = pair_round(&players, &rounds[..played]).unwrap();
+            let mut ours = boards(&pairings);
+            let mut theirs = boards(&round.pairings);
+            ours.sort();
+            theirs.sort();
+            assert_eq!(ours, theirs, "round {}", round.number);
+        }
+    }
+
+    /// The first two rounds of the 8 player example above, as a tournament report
+    const WORKED_EXAMPLE: &str = concat!(
+        "012 Worked example\n",
+        "001    1      Player 1                          2400                             1.5    2     5 w 1     2 b =\n",
+        "001    2      Player 2                          2350                             1.5    3     6 b 1     1 w =\n",
+        "001    3      Player 3                          2300                             0.5    6     7 w =     4 b 0\n",
+        "001    4      Player 4                          2250                             2.0    1     8 b 1     3 w 1\n",
+        "001    5      Player 5                          2200                             1.0    4     1 b 0     8 w 1\n",
+        "001    6      Player 6                          2150                             0.5    7     2 w 0     7 b =\n",
+        "001    7      Player 7                          2100                             1.0    5     3 b =     6 w =\n",
+        "001    8      Player 8                          2050                             0.0    8     4 w 0     5 b 0\n",
+    );
+
+    #[test]
+    fn test_tournament_report_replays_round_by_round() {
+        let (players, rounds) = read_trf(WORKED_EXAMPLE);
+        assert_eq!(players.len(), 8);
+        assert_eq!(rounds.len(), 2);
+        assert_eq!(rounds[0].pairings[0], {
+            let mut pairing = Pairing::game(1, "p1", "p5");
+            pairing.result = Some(GameResult::WhiteWins);
+            pairing
+        });
+        replay(WORKED_EXAMPLE);
+    }
+
+    #[test]
+    #[ignore = "no published event report in fixtures/swiss yet, see its README"]
+    fn test_published_event_pairings() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/swiss");
+        let mut reports: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "trf"))
+            .collect();
+        reports.sort();
+        assert!(!reports.is_empty(), "no reports in {}", dir);
+        for report in reports {
+            println!("replaying {}", report.display());
+            replay(&std::fs::read_to_string(report).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_too_few_players() {
+        assert_eq!(
+            pair_round(&ids(1), &[]),
+            Err(PairingError::NotEnoughPlayers)
+        );
+    }
+
+    #[test]
+    fn test_impossible_round_is_reported() {
+        // Two players who have already met have nobody else to play
+        let players = ids(2);
+        let round = with_results(1, vec![Pairing::game(1, "p1", "p2")], &[GameResult::Draw]);
+        assert_eq!(
+            pair_round(&players, &[round]),
+            Err(PairingError::NoValidPairing)
+        );
+    }
+}
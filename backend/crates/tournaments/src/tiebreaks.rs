@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+use crate::arena::{self, ArenaStanding};
+use crate::knockout::KnockoutStanding;
+use crate::model::{HalfPoints, Tournament, BYE_POINTS};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    pub rank: u32,
+    pub user_id: String,
+    pub points: f64,
+    /// Sum of the opponents' scores
+    pub buchholz: f64,
+    /// Sum of the scores of beaten opponents plus half of those drawn with
+    pub sonneborn_berger: f64,
+}
+
+/// Standings in the form the tournament's format ranks by
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Standings {
+    Rounds(Vec<Standing>),
+    Knockout(Vec<KnockoutStanding>),
+    Arena(Vec<ArenaStanding>),
+}
+
+impl Standings {
+    pub fn of(tournament: &Tournament) -> Self {
+        match tournament.format.round_format() {
+            Some(format) => format.standings(tournament),
+            None => Standings::Arena(arena::standings(tournament)),
+        }
+    }
+}
+
+/// Ranks the players by points, then Buchholz, then Sonneborn-Berger, then pairing order
+///
+/// A bye counts as a game against a virtual opponent, as in the FIDE tie-break rules:
+/// one who had the player's score before the round, lost that round and drew the rest.
+pub fn standings(tournament: &Tournament) -> Vec<Standing> {
+    let rounds_played = tournament.rounds.len() as HalfPoints;
+    let mut rows: Vec<(usize, String, HalfPoints, HalfPoints, HalfPoints)> = tournament
+        .ranked_player_ids()
+        .into_iter()
+        .enumerate()
+        .map(|(seed, user_id)| {
+            let mut score = 0;
+            let mut buchholz = 0;
+            // In quarter points: half points scored times the opponent's half points
+            let mut sonneborn_berger = 0;
+            for (index, round) in tournament.rounds.iter().enumerate() {
+                let Some(pairing) = round.pairing_of(&user_id) else {
+                    continue;
+                };
+                let opponent_score = match pairing.opponent_of(&user_id) {
+                    Some(opponent) => tournament.score_of(opponent),
+                    None => score + (2 - BYE_POINTS) + (rounds_played - index as HalfPoints - 1),
+                };
+                buchholz += opponent_score;
+                if let Some(points) = pairing.points_for(&user_id) {
+                    sonneborn_berger += points * opponent_score;
+                    score += points;
+                }
+            }
+            (seed, user_id, score, buchholz, sonneborn_berger)
+        })
+        .collect();
+    rows.sort_by_key(|&(seed, _, score, buchholz, sonneborn_berger)| {
+        (
+            Reverse(score),
+            Reverse(buchholz),
+            Reverse(sonneborn_berger),
+            seed,
+        )
+    });
+
+    rows.into_iter()
+        .enumerate()
+        .map(
+            |(index, (_, user_id, score, buchholz, sonneborn_berger))| Standing {
+                rank: index as u32 + 1,
+                user_id,
+                points: score as f64 / 2.0,
+                buchholz: buchholz as f64 / 2.0,
+                sonneborn_berger: sonneborn_berger as f64 / 4.0,
+            },
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Pairing, Round, TournamentFormat, TournamentPlayer};
+    use shared::GameResult;
+
+    // Small worked examples, totals checked by hand
+
+    fn tournament(players: &[(&str, i32)], rounds: Vec<Round>) -> Tournament {
+        let mut tournament = Tournament::new(
+            "t1".to_string(),
+            "Club Swiss".to_string(),
+            "org".to_string(),
+            "blitz".to_string(),
+            true,
+            TournamentFormat::Swiss { rounds: 3 },
+            0,
+        );
+        for (user_id, rating) in players {
+            tournament.players.insert(
+                user_id.to_string(),
+                TournamentPlayer {
+                    user_id: user_id.to_string(),
+                    rating: *rating,
+                    registered_at: 0,
+                },
+            );
+        }
+        tournament.rounds = rounds;
+        tournament
+    }
+
+    fn played(board: u32, white: &str, black: &str, result: GameResult) -> Pairing {
+        Pairing {
+            result: Some(result),
+            ..Pairing::game(board, white, black)
+        }
+    }
+
+    fn summary(standings: &[Standing]) -> Vec<(&str, f64, f64, f64)> {
+        standings
+            .iter()
+            .map(|s| (s.user_id.as_str(), s.points, s.buchholz, s.sonneborn_berger))
+            .collect()
+    }
+
+    #[test]
+    fn test_buchholz_and_sonneborn_berger() {
+        let rounds = vec![
+            Round {
+                number: 1,
+                pairings: vec![
+                    played(1, "a", "b", GameResult::WhiteWins),
+                    played(2, "c", "d", GameResult::Draw),
+                ],
+            },
+            Round {
+                number: 2,
+                pairings: vec![
+                    played(1, "c", "a", GameResult::Draw),
+                    played(2, "b", "d", GameResult::BlackWins),
+                ],
+            },
+            Round {
+                number: 3,
+                pairings: vec![
+                    played(1, "d", "a", GameResult::BlackWins),
+                    played(2, "b", "c", GameResult::BlackWins),
+                ],
+            },
+        ];
+        let tournament = tournament(
+            &[("a", 2000), ("b", 1900), ("c", 1800), ("d", 1700)],
+            rounds,
+        );
+        let standings = standings(&tournament);
+        assert_eq!(
+            summary(&standings),
+            [
+                ("a", 2.5, 3.5, 2.5),
+                ("c", 2.0, 4.0, 2.0),
+                ("d", 1.5, 4.5, 1.0),
+                ("b", 0.0, 6.0, 0.0),
+            ]
+        );
+        assert_eq!(
+            standings.iter().map(|s| s.rank).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_bye_counts_a_virtual_opponent() {
+        let rounds = vec![
+            Round {
+                number: 1,
+                pairings: vec![
+                    played(1, "a", "b", GameResult::WhiteWins),
+                    Pairing::bye(2, "c"),
+                ],
+            },
+            Round {
+                number: 2,
+                pairings: vec![played(1, "c", "a", GameResult::Draw), Pairing::bye(2, "b")],
+            },
+        ];
+        let tournament = tournament(&[("a", 2000), ("b", 1900), ("c", 1800)], rounds);
+        // c's virtual opponent in round 1 started on 0, lost, then drew round 2: ½
+        // b's in round 2 started on 0 and lost the last round: 0
+        assert_eq!(
+            summary(&standings(&tournament)),
+            [
+                ("a", 1.5, 2.5, 1.75),
+                ("c", 1.5, 2.0, 1.25),
+                ("b", 1.0, 1.5, 0.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ties_fall_back_to_pairing_order() {
+        let tournament = tournament(&[("a", 1500), ("b", 1600)], Vec::new());
+        let standings = standings(&tournament);
+        assert_eq!(standings[0].user_id, "b");
+        assert_eq!(standings[1].points, 0.0);
+    }
+}
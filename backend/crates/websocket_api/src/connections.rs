@@ -75,3 +75,33 @@ pub async fn remove_connection(state: &AppState, connection_id: &str) -> Result<
     );
     Ok(())
 }
+
+/// Returns true if the user has a live connection other than `connection_id`
+///
+/// Queries the UserIdIndex GSI. The index is eventually consistent, so a connection that
+/// was just removed may still be listed; it is excluded explicitly.
+pub async fn has_other_connection(
+    state: &AppState,
+    user_id: &str,
+    connection_id: &str,
+) -> Result<bool, Error> {
+    info!(
+        "Checking for other connections of user {} besides {}",
+        user_id, connection_id
+    );
+    let resp = state
+        .dynamodb
+        .query()
+        .table_name(&state.connections_table)
+        .index_name("UserIdIndex")
+        .key_condition_expression("user_id = :uid")
+        .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+        .send()
+        .await?;
+
+    let other = resp.items.unwrap_or_default().into_iter().any(|item| {
+        !matches!(item.get("connection_id"), Some(AttributeValue::S(id)) if id == connection_id)
+    });
+    info!("User {} has other connections: {}", user_id, other);
+    Ok(other)
+}
@@ -2,9 +2,23 @@ use lambda_runtime::Error;
 use serde_json;
 use tracing::{error, info};
 
-use crate::connections::{get_user_id_by_connection, remove_connection, store_connection};
-use crate::models::{Connection, JoinQueueMessage, LeaveQueueMessage, ResponseMessage};
-use crate::queue::{join_queue, leave_queue};
+use crate::connections::{
+    get_user_id_by_connection, has_other_connection, remove_connection, store_connection,
+};
+use crate::models::{
+    ArenaMessage, BerserkMessage, ChallengeIdMessage, Connection, ErrorResponseMessage,
+    JoinQueueMessage, LeaveQueueMessage, RematchMessage, ResponseMessage, SeekIdMessage,
+};
+use crate::queue::{
+    find_user_queue_entries, join_queue, leave_queue, remove_user_from_queues,
+    requested_time_controls,
+};
+use matchmaker::challenges::{ChallengeMessage, ChallengeRequest};
+use matchmaker::errors::ServiceError;
+use matchmaker::matching::MatchParams;
+use matchmaker::rematch::RematchOutcome;
+use matchmaker::seeks::{SeekMessage, SeekRequest};
+use matchmaker::status::{load_pool_stats, queue_status};
 use shared::auth::extract_claims;
 
 pub async fn handle_connect(
@@ -27,8 +41,7 @@ pub async fn handle_connect(
     }
 
     let user_id = if let Some(header) = auth_header {
-        if header.starts_with("Bearer ") {
-            let token = &header[7..];
+        if let Some(token) = header.strip_prefix("Bearer ") {
             let claims = extract_claims(token)?;
             info!(
                 "JWT validated, user_id: {} for connection {}",
@@ -83,9 +96,27 @@ pub async fn handle_disconnect(
     state: &crate::AppState,
 ) -> Result<(), Error> {
     let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    let user_id = get_user_id_by_connection(state, connection_id).await?;
+
     info!("Removing connection for connection_id {}", connection_id);
     remove_connection(state, connection_id).await?;
     info!("Connection {} disconnected", connection_id);
+
+    // A player who can't be notified of a match must not stay in the queue or the lobby,
+    // unless they are still connected from another tab or device
+    if let Some(user_id) = user_id {
+        if has_other_connection(state, &user_id, connection_id).await? {
+            info!(
+                "User {} still has another connection, keeping queue entries and seeks",
+                user_id
+            );
+        } else {
+            remove_user_from_queues(state, &user_id).await?;
+            if let Err(e) = state.seeks.remove_user_seeks(&user_id).await {
+                error!("Failed to remove seeks of user {}: {:?}", user_id, e);
+            }
+        }
+    }
     Ok(())
 }
 
@@ -104,10 +135,22 @@ pub async fn handle_join_queue(
     info!("Parsing join queue message from body: {}", body);
     let join_msg: JoinQueueMessage = serde_json::from_str(body)?;
     info!(
-        "Joining queue for user {} with time_control {} min_rating {:?} max_rating {:?}",
-        user_id, join_msg.time_control, join_msg.min_rating, join_msg.max_rating
+        "Joining queue for user {} with time_control {:?} time_controls {:?} min_rating {:?} max_rating {:?}",
+        user_id, join_msg.time_control, join_msg.time_controls, join_msg.min_rating, join_msg.max_rating
     );
-    join_queue(state, &user_id, &join_msg).await?;
+    let time_controls = match requested_time_controls(&join_msg) {
+        Ok(time_controls) => time_controls,
+        Err(e) => {
+            info!("Rejected join_queue from user {}: {}", user_id, e);
+            let response = ErrorResponseMessage {
+                status: "error".to_string(),
+                code: e.code().to_string(),
+                message: e.to_string(),
+            };
+            return send_message(request_context, &response, state).await;
+        }
+    };
+    join_queue(state, &user_id, &join_msg, &time_controls).await?;
     info!("Successfully joined queue for user {}", user_id);
 
     info!(
@@ -145,10 +188,17 @@ pub async fn handle_leave_queue(
     info!("Parsing leave queue message from body: {}", body);
     let leave_msg: LeaveQueueMessage = serde_json::from_str(body)?;
     info!(
-        "Leaving queue for user {} with time_control {}",
-        user_id, leave_msg.time_control
+        "Leaving queue for user {} with time_control {:?}, variant {:?}, rated {}",
+        user_id, leave_msg.time_control, leave_msg.variant, leave_msg.rated
     );
-    leave_queue(state, &user_id, &leave_msg.time_control).await?;
+    leave_queue(
+        state,
+        &user_id,
+        leave_msg.time_control.as_deref(),
+        leave_msg.variant,
+        leave_msg.rated,
+    )
+    .await?;
     info!("Successfully left queue for user {}", user_id);
 
     info!(
@@ -171,6 +221,372 @@ pub async fn handle_leave_queue(
     Ok(())
 }
 
+/// Replies with a queue_status message for each queue the user is waiting in
+pub async fn handle_queue_status(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+    info!("User {} is connected", user_id);
+
+    let entries = find_user_queue_entries(state, &user_id).await?;
+    if entries.is_empty() {
+        return send_response(
+            request_context,
+            &ResponseMessage {
+                status: "error".to_string(),
+                message: "Not in queue".to_string(),
+            },
+            state,
+        )
+        .await;
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let params = MatchParams::default();
+    for entry in entries {
+        // Stats are kept per pool, which is the queue key without its rating bucket
+        let pool = entry
+            .queue_key
+            .rsplit_once('#')
+            .map_or(entry.queue_key.as_str(), |(pool, _)| pool);
+        let stats = load_pool_stats(&state.dynamodb, &state.stats_table, pool, now).await?;
+        let waited_secs = now.saturating_sub(entry.joined_at.parse().unwrap_or(now));
+        let status = queue_status(
+            &entry.time_control,
+            entry.variant,
+            entry.rated,
+            waited_secs,
+            &params,
+            &stats,
+        );
+        info!(
+            "Sending queue status to user {}: time_control={}, waited={}s, range=±{}, estimate={:?}",
+            user_id,
+            status.time_control,
+            status.waited_secs,
+            status.search_range,
+            status.estimated_wait_secs
+        );
+        send_message(request_context, &status, state).await?;
+    }
+    Ok(())
+}
+
+pub async fn handle_create_challenge(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    info!("Parsing challenge request from body: {}", body);
+    let request: ChallengeRequest = serde_json::from_str(body)?;
+    match state.challenges.create(&user_id, request).await {
+        Ok(challenge) => {
+            info!(
+                "User {} created challenge {}",
+                user_id, challenge.challenge.challenge_id
+            );
+            let message = ChallengeMessage {
+                action: "challenge_created".to_string(),
+                challenge,
+            };
+            send_message(request_context, &message, state).await
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_accept_challenge(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: ChallengeIdMessage = serde_json::from_str(body)?;
+    info!("User {} accepting challenge {}", user_id, msg.challenge_id);
+    // Both players hear about the game through game_matched
+    match state.challenges.accept(&msg.challenge_id, &user_id).await {
+        Ok(game) => {
+            info!(
+                "Challenge {} became game {}",
+                msg.challenge_id, game.game_id
+            );
+            Ok(())
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_cancel_challenge(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: ChallengeIdMessage = serde_json::from_str(body)?;
+    info!("User {} cancelling challenge {}", user_id, msg.challenge_id);
+    match state.challenges.cancel(&msg.challenge_id, &user_id).await {
+        Ok(()) => {
+            send_response(
+                request_context,
+                &ResponseMessage {
+                    status: "success".to_string(),
+                    message: "Challenge cancelled".to_string(),
+                },
+                state,
+            )
+            .await
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_offer_rematch(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: RematchMessage = serde_json::from_str(body)?;
+    info!("User {} offering rematch of game {}", user_id, msg.game_id);
+    match state.challenges.offer_rematch(&msg.game_id, &user_id).await {
+        Ok(RematchOutcome::Offered) => {
+            send_response(
+                request_context,
+                &ResponseMessage {
+                    status: "success".to_string(),
+                    message: "Rematch offered".to_string(),
+                },
+                state,
+            )
+            .await
+        }
+        // Both players hear about the new game through game_matched
+        Ok(RematchOutcome::Started(game)) => {
+            info!(
+                "Rematch of {} started as game {}",
+                msg.game_id, game.game_id
+            );
+            Ok(())
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_join_arena(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: ArenaMessage = serde_json::from_str(body)?;
+    info!("User {} joining arena {}", user_id, msg.tournament_id);
+    match state
+        .tournaments
+        .join_arena(&msg.tournament_id, &user_id)
+        .await
+    {
+        Ok(tournament) => {
+            send_response(
+                request_context,
+                &ResponseMessage {
+                    status: "success".to_string(),
+                    message: format!("Joined arena {}", tournament.name),
+                },
+                state,
+            )
+            .await
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_leave_arena(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: ArenaMessage = serde_json::from_str(body)?;
+    info!("User {} leaving arena {}", user_id, msg.tournament_id);
+    match state
+        .tournaments
+        .leave_arena(&msg.tournament_id, &user_id)
+        .await
+    {
+        Ok(()) => {
+            send_response(
+                request_context,
+                &ResponseMessage {
+                    status: "success".to_string(),
+                    message: "Left the arena".to_string(),
+                },
+                state,
+            )
+            .await
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_berserk(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: BerserkMessage = serde_json::from_str(body)?;
+    info!("User {} berserking in game {}", user_id, msg.game_id);
+    // Both players hear about it through the berserk message
+    match state.tournaments.berserk(&msg.game_id, &user_id).await {
+        Ok(_) => Ok(()),
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_create_seek(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    info!("Parsing seek request from body: {}", body);
+    let request: SeekRequest = serde_json::from_str(body)?;
+    // Everyone, the seeker included, also gets the new lobby through lobby_update
+    match state.seeks.create(&user_id, request).await {
+        Ok(seek) => {
+            info!("User {} created seek {}", user_id, seek.seek_id);
+            let message = SeekMessage {
+                action: "seek_created".to_string(),
+                seek,
+            };
+            send_message(request_context, &message, state).await
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_accept_seek(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: SeekIdMessage = serde_json::from_str(body)?;
+    info!("User {} accepting seek {}", user_id, msg.seek_id);
+    // Both players hear about the game through game_matched
+    match state.seeks.accept(&msg.seek_id, &user_id).await {
+        Ok(game) => {
+            info!("Seek {} became game {}", msg.seek_id, game.game_id);
+            Ok(())
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+pub async fn handle_cancel_seek(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    body: &str,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
+    info!("Retrieving user_id for connection_id {}", connection_id);
+    let user_id = get_user_id_by_connection(state, connection_id)
+        .await?
+        .ok_or("Not connected")?;
+
+    let msg: SeekIdMessage = serde_json::from_str(body)?;
+    info!("User {} cancelling seek {}", user_id, msg.seek_id);
+    match state.seeks.cancel(&msg.seek_id, &user_id).await {
+        Ok(()) => {
+            send_response(
+                request_context,
+                &ResponseMessage {
+                    status: "success".to_string(),
+                    message: "Seek cancelled".to_string(),
+                },
+                state,
+            )
+            .await
+        }
+        Err(e) => send_service_error(request_context, e, state).await,
+    }
+}
+
+/// Reports a rejected lobby, arena or challenge action to the client; internal errors fail
+/// the request
+async fn send_service_error(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    e: ServiceError,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    if let ServiceError::Internal(e) = e {
+        return Err(e);
+    }
+    info!("Action rejected: {}", e);
+    send_response(
+        request_context,
+        &ResponseMessage {
+            status: "error".to_string(),
+            message: e.to_string(),
+        },
+        state,
+    )
+    .await
+}
+
 pub async fn handle_default(
     request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
     state: &crate::AppState,
@@ -201,12 +617,21 @@ async fn send_response(
     response: &ResponseMessage,
     state: &crate::AppState,
 ) -> Result<(), Error> {
-    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
-
     info!(
         "Preparing to send response to connection {}: status={}, message={}",
-        connection_id, response.status, response.message
+        request_context.connection_id.as_deref().unwrap_or(""),
+        response.status,
+        response.message
     );
+    send_message(request_context, response, state).await
+}
+
+async fn send_message(
+    request_context: &aws_lambda_events::event::apigw::ApiGatewayWebsocketProxyRequestContext,
+    message: &impl serde::Serialize,
+    state: &crate::AppState,
+) -> Result<(), Error> {
+    let connection_id = request_context.connection_id.as_deref().unwrap_or("");
     let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
     let endpoint_url = &state.websocket_api_endpoint;
     info!("Using API Gateway endpoint: {}", endpoint_url);
@@ -215,7 +640,7 @@ async fn send_response(
         .build();
     let client = aws_sdk_apigatewaymanagement::Client::from_conf(api_config);
 
-    let data = serde_json::to_string(response)?;
+    let data = serde_json::to_string(message)?;
     info!("Sending data: {}", data);
     client
         .post_to_connection()
@@ -3,7 +3,11 @@ pub mod handlers;
 pub mod models;
 pub mod queue;
 
+use aws_sdk_apigatewaymanagement::Client as ApiGatewayClient;
 use aws_sdk_dynamodb::Client as DynamoClient;
+use matchmaker::challenges::ChallengeContext;
+use matchmaker::seeks::SeekContext;
+use matchmaker::tournaments::TournamentContext;
 use tracing::info;
 
 #[derive(Clone)]
@@ -11,8 +15,12 @@ pub struct AppState {
     pub dynamodb: DynamoClient,
     pub queue_table: String,
     pub connections_table: String,
+    pub stats_table: String,
     pub region: String,
     pub websocket_api_endpoint: String,
+    pub challenges: ChallengeContext,
+    pub tournaments: TournamentContext,
+    pub seeks: SeekContext,
 }
 
 impl AppState {
@@ -22,9 +30,20 @@ impl AppState {
         let queue_table = std::env::var("QUEUE_TABLE").expect("QUEUE_TABLE must be set");
         let connections_table =
             std::env::var("CONNECTIONS_TABLE").expect("CONNECTIONS_TABLE must be set");
+        let stats_table = std::env::var("STATS_TABLE").expect("STATS_TABLE must be set");
         let region = std::env::var("AWS_REGION").unwrap_or("eu-west-1".to_string());
         let websocket_api_endpoint =
             std::env::var("WEBSOCKET_API_ENDPOINT").expect("WEBSOCKET_API_ENDPOINT must be set");
+        let api_config = aws_sdk_apigatewaymanagement::config::Builder::from(&config)
+            .endpoint_url(&websocket_api_endpoint)
+            .build();
+        let api_gateway = ApiGatewayClient::from_conf(api_config);
+        let challenges = ChallengeContext::from_env(dynamodb.clone(), api_gateway.clone())
+            .expect("Invalid challenge configuration");
+        let tournaments = TournamentContext::from_env(dynamodb.clone(), api_gateway.clone())
+            .expect("Invalid tournament configuration");
+        let seeks = SeekContext::from_env(dynamodb.clone(), api_gateway)
+            .expect("Invalid seek configuration");
         info!(
             "Initialized AppState with queue_table={}, connections_table={}, region={}, websocket_api_endpoint={}",
             queue_table, connections_table, region, websocket_api_endpoint
@@ -33,8 +52,12 @@ impl AppState {
             dynamodb,
             queue_table,
             connections_table,
+            stats_table,
             region,
             websocket_api_endpoint,
+            challenges,
+            tournaments,
+            seeks,
         }
     }
 }
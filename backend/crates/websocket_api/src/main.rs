@@ -6,7 +6,10 @@ use lambda_runtime::{
 };
 
 use websocket_api::handlers::{
-    handle_connect, handle_default, handle_disconnect, handle_join_queue, handle_leave_queue,
+    handle_accept_challenge, handle_accept_seek, handle_berserk, handle_cancel_challenge,
+    handle_cancel_seek, handle_connect, handle_create_challenge, handle_create_seek,
+    handle_default, handle_disconnect, handle_join_arena, handle_join_queue, handle_leave_arena,
+    handle_leave_queue, handle_offer_rematch, handle_queue_status,
 };
 use websocket_api::AppState;
 
@@ -127,6 +130,289 @@ async fn handler(
                 Ok(())
             }
         }
+        "queue_status" => {
+            info!("Processing queue_status for connection {}", connection_id);
+            let res = handle_queue_status(request_context, &state).await;
+            if res.is_ok() {
+                info!(
+                    "queue_status handled successfully for connection {}",
+                    connection_id
+                );
+            } else {
+                error!(
+                    "queue_status failed for connection {}: {:?}",
+                    connection_id, res
+                );
+            }
+            res
+        }
+        "create_challenge" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing create_challenge for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_create_challenge(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "create_challenge handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "create_challenge failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for create_challenge for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "accept_challenge" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing accept_challenge for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_accept_challenge(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "accept_challenge handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "accept_challenge failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for accept_challenge for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "cancel_challenge" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing cancel_challenge for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_cancel_challenge(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "cancel_challenge handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "cancel_challenge failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for cancel_challenge for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "offer_rematch" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing offer_rematch for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_offer_rematch(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "offer_rematch handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "offer_rematch failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for offer_rematch for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "join_arena" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing join_arena for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_join_arena(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "join_arena handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "join_arena failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for join_arena for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "leave_arena" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing leave_arena for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_leave_arena(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "leave_arena handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "leave_arena failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for leave_arena for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "berserk" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing berserk for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_berserk(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "berserk handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!("berserk failed for connection {}: {:?}", connection_id, res);
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for berserk for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "create_seek" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing create_seek for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_create_seek(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "create_seek handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "create_seek failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for create_seek for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "accept_seek" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing accept_seek for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_accept_seek(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "accept_seek handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "accept_seek failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for accept_seek for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
+        "cancel_seek" => {
+            if let Some(body) = &request.body {
+                info!(
+                    "Processing cancel_seek for connection {} with body: {}",
+                    connection_id, body
+                );
+                let res = handle_cancel_seek(request_context, body, &state).await;
+                if res.is_ok() {
+                    info!(
+                        "cancel_seek handled successfully for connection {}",
+                        connection_id
+                    );
+                } else {
+                    error!(
+                        "cancel_seek failed for connection {}: {:?}",
+                        connection_id, res
+                    );
+                }
+                res
+            } else {
+                warn!(
+                    "No body provided for cancel_seek for connection {}",
+                    connection_id
+                );
+                Ok(())
+            }
+        }
         _ => {
             info!(
                 "Processing default route {} for connection {}",
@@ -171,7 +457,7 @@ async fn handler(
                 status_code: 500,
                 headers: Default::default(),
                 multi_value_headers: Default::default(),
-                body: Some(format!("{{\"message\": \"Internal server error\"}}").into()),
+                body: Some("{\"message\": \"Internal server error\"}".into()),
                 is_base64_encoded: false,
             })
         }
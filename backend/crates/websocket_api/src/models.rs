@@ -1,17 +1,79 @@
 use serde::{Deserialize, Serialize};
+use shared::variant::Variant;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct JoinQueueMessage {
     pub action: String, // "join_queue"
-    pub time_control: String,
+    /// Single time control, as sent by clients that predate `time_controls`
+    #[serde(default)]
+    pub time_control: Option<String>,
+    /// Time controls the player will accept; they are matched in whichever pool finds an
+    /// opponent first
+    #[serde(default)]
+    pub time_controls: Vec<String>,
     pub min_rating: Option<i32>,
     pub max_rating: Option<i32>,
+    /// Accept a bot opponent of similar strength if no human is found in time; joins
+    /// asking for it are refused until bots can play on the server
+    #[serde(default)]
+    pub bot_fallback: bool,
+    /// Rated unless asked otherwise; casual players are only paired with each other
+    #[serde(default = "default_rated")]
+    pub rated: bool,
+    /// Standard chess unless asked otherwise; each variant has its own pools and rating
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+fn default_rated() -> bool {
+    true
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LeaveQueueMessage {
     pub action: String, // "leave_queue"
-    pub time_control: String,
+    /// Leaves this time control and any joined together with it; all queues if omitted
+    #[serde(default)]
+    pub time_control: Option<String>,
+    /// Whether `time_control` names the rated or the casual queue
+    #[serde(default = "default_rated")]
+    pub rated: bool,
+    /// Variant of the queue `time_control` names
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+/// Body of accept_challenge and cancel_challenge
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChallengeIdMessage {
+    pub action: String,
+    pub challenge_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RematchMessage {
+    pub action: String, // "offer_rematch"
+    pub game_id: String,
+}
+
+/// Body of join_arena and leave_arena
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArenaMessage {
+    pub action: String,
+    pub tournament_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BerserkMessage {
+    pub action: String, // "berserk"
+    pub game_id: String,
+}
+
+/// Body of accept_seek and cancel_seek
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeekIdMessage {
+    pub action: String,
+    pub seek_id: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -20,6 +82,14 @@ pub struct ResponseMessage {
     pub message: String,
 }
 
+/// Error reply with a code clients can act on without parsing the message
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ErrorResponseMessage {
+    pub status: String, // "error"
+    pub code: String,
+    pub message: String,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct QueueEntry {
     pub queue_key: String,
@@ -29,10 +99,24 @@ pub struct QueueEntry {
     pub rating: i32,
     pub joined_at: String,
     pub status: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub matched_at: Option<String>,
     pub min_rating: Option<i32>,
     pub max_rating: Option<i32>,
+    /// DynamoDB TTL attribute (unix seconds) so abandoned entries are cleaned up
+    #[serde(default)]
+    pub expires_at: u64,
+    /// Snapshot of the user's block list, read by the matchmaker
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub blocked_user_ids: Vec<String>,
+    /// Queue keys of the other entries created by the same join, which the matchmaker
+    /// cancels when this one is matched
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub linked_queue_keys: Vec<String>,
+    #[serde(default)]
+    pub bot_fallback: bool,
+    #[serde(default = "default_rated")]
+    pub rated: bool,
+    #[serde(default)]
+    pub variant: Variant,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
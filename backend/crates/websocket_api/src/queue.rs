@@ -1,20 +1,84 @@
-use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::types::{AttributeValue, Put, ReturnValue, TransactWriteItem};
 use lambda_runtime::Error;
 use serde_dynamo;
+use shared::time_control::{find_time_control, unsupported_time_control};
 use std::collections::HashMap;
+use std::fmt;
 use tracing::info;
 
 use crate::models::{JoinQueueMessage, QueueEntry};
 use crate::AppState;
+use matchmaker::models::{casual_pool_name, variant_pool_name};
+use shared::variant::Variant;
 
+/// How long a queue entry lives without being matched before DynamoDB TTL removes it
+///
+/// Covers clients that vanish without leaving the queue or disconnecting cleanly.
+pub const QUEUE_ENTRY_TTL_SECS: u64 = 15 * 60;
+
+/// Most time controls a player can wait in at once
+pub const MAX_TIME_CONTROLS_PER_JOIN: usize = 5;
+
+/// Returns true if an existing queue item still holds the user's place in the queue
+///
+/// Items left behind by older versions (status "matched") and items past their TTL that
+/// DynamoDB hasn't deleted yet don't count, so the user can join again.
+fn is_active_entry(item: &HashMap<String, AttributeValue>, now: u64) -> bool {
+    let waiting = matches!(item.get("status"), Some(AttributeValue::S(s)) if s == "waiting");
+    let expired = match item.get("expires_at") {
+        Some(AttributeValue::N(n)) => n.parse::<u64>().is_ok_and(|expires_at| expires_at <= now),
+        _ => false,
+    };
+    waiting && !expired
+}
+
+/// A join_queue request the client has to correct before trying again
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueRequestError {
+    Invalid(String),
+    /// A time control that isn't in the catalogue; custom ones need a challenge
+    UnsupportedTimeControl(String),
+    /// `bot_fallback` was asked for, but nothing plays the bots' moves on the server yet
+    BotsUnavailable,
+}
+
+impl QueueRequestError {
+    /// Machine-readable code sent to the client alongside the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            QueueRequestError::Invalid(_) => "invalid_request",
+            QueueRequestError::UnsupportedTimeControl(_) => "unsupported_time_control",
+            QueueRequestError::BotsUnavailable => "bots_unavailable",
+        }
+    }
+}
+
+impl fmt::Display for QueueRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueueRequestError::Invalid(message) => write!(f, "{}", message),
+            QueueRequestError::UnsupportedTimeControl(id) => {
+                write!(f, "{}", unsupported_time_control(id))
+            }
+            QueueRequestError::BotsUnavailable => {
+                write!(f, "Bot opponents are not available yet")
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueueRequestError {}
+
+/// Puts the user in the queue for `time_controls`, as validated by `requested_time_controls`
 pub async fn join_queue(
     state: &AppState,
     user_id: &str,
     msg: &JoinQueueMessage,
+    time_controls: &[String],
 ) -> Result<(), Error> {
     info!(
-        "Joining queue for user {} with time_control {}, min_rating {:?}, max_rating {:?}",
-        user_id, msg.time_control, msg.min_rating, msg.max_rating
+        "Joining queue for user {} with time_controls {:?}, variant {:?}, min_rating {:?}, max_rating {:?}, bot_fallback {}, rated {}",
+        user_id, time_controls, msg.variant, msg.min_rating, msg.max_rating, msg.bot_fallback, msg.rated
     );
     // Get user's rating
     let users_table = std::env::var("USERS_TABLE").expect("USERS_TABLE must be set");
@@ -30,89 +94,137 @@ pub async fn join_queue(
         .send()
         .await?;
 
-    let rating = if let Some(item) = user_item.item {
-        if let Some(AttributeValue::N(r)) = item.get("rating") {
-            r.parse::<i32>().unwrap_or(1200)
-        } else {
-            1200
-        }
-    } else {
-        1200
+    let rating = user_item
+        .item
+        .as_ref()
+        .map_or(1200, |item| variant_rating(item, msg.variant));
+
+    // The matchmaker filters blocked opponents using this snapshot of the block list
+    let blocked_user_ids = match user_item
+        .item
+        .as_ref()
+        .and_then(|i| i.get("blocked_user_ids"))
+    {
+        Some(AttributeValue::L(ids)) => ids
+            .iter()
+            .filter_map(|id| id.as_s().ok().cloned())
+            .collect(),
+        _ => Vec::new(),
     };
 
-    info!("User {} has rating {}", user_id, rating);
+    info!(
+        "User {} has rating {} and {} blocked users",
+        user_id,
+        rating,
+        blocked_user_ids.len()
+    );
     let rating_bucket = ((rating / 50) * 50).to_string();
-    let pk = format!("{}#{}", msg.time_control, rating_bucket);
+    let queue_keys: Vec<String> = time_controls
+        .iter()
+        .map(|time_control| queue_key(time_control, msg.rated, msg.variant, &rating_bucket))
+        .collect();
     info!(
-        "Calculated rating bucket {} for time_control {}, queue_key {}",
-        rating_bucket, msg.time_control, pk
+        "Calculated rating bucket {}, queue_keys {:?}",
+        rating_bucket, queue_keys
     );
 
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
     // Check if already in queue
-    let key = HashMap::from([
-        ("queue_key".to_string(), AttributeValue::S(pk.clone())),
-        (
-            "user_id".to_string(),
-            AttributeValue::S(user_id.to_string()),
-        ),
-    ]);
-    info!(
-        "Checking if user {} is already in queue for key {}",
-        user_id, pk
-    );
-    let existing = state
-        .dynamodb
-        .get_item()
-        .table_name(&state.queue_table)
-        .set_key(Some(key))
-        .send()
-        .await?;
+    for pk in &queue_keys {
+        info!(
+            "Checking if user {} is already in queue for key {}",
+            user_id, pk
+        );
+        let existing = state
+            .dynamodb
+            .get_item()
+            .table_name(&state.queue_table)
+            .set_key(Some(queue_entry_key(pk, user_id)))
+            .send()
+            .await?;
 
-    if existing.item.is_some() {
-        info!("User {} already in queue for key {}", user_id, pk);
-        return Err("Already in queue".into());
+        match existing.item {
+            Some(item) if is_active_entry(&item, now) => {
+                info!("User {} already in queue for key {}", user_id, pk);
+                return Err("Already in queue".into());
+            }
+            Some(_) => info!(
+                "User {} has a stale queue entry for key {}, replacing it",
+                user_id, pk
+            ),
+            None => info!("User {} not in queue for key {}", user_id, pk),
+        }
     }
-    info!("User {} not in queue, proceeding to join", user_id);
 
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs()
-        .to_string();
-
-    let entry = QueueEntry {
-        queue_key: pk.clone(),
-        user_id: user_id.to_string(),
-        time_control: msg.time_control.clone(),
-        rating_bucket,
-        rating,
-        joined_at: now.clone(),
-        status: "waiting".to_string(),
-        matched_at: None,
-        min_rating: msg.min_rating,
-        max_rating: msg.max_rating,
-    };
+    // Each entry links to the others so that matching one cancels the rest
+    let mut transact_items = Vec::new();
+    for (time_control, pk) in time_controls.iter().zip(&queue_keys) {
+        let entry = QueueEntry {
+            queue_key: pk.clone(),
+            user_id: user_id.to_string(),
+            time_control: time_control.clone(),
+            rating_bucket: rating_bucket.clone(),
+            rating,
+            joined_at: now.to_string(),
+            status: "waiting".to_string(),
+            min_rating: msg.min_rating,
+            max_rating: msg.max_rating,
+            expires_at: now + QUEUE_ENTRY_TTL_SECS,
+            blocked_user_ids: blocked_user_ids.clone(),
+            linked_queue_keys: linked_keys(&queue_keys, pk),
+            bot_fallback: msg.bot_fallback,
+            rated: msg.rated,
+            variant: msg.variant,
+        };
+
+        info!(
+            "Creating queue entry for user {}: queue_key={}, time_control={}, rating_bucket={}, rating={}, joined_at={}, linked={:?}",
+            user_id, entry.queue_key, entry.time_control, entry.rating_bucket, entry.rating, entry.joined_at, entry.linked_queue_keys
+        );
+        let put = Put::builder()
+            .table_name(&state.queue_table)
+            .set_item(Some(serde_dynamo::to_item(&entry)?))
+            .build()
+            .map_err(|e| format!("Failed to build put: {:?}", e))?;
+        transact_items.push(TransactWriteItem::builder().put(put).build());
+    }
 
+    // Written in one transaction so the matchmaker never sees half of a linked group
     info!(
-        "Creating queue entry for user {}: queue_key={}, time_control={}, rating_bucket={}, rating={}, joined_at={}",
-        user_id, entry.queue_key, entry.time_control, entry.rating_bucket, entry.rating, entry.joined_at
+        "Storing {} queue entries in table {}",
+        transact_items.len(),
+        state.queue_table
     );
-    let item = serde_dynamo::to_item(&entry)?;
-    info!("Storing queue entry in table {}", state.queue_table);
     state
         .dynamodb
-        .put_item()
-        .table_name(&state.queue_table)
-        .set_item(Some(item))
+        .transact_write_items()
+        .set_transact_items(Some(transact_items))
         .send()
         .await?;
     info!(
-        "Successfully joined queue for user {} with queue_key {}",
-        user_id, pk
+        "Successfully joined queue for user {} with queue_keys {:?}",
+        user_id, queue_keys
     );
     Ok(())
 }
 
-pub async fn leave_queue(state: &AppState, user_id: &str, time_control: &str) -> Result<(), Error> {
+/// Leaves the queue for `time_control` along with any entries joined together with it,
+/// or every queue the user is in if no time control is given
+pub async fn leave_queue(
+    state: &AppState,
+    user_id: &str,
+    time_control: Option<&str>,
+    variant: Variant,
+    rated: bool,
+) -> Result<(), Error> {
+    let Some(time_control) = time_control else {
+        remove_user_from_queues(state, user_id).await?;
+        return Ok(());
+    };
+
     info!(
         "Leaving queue for user {} with time_control {}",
         user_id, time_control
@@ -131,43 +243,384 @@ pub async fn leave_queue(state: &AppState, user_id: &str, time_control: &str) ->
         .send()
         .await?;
 
-    let rating = if let Some(item) = user_item.item {
-        if let Some(AttributeValue::N(r)) = item.get("rating") {
-            r.parse::<i32>().unwrap_or(1200)
-        } else {
-            1200
-        }
-    } else {
-        1200
-    };
+    let rating = user_item
+        .item
+        .as_ref()
+        .map_or(1200, |item| variant_rating(item, variant));
 
     info!("User {} has rating {} for leaving queue", user_id, rating);
     let rating_bucket = ((rating / 50) * 50).to_string();
-    let pk = format!("{}#{}", time_control, rating_bucket);
+    let pk = queue_key(time_control, rated, variant, &rating_bucket);
     info!(
         "Calculated queue_key {} for user {} leaving queue with time_control {}",
         pk, user_id, time_control
     );
 
-    let key = HashMap::from([
-        ("queue_key".to_string(), AttributeValue::S(pk.clone())),
-        (
-            "user_id".to_string(),
-            AttributeValue::S(user_id.to_string()),
-        ),
-    ]);
-
     info!("Removing user {} from queue with key {}", user_id, pk);
-    state
+    let removed = state
         .dynamodb
         .delete_item()
         .table_name(&state.queue_table)
-        .set_key(Some(key))
+        .set_key(Some(queue_entry_key(&pk, user_id)))
+        .return_values(ReturnValue::AllOld)
         .send()
         .await?;
+
+    let linked_queue_keys = match removed
+        .attributes
+        .as_ref()
+        .and_then(|item| item.get("linked_queue_keys"))
+    {
+        Some(AttributeValue::L(keys)) => keys
+            .iter()
+            .filter_map(|key| key.as_s().ok().cloned())
+            .collect(),
+        _ => Vec::new(),
+    };
+    for linked_key in &linked_queue_keys {
+        info!(
+            "Removing user {} from linked queue with key {}",
+            user_id, linked_key
+        );
+        state
+            .dynamodb
+            .delete_item()
+            .table_name(&state.queue_table)
+            .set_key(Some(queue_entry_key(linked_key, user_id)))
+            .send()
+            .await?;
+    }
     info!(
-        "Successfully removed user {} from queue with key {}",
-        user_id, pk
+        "Successfully removed user {} from queue with key {} and {} linked entries",
+        user_id,
+        pk,
+        linked_queue_keys.len()
     );
     Ok(())
 }
+
+/// Validates a join and de-duplicates the time controls it asks for
+///
+/// Accepts the older single `time_control` field, the `time_controls` list, or both.
+/// Every time control must be in the catalogue, so a typo can't open a pool nobody else
+/// will ever join. Joins asking for a bot fallback are refused until bots can play.
+pub fn requested_time_controls(msg: &JoinQueueMessage) -> Result<Vec<String>, QueueRequestError> {
+    if msg.bot_fallback {
+        return Err(QueueRequestError::BotsUnavailable);
+    }
+    let mut time_controls: Vec<String> = Vec::new();
+    for time_control in msg.time_control.iter().chain(&msg.time_controls) {
+        if time_control.is_empty() {
+            return Err(QueueRequestError::Invalid(
+                "Time control must not be empty".to_string(),
+            ));
+        }
+        if find_time_control(time_control).is_none() {
+            return Err(QueueRequestError::UnsupportedTimeControl(
+                time_control.clone(),
+            ));
+        }
+        if !time_controls.contains(time_control) {
+            time_controls.push(time_control.clone());
+        }
+    }
+
+    if time_controls.is_empty() {
+        return Err(QueueRequestError::Invalid(
+            "No time control given".to_string(),
+        ));
+    }
+    if time_controls.len() > MAX_TIME_CONTROLS_PER_JOIN {
+        return Err(QueueRequestError::Invalid(format!(
+            "At most {} time controls can be joined at once",
+            MAX_TIME_CONTROLS_PER_JOIN
+        )));
+    }
+    Ok(time_controls)
+}
+
+/// The user's rating in `variant`, falling back to their standard rating until they
+/// have played it
+fn variant_rating(item: &HashMap<String, AttributeValue>, variant: Variant) -> i32 {
+    [variant.rating_attribute(), "rating"]
+        .iter()
+        .find_map(|attribute| match item.get(*attribute) {
+            Some(AttributeValue::N(r)) => r.parse::<i32>().ok(),
+            _ => None,
+        })
+        .unwrap_or(1200)
+}
+
+/// Queue key of the bucket a player waits in; casual players and each variant have pools
+/// of their own
+fn queue_key(time_control: &str, rated: bool, variant: Variant, rating_bucket: &str) -> String {
+    let pool = variant_pool_name(variant, time_control);
+    if rated {
+        format!("{}#{}", pool, rating_bucket)
+    } else {
+        format!("{}#{}", casual_pool_name(&pool), rating_bucket)
+    }
+}
+
+/// The queue keys in a linked group other than `own`
+fn linked_keys(queue_keys: &[String], own: &str) -> Vec<String> {
+    queue_keys
+        .iter()
+        .filter(|key| key.as_str() != own)
+        .cloned()
+        .collect()
+}
+
+fn queue_entry_key(queue_key: &str, user_id: &str) -> HashMap<String, AttributeValue> {
+    HashMap::from([
+        (
+            "queue_key".to_string(),
+            AttributeValue::S(queue_key.to_string()),
+        ),
+        (
+            "user_id".to_string(),
+            AttributeValue::S(user_id.to_string()),
+        ),
+    ])
+}
+
+/// Removes every queue entry the user has, across all time controls and rating buckets
+///
+/// Returns the number of entries removed.
+pub async fn remove_user_from_queues(state: &AppState, user_id: &str) -> Result<usize, Error> {
+    info!("Removing all queue entries for user {}", user_id);
+    let keys = user_queue_keys(state, user_id).await?;
+
+    for key in &keys {
+        state
+            .dynamodb
+            .delete_item()
+            .table_name(&state.queue_table)
+            .set_key(Some(key.clone()))
+            .send()
+            .await?;
+    }
+
+    info!("Removed {} queue entries for user {}", keys.len(), user_id);
+    Ok(keys.len())
+}
+
+/// Loads every queue entry the user has that is still waiting and hasn't expired
+pub async fn find_user_queue_entries(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<QueueEntry>, Error> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+
+    let mut entries = Vec::new();
+    for key in user_queue_keys(state, user_id).await? {
+        let resp = state
+            .dynamodb
+            .get_item()
+            .table_name(&state.queue_table)
+            .set_key(Some(key))
+            .send()
+            .await?;
+        if let Some(item) = resp.item {
+            if is_active_entry(&item, now) {
+                entries.push(serde_dynamo::from_item(item)?);
+            }
+        }
+    }
+
+    info!(
+        "Found {} active queue entries for user {}",
+        entries.len(),
+        user_id
+    );
+    Ok(entries)
+}
+
+/// Finds the keys of all the user's queue entries through the QueueTable UserIdIndex GSI
+async fn user_queue_keys(
+    state: &AppState,
+    user_id: &str,
+) -> Result<Vec<HashMap<String, AttributeValue>>, Error> {
+    let mut keys = Vec::new();
+    let mut exclusive_start_key = None;
+
+    loop {
+        let resp = state
+            .dynamodb
+            .query()
+            .table_name(&state.queue_table)
+            .index_name("UserIdIndex")
+            .key_condition_expression("user_id = :uid")
+            .expression_attribute_values(":uid", AttributeValue::S(user_id.to_string()))
+            .set_exclusive_start_key(exclusive_start_key)
+            .send()
+            .await?;
+
+        for item in resp.items.unwrap_or_default() {
+            let Some(AttributeValue::S(queue_key)) = item.get("queue_key") else {
+                continue;
+            };
+            keys.push(queue_entry_key(queue_key, user_id));
+        }
+
+        exclusive_start_key = resp.last_evaluated_key;
+        if exclusive_start_key.is_none() {
+            break;
+        }
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(status: &str, expires_at: Option<u64>) -> HashMap<String, AttributeValue> {
+        let mut item =
+            HashMap::from([("status".to_string(), AttributeValue::S(status.to_string()))]);
+        if let Some(expires_at) = expires_at {
+            item.insert(
+                "expires_at".to_string(),
+                AttributeValue::N(expires_at.to_string()),
+            );
+        }
+        item
+    }
+
+    #[test]
+    fn test_waiting_entry_is_active() {
+        assert!(is_active_entry(&item("waiting", Some(1_000)), 999));
+        assert!(is_active_entry(&item("waiting", None), 999));
+    }
+
+    #[test]
+    fn test_expired_entry_is_not_active() {
+        assert!(!is_active_entry(&item("waiting", Some(1_000)), 1_000));
+    }
+
+    #[test]
+    fn test_matched_entry_is_not_active() {
+        assert!(!is_active_entry(&item("matched", None), 0));
+    }
+
+    fn join(time_control: Option<&str>, time_controls: &[&str]) -> JoinQueueMessage {
+        JoinQueueMessage {
+            action: "join_queue".to_string(),
+            time_control: time_control.map(str::to_string),
+            time_controls: time_controls.iter().map(|tc| tc.to_string()).collect(),
+            min_rating: None,
+            max_rating: None,
+            bot_fallback: false,
+            rated: true,
+            variant: Variant::Standard,
+        }
+    }
+
+    #[test]
+    fn test_single_time_control_still_accepted() {
+        assert_eq!(
+            requested_time_controls(&join(Some("3+2"), &[])).unwrap(),
+            vec!["3+2"]
+        );
+    }
+
+    #[test]
+    fn test_time_controls_are_deduplicated_in_order() {
+        assert_eq!(
+            requested_time_controls(&join(Some("3+0"), &["3+2", "3+0", "5+0"])).unwrap(),
+            vec!["3+0", "3+2", "5+0"]
+        );
+    }
+
+    #[test]
+    fn test_time_controls_are_validated() {
+        assert!(requested_time_controls(&join(None, &[])).is_err());
+        assert!(requested_time_controls(&join(None, &["3+0", ""])).is_err());
+        let too_many: Vec<&str> = shared::time_control::TIME_CONTROLS
+            .iter()
+            .take(MAX_TIME_CONTROLS_PER_JOIN + 1)
+            .map(|tc| tc.id)
+            .collect();
+        assert!(matches!(
+            requested_time_controls(&join(None, &too_many)),
+            Err(QueueRequestError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_time_controls_are_rejected() {
+        let error = requested_time_controls(&join(Some("3+0"), &["3+1"])).unwrap_err();
+        assert_eq!(
+            error,
+            QueueRequestError::UnsupportedTimeControl("3+1".to_string())
+        );
+        assert_eq!(error.code(), "unsupported_time_control");
+        assert!(requested_time_controls(&join(Some("blitz"), &[])).is_err());
+    }
+
+    #[test]
+    fn test_bot_fallback_is_refused() {
+        let msg = JoinQueueMessage {
+            bot_fallback: true,
+            ..join(Some("3+0"), &[])
+        };
+        let error = requested_time_controls(&msg).unwrap_err();
+        assert_eq!(error, QueueRequestError::BotsUnavailable);
+        assert_eq!(error.code(), "bots_unavailable");
+    }
+
+    #[test]
+    fn test_casual_queue_keys_are_separate() {
+        assert_eq!(
+            queue_key("blitz", true, Variant::Standard, "1200"),
+            "blitz#1200"
+        );
+        assert_eq!(
+            queue_key("blitz", false, Variant::Standard, "1200"),
+            "casual:blitz#1200"
+        );
+    }
+
+    #[test]
+    fn test_variant_queue_keys_are_separate() {
+        assert_eq!(
+            queue_key("3+2", true, Variant::Chess960, "1500"),
+            "chess960:3+2#1500"
+        );
+        assert_eq!(
+            queue_key("3+2", false, Variant::Chess960, "1500"),
+            "casual:chess960:3+2#1500"
+        );
+    }
+
+    #[test]
+    fn test_variant_rating_falls_back_to_standard() {
+        let mut item = HashMap::from([("rating".to_string(), AttributeValue::N("1500".into()))]);
+        assert_eq!(variant_rating(&item, Variant::Chess960), 1500);
+        item.insert(
+            "chess960_rating".to_string(),
+            AttributeValue::N("1620".into()),
+        );
+        assert_eq!(variant_rating(&item, Variant::Chess960), 1620);
+        assert_eq!(variant_rating(&item, Variant::Standard), 1500);
+        assert_eq!(variant_rating(&HashMap::new(), Variant::Standard), 1200);
+    }
+
+    #[test]
+    fn test_join_defaults_to_rated() {
+        let msg: JoinQueueMessage =
+            serde_json::from_str(r#"{"action": "join_queue", "time_control": "blitz"}"#).unwrap();
+        assert!(msg.rated);
+        assert_eq!(msg.variant, Variant::Standard);
+    }
+
+    #[test]
+    fn test_linked_keys_exclude_own_entry() {
+        let keys = vec!["3+0#1200".to_string(), "5+0#1200".to_string()];
+        assert_eq!(linked_keys(&keys, "3+0#1200"), vec!["5+0#1200"]);
+        assert!(linked_keys(&keys[..1], "3+0#1200").is_empty());
+    }
+}
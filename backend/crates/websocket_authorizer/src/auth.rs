@@ -71,8 +71,8 @@ impl AuthService {
 
         // Validate JWT for ID token
         let mut validation = Validation::new(Algorithm::RS256);
-        validation.set_issuer(&[self.issuer.clone()]);
-        validation.set_audience(&[self.client_id.clone()]);
+        validation.set_issuer(std::slice::from_ref(&self.issuer));
+        validation.set_audience(std::slice::from_ref(&self.client_id));
 
         let token_data =
             jsonwebtoken::decode::<shared::auth::Claims>(token, &decoding_key, &validation)?;
@@ -31,8 +31,8 @@ async fn authorizer_handler(
             .get("Authorization")
             .or_else(|| headers.get("authorization"));
         if let Some(auth_header) = auth_header {
-            if auth_header.starts_with("Bearer ") {
-                auth_header[7..].to_string()
+            if let Some(token) = auth_header.strip_prefix("Bearer ") {
+                token.to_string()
             } else {
                 error!("Authorization header must use Bearer scheme");
                 return Ok(AuthPolicy::deny());
@@ -202,6 +202,8 @@ pub async fn create_test_dynamodb_user(user_id: &str) -> Result<(), String> {
     let user = shared::User {
         user_id: user_id.to_string(),
         rating: 1200,
+        chess960_rating: None,
+        blocked_user_ids: Vec::new(),
     };
 
     // Serialize to DynamoDB item
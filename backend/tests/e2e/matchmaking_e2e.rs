@@ -124,8 +124,8 @@ async fn test_matchmaking_two_users() {
 
     let result = timeout(Duration::from_secs(90), async {
         println!("\n--- Step 1: Setting up two test users ---");
-        let (user_id1, id_token1) = setup_test_user(&test_email1, &test_password).await;
-        let (user_id2, id_token2) = setup_test_user(&test_email2, &test_password).await;
+        let (user_id1, id_token1) = setup_test_user(&test_email1, test_password).await;
+        let (user_id2, id_token2) = setup_test_user(&test_email2, test_password).await;
         println!("Test users created: {} and {}", user_id1, user_id2);
 
         println!("\n--- Step 2: Connecting both users to WebSocket ---");
@@ -136,7 +136,7 @@ async fn test_matchmaking_two_users() {
         println!("\n--- Step 3: Both users join the queue ---");
         let join_queue_msg1 = serde_json::json!({
             "action": "join_queue",
-            "time_control": "blitz",
+            "time_control": "3+0",
             "min_rating": 1000,
             "max_rating": 2000
         });
@@ -147,7 +147,7 @@ async fn test_matchmaking_two_users() {
 
         let join_queue_msg2 = serde_json::json!({
             "action": "join_queue",
-            "time_control": "blitz",
+            "time_control": "3+0",
             "min_rating": 1000,
             "max_rating": 2000
         });
@@ -194,6 +194,10 @@ async fn test_matchmaking_two_users() {
 
         let game_id1 = match_result1["game_id"].as_str().expect("Missing game_id");
         let game_id2 = match_result2["game_id"].as_str().expect("Missing game_id");
+        assert_eq!(
+            game_id1, game_id2,
+            "Both players should be in the same game"
+        );
 
         let opponent_id1 = match_result1["opponent_id"]
             .as_str()
@@ -201,6 +205,8 @@ async fn test_matchmaking_two_users() {
         let opponent_id2 = match_result2["opponent_id"]
             .as_str()
             .expect("Missing opponent_id");
+        assert_eq!(opponent_id1, user_id2);
+        assert_eq!(opponent_id2, user_id1);
 
         let color1 = match_result1["color"].as_str().expect("Missing color");
         let color2 = match_result2["color"].as_str().expect("Missing color");
@@ -29,12 +29,12 @@ async fn e2e_users_me_endpoint_returns_user_data() {
     let test_password = "TempPassword123!";
 
     // Create a new Cognito user
-    create_test_cognito_user(&test_email, &test_password)
+    create_test_cognito_user(&test_email, test_password)
         .await
         .expect("Failed to create test Cognito user");
 
     // Authenticate with the new user
-    let tokens = authenticate_with_cognito(&test_email, &test_password)
+    let tokens = authenticate_with_cognito(&test_email, test_password)
         .await
         .expect("Failed to authenticate with test user");
 
@@ -113,12 +113,12 @@ async fn e2e_users_me_endpoint_delete_user() {
     // Ensure cleanup happens even if test fails
     let result = async {
         // Create a new Cognito user
-        create_test_cognito_user(&test_email, &test_password)
+        create_test_cognito_user(&test_email, test_password)
             .await
             .expect("Failed to create test Cognito user");
 
         // Authenticate with the new user
-        let tokens = authenticate_with_cognito(&test_email, &test_password)
+        let tokens = authenticate_with_cognito(&test_email, test_password)
             .await
             .expect("Failed to authenticate with test user");
 
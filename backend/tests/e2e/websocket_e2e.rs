@@ -108,11 +108,12 @@ async fn send_and_validate_response(
 
     // Receive response
     println!("[send_and_validate_response] Waiting for response...");
-    let response = ws_stream
-        .next()
-        .await
-        .unwrap_or_else(|| panic!("No response received for {} (connection closed)", action))
-        .unwrap_or_else(|e| panic!("Failed to receive {} response: {:?}", action, e));
+    let response = match ws_stream.next().await {
+        Some(response) => {
+            response.unwrap_or_else(|e| panic!("Failed to receive {} response: {:?}", action, e))
+        }
+        None => panic!("No response received for {} (connection closed)", action),
+    };
 
     println!(
         "[send_and_validate_response] Received {} response: {:?}",
@@ -184,7 +185,7 @@ async fn test_websocket_join_leave_queue_flow() {
     let result = timeout(Duration::from_secs(30), async {
         println!("\n--- Step 1: Setting up test user ---");
         // Setup test user and authenticate
-        let (_user_id, id_token) = setup_test_user(&test_email, &test_password).await;
+        let (_user_id, id_token) = setup_test_user(&test_email, test_password).await;
         println!("Test user setup complete");
 
         println!("\n--- Step 2: Connecting to WebSocket ---");
@@ -196,7 +197,7 @@ async fn test_websocket_join_leave_queue_flow() {
         // Join queue
         let join_queue_msg = serde_json::json!({
             "action": "join_queue",
-            "time_control": "blitz",
+            "time_control": "3+0",
             "min_rating": 1000,
             "max_rating": 2000
         });
@@ -208,7 +209,7 @@ async fn test_websocket_join_leave_queue_flow() {
         // Leave queue
         let leave_queue_msg = serde_json::json!({
             "action": "leave_queue",
-            "time_control": "blitz"
+            "time_control": "3+0"
         });
         println!("Sending leave_queue message: {}", leave_queue_msg);
         send_and_validate_response(&mut ws_stream, leave_queue_msg, "success", "Left queue").await;